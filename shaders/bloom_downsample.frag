@@ -0,0 +1,56 @@
+#version 450
+
+layout(location = 0) in vec2 fragUv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texelSize;
+    float threshold;
+    float knee;
+    uint prefilter;
+} pushConstants;
+
+layout(location = 0) out vec4 outColor;
+
+// Soft threshold, only what is brighter than it blooms
+vec3 prefilter(vec3 color) {
+    float brightness = max(color.r, max(color.g, color.b));
+    float knee = pushConstants.threshold * pushConstants.knee + 1e-5;
+    float soft = clamp(brightness - pushConstants.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    float contribution = max(soft, brightness - pushConstants.threshold) / max(brightness, 1e-5);
+    return color * contribution;
+}
+
+vec3 tap(vec2 offset) {
+    return texture(source, fragUv + offset * pushConstants.texelSize).rgb;
+}
+
+// 13 taps from Call of Duty: Advanced Warfare, doesn't flicker like a box filter
+void main() {
+    vec3 a = tap(vec2(-2.0, -2.0));
+    vec3 b = tap(vec2(0.0, -2.0));
+    vec3 c = tap(vec2(2.0, -2.0));
+    vec3 d = tap(vec2(-2.0, 0.0));
+    vec3 e = tap(vec2(0.0, 0.0));
+    vec3 f = tap(vec2(2.0, 0.0));
+    vec3 g = tap(vec2(-2.0, 2.0));
+    vec3 h = tap(vec2(0.0, 2.0));
+    vec3 i = tap(vec2(2.0, 2.0));
+    vec3 j = tap(vec2(-1.0, -1.0));
+    vec3 k = tap(vec2(1.0, -1.0));
+    vec3 l = tap(vec2(-1.0, 1.0));
+    vec3 m = tap(vec2(1.0, 1.0));
+
+    vec3 color = e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+
+    if (pushConstants.prefilter != 0) {
+        color = prefilter(color);
+    }
+
+    outColor = vec4(max(color, 0.0), 1.0);
+}
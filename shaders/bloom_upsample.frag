@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec2 fragUv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    vec2 texelSize;
+    float radius;
+} pushConstants;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 tap(vec2 offset) {
+    return texture(source, fragUv + offset * pushConstants.texelSize * pushConstants.radius).rgb;
+}
+
+// 3x3 tent filter, added on top of the mip it is drawn to
+void main() {
+    vec3 color = tap(vec2(0.0, 0.0)) * 4.0;
+    color += (tap(vec2(0.0, -1.0)) + tap(vec2(-1.0, 0.0)) + tap(vec2(1.0, 0.0)) + tap(vec2(0.0, 1.0))) * 2.0;
+    color += tap(vec2(-1.0, -1.0)) + tap(vec2(1.0, -1.0)) + tap(vec2(-1.0, 1.0)) + tap(vec2(1.0, 1.0));
+
+    outColor = vec4(color / 16.0, 1.0);
+}
@@ -1,7 +0,0 @@
-#version 450
-
-layout(location = 0) in vec2 pos;
-
-void main() {
-    gl_Position = vec4(vec3(pos, 0.0), 1.0);
-}
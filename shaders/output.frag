@@ -0,0 +1,59 @@
+#version 450
+
+const uint ENCODING_LINEAR = 0;
+const uint ENCODING_SRGB = 1;
+const uint ENCODING_PQ = 2;
+const uint ENCODING_SCRGB = 3;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform PushConstants {
+    uint encoding;
+    float paperWhite;
+} pushConstants;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 linearToSrgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+// Same linear light, wider primaries
+vec3 rec709ToRec2020(vec3 color) {
+    const mat3 conversion = mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956
+    );
+    return conversion * color;
+}
+
+// SMPTE ST 2084 inverse EOTF, from absolute nits
+vec3 linearToPq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+    vec3 color = max(texture(source, fragUv).rgb, 0.0);
+
+    if (pushConstants.encoding == ENCODING_SRGB) {
+        // Only when the swap chain format can't do it
+        color = linearToSrgb(color);
+    } else if (pushConstants.encoding == ENCODING_PQ) {
+        color = linearToPq(rec709ToRec2020(color) * pushConstants.paperWhite);
+    } else if (pushConstants.encoding == ENCODING_SCRGB) {
+        color = color * pushConstants.paperWhite / 80.0;
+    }
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+layout(location = 2) flat in float fragAdditive;
+
+layout(set = 0, binding = 1) uniform sampler2D particleTexture;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec4 color = texture(particleTexture, fragUv) * fragColor;
+    // Premultiplied, blended with one and one minus source alpha.
+    // Without alpha nothing behind is covered, the color is only added
+    outColor = vec4(color.rgb * color.a, color.a * (1.0 - fragAdditive));
+}
@@ -0,0 +1,49 @@
+#version 450
+
+// Corner of the unit quad
+layout(location = 0) in vec2 corner;
+
+struct Particle {
+    // World position, size
+    vec4 positionSize;
+    // Velocity before the speed curve, age in seconds
+    vec4 velocityAge;
+    vec4 color;
+    // Lifetime, rotation, spin, random
+    vec4 params;
+};
+
+layout(set = 0, binding = 0) readonly buffer Particles {
+    Particle particles[];
+} particles;
+
+layout(push_constant) uniform PushConstants {
+    mat4 viewProjection;
+    // World space, facing the camera
+    vec4 cameraRight;
+    // W is 1 for additive blending
+    vec4 cameraUp;
+} pushConstants;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragColor;
+layout(location = 2) flat out float fragAdditive;
+
+void main() {
+    Particle particle = particles.particles[gl_InstanceIndex];
+
+    float rotation = particle.params.y;
+    float c = cos(rotation);
+    float s = sin(rotation);
+    vec2 offset = corner * 2.0 - 1.0;
+    offset = vec2(offset.x * c - offset.y * s, offset.x * s + offset.y * c) * particle.positionSize.w * 0.5;
+
+    vec3 position = particle.positionSize.xyz
+        + pushConstants.cameraRight.xyz * offset.x
+        + pushConstants.cameraUp.xyz * offset.y;
+    gl_Position = pushConstants.viewProjection * vec4(position, 1.0);
+
+    fragUv = vec2(corner.x, 1.0 - corner.y);
+    fragColor = particle.color;
+    fragAdditive = pushConstants.cameraUp.w;
+}
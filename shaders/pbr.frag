@@ -0,0 +1,259 @@
+#version 450
+
+const float PI = 3.14159265359;
+
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+
+// Roughness levels of the prefiltered environment, same as `PREFILTER_LEVELS` on the CPU
+const int PREFILTER_LEVELS = 5;
+
+layout(location = 0) in vec3 fragWorldPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec2 fragUv;
+layout(location = 4) in vec4 fragTint;
+layout(location = 5) flat in vec4 fragParams;
+
+layout(set = 0, binding = 0) uniform Frame {
+    mat4 viewProjection;
+    vec4 cameraPosition;
+    vec4 cameraForward;
+    vec4 ambientSky;
+    vec4 ambientGround;
+    uvec4 lightCount;
+    vec4 shadowParams;
+    // Intensity, whether there is an environment
+    vec4 environmentParams;
+} frame;
+
+struct Light {
+    vec4 positionRange;
+    vec4 directionType;
+    vec4 color;
+    vec4 spotCos;
+    vec4 shadow;
+};
+
+layout(set = 0, binding = 1) readonly buffer Lights {
+    Light lights[];
+};
+
+layout(set = 0, binding = 2) uniform sampler2DShadow shadowAtlas;
+
+struct Shadow {
+    mat4 viewProjection;
+    vec4 atlasRect;
+    vec4 split;
+};
+
+layout(set = 0, binding = 3) readonly buffer Shadows {
+    Shadow shadows[];
+};
+
+layout(set = 0, binding = 4) uniform samplerCube irradianceMap;
+layout(set = 0, binding = 5) uniform samplerCube prefilteredMaps[PREFILTER_LEVELS];
+layout(set = 0, binding = 6) uniform sampler2D brdfLut;
+
+layout(set = 1, binding = 0) uniform Material {
+    vec4 baseColor;
+    vec4 emissiveNormalScale;
+    vec4 metallicRoughnessOcclusion;
+} material;
+
+layout(set = 1, binding = 1) uniform sampler2D baseColorTexture;
+layout(set = 1, binding = 2) uniform sampler2D metallicRoughnessTexture;
+layout(set = 1, binding = 3) uniform sampler2D normalTexture;
+layout(set = 1, binding = 4) uniform sampler2D occlusionTexture;
+layout(set = 1, binding = 5) uniform sampler2D emissiveTexture;
+
+layout(location = 0) out vec4 outColor;
+
+// Trowbridge-Reitz GGX normal distribution
+float distributionGgx(float NdotH, float alpha) {
+    float alpha2 = alpha * alpha;
+    float denom = NdotH * NdotH * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denom * denom);
+}
+
+// Height correlated Smith, already divided by 4 NdotL NdotV
+float visibilitySmithGgx(float NdotL, float NdotV, float alpha) {
+    float alpha2 = alpha * alpha;
+    float ggxV = NdotL * sqrt(NdotV * NdotV * (1.0 - alpha2) + alpha2);
+    float ggxL = NdotV * sqrt(NdotL * NdotL * (1.0 - alpha2) + alpha2);
+    return 0.5 / max(ggxV + ggxL, 1e-5);
+}
+
+vec3 fresnelSchlick(float VdotH, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - VdotH, 5.0);
+}
+
+// Same falloff as KHR_lights_punctual, reaches zero at the range
+float rangeAttenuation(float distance, float range) {
+    float ratio = distance / range;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / max(distance * distance, 1e-4);
+}
+
+vec3 perturbedNormal(float normalScale) {
+    vec3 normal = normalize(fragNormal);
+    vec3 tangent = normalize(fragTangent.xyz - normal * dot(normal, fragTangent.xyz));
+    vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+
+    vec3 tangentNormal = texture(normalTexture, fragUv).xyz * 2.0 - 1.0;
+    tangentNormal.xy *= normalScale;
+    return normalize(mat3(tangent, bitangent, normal) * tangentNormal);
+}
+
+// Fraction of light reaching the position, filtered over the PCF kernel
+float sampleShadow(uint index, vec3 worldPosition) {
+    Shadow shadow = shadows[index];
+    vec4 clip = shadow.viewProjection * vec4(worldPosition, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+    if (clip.w <= 0.0 || ndc.z > 1.0 || any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        return 1.0;
+    }
+
+    vec2 texel = vec2(frame.shadowParams.x);
+    vec2 atlasUv = shadow.atlasRect.xy + uv * shadow.atlasRect.zw;
+    // Keep the kernel inside the tile, the neighbours belong to other lights
+    vec2 minUv = shadow.atlasRect.xy + texel * 0.5;
+    vec2 maxUv = shadow.atlasRect.xy + shadow.atlasRect.zw - texel * 0.5;
+
+    int radius = int(frame.shadowParams.y);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            vec2 sampleUv = clamp(atlasUv + vec2(x, y) * texel, minUv, maxUv);
+            lit += texture(shadowAtlas, vec3(sampleUv, ndc.z));
+        }
+    }
+    float size = float(radius * 2 + 1);
+    return lit / (size * size);
+}
+
+// Pick the cascade covering the distance to the camera, fading into the next one
+// at its end, past the last one everything is lit
+float sampleCascades(uint first, uint count, vec3 worldPosition, float distance) {
+    for (uint i = 0; i < count; i++) {
+        vec4 split = shadows[first + i].split;
+        if (distance > split.y) {
+            continue;
+        }
+
+        float lit = sampleShadow(first + i, worldPosition);
+        float blendStart = split.y - (split.y - split.x) * frame.shadowParams.z;
+        if (distance > blendStart) {
+            float next = i + 1 < count ? sampleShadow(first + i + 1, worldPosition) : 1.0;
+            lit = mix(lit, next, (distance - blendStart) / max(split.y - blendStart, 1e-5));
+        }
+        return lit;
+    }
+    return 1.0;
+}
+
+// Constant indices only, dynamic indexing of sampler arrays is an optional feature
+vec3 samplePrefilteredLevel(int level, vec3 R) {
+    switch (level) {
+        case 0: return textureLod(prefilteredMaps[0], R, 0.0).rgb;
+        case 1: return textureLod(prefilteredMaps[1], R, 0.0).rgb;
+        case 2: return textureLod(prefilteredMaps[2], R, 0.0).rgb;
+        case 3: return textureLod(prefilteredMaps[3], R, 0.0).rgb;
+        default: return textureLod(prefilteredMaps[4], R, 0.0).rgb;
+    }
+}
+
+// Levels are evenly spread over the roughness, blend the two closest
+vec3 samplePrefiltered(vec3 R, float roughness) {
+    float level = roughness * float(PREFILTER_LEVELS - 1);
+    int low = int(floor(level));
+    int high = min(low + 1, PREFILTER_LEVELS - 1);
+    return mix(samplePrefilteredLevel(low, R), samplePrefilteredLevel(high, R), level - float(low));
+}
+
+void main() {
+    vec4 baseColor = material.baseColor * texture(baseColorTexture, fragUv) * fragTint;
+    vec4 metallicRoughness = texture(metallicRoughnessTexture, fragUv);
+    float metallic = clamp(material.metallicRoughnessOcclusion.x * metallicRoughness.b, 0.0, 1.0);
+    float roughness = clamp(material.metallicRoughnessOcclusion.y * metallicRoughness.g, 0.04, 1.0);
+    float alpha = roughness * roughness;
+
+    vec3 diffuseColor = baseColor.rgb * (1.0 - metallic);
+    vec3 f0 = mix(vec3(0.04), baseColor.rgb, metallic);
+
+    vec3 N = perturbedNormal(material.emissiveNormalScale.w);
+    vec3 V = normalize(frame.cameraPosition.xyz - fragWorldPosition);
+    float NdotV = max(dot(N, V), 1e-4);
+
+    vec3 color = vec3(0.0);
+    for (uint i = 0; i < frame.lightCount.x; i++) {
+        Light light = lights[i];
+        uint type = uint(light.directionType.w);
+
+        vec3 L;
+        float attenuation = 1.0;
+        if (type == LIGHT_DIRECTIONAL) {
+            L = -light.directionType.xyz;
+        } else {
+            vec3 toLight = light.positionRange.xyz - fragWorldPosition;
+            float distance = length(toLight);
+            L = toLight / distance;
+            attenuation = rangeAttenuation(distance, light.positionRange.w);
+
+            if (type == LIGHT_SPOT) {
+                float cosAngle = dot(light.directionType.xyz, -L);
+                attenuation *= smoothstep(light.spotCos.y, light.spotCos.x, cosAngle);
+            }
+        }
+
+        float NdotL = dot(N, L);
+        if (NdotL <= 0.0 || attenuation <= 0.0) {
+            continue;
+        }
+
+        if (light.shadow.x >= 0.0) {
+            // Pushed off the surface against acne, more so at grazing angles
+            vec3 geometricNormal = normalize(fragNormal);
+            float slope = 1.0 - clamp(dot(geometricNormal, L), 0.0, 1.0);
+            vec3 biasedPosition = fragWorldPosition + L * light.shadow.y + geometricNormal * light.shadow.z * slope;
+            if (type == LIGHT_DIRECTIONAL) {
+                float distance = dot(fragWorldPosition - frame.cameraPosition.xyz, frame.cameraForward.xyz);
+                attenuation *= sampleCascades(uint(light.shadow.x), uint(light.shadow.w), biasedPosition, distance);
+            } else {
+                attenuation *= sampleShadow(uint(light.shadow.x), biasedPosition);
+            }
+            if (attenuation <= 0.0) {
+                continue;
+            }
+        }
+
+        vec3 H = normalize(L + V);
+        float NdotH = max(dot(N, H), 0.0);
+        float VdotH = max(dot(V, H), 0.0);
+
+        vec3 F = fresnelSchlick(VdotH, f0);
+        vec3 specular = F * distributionGgx(NdotH, alpha) * visibilitySmithGgx(NdotL, NdotV, alpha);
+        vec3 diffuse = (1.0 - F) * diffuseColor / PI;
+
+        color += (diffuse + specular) * light.color.rgb * attenuation * NdotL;
+    }
+
+    // Ambient from the environment or the hemisphere, the only light darkened by the occlusion map
+    float occlusion = mix(1.0, texture(occlusionTexture, fragUv).r, material.metallicRoughnessOcclusion.z);
+    if (frame.environmentParams.y > 0.0) {
+        vec3 R = reflect(-V, N);
+        vec2 brdf = texture(brdfLut, vec2(NdotV, roughness)).rg;
+        vec3 diffuse = texture(irradianceMap, N).rgb * diffuseColor;
+        vec3 specular = samplePrefiltered(R, roughness) * (f0 * brdf.x + brdf.y);
+        color += (diffuse + specular) * frame.environmentParams.x * occlusion;
+    } else {
+        vec3 ambient = mix(frame.ambientGround.rgb, frame.ambientSky.rgb, N.y * 0.5 + 0.5);
+        color += ambient * (diffuseColor + f0 * 0.25) * occlusion;
+    }
+
+    color += material.emissiveNormalScale.rgb * texture(emissiveTexture, fragUv).rgb;
+
+    outColor = vec4(color, baseColor.a);
+}
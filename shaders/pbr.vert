@@ -0,0 +1,43 @@
+#version 450
+
+// Per vertex
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+
+// Per instance
+layout(location = 4) in mat4 model;
+layout(location = 8) in vec4 tint;
+layout(location = 9) in vec4 params;
+
+layout(set = 0, binding = 0) uniform Frame {
+    mat4 viewProjection;
+    vec4 cameraPosition;
+    vec4 cameraForward;
+    vec4 ambientSky;
+    vec4 ambientGround;
+    uvec4 lightCount;
+    vec4 shadowParams;
+} frame;
+
+layout(location = 0) out vec3 fragWorldPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 fragTangent;
+layout(location = 3) out vec2 fragUv;
+layout(location = 4) out vec4 fragTint;
+layout(location = 5) flat out vec4 fragParams;
+
+void main() {
+    vec4 worldPosition = model * vec4(position, 1.0);
+    // Keeps the normals perpendicular under non uniform scale
+    mat3 normalMatrix = transpose(inverse(mat3(model)));
+
+    gl_Position = frame.viewProjection * worldPosition;
+    fragWorldPosition = worldPosition.xyz;
+    fragNormal = normalMatrix * normal;
+    fragTangent = vec4(mat3(model) * tangent.xyz, tangent.w);
+    fragUv = uv;
+    fragTint = tint;
+    fragParams = params;
+}
@@ -0,0 +1,81 @@
+#version 450
+
+// Per vertex
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec4 tangent;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in uvec4 joints;
+layout(location = 5) in vec4 weights;
+
+// Per instance
+layout(location = 6) in mat4 model;
+layout(location = 10) in vec4 tint;
+layout(location = 11) in vec4 params;
+
+layout(set = 0, binding = 0) uniform Frame {
+    mat4 viewProjection;
+    vec4 cameraPosition;
+    vec4 cameraForward;
+    vec4 ambientSky;
+    vec4 ambientGround;
+    uvec4 lightCount;
+    vec4 shadowParams;
+} frame;
+
+// From the bind pose to the current pose, in the space of the mesh
+layout(set = 2, binding = 0) readonly buffer Joints {
+    mat4 matrices[];
+} skin;
+
+// Position then normal displacement of each vertex, target after target
+layout(set = 2, binding = 1) readonly buffer MorphTargets {
+    vec4 displacements[];
+} morph;
+
+struct ActiveTarget {
+    uint index;
+    float weight;
+};
+
+layout(set = 2, binding = 2) readonly buffer MorphWeights {
+    uint count;
+    uint vertexCount;
+    ActiveTarget targets[];
+} morphWeights;
+
+layout(location = 0) out vec3 fragWorldPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 fragTangent;
+layout(location = 3) out vec2 fragUv;
+layout(location = 4) out vec4 fragTint;
+layout(location = 5) flat out vec4 fragParams;
+
+void main() {
+    vec3 morphedPosition = position;
+    vec3 morphedNormal = normal;
+    for (uint i = 0; i < morphWeights.count; i++) {
+        ActiveTarget target = morphWeights.targets[i];
+        uint displacement = (target.index * morphWeights.vertexCount + uint(gl_VertexIndex)) * 2;
+        morphedPosition += target.weight * morph.displacements[displacement].xyz;
+        morphedNormal += target.weight * morph.displacements[displacement + 1].xyz;
+    }
+
+    mat4 skinMatrix = weights.x * skin.matrices[joints.x]
+        + weights.y * skin.matrices[joints.y]
+        + weights.z * skin.matrices[joints.z]
+        + weights.w * skin.matrices[joints.w];
+    mat4 skinnedModel = model * skinMatrix;
+
+    vec4 worldPosition = skinnedModel * vec4(morphedPosition, 1.0);
+    // Keeps the normals perpendicular under non uniform scale
+    mat3 normalMatrix = transpose(inverse(mat3(skinnedModel)));
+
+    gl_Position = frame.viewProjection * worldPosition;
+    fragWorldPosition = worldPosition.xyz;
+    fragNormal = normalMatrix * morphedNormal;
+    fragTangent = vec4(mat3(skinnedModel) * tangent.xyz, tangent.w);
+    fragUv = uv;
+    fragTint = tint;
+    fragParams = params;
+}
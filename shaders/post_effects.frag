@@ -0,0 +1,207 @@
+#version 450
+
+const uint EFFECT_FXAA = 0;
+const uint EFFECT_CHROMATIC_ABERRATION = 1;
+const uint EFFECT_VIGNETTE = 2;
+const uint EFFECT_FILM_GRAIN = 3;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+// One shader for every effect so the chain is a single pipeline, picked by `effect`
+layout(push_constant) uniform PushConstants {
+    vec4 params;
+    vec2 texelSize;
+    uint effect;
+    float seed;
+} pushConstants;
+
+layout(location = 0) out vec4 outColor;
+
+// Perceptual, the edges FXAA finds are the ones visible on screen
+float luma(vec3 color) {
+    return sqrt(dot(clamp(color, 0.0, 1.0), vec3(0.299, 0.587, 0.114)));
+}
+
+float lumaAt(vec2 uv) {
+    return luma(texture(source, uv).rgb);
+}
+
+// FXAA 3.11 quality preset, after Timothy Lottes
+vec3 fxaa(vec2 uv) {
+    const float QUALITY[12] = float[](1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0);
+    float subpixel = pushConstants.params.x;
+    float edgeThreshold = pushConstants.params.y;
+    float edgeThresholdMin = pushConstants.params.z;
+    vec2 texel = pushConstants.texelSize;
+
+    vec3 colorCenter = texture(source, uv).rgb;
+    float lumaCenter = luma(colorCenter);
+    float lumaUp = lumaAt(uv + vec2(0.0, -texel.y));
+    float lumaDown = lumaAt(uv + vec2(0.0, texel.y));
+    float lumaLeft = lumaAt(uv + vec2(-texel.x, 0.0));
+    float lumaRight = lumaAt(uv + vec2(texel.x, 0.0));
+
+    float lumaMin = min(lumaCenter, min(min(lumaUp, lumaDown), min(lumaLeft, lumaRight)));
+    float lumaMax = max(lumaCenter, max(max(lumaUp, lumaDown), max(lumaLeft, lumaRight)));
+    float lumaRange = lumaMax - lumaMin;
+    if (lumaRange < max(edgeThresholdMin, lumaMax * edgeThreshold)) {
+        return colorCenter;
+    }
+
+    float lumaUpLeft = lumaAt(uv + vec2(-texel.x, -texel.y));
+    float lumaUpRight = lumaAt(uv + vec2(texel.x, -texel.y));
+    float lumaDownLeft = lumaAt(uv + vec2(-texel.x, texel.y));
+    float lumaDownRight = lumaAt(uv + vec2(texel.x, texel.y));
+
+    float lumaUpDown = lumaUp + lumaDown;
+    float lumaLeftRight = lumaLeft + lumaRight;
+    float lumaLeftCorners = lumaUpLeft + lumaDownLeft;
+    float lumaRightCorners = lumaUpRight + lumaDownRight;
+    float lumaUpCorners = lumaUpLeft + lumaUpRight;
+    float lumaDownCorners = lumaDownLeft + lumaDownRight;
+
+    // Which way the edge goes
+    float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners)
+        + abs(-2.0 * lumaCenter + lumaUpDown) * 2.0
+        + abs(-2.0 * lumaRight + lumaRightCorners);
+    float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners)
+        + abs(-2.0 * lumaCenter + lumaLeftRight) * 2.0
+        + abs(-2.0 * lumaDown + lumaDownCorners);
+    bool horizontal = edgeHorizontal >= edgeVertical;
+
+    // Which side of the pixel it is on
+    float luma1 = horizontal ? lumaUp : lumaLeft;
+    float luma2 = horizontal ? lumaDown : lumaRight;
+    float gradient1 = luma1 - lumaCenter;
+    float gradient2 = luma2 - lumaCenter;
+    bool steepest1 = abs(gradient1) >= abs(gradient2);
+    float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+    float stepLength = horizontal ? texel.y : texel.x;
+    float lumaLocalAverage;
+    if (steepest1) {
+        stepLength = -stepLength;
+        lumaLocalAverage = 0.5 * (luma1 + lumaCenter);
+    } else {
+        lumaLocalAverage = 0.5 * (luma2 + lumaCenter);
+    }
+
+    // Walk along the edge, in between the two pixels, until both of its ends are found
+    vec2 edgeUv = uv;
+    if (horizontal) {
+        edgeUv.y += stepLength * 0.5;
+    } else {
+        edgeUv.x += stepLength * 0.5;
+    }
+    vec2 offset = horizontal ? vec2(texel.x, 0.0) : vec2(0.0, texel.y);
+    vec2 uv1 = edgeUv - offset;
+    vec2 uv2 = edgeUv + offset;
+    float lumaEnd1 = 0.0;
+    float lumaEnd2 = 0.0;
+    bool reached1 = false;
+    bool reached2 = false;
+    for (int i = 0; i < 12; i++) {
+        if (!reached1) {
+            lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+            reached1 = abs(lumaEnd1) >= gradientScaled;
+        }
+        if (!reached2) {
+            lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+            reached2 = abs(lumaEnd2) >= gradientScaled;
+        }
+        if (reached1 && reached2) {
+            break;
+        }
+        if (!reached1) {
+            uv1 -= offset * QUALITY[i];
+        }
+        if (!reached2) {
+            uv2 += offset * QUALITY[i];
+        }
+    }
+
+    // Blend more the closer the pixel is to an end of the edge
+    float distance1 = horizontal ? uv.x - uv1.x : uv.y - uv1.y;
+    float distance2 = horizontal ? uv2.x - uv.x : uv2.y - uv.y;
+    bool closest1 = distance1 < distance2;
+    float edgeLength = distance1 + distance2;
+    float pixelOffset = 0.5 - min(distance1, distance2) / edgeLength;
+    bool centerSmaller = lumaCenter < lumaLocalAverage;
+    bool correctVariation = ((closest1 ? lumaEnd1 : lumaEnd2) < 0.0) != centerSmaller;
+    float finalOffset = correctVariation ? pixelOffset : 0.0;
+
+    // Aliasing smaller than a pixel
+    float lumaAverage = (2.0 * (lumaUpDown + lumaLeftRight) + lumaLeftCorners + lumaRightCorners) / 12.0;
+    float subpixelOffset = clamp(abs(lumaAverage - lumaCenter) / lumaRange, 0.0, 1.0);
+    subpixelOffset = (-2.0 * subpixelOffset + 3.0) * subpixelOffset * subpixelOffset;
+    finalOffset = max(finalOffset, subpixelOffset * subpixelOffset * subpixel);
+
+    vec2 finalUv = uv;
+    if (horizontal) {
+        finalUv.y += finalOffset * stepLength;
+    } else {
+        finalUv.x += finalOffset * stepLength;
+    }
+    return texture(source, finalUv).rgb;
+}
+
+// Channels split apart towards the edges, like a cheap lens
+vec3 chromaticAberration(vec2 uv) {
+    vec2 fromCenter = uv - 0.5;
+    vec2 offset = fromCenter * dot(fromCenter, fromCenter) * pushConstants.params.x;
+    return vec3(
+        texture(source, uv - offset).r,
+        texture(source, uv).g,
+        texture(source, uv + offset).b
+    );
+}
+
+vec3 vignette(vec3 color, vec2 uv) {
+    float intensity = pushConstants.params.x;
+    float smoothness = pushConstants.params.y;
+    float roundness = pushConstants.params.z;
+
+    // Round, or following the shape of the screen
+    float aspectRatio = pushConstants.texelSize.y / pushConstants.texelSize.x;
+    vec2 fromCenter = uv - 0.5;
+    fromCenter.x *= mix(1.0, aspectRatio, roundness);
+
+    float factor = pow(clamp(1.0 - dot(fromCenter, fromCenter) * intensity, 0.0, 1.0), smoothness);
+    return color * factor;
+}
+
+float hash(vec2 p) {
+    vec3 p3 = fract(p.xyx * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+vec3 filmGrain(vec3 color, vec2 uv) {
+    float intensity = pushConstants.params.x;
+    float response = pushConstants.params.y;
+
+    vec2 pixel = uv / pushConstants.texelSize;
+    float noise = hash(pixel + pushConstants.seed * vec2(13.7, 7.3)) - 0.5;
+
+    // Less visible in the highlights, like on film
+    float luminance = dot(clamp(color, 0.0, 1.0), vec3(0.2126, 0.7152, 0.0722));
+    float strength = intensity * mix(1.0, 1.0 - luminance, response);
+    return max(color + noise * strength, 0.0);
+}
+
+void main() {
+    vec3 color;
+    if (pushConstants.effect == EFFECT_FXAA) {
+        color = fxaa(fragUv);
+    } else if (pushConstants.effect == EFFECT_CHROMATIC_ABERRATION) {
+        color = chromaticAberration(fragUv);
+    } else if (pushConstants.effect == EFFECT_VIGNETTE) {
+        color = vignette(texture(source, fragUv).rgb, fragUv);
+    } else {
+        color = filmGrain(texture(source, fragUv).rgb, fragUv);
+    }
+
+    outColor = vec4(color, 1.0);
+}
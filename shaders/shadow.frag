@@ -0,0 +1,5 @@
+#version 450
+
+// Depth only, the pipeline still needs a fragment stage
+void main() {
+}
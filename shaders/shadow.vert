@@ -0,0 +1,15 @@
+#version 450
+
+// Per vertex
+layout(location = 0) in vec3 position;
+
+// Per instance
+layout(location = 4) in mat4 model;
+
+layout(push_constant) uniform PushConstants {
+    mat4 lightViewProjection;
+} pushConstants;
+
+void main() {
+    gl_Position = pushConstants.lightViewProjection * model * vec4(position, 1.0);
+}
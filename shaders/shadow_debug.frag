@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec2 fragUv;
+
+layout(set = 0, binding = 0) uniform sampler2D shadowAtlas;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Most of the depth range is bunched up near 1, stretch it to see something
+    float depth = texture(shadowAtlas, fragUv).r;
+    outColor = vec4(vec3(pow(depth, 16.0)), 1.0);
+}
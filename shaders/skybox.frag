@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec4 fragDirection;
+layout(location = 1) flat in float fragIntensity;
+
+layout(set = 0, binding = 0) uniform samplerCube environment;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec3 direction = normalize(fragDirection.xyz / fragDirection.w);
+    outColor = vec4(textureLod(environment, direction, 0.0).rgb * fragIntensity, 1.0);
+}
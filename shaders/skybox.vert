@@ -0,0 +1,21 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+    // Without the camera translation, the environment is infinitely far
+    mat4 inverseViewProjection;
+    float intensity;
+} pushConstants;
+
+layout(location = 0) out vec4 fragDirection;
+layout(location = 1) flat out float fragIntensity;
+
+// One triangle covering the viewport on the far plane, behind everything
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    vec4 position = vec4(uv * 2.0 - 1.0, 1.0, 1.0);
+    gl_Position = position;
+
+    // Divided per fragment, it isn't linear across the screen
+    fragDirection = pushConstants.inverseViewProjection * position;
+    fragIntensity = pushConstants.intensity;
+}
@@ -0,0 +1,14 @@
+#version 450
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragTint;
+
+layout(set = 0, binding = 0) uniform sampler2D spriteTexture;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec4 color = texture(spriteTexture, fragUv) * fragTint;
+    // Premultiplied, blended with one and one minus source alpha
+    outColor = vec4(color.rgb * color.a, color.a);
+}
@@ -0,0 +1,36 @@
+#version 450
+
+// Per vertex, a corner of the unit quad
+layout(location = 0) in vec2 corner;
+
+// Per instance
+// X axis then Y axis of the sprite, scaled by its size
+layout(location = 1) in vec4 axes;
+layout(location = 2) in vec2 translation;
+// Min UV, max UV
+layout(location = 3) in vec4 region;
+layout(location = 4) in vec4 tint;
+
+layout(push_constant) uniform PushConstants {
+    mat4 viewProjection;
+    vec2 viewportSize;
+    uint pixelSnap;
+} pushConstants;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragTint;
+
+void main() {
+    vec2 position = translation + axes.xy * corner.x + axes.zw * corner.y;
+    vec4 clip = pushConstants.viewProjection * vec4(position, 0.0, 1.0);
+
+    // Corners on whole pixels, the texels of unrotated sprites then line up with the screen
+    if (pushConstants.pixelSnap != 0) {
+        vec2 pixel = round((clip.xy * 0.5 + 0.5) * pushConstants.viewportSize);
+        clip.xy = pixel / pushConstants.viewportSize * 2.0 - 1.0;
+    }
+
+    gl_Position = clip;
+    fragUv = mix(region.xy, region.zw, corner);
+    fragTint = tint;
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec2 fragUv;
+layout(location = 1) in vec4 fragColor;
+layout(location = 2) flat in uint fragSdf;
+
+// Coverage, or the distance to the outline with 0.5 on it
+layout(set = 0, binding = 0) uniform sampler2D glyphAtlas;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    float value = texture(glyphAtlas, fragUv).r;
+
+    float alpha = value;
+    if (fragSdf != 0) {
+        // About one pixel of smoothing, whatever the scale
+        float smoothing = max(fwidth(value) * 0.5, 1e-4);
+        alpha = smoothstep(0.5 - smoothing, 0.5 + smoothing, value);
+    }
+
+    // Premultiplied, blended with one and one minus source alpha
+    alpha *= fragColor.a;
+    outColor = vec4(fragColor.rgb * alpha, alpha);
+}
@@ -0,0 +1,31 @@
+#version 450
+
+// Per vertex, a corner of the unit quad
+layout(location = 0) in vec2 corner;
+
+// Per instance
+// Top left corner, then the edges going right and down
+layout(location = 1) in vec3 origin;
+layout(location = 2) in vec3 right;
+layout(location = 3) in vec3 down;
+// Min UV, max UV
+layout(location = 4) in vec4 region;
+layout(location = 5) in vec4 color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 viewProjection;
+    uint sdf;
+} pushConstants;
+
+layout(location = 0) out vec2 fragUv;
+layout(location = 1) out vec4 fragColor;
+layout(location = 2) flat out uint fragSdf;
+
+void main() {
+    vec3 position = origin + right * corner.x + down * corner.y;
+    gl_Position = pushConstants.viewProjection * vec4(position, 1.0);
+
+    fragUv = mix(region.xy, region.zw, corner);
+    fragColor = color;
+    fragSdf = pushConstants.sdf;
+}
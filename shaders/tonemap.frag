@@ -0,0 +1,68 @@
+#version 450
+
+const uint OPERATOR_ACES = 0;
+const uint OPERATOR_REINHARD = 1;
+const uint OPERATOR_UNCHARTED2 = 2;
+
+layout(location = 0) in vec2 fragUv;
+
+layout(set = 0, binding = 0) uniform sampler2D hdrColor;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+
+layout(push_constant) uniform PushConstants {
+    float exposure;
+    uint operator;
+    float bloomIntensity;
+    // Brightest output relative to paper white, 1.0 in SDR
+    float peak;
+} pushConstants;
+
+layout(location = 0) out vec4 outColor;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 color) {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 color) {
+    return color / (1.0 + color);
+}
+
+// John Hable's filmic curve
+vec3 uncharted2Curve(vec3 x) {
+    const float A = 0.15;
+    const float B = 0.50;
+    const float C = 0.10;
+    const float D = 0.20;
+    const float E = 0.02;
+    const float F = 0.30;
+    return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+vec3 uncharted2(vec3 color) {
+    const float whitePoint = 11.2;
+    const float exposureBias = 2.0;
+    return uncharted2Curve(color * exposureBias) / uncharted2Curve(vec3(whitePoint));
+}
+
+vec3 tonemap(vec3 color) {
+    if (pushConstants.operator == OPERATOR_REINHARD) {
+        return reinhard(color);
+    } else if (pushConstants.operator == OPERATOR_UNCHARTED2) {
+        return uncharted2(color);
+    } else {
+        return aces(color);
+    }
+}
+
+void main() {
+    vec3 color = texture(hdrColor, fragUv).rgb;
+    color += texture(bloom, fragUv).rgb * pushConstants.bloomIntensity;
+    color *= pushConstants.exposure;
+
+    // In HDR, 1.0 is paper white and the curve rolls off to the peak instead
+    float peak = pushConstants.peak;
+    color = tonemap(color / peak) * peak;
+
+    outColor = vec4(color, 1.0);
+}
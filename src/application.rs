@@ -1,27 +1,281 @@
-use crate::renderer::VulkanApplication;
-use log::info;
-use winit::event::{Event, WindowEvent};
+#[cfg(feature = "audio-device")]
+use crate::audio::DeviceBackend;
+use crate::audio::{
+    Attenuation, Audio, NullBackend, Sound, SpatialSettings, VoiceSettings, DEFAULT_SAMPLE_RATE,
+};
+use crate::math::{Quat, Vec3, Vec4};
+use crate::renderer::display_output::DisplayOutput;
+use crate::renderer::material::PbrMaterial;
+use crate::renderer::{primitives, VulkanApplication};
+use crate::scene::{
+    Animator, AnimatorController, AudioEmitter, Camera, Curve, Easing, EnvironmentLight, Light,
+    LightShadow, MeshRenderer, ParticleBlend, ParticleEffect, ParticleEmitter, ParticleForces,
+    Repeat, Scene, Transform, Tween,
+};
+use log::{info, warn};
+use std::sync::Arc;
+use std::time::Instant;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 
+const ENVIRONMENT_PATH: &str = "assets/environment.hdr";
+const MODEL_PATH: &str = "assets/character.glb";
+const ANIMATOR_PATH: &str = "assets/character.animator";
+const AMBIENCE_PATH: &str = "assets/ambience.ogg";
+const SPARKS_SOUND_PATH: &str = "assets/sparks.ogg";
+
 pub struct Application {
     vulkan_app: VulkanApplication,
     event_loop: EventLoop<()>,
+    scene: Scene,
+    audio: Audio,
+}
+
+impl Default for Application {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Application {
     pub fn new() -> Self {
-        let (vulkan_app, event_loop) = VulkanApplication::new_with_event_loop();
+        let (mut vulkan_app, event_loop) = VulkanApplication::new_with_event_loop();
+        let scene = Self::create_test_scene(&mut vulkan_app);
+        let audio = Self::create_audio();
+
+        // Optional, looped quietly in the background
+        match Sound::load(AMBIENCE_PATH) {
+            Ok(ambience) => {
+                audio.play(
+                    &ambience,
+                    VoiceSettings::default().with_volume(0.5).with_looping(true),
+                );
+            }
+            Err(err) => warn!("No ambience loaded from {}: {}", AMBIENCE_PATH, err),
+        }
 
         Self {
             vulkan_app,
             event_loop,
+            scene,
+            audio,
+        }
+    }
+
+    /// On the default device when the audio-device feature is enabled, the mix is discarded
+    /// otherwise.
+    fn create_audio() -> Audio {
+        #[cfg(feature = "audio-device")]
+        match DeviceBackend::new().and_then(|backend| Audio::new(Box::new(backend))) {
+            Ok(audio) => return audio,
+            Err(err) => warn!("No audio output, sound is muted: {}", err),
+        }
+
+        Audio::new(Box::new(NullBackend::new(DEFAULT_SAMPLE_RATE)))
+            .expect("Failed to start the null audio backend !")
+    }
+
+    /// A row of spheres going from rough to smooth and dielectric to metal, lit by
+    /// one light of each kind.
+    fn create_test_scene(vulkan_app: &mut VulkanApplication) -> Scene {
+        let mut scene = Scene::new();
+
+        let (vertices, indices) = primitives::plane(20.0);
+        let plane = vulkan_app.create_mesh(&vertices, Some(&indices));
+        let (vertices, indices) = primitives::cube(1.0);
+        let cube = vulkan_app.create_mesh(&vertices, Some(&indices));
+        let (vertices, indices) = primitives::uv_sphere(0.5, 32, 16);
+        let sphere = vulkan_app.create_mesh(&vertices, Some(&indices));
+
+        let mut camera_transform = Transform::from_translation(Vec3::new(0.0, 3.0, 7.0));
+        camera_transform.look_at(Vec3::ZERO, Vec3::Y);
+        let camera = scene.graph_mut().add_node("Camera", camera_transform);
+        scene.set_camera(camera, Camera::default());
+        scene.set_active_camera(Some(camera));
+
+        let ground_material = vulkan_app.create_material(PbrMaterial {
+            base_color: Vec4::new(0.5, 0.5, 0.5, 1.0),
+            roughness: 0.9,
+            ..PbrMaterial::default()
+        });
+        let ground = scene.graph_mut().add_node(
+            "Ground",
+            Transform::from_translation(Vec3::new(0.0, -0.5, 0.0)),
+        );
+        scene.set_renderable(
+            ground,
+            MeshRenderer::new(plane).with_material(ground_material),
+        );
+
+        let cube_material = vulkan_app.create_material(PbrMaterial {
+            base_color: Vec4::new(0.8, 0.2, 0.1, 1.0),
+            roughness: 0.4,
+            ..PbrMaterial::default()
+        });
+        let cube_node = scene.graph_mut().add_node(
+            "Cube",
+            Transform::from_translation(Vec3::new(0.0, 0.0, -2.0))
+                .with_rotation(Quat::from_axis_angle(Vec3::Y, 0.6)),
+        );
+        scene.set_renderable(
+            cube_node,
+            MeshRenderer::new(cube).with_material(cube_material),
+        );
+        scene.tweens_mut().play(
+            Tween::translation(cube_node, Vec3::new(0.0, 0.5, -2.0), 1.5)
+                .with_easing(Easing::SineInOut)
+                .with_repeat(Repeat::Forever)
+                .with_yoyo(true),
+        );
+
+        for i in 0..5 {
+            let t = i as f32 / 4.0;
+            let material = vulkan_app.create_material(PbrMaterial {
+                base_color: Vec4::new(1.0, 0.8, 0.4, 1.0),
+                metallic: t,
+                roughness: 1.0 - t * 0.9,
+                ..PbrMaterial::default()
+            });
+            let node = scene.graph_mut().add_node(
+                &format!("Sphere {}", i),
+                Transform::from_translation(Vec3::new(i as f32 * 1.2 - 2.4, 0.0, 0.0)),
+            );
+            scene.set_renderable(
+                node,
+                MeshRenderer::new(sphere.clone()).with_material(material),
+            );
+        }
+
+        let sun = scene.graph_mut().add_node(
+            "Sun",
+            Transform::from_rotation(Quat::from_euler(0.5, -0.9, 0.0)),
+        );
+        scene.set_light(
+            sun,
+            Light::directional(Vec3::new(1.0, 0.95, 0.85), 2.0).with_shadow(LightShadow::default()),
+        );
+
+        let point = scene.graph_mut().add_node(
+            "Point light",
+            Transform::from_translation(Vec3::new(-2.0, 1.5, 1.5)),
+        );
+        scene.set_light(point, Light::point(Vec3::new(0.3, 0.5, 1.0), 8.0, 6.0));
+
+        let mut spot_transform = Transform::from_translation(Vec3::new(2.5, 3.0, -1.0));
+        spot_transform.look_at(Vec3::new(0.0, 0.0, -2.0), Vec3::Y);
+        let spot = scene.graph_mut().add_node("Spot light", spot_transform);
+        scene.set_light(
+            spot,
+            Light::spot(
+                Vec3::new(1.0, 0.4, 0.2),
+                20.0,
+                10.0,
+                15f32.to_radians(),
+                30f32.to_radians(),
+            )
+            .with_shadow(LightShadow::default()),
+        );
+
+        let sparks = Arc::new(ParticleEffect {
+            spawn_rate: 200.0,
+            velocity: Vec3::new(0.0, 3.0, 0.0),
+            spread: 0.4,
+            forces: ParticleForces {
+                gravity: Vec3::new(0.0, -4.0, 0.0),
+                turbulence: 1.0,
+                ..ParticleForces::default()
+            },
+            color_over_life: Curve::new(&[
+                (0.0, Vec4::new(4.0, 2.0, 0.6, 1.0)),
+                (0.6, Vec4::new(2.0, 0.5, 0.1, 1.0)),
+                (1.0, Vec4::ZERO),
+            ]),
+            size_over_life: Curve::linear(0.06, 0.02),
+            blend: ParticleBlend::Additive,
+            ..ParticleEffect::default()
+        });
+        let fountain = scene.graph_mut().add_node(
+            "Sparks",
+            Transform::from_translation(Vec3::new(0.0, 0.5, -2.0)),
+        );
+        scene.set_particle_emitter(fountain, ParticleEmitter::new(sparks));
+        // Optional, crackles from the fountain as the camera moves around
+        match Sound::load(SPARKS_SOUND_PATH) {
+            Ok(sound) => scene.set_audio_emitter(
+                fountain,
+                AudioEmitter::new(sound)
+                    .with_settings(VoiceSettings::default().with_looping(true))
+                    .with_spatial(
+                        SpatialSettings::default()
+                            .with_attenuation(Attenuation::Linear)
+                            .with_distances(1.0, 15.0),
+                    ),
+            ),
+            Err(err) => warn!("No sound loaded from {}: {}", SPARKS_SOUND_PATH, err),
+        }
+
+        // Optional, the hemisphere ambient is used without it
+        match vulkan_app.load_environment(ENVIRONMENT_PATH) {
+            Ok(environment) => scene.set_environment(Some(EnvironmentLight::new(environment))),
+            Err(err) => warn!("No environment loaded from {}: {}", ENVIRONMENT_PATH, err),
+        }
+
+        // Optional too, its skinned meshes are driven by the animator controller if there is
+        // one, or play the first animation of their skin
+        match vulkan_app.load_gltf(MODEL_PATH) {
+            Ok(model) => {
+                let controller = match AnimatorController::load(ANIMATOR_PATH) {
+                    Ok(controller) => Some(Arc::new(controller)),
+                    Err(err) => {
+                        warn!("No animator loaded from {}: {}", ANIMATOR_PATH, err);
+                        None
+                    }
+                };
+
+                let character = model.add_to_scene(
+                    &mut scene,
+                    "Character",
+                    Transform::from_translation(Vec3::new(2.5, -0.5, -1.0)),
+                );
+                let nodes = scene.graph().children(character).to_vec();
+                for node in nodes {
+                    if let Some(skinned_mesh) = scene.skinned_mesh_mut(node) {
+                        let skin = match model
+                            .skins
+                            .iter()
+                            .find(|skin| Arc::ptr_eq(&skin.skin, &skinned_mesh.skin))
+                        {
+                            Some(skin) => skin,
+                            None => continue,
+                        };
+                        let animator = controller.as_ref().map(|controller| {
+                            Animator::new(controller.clone(), &skin.skin, &skin.clips)
+                        });
+                        match animator {
+                            Some(Ok(animator)) => skinned_mesh.animator = Some(animator),
+                            Some(Err(err)) => warn!("Failed to create the animator: {}", err),
+                            None => {
+                                if let Some(clip) = skin.clips.first() {
+                                    skinned_mesh.player.play(clip.clone());
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Err(err) => warn!("No model loaded from {}: {}", MODEL_PATH, err),
         }
+
+        scene
     }
 
     /// Run until the application closes.
     /// The application is thus consumed.
     pub fn main_loop(self) {
         let mut vulkan_app = self.vulkan_app;
+        let mut scene = self.scene;
+        let mut audio = self.audio;
+        let mut last_update = Instant::now();
 
         self.event_loop.run(move |event, _, control_flow| {
             // Continuously run the loop without waiting for an event
@@ -35,15 +289,78 @@ impl Application {
                     info!("Close requested, stopping");
                     *control_flow = ControlFlow::Exit;
                 }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::F2),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    // Print the GPU timings of the last frame with results
+                    vulkan_app.gpu_profiler().log_last_frame();
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::F3),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    // Toggle the shadow atlas debug view
+                    let enabled = !vulkan_app.shadow_debug_view();
+                    vulkan_app.set_shadow_debug_view(enabled);
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(VirtualKeyCode::F4),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    // Cycle through the display outputs, unsupported ones fall back to SDR
+                    let output = match vulkan_app.preferred_display_output() {
+                        DisplayOutput::Sdr => DisplayOutput::Hdr10,
+                        DisplayOutput::Hdr10 => DisplayOutput::ScRgb,
+                        DisplayOutput::ScRgb => DisplayOutput::Sdr,
+                    };
+                    info!("Switching display output to {:?}", output);
+                    vulkan_app.set_display_output(output);
+                }
                 Event::MainEventsCleared => {
-                    // TODO: Update scene and stuff
+                    let now = Instant::now();
+                    let time_step = now.duration_since(last_update).as_secs_f32();
+                    scene.update(time_step);
+                    audio.update_scene(&mut scene, time_step);
+                    audio.update(time_step);
+                    last_update = now;
 
                     // And request a draw
                     vulkan_app.window().request_redraw();
                 }
                 Event::RedrawRequested(_) => {
                     // Redraw
-                    vulkan_app.draw_frame();
+                    vulkan_app.draw_frame(&mut scene);
+                }
+                Event::LoopDestroyed => {
+                    vulkan_app.shutdown();
                 }
                 _ => (),
             }
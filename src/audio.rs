@@ -0,0 +1,201 @@
+pub mod backend;
+pub mod mixer;
+pub mod sound;
+pub mod spatial;
+mod wav;
+
+#[cfg(feature = "audio-device")]
+pub use backend::DeviceBackend;
+pub use backend::{AudioBackend, AudioError, NullBackend, WavFileBackend};
+pub use mixer::{BusId, Mixer, VoiceId, VoiceSettings};
+pub use sound::{Sound, SoundError};
+pub use spatial::{Attenuation, Listener, SpatialSettings, SpatialSource, Spatialization};
+
+use crate::math::Vec3;
+use crate::scene::{NodeId, Scene, Transform};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Rate of the mix for the backends that don't impose one.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// Occlusion of an emitter from 0 to 1, given the listener and emitter positions. Lets the
+/// game raycast against its level for example.
+pub type OcclusionQuery = Box<dyn FnMut(&Scene, Vec3, Vec3) -> f32>;
+
+/// The voice of an audio emitter of the scene.
+struct EmitterVoice {
+    voice: VoiceId,
+    /// To get the velocity on the next update
+    position: Vec3,
+}
+
+/// What an audio emitter of the scene asks of its voice this update.
+struct EmitterUpdate {
+    node: NodeId,
+    playing: bool,
+    sound: Sound,
+    settings: VoiceSettings,
+    spatial: SpatialSource,
+}
+
+/// The sound output of the engine: a mixer and the backend playing it.
+pub struct Audio {
+    mixer: Arc<Mutex<Mixer>>,
+    backend: Box<dyn AudioBackend>,
+    emitter_voices: HashMap<NodeId, EmitterVoice>,
+    listener_position: Option<Vec3>,
+    occlusion_query: Option<OcclusionQuery>,
+}
+
+impl Audio {
+    pub fn new(mut backend: Box<dyn AudioBackend>) -> Result<Self, AudioError> {
+        let mixer = Arc::new(Mutex::new(Mixer::new(backend.sample_rate())));
+        backend.start(mixer.clone())?;
+        Ok(Self {
+            mixer,
+            backend,
+            emitter_voices: HashMap::new(),
+            listener_position: None,
+            occlusion_query: None,
+        })
+    }
+
+    /// Lock the mixer to play voices or change buses. A device waits for it to mix, so it
+    /// shouldn't be held for long.
+    pub fn mixer(&self) -> MutexGuard<'_, Mixer> {
+        self.mixer.lock().expect("Audio mixer poisoned !")
+    }
+
+    pub fn play(&self, sound: &Sound, settings: VoiceSettings) -> VoiceId {
+        self.mixer().play(sound, settings)
+    }
+
+    /// False if the voice already ended.
+    pub fn stop(&self, voice: VoiceId) -> bool {
+        self.mixer().stop(voice)
+    }
+
+    /// Combined with the occlusion of each emitter, the highest one wins.
+    pub fn set_occlusion_query(&mut self, query: Option<OcclusionQuery>) {
+        self.occlusion_query = query;
+    }
+
+    /// Move the listener to the active camera and the voices of the audio emitters to their
+    /// nodes, velocities come from the movement since the last update.
+    ///
+    /// Voices start and stop with the playing flag of their emitter, and stop when the emitter
+    /// is removed. The occlusion query runs before the mixer is locked, so it can play sounds.
+    pub fn update_scene(&mut self, scene: &mut Scene, time_step: f32) {
+        scene.graph_mut().update();
+
+        let listener = scene.active_camera().map(|camera| {
+            let transform = Transform::from_matrix(&scene.graph_mut().world_matrix(camera));
+            Listener {
+                position: transform.translation,
+                velocity: velocity(self.listener_position, transform.translation, time_step),
+                rotation: transform.rotation,
+            }
+        });
+        let listener_position = match &listener {
+            Some(listener) => {
+                self.listener_position = Some(listener.position);
+                listener.position
+            }
+            None => self.mixer().listener().position,
+        };
+
+        let nodes: Vec<_> = scene.audio_emitters().map(|(node, _)| node).collect();
+        let mut updates = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let position = scene.graph_mut().world_position(node);
+            let query_occlusion = match &mut self.occlusion_query {
+                Some(query) => query(scene, listener_position, position),
+                None => 0.0,
+            };
+            let emitter = scene
+                .audio_emitter(node)
+                .expect("The audio emitter disappeared !");
+            let velocity = match self.emitter_voices.get(&node) {
+                Some(emitter_voice) => velocity(Some(emitter_voice.position), position, time_step),
+                None => Vec3::ZERO,
+            };
+
+            updates.push(EmitterUpdate {
+                node,
+                playing: emitter.playing,
+                sound: emitter.sound.clone(),
+                settings: emitter.settings,
+                spatial: SpatialSource {
+                    position,
+                    velocity,
+                    settings: emitter.spatial,
+                    occlusion: emitter.occlusion.max(query_occlusion),
+                    low_pass: emitter.low_pass,
+                },
+            });
+        }
+
+        let mut ended = Vec::new();
+        {
+            let mut mixer = self.mixer.lock().expect("Audio mixer poisoned !");
+            if let Some(listener) = listener {
+                mixer.set_listener(listener);
+            }
+
+            self.emitter_voices.retain(|node, emitter_voice| {
+                let kept = scene.audio_emitter(*node).is_some();
+                if !kept {
+                    mixer.stop(emitter_voice.voice);
+                }
+                kept
+            });
+
+            for update in updates {
+                let voice = match self.emitter_voices.get(&update.node) {
+                    Some(emitter_voice) if !update.playing => {
+                        mixer.stop(emitter_voice.voice);
+                        self.emitter_voices.remove(&update.node);
+                        continue;
+                    }
+                    Some(emitter_voice) if !mixer.is_playing(emitter_voice.voice) => {
+                        // Reached its end
+                        ended.push(update.node);
+                        self.emitter_voices.remove(&update.node);
+                        continue;
+                    }
+                    Some(emitter_voice) => emitter_voice.voice,
+                    None if !update.playing => continue,
+                    None => mixer.play(&update.sound, update.settings),
+                };
+
+                if let Some(settings) = mixer.settings_mut(voice) {
+                    *settings = update.settings;
+                }
+                let position = update.spatial.position;
+                mixer.set_spatial(voice, Some(update.spatial));
+                self.emitter_voices
+                    .insert(update.node, EmitterVoice { voice, position });
+            }
+        }
+
+        for node in ended {
+            if let Some(emitter) = scene.audio_emitter_mut(node) {
+                emitter.playing = false;
+            }
+        }
+    }
+
+    /// Call every frame, the backends without their own clock mix here.
+    pub fn update(&mut self, time_step: f32) {
+        self.backend.update(time_step);
+    }
+}
+
+/// Units per second between two updates, none on the first one.
+fn velocity(previous: Option<Vec3>, position: Vec3, time_step: f32) -> Vec3 {
+    match previous {
+        Some(previous) if time_step > 0.0 => (position - previous) / time_step,
+        _ => Vec3::ZERO,
+    }
+}
@@ -0,0 +1,187 @@
+#[cfg(feature = "audio-device")]
+mod device;
+
+#[cfg(feature = "audio-device")]
+pub use device::DeviceBackend;
+
+use crate::audio::mixer::Mixer;
+use crate::audio::wav::WavWriter;
+use log::error;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Why an audio backend can't start.
+#[derive(Debug)]
+pub enum AudioError {
+    Io(io::Error),
+    /// There is no default output device
+    NoDevice,
+    /// The device refused the stream
+    Device(String),
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::Io(err) => write!(f, "Failed to write audio: {}", err),
+            AudioError::NoDevice => write!(f, "No audio output device"),
+            AudioError::Device(message) => write!(f, "Audio device error: {}", message),
+        }
+    }
+}
+
+impl Error for AudioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AudioError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AudioError {
+    fn from(err: io::Error) -> Self {
+        AudioError::Io(err)
+    }
+}
+
+/// Where the mix goes. The backend pulls samples from the mixer, either from its own thread
+/// or on the game clock in [AudioBackend::update].
+pub trait AudioBackend {
+    /// The mixer is created at this rate.
+    fn sample_rate(&self) -> u32;
+
+    /// Called once before any update.
+    fn start(&mut self, mixer: Arc<Mutex<Mixer>>) -> Result<(), AudioError>;
+
+    /// Called every frame with the elapsed time in seconds.
+    fn update(&mut self, _time_step: f32) {}
+}
+
+/// Mixes on the game clock for the backends without a device asking for samples.
+struct ClockedRenderer {
+    sample_rate: u32,
+    mixer: Option<Arc<Mutex<Mixer>>>,
+    buffer: Vec<f32>,
+    /// The fraction of a frame left from the previous update
+    pending_frames: f64,
+}
+
+impl ClockedRenderer {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            mixer: None,
+            buffer: Vec::new(),
+            pending_frames: 0.0,
+        }
+    }
+
+    /// The stereo samples of the elapsed time.
+    fn render(&mut self, time_step: f32) -> &[f32] {
+        self.pending_frames += time_step.max(0.0) as f64 * self.sample_rate as f64;
+        let frames = self.pending_frames as usize;
+        self.pending_frames -= frames as f64;
+
+        self.buffer.resize(frames * 2, 0.0);
+        if let Some(mixer) = &self.mixer {
+            mixer
+                .lock()
+                .expect("Audio mixer poisoned !")
+                .mix(&mut self.buffer);
+        }
+        &self.buffer
+    }
+}
+
+/// Mixes and throws the samples away, voices still play and end on time.
+pub struct NullBackend {
+    renderer: ClockedRenderer,
+}
+
+impl NullBackend {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            renderer: ClockedRenderer::new(sample_rate),
+        }
+    }
+}
+
+impl AudioBackend for NullBackend {
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.renderer.sample_rate
+    }
+
+    fn start(&mut self, mixer: Arc<Mutex<Mixer>>) -> Result<(), AudioError> {
+        self.renderer.mixer = Some(mixer);
+        Ok(())
+    }
+
+    fn update(&mut self, time_step: f32) {
+        self.renderer.render(time_step);
+    }
+}
+
+/// Records the mix to a 16 bit stereo WAV file, for machines without a sound card.
+///
+/// The file is complete once the backend is finished or dropped.
+pub struct WavFileBackend {
+    renderer: ClockedRenderer,
+    writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavFileBackend {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self, AudioError> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            renderer: ClockedRenderer::new(sample_rate),
+            writer: Some(WavWriter::new(file, 2, sample_rate)?),
+        })
+    }
+
+    /// Complete the header of the file, otherwise done when dropped.
+    pub fn finish(mut self) -> Result<(), AudioError> {
+        match self.writer.take() {
+            Some(writer) => writer.finish().map(|_| ()).map_err(AudioError::from),
+            None => Ok(()),
+        }
+    }
+}
+
+impl AudioBackend for WavFileBackend {
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.renderer.sample_rate
+    }
+
+    fn start(&mut self, mixer: Arc<Mutex<Mixer>>) -> Result<(), AudioError> {
+        self.renderer.mixer = Some(mixer);
+        Ok(())
+    }
+
+    fn update(&mut self, time_step: f32) {
+        let samples = self.renderer.render(time_step);
+        if let Some(writer) = &mut self.writer {
+            if let Err(err) = writer.write(samples) {
+                // Stop recording rather than failing every frame
+                error!("Failed to write the audio mix, recording stopped: {}", err);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl Drop for WavFileBackend {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            if let Err(err) = writer.finish() {
+                error!("Failed to finish the audio recording: {}", err);
+            }
+        }
+    }
+}
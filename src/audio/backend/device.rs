@@ -0,0 +1,82 @@
+use crate::audio::backend::{AudioBackend, AudioError};
+use crate::audio::mixer::Mixer;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, Sample, SampleFormat, Stream, StreamConfig};
+use log::error;
+use std::sync::{Arc, Mutex};
+
+/// Plays the mix on the default output device, which pulls the samples from its own thread.
+pub struct DeviceBackend {
+    device: Device,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    /// Plays as long as it is alive
+    stream: Option<Stream>,
+}
+
+impl DeviceBackend {
+    /// Use the default device of the default host with its preferred configuration.
+    pub fn new() -> Result<Self, AudioError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(AudioError::NoDevice)?;
+        let config = device
+            .default_output_config()
+            .map_err(|err| AudioError::Device(err.to_string()))?;
+
+        Ok(Self {
+            device,
+            sample_format: config.sample_format(),
+            config: config.config(),
+            stream: None,
+        })
+    }
+
+    fn build_stream<T: Sample>(&self, mixer: Arc<Mutex<Mixer>>) -> Result<Stream, AudioError> {
+        let channels = self.config.channels as usize;
+        let mut buffer = Vec::new();
+        self.device
+            .build_output_stream(
+                &self.config,
+                move |data: &mut [T], _| {
+                    buffer.resize(data.len() / channels * 2, 0.0);
+                    match mixer.lock() {
+                        Ok(mut mixer) => mixer.mix(&mut buffer),
+                        Err(_) => buffer.iter_mut().for_each(|sample| *sample = 0.0),
+                    }
+                    for (frame, stereo) in data.chunks_mut(channels).zip(buffer.chunks(2)) {
+                        if channels == 1 {
+                            frame[0] = T::from(&((stereo[0] + stereo[1]) * 0.5));
+                            continue;
+                        }
+                        // Extra channels stay silent
+                        for (i, sample) in frame.iter_mut().enumerate() {
+                            *sample = T::from(&stereo.get(i).copied().unwrap_or(0.0));
+                        }
+                    }
+                },
+                |err| error!("Audio stream error: {}", err),
+            )
+            .map_err(|err| AudioError::Device(err.to_string()))
+    }
+}
+
+impl AudioBackend for DeviceBackend {
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
+    fn start(&mut self, mixer: Arc<Mutex<Mixer>>) -> Result<(), AudioError> {
+        let stream = match self.sample_format {
+            SampleFormat::F32 => self.build_stream::<f32>(mixer)?,
+            SampleFormat::I16 => self.build_stream::<i16>(mixer)?,
+            SampleFormat::U16 => self.build_stream::<u16>(mixer)?,
+        };
+        stream
+            .play()
+            .map_err(|err| AudioError::Device(err.to_string()))?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
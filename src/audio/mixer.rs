@@ -0,0 +1,474 @@
+use crate::audio::sound::Sound;
+use crate::audio::spatial::{Listener, SpatialSource, DEFAULT_SPEED_OF_SOUND};
+use std::f32::consts::{FRAC_PI_4, PI};
+
+/// A group of voices sharing a volume, every bus goes through the master one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BusId(usize);
+
+impl BusId {
+    pub const MASTER: BusId = BusId(0);
+}
+
+/// A sound being played by a mixer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VoiceId(u64);
+
+/// How a voice plays, can be changed while it plays.
+#[derive(Clone, Copy, Debug)]
+pub struct VoiceSettings {
+    /// Linear gain
+    pub volume: f32,
+    /// Playback speed, 2 is an octave up
+    pub pitch: f32,
+    /// From -1 on the left to 1 on the right
+    pub pan: f32,
+    pub looping: bool,
+    pub bus: BusId,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            pitch: 1.0,
+            pan: 0.0,
+            looping: false,
+            bus: BusId::MASTER,
+        }
+    }
+}
+
+impl VoiceSettings {
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn with_pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    pub fn with_pan(mut self, pan: f32) -> Self {
+        self.pan = pan;
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_bus(mut self, bus: BusId) -> Self {
+        self.bus = bus;
+        self
+    }
+}
+
+struct Voice {
+    id: VoiceId,
+    sound: Sound,
+    settings: VoiceSettings,
+    /// In frames of the sound, between two of them while resampling
+    position: f64,
+    paused: bool,
+    /// Positional voices are attenuated, panned and pitched from the listener
+    spatial: Option<SpatialSource>,
+    /// Last left and right output of the low-pass filter
+    filtered: (f32, f32),
+}
+
+/// What a voice is mixed with for one buffer.
+struct VoiceMix {
+    gain: f32,
+    pan: f32,
+    pitch: f32,
+    /// Coefficient of the one pole low-pass filter, 1 lets everything through
+    low_pass: f32,
+}
+
+struct Bus {
+    name: String,
+    volume: f32,
+    muted: bool,
+}
+
+/// Mixes any number of voices into interleaved stereo samples at its sample rate.
+///
+/// Voices are resampled linearly from the rate of their sound, and removed once they reach
+/// their end unless they loop. Positional voices are spatialized from the listener every
+/// time samples are mixed.
+pub struct Mixer {
+    sample_rate: u32,
+    voices: Vec<Voice>,
+    buses: Vec<Bus>,
+    next_voice: u64,
+    listener: Listener,
+    speed_of_sound: f32,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            voices: Vec::new(),
+            buses: vec![Bus {
+                name: "Master".to_owned(),
+                volume: 1.0,
+                muted: false,
+            }],
+            next_voice: 0,
+            listener: Listener::default(),
+            speed_of_sound: DEFAULT_SPEED_OF_SOUND,
+        }
+    }
+
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn play(&mut self, sound: &Sound, settings: VoiceSettings) -> VoiceId {
+        let id = VoiceId(self.next_voice);
+        self.next_voice += 1;
+        self.voices.push(Voice {
+            id,
+            sound: sound.clone(),
+            settings,
+            position: 0.0,
+            paused: false,
+            spatial: None,
+            filtered: (0.0, 0.0),
+        });
+        id
+    }
+
+    /// False if the voice already ended.
+    pub fn stop(&mut self, voice: VoiceId) -> bool {
+        let count = self.voices.len();
+        self.voices.retain(|other| other.id != voice);
+        self.voices.len() != count
+    }
+
+    pub fn stop_all(&mut self) {
+        self.voices.clear();
+    }
+
+    /// Still playing or paused.
+    pub fn is_playing(&self, voice: VoiceId) -> bool {
+        self.voice(voice).is_some()
+    }
+
+    #[inline]
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn settings(&self, voice: VoiceId) -> Option<&VoiceSettings> {
+        self.voice(voice).map(|voice| &voice.settings)
+    }
+
+    pub fn settings_mut(&mut self, voice: VoiceId) -> Option<&mut VoiceSettings> {
+        self.voice_mut(voice).map(|voice| &mut voice.settings)
+    }
+
+    /// A paused voice keeps its position. False if the voice already ended.
+    pub fn set_paused(&mut self, voice: VoiceId, paused: bool) -> bool {
+        match self.voice_mut(voice) {
+            Some(voice) => {
+                voice.paused = paused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_paused(&self, voice: VoiceId) -> bool {
+        self.voice(voice).is_some_and(|voice| voice.paused)
+    }
+
+    pub fn spatial(&self, voice: VoiceId) -> Option<&SpatialSource> {
+        self.voice(voice)?.spatial.as_ref()
+    }
+
+    /// Make a voice positional, its pan is then added to the one from its position.
+    /// False if the voice already ended.
+    pub fn set_spatial(&mut self, voice: VoiceId, spatial: Option<SpatialSource>) -> bool {
+        match self.voice_mut(voice) {
+            Some(voice) => {
+                voice.spatial = spatial;
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[inline]
+    pub fn listener(&self) -> &Listener {
+        &self.listener
+    }
+
+    #[inline]
+    pub fn set_listener(&mut self, listener: Listener) {
+        self.listener = listener;
+    }
+
+    #[inline]
+    pub fn speed_of_sound(&self) -> f32 {
+        self.speed_of_sound
+    }
+
+    /// In units per second, for the doppler shift.
+    #[inline]
+    pub fn set_speed_of_sound(&mut self, speed_of_sound: f32) {
+        self.speed_of_sound = speed_of_sound;
+    }
+
+    pub fn add_bus(&mut self, name: &str) -> BusId {
+        self.buses.push(Bus {
+            name: name.to_owned(),
+            volume: 1.0,
+            muted: false,
+        });
+        BusId(self.buses.len() - 1)
+    }
+
+    pub fn find_bus(&self, name: &str) -> Option<BusId> {
+        self.buses
+            .iter()
+            .position(|bus| bus.name == name)
+            .map(BusId)
+    }
+
+    #[inline]
+    pub fn bus_volume(&self, bus: BusId) -> f32 {
+        self.buses[bus.0].volume
+    }
+
+    #[inline]
+    pub fn set_bus_volume(&mut self, bus: BusId, volume: f32) {
+        self.buses[bus.0].volume = volume;
+    }
+
+    #[inline]
+    pub fn is_bus_muted(&self, bus: BusId) -> bool {
+        self.buses[bus.0].muted
+    }
+
+    /// The voices of a muted bus keep playing silently.
+    #[inline]
+    pub fn set_bus_muted(&mut self, bus: BusId, muted: bool) {
+        self.buses[bus.0].muted = muted;
+    }
+
+    /// Fill interleaved stereo samples with the next frames of the mix, clipped to [-1, 1].
+    pub fn mix(&mut self, output: &mut [f32]) {
+        output.iter_mut().for_each(|sample| *sample = 0.0);
+
+        let sample_rate = self.sample_rate as f32;
+        let buses = &self.buses;
+        let listener = &self.listener;
+        let speed_of_sound = self.speed_of_sound;
+        self.voices.retain_mut(|voice| {
+            let mut mix = VoiceMix {
+                gain: voice.settings.volume * bus_gain(buses, voice.settings.bus),
+                pan: voice.settings.pan,
+                pitch: voice.settings.pitch,
+                low_pass: 1.0,
+            };
+            if let Some(spatial) = &voice.spatial {
+                let spatialization = spatial.spatialize(listener, speed_of_sound);
+                mix.gain *= spatialization.gain;
+                mix.pan += spatialization.pan;
+                mix.pitch *= spatialization.pitch;
+                if let Some(cutoff) = spatialization.low_pass {
+                    mix.low_pass = 1.0 - (-2.0 * PI * cutoff.max(0.0) / sample_rate).exp();
+                }
+            }
+            mix_voice(voice, &mix, sample_rate as f64, output)
+        });
+
+        output
+            .iter_mut()
+            .for_each(|sample| *sample = sample.clamp(-1.0, 1.0));
+    }
+
+    fn voice(&self, voice: VoiceId) -> Option<&Voice> {
+        self.voices.iter().find(|other| other.id == voice)
+    }
+
+    fn voice_mut(&mut self, voice: VoiceId) -> Option<&mut Voice> {
+        self.voices.iter_mut().find(|other| other.id == voice)
+    }
+}
+
+/// Volume of the bus times the master one, or 0 if either is muted.
+fn bus_gain(buses: &[Bus], bus: BusId) -> f32 {
+    let gain = |bus: &Bus| if bus.muted { 0.0 } else { bus.volume };
+    let master = gain(&buses[BusId::MASTER.0]);
+    if bus == BusId::MASTER {
+        master
+    } else {
+        buses.get(bus.0).map_or(0.0, gain) * master
+    }
+}
+
+/// Equal power panning for mono sounds, balance for stereo ones that are already spread.
+fn pan_gains(channels: u16, pan: f32) -> (f32, f32) {
+    let pan = pan.clamp(-1.0, 1.0);
+    if channels == 1 {
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        (angle.cos(), angle.sin())
+    } else {
+        ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+    }
+}
+
+/// Add the voice to the output, false once it reached its end.
+fn mix_voice(voice: &mut Voice, mix: &VoiceMix, sample_rate: f64, output: &mut [f32]) -> bool {
+    if voice.paused {
+        return true;
+    }
+
+    let sound = &voice.sound;
+    let frame_count = sound.frame_count();
+    let looping = voice.settings.looping;
+    if frame_count == 0 {
+        return false;
+    }
+    let step = mix.pitch.max(0.0) as f64 * sound.sample_rate() as f64 / sample_rate;
+
+    // Silent voices only move forward
+    if mix.gain == 0.0 {
+        voice.position += step * (output.len() / 2) as f64;
+        return wrap_position(&mut voice.position, frame_count, looping);
+    }
+
+    let (left_gain, right_gain) = pan_gains(sound.channels(), mix.pan);
+    for frame in output.chunks_exact_mut(2) {
+        let index = voice.position as usize;
+        let t = (voice.position - index as f64) as f32;
+        let (left, right) = sound.frame(index);
+        // Interpolated towards silence after the last frame
+        let (next_left, next_right) = if index + 1 < frame_count {
+            sound.frame(index + 1)
+        } else if looping {
+            sound.frame(0)
+        } else {
+            (0.0, 0.0)
+        };
+
+        let left = left + (next_left - left) * t;
+        let right = right + (next_right - right) * t;
+        // Follows the signal exactly without a cutoff, so enabling it doesn't pop
+        let filtered = &mut voice.filtered;
+        filtered.0 += (left - filtered.0) * mix.low_pass;
+        filtered.1 += (right - filtered.1) * mix.low_pass;
+
+        frame[0] += filtered.0 * left_gain * mix.gain;
+        frame[1] += filtered.1 * right_gain * mix.gain;
+
+        voice.position += step;
+        if !wrap_position(&mut voice.position, frame_count, looping) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Loop the position back to the start, false if it ended.
+#[inline]
+fn wrap_position(position: &mut f64, frame_count: usize, looping: bool) -> bool {
+    if *position < frame_count as f64 {
+        true
+    } else if looping {
+        *position %= frame_count as f64;
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three stereo frames, mixed at their own rate so no interpolation happens.
+    fn sound() -> Sound {
+        Sound::from_interleaved(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 2, 48000)
+    }
+
+    fn assert_samples(output: &[f32], expected: &[f32]) {
+        assert_eq!(output.len(), expected.len());
+        for (sample, expected) in output.iter().zip(expected) {
+            assert!(
+                (sample - expected).abs() < 1e-6,
+                "{:?} != {:?}",
+                output,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn voice_ends() {
+        let mut mixer = Mixer::new(48000);
+        let voice = mixer.play(&sound(), VoiceSettings::default());
+
+        let mut output = [1.0; 10];
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.0, 0.0, 0.0, 0.0]);
+        assert!(!mixer.is_playing(voice));
+        assert_eq!(mixer.voice_count(), 0);
+        assert!(!mixer.stop(voice));
+    }
+
+    #[test]
+    fn voice_ends_on_a_buffer_boundary() {
+        let mut mixer = Mixer::new(48000);
+        let voice = mixer.play(&sound(), VoiceSettings::default());
+
+        let mut output = [0.0; 4];
+        mixer.mix(&mut output);
+        assert!(mixer.is_playing(voice));
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.5, 0.6, 0.0, 0.0]);
+        assert!(!mixer.is_playing(voice));
+    }
+
+    #[test]
+    fn voice_loops() {
+        let mut mixer = Mixer::new(48000);
+        let voice = mixer.play(&sound(), VoiceSettings::default().with_looping(true));
+
+        let mut output = [0.0; 10];
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.1, 0.2, 0.3, 0.4]);
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.5, 0.6, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.1, 0.2]);
+        assert!(mixer.is_playing(voice));
+
+        // Stops at the end once it doesn't loop anymore
+        mixer.settings_mut(voice).unwrap().looping = false;
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.3, 0.4, 0.5, 0.6, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert!(!mixer.is_playing(voice));
+    }
+
+    #[test]
+    fn paused_voice_keeps_its_position() {
+        let mut mixer = Mixer::new(48000);
+        let voice = mixer.play(&sound(), VoiceSettings::default());
+
+        let mut output = [0.0; 2];
+        mixer.mix(&mut output);
+        mixer.set_paused(voice, true);
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.0, 0.0]);
+        mixer.set_paused(voice, false);
+        mixer.mix(&mut output);
+        assert_samples(&output, &[0.3, 0.4]);
+    }
+}
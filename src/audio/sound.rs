@@ -0,0 +1,171 @@
+use crate::audio::wav;
+use lewton::inside_ogg::OggStreamReader;
+use lewton::VorbisError;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Why a sound can't be loaded.
+#[derive(Debug)]
+pub enum SoundError {
+    Io(io::Error),
+    /// Neither a RIFF WAV nor an Ogg file
+    UnknownFormat,
+    /// The WAV file is malformed
+    InvalidWav(&'static str),
+    /// Only integer PCM and 32 bit float samples are supported
+    UnsupportedWav {
+        format: u16,
+        bits: u16,
+    },
+    /// Reading the Ogg stream or decoding the Vorbis packets
+    Vorbis(VorbisError),
+}
+
+impl fmt::Display for SoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SoundError::Io(err) => write!(f, "Failed to read sound: {}", err),
+            SoundError::UnknownFormat => write!(f, "Unknown sound format, expected WAV or OGG"),
+            SoundError::InvalidWav(reason) => write!(f, "Invalid WAV file: {}", reason),
+            SoundError::UnsupportedWav { format, bits } => write!(
+                f,
+                "Unsupported WAV sample format {} with {} bits",
+                format, bits
+            ),
+            SoundError::Vorbis(err) => write!(f, "Failed to decode Ogg Vorbis: {}", err),
+        }
+    }
+}
+
+impl Error for SoundError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SoundError::Io(err) => Some(err),
+            SoundError::Vorbis(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SoundError {
+    fn from(err: io::Error) -> Self {
+        SoundError::Io(err)
+    }
+}
+
+impl From<VorbisError> for SoundError {
+    fn from(err: VorbisError) -> Self {
+        SoundError::Vorbis(err)
+    }
+}
+
+/// Decoded samples, mono or stereo, shared by every voice playing them.
+#[derive(Clone)]
+pub struct Sound {
+    /// Interleaved frames
+    samples: Arc<[f32]>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl Sound {
+    /// Load a WAV or Ogg Vorbis file, recognized by its content rather than its extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SoundError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SoundError> {
+        if bytes.starts_with(b"RIFF") {
+            Self::from_wav_bytes(bytes)
+        } else if bytes.starts_with(b"OggS") {
+            Self::from_ogg_bytes(bytes)
+        } else {
+            Err(SoundError::UnknownFormat)
+        }
+    }
+
+    pub fn from_wav_bytes(bytes: &[u8]) -> Result<Self, SoundError> {
+        let data = wav::decode(bytes)?;
+        Ok(Self::from_interleaved(
+            data.samples,
+            data.channels,
+            data.sample_rate,
+        ))
+    }
+
+    /// Decode the whole Vorbis stream up front, sounds are expected to be short.
+    pub fn from_ogg_bytes(bytes: &[u8]) -> Result<Self, SoundError> {
+        let mut reader = OggStreamReader::new(Cursor::new(bytes))?;
+        let channels = reader.ident_hdr.audio_channels as u16;
+        let sample_rate = reader.ident_hdr.audio_sample_rate;
+
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl()? {
+            samples.extend(packet.iter().map(|sample| *sample as f32 / 32768.0));
+        }
+
+        Ok(Self::from_interleaved(samples, channels, sample_rate))
+    }
+
+    /// Interleaved samples in [-1, 1], only the first two channels are kept.
+    pub fn from_interleaved(samples: Vec<f32>, channels: u16, sample_rate: u32) -> Self {
+        assert!(channels > 0, "A sound needs at least one channel !");
+        let samples: Arc<[f32]> = if channels > 2 {
+            samples
+                .chunks_exact(channels as usize)
+                .flat_map(|frame| frame[..2].iter().copied())
+                .collect()
+        } else {
+            samples.into()
+        };
+
+        Self {
+            samples,
+            channels: channels.min(2),
+            sample_rate,
+        }
+    }
+
+    /// Interleaved frames of one or two samples.
+    #[inline]
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// 1 for mono, 2 for stereo.
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline]
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    /// At its own sample rate, without pitch.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_count() as f64 / self.sample_rate as f64)
+    }
+
+    /// Left and right samples of a frame, a mono sample goes to both.
+    #[inline]
+    pub fn frame(&self, index: usize) -> (f32, f32) {
+        if self.channels == 1 {
+            let sample = self.samples[index];
+            (sample, sample)
+        } else {
+            (self.samples[index * 2], self.samples[index * 2 + 1])
+        }
+    }
+}
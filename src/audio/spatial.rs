@@ -0,0 +1,174 @@
+use crate::math::{Quat, Vec3};
+
+/// Speed of sound in air in meters per second, for the doppler shift.
+pub const DEFAULT_SPEED_OF_SOUND: f32 = 343.0;
+
+/// Gain of a fully occluded source.
+const OCCLUDED_GAIN: f32 = 0.3;
+/// Cutoff frequency in Hz of the low-pass filter of a fully occluded source, the filter opens
+/// up to the audible range as the occlusion goes down.
+const OCCLUDED_CUTOFF: f32 = 800.0;
+const OPEN_CUTOFF: f32 = 20000.0;
+
+/// How the gain goes down with the distance, past the minimum distance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Attenuation {
+    /// Down to silence at the maximum distance
+    Linear,
+    /// Halved every time the distance is doubled, like in the real world
+    #[default]
+    Inverse,
+    /// Steeper than inverse as the rolloff goes up
+    Exponential,
+}
+
+/// How a positional sound carries through the world.
+#[derive(Clone, Copy, Debug)]
+pub struct SpatialSettings {
+    pub attenuation: Attenuation,
+    /// Full volume up to this distance
+    pub min_distance: f32,
+    /// The gain stops going down past it
+    pub max_distance: f32,
+    /// How fast the gain goes down, 1 is physically correct for inverse
+    pub rolloff: f32,
+    /// Scale of the doppler shift, 0 disables it
+    pub doppler: f32,
+}
+
+impl Default for SpatialSettings {
+    fn default() -> Self {
+        Self {
+            attenuation: Attenuation::Inverse,
+            min_distance: 1.0,
+            max_distance: 50.0,
+            rolloff: 1.0,
+            doppler: 1.0,
+        }
+    }
+}
+
+impl SpatialSettings {
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    pub fn with_distances(mut self, min_distance: f32, max_distance: f32) -> Self {
+        self.min_distance = min_distance;
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn with_rolloff(mut self, rolloff: f32) -> Self {
+        self.rolloff = rolloff;
+        self
+    }
+
+    pub fn with_doppler(mut self, doppler: f32) -> Self {
+        self.doppler = doppler;
+        self
+    }
+
+    /// Gain at a distance from the listener, the distance is clamped to the min and max ones.
+    pub fn gain(&self, distance: f32) -> f32 {
+        let min_distance = self.min_distance.max(f32::EPSILON);
+        let max_distance = self.max_distance.max(min_distance);
+        let distance = distance.clamp(min_distance, max_distance);
+
+        match self.attenuation {
+            Attenuation::Linear => {
+                if max_distance == min_distance {
+                    return 1.0;
+                }
+                let t = (distance - min_distance) / (max_distance - min_distance);
+                (1.0 - self.rolloff * t).clamp(0.0, 1.0)
+            }
+            Attenuation::Inverse => {
+                min_distance / (min_distance + self.rolloff * (distance - min_distance))
+            }
+            Attenuation::Exponential => (distance / min_distance).powf(-self.rolloff),
+        }
+    }
+}
+
+/// Where the sounds are heard from, usually the active camera.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Listener {
+    pub position: Vec3,
+    /// In units per second
+    pub velocity: Vec3,
+    /// Right is +X and forward is -Z, like cameras
+    pub rotation: Quat,
+}
+
+/// Where a positional voice comes from.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpatialSource {
+    pub position: Vec3,
+    /// In units per second
+    pub velocity: Vec3,
+    pub settings: SpatialSettings,
+    /// From 0 when nothing is in the way to 1 when fully blocked
+    pub occlusion: f32,
+    /// Cutoff frequency in Hz of a low-pass filter, on top of the occlusion one
+    pub low_pass: Option<f32>,
+}
+
+/// What the position of a source does to its voice.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spatialization {
+    pub gain: f32,
+    pub pan: f32,
+    /// Pitch factor of the doppler shift
+    pub pitch: f32,
+    /// Cutoff frequency in Hz, none when the sound isn't filtered
+    pub low_pass: Option<f32>,
+}
+
+impl SpatialSource {
+    pub fn spatialize(&self, listener: &Listener, speed_of_sound: f32) -> Spatialization {
+        let offset = self.position - listener.position;
+        let distance = offset.length();
+
+        let mut pan = 0.0;
+        if distance > f32::EPSILON {
+            let local = listener.rotation.conjugate().rotate(offset / distance);
+            // Fades to the center inside the min distance rather than flipping sides
+            pan = local.x * (distance / self.settings.min_distance.max(f32::EPSILON)).min(1.0);
+        }
+
+        let occlusion = self.occlusion.clamp(0.0, 1.0);
+        let gain = self.settings.gain(distance) * (1.0 - occlusion * (1.0 - OCCLUDED_GAIN));
+
+        let mut low_pass = self.low_pass;
+        if occlusion > 0.0 {
+            // Interpolated in octaves, the way the cutoff is heard
+            let cutoff = OPEN_CUTOFF * (OCCLUDED_CUTOFF / OPEN_CUTOFF).powf(occlusion);
+            low_pass = Some(low_pass.map_or(cutoff, |low_pass| low_pass.min(cutoff)));
+        }
+
+        Spatialization {
+            gain,
+            pan,
+            pitch: self.doppler(listener, distance, speed_of_sound),
+            low_pass,
+        }
+    }
+
+    /// Pitch factor from the velocities along the line between the source and the listener.
+    fn doppler(&self, listener: &Listener, distance: f32, speed_of_sound: f32) -> f32 {
+        let factor = self.settings.doppler;
+        if factor <= 0.0 || distance <= f32::EPSILON || speed_of_sound <= 0.0 {
+            return 1.0;
+        }
+
+        // Both positive when moving towards the listener, kept under the speed of sound
+        let direction = (listener.position - self.position) / distance;
+        let limit = speed_of_sound * 0.9 / factor;
+        let listener_speed = (-listener.velocity.dot(direction)).clamp(-limit, limit);
+        let source_speed = self.velocity.dot(direction).clamp(-limit, limit);
+
+        (speed_of_sound + factor * listener_speed) / (speed_of_sound - factor * source_speed)
+    }
+}
@@ -0,0 +1,252 @@
+use crate::audio::sound::SoundError;
+use std::io::{self, Seek, SeekFrom, Write};
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Interleaved samples of a WAV file, converted to floats.
+pub struct WavData {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+/// Decode the content of a RIFF WAV file, 8, 16, 24 or 32 bit integer PCM or 32 bit float.
+pub fn decode(bytes: &[u8]) -> Result<WavData, SoundError> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(SoundError::InvalidWav("not a RIFF WAVE file"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut cursor = 12;
+    while cursor + 8 <= bytes.len() {
+        let id = &bytes[cursor..cursor + 4];
+        let size = read_u32(bytes, cursor + 4) as usize;
+        let start = cursor + 8;
+        // Some writers put a wrong size on the last chunk, keep what is there. A size too
+        // big to add up is as truncated as one past the end
+        let chunk_end = start.checked_add(size);
+        let end = chunk_end.map_or(bytes.len(), |end| end.min(bytes.len()));
+        match id {
+            b"fmt " => format = Some(&bytes[start..end]),
+            b"data" => data = Some(&bytes[start..end]),
+            _ => (),
+        }
+        // Chunks are padded to an even size
+        cursor = match chunk_end.and_then(|end| end.checked_add(size & 1)) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    let format = format.ok_or(SoundError::InvalidWav("no fmt chunk"))?;
+    let data = data.ok_or(SoundError::InvalidWav("no data chunk"))?;
+    if format.len() < 16 {
+        return Err(SoundError::InvalidWav("fmt chunk is too short"));
+    }
+
+    let mut tag = read_u16(format, 0);
+    let channels = read_u16(format, 2);
+    let sample_rate = read_u32(format, 4);
+    let bits = read_u16(format, 14);
+    if tag == FORMAT_EXTENSIBLE {
+        // The actual format is the first two bytes of the sub format GUID
+        if format.len() < 26 {
+            return Err(SoundError::InvalidWav("extensible fmt chunk is too short"));
+        }
+        tag = read_u16(format, 24);
+    }
+    if channels == 0 || sample_rate == 0 {
+        return Err(SoundError::InvalidWav("no channels or no sample rate"));
+    }
+
+    let samples = match (tag, bits) {
+        (FORMAT_PCM, 8) => data
+            .iter()
+            .map(|sample| (*sample as f32 - 128.0) / 128.0)
+            .collect(),
+        (FORMAT_PCM, 16) => data
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32768.0)
+            .collect(),
+        (FORMAT_PCM, 24) => data
+            .chunks_exact(3)
+            .map(|sample| {
+                // Shifted into the top of an i32 to keep the sign
+                i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) as f32 / 2_147_483_648.0
+            })
+            .collect(),
+        (FORMAT_PCM, 32) => data
+            .chunks_exact(4)
+            .map(|sample| {
+                i32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]) as f32
+                    / 2_147_483_648.0
+            })
+            .collect(),
+        (FORMAT_FLOAT, 32) => data
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            .collect(),
+        (format, bits) => return Err(SoundError::UnsupportedWav { format, bits }),
+    };
+
+    Ok(WavData {
+        channels,
+        sample_rate,
+        samples,
+    })
+}
+
+/// Writes interleaved float samples as a 16 bit PCM WAV file.
+///
+/// The sizes in the header are only known at the end, they are filled by [WavWriter::finish].
+pub struct WavWriter<W: Write + Seek> {
+    output: W,
+    channels: u16,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut output: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        write_header(&mut output, channels, sample_rate, 0)?;
+        Ok(Self {
+            output,
+            channels,
+            sample_rate,
+            data_size: 0,
+        })
+    }
+
+    /// Append samples, clipped to [-1, 1].
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.output.write_all(&bytes)?;
+        self.data_size += bytes.len() as u32;
+        Ok(())
+    }
+
+    /// Fill the sizes of the header and flush, the output is returned at the end of the data.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.output.seek(SeekFrom::Start(0))?;
+        write_header(
+            &mut self.output,
+            self.channels,
+            self.sample_rate,
+            self.data_size,
+        )?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+fn write_header<W: Write>(
+    output: &mut W,
+    channels: u16,
+    sample_rate: u32,
+    data_size: u32,
+) -> io::Result<()> {
+    let block_align = channels * 2;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    output.write_all(&header)
+}
+
+#[inline]
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+#[inline]
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{Mixer, Sound, VoiceSettings};
+    use std::io::Cursor;
+
+    /// One quantization step of the 16 bit samples, plus the rounding of the writer.
+    const TOLERANCE: f32 = 2.0 / 32768.0;
+
+    fn write(samples: &[f32], channels: u16, sample_rate: u32) -> Vec<u8> {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), channels, sample_rate).unwrap();
+        writer.write(samples).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn mix_round_trip() {
+        let tone: Vec<f32> = (0..64).map(|i| (i as f32 * 0.3).sin() * 0.5).collect();
+        let mut mixer = Mixer::new(44100);
+        mixer.play(
+            &Sound::from_interleaved(tone, 1, 44100),
+            VoiceSettings::default().with_pan(-0.5),
+        );
+        // Longer than the sound, so the end of the mix is silent
+        let mut mix = vec![0.0; 2 * 80];
+        mixer.mix(&mut mix);
+
+        let decoded = decode(&write(&mix, 2, 44100)).unwrap();
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.sample_rate, 44100);
+        assert_eq!(decoded.samples.len(), mix.len());
+        for (decoded, mixed) in decoded.samples.iter().zip(&mix) {
+            assert!(
+                (decoded - mixed).abs() <= TOLERANCE,
+                "{} != {}",
+                decoded,
+                mixed
+            );
+        }
+    }
+
+    #[test]
+    fn oversized_data_chunk_is_truncated() {
+        let mut bytes = write(&[0.25, -0.25, 0.5, -0.5], 2, 8000);
+        // Claims more than the file and more than the address space
+        bytes[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.samples.len(), 4);
+        assert!((decoded.samples[2] - 0.5).abs() <= TOLERANCE);
+    }
+
+    #[test]
+    fn missing_chunks() {
+        let bytes = write(&[0.0; 4], 1, 8000);
+        assert!(matches!(
+            decode(&bytes[..36]),
+            Err(SoundError::InvalidWav("no data chunk"))
+        ));
+        assert!(matches!(
+            decode(&bytes[..12]),
+            Err(SoundError::InvalidWav("no fmt chunk"))
+        ));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod application;
+pub mod audio;
+pub mod math;
 pub mod renderer;
+pub mod scene;
@@ -7,7 +7,7 @@ fn setup_logger() {
     let log_level = LevelFilter::Trace;
     let config = Config::default();
 
-    if let Err(_) = TermLogger::init(log_level, config.clone(), TerminalMode::Mixed) {
+    if TermLogger::init(log_level, config.clone(), TerminalMode::Mixed).is_err() {
         SimpleLogger::init(log_level, config).unwrap();
     }
 }
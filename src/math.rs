@@ -0,0 +1,9 @@
+mod bounds;
+mod matrix;
+mod quaternion;
+mod vector;
+
+pub use bounds::{Aabb, BoundingSphere, Frustum, Plane};
+pub use matrix::Mat4;
+pub use quaternion::Quat;
+pub use vector::{Vec2, Vec3, Vec4};
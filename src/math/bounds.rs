@@ -0,0 +1,195 @@
+use crate::math::{Mat4, Vec3, Vec4};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// Bounding sphere, cheaper to test than a box but usually looser.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+/// Plane of equation `normal . p + distance = 0`, the normal points inside.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+/// The six planes of a view frustum, extracted from a view projection matrix.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Aabb {
+    #[inline]
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// `None` if there are no points.
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Aabb::new(first, first), |aabb, point| Aabb {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        }))
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half of the size on each axis.
+    #[inline]
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    #[inline]
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    #[inline]
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.x >= self.min.x
+            && point.y >= self.min.y
+            && point.z >= self.min.z
+            && point.x <= self.max.x
+            && point.y <= self.max.y
+            && point.z <= self.max.z
+    }
+
+    /// Box enclosing this one once transformed by an affine matrix.
+    pub fn transform(&self, matrix: &Mat4) -> Aabb {
+        let center = matrix.transform_point(self.center());
+        let extents = self.extents();
+
+        // Each axis of the new box is the sum of the absolute projections of the old axes
+        let extents = Vec3::new(
+            matrix.row(0).truncate().abs().dot(extents),
+            matrix.row(1).truncate().abs().dot(extents),
+            matrix.row(2).truncate().abs().dot(extents),
+        );
+
+        Aabb::new(center - extents, center + extents)
+    }
+
+    /// The 8 corners of the box.
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+}
+
+impl BoundingSphere {
+    #[inline]
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    /// Centered on the box of the points, tighter than the sphere around the box.
+    pub fn from_points<I>(points: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = Vec3>,
+        I::IntoIter: Clone,
+    {
+        let points = points.into_iter();
+        let center = Aabb::from_points(points.clone())?.center();
+        let radius = points
+            .map(|point| point.distance(center))
+            .fold(0.0, f32::max);
+
+        Some(BoundingSphere::new(center, radius))
+    }
+
+    /// Sphere enclosing this one once transformed, non uniform scales make it looser.
+    pub fn transform(&self, matrix: &Mat4) -> BoundingSphere {
+        let scale = Vec3::new(
+            matrix.column(0).truncate().length(),
+            matrix.column(1).truncate().length(),
+            matrix.column(2).truncate().length(),
+        )
+        .max_element();
+
+        BoundingSphere::new(matrix.transform_point(self.center), self.radius * scale)
+    }
+}
+
+impl Plane {
+    /// Build from the `(a, b, c, d)` coefficients, normalizing them.
+    pub fn from_coefficients(coefficients: Vec4) -> Self {
+        let normal = coefficients.truncate();
+        let length = normal.length();
+
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    /// Positive on the side the normal points to.
+    #[inline]
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+impl Frustum {
+    /// Planes in world space when given `projection * view`, expects a [0, 1] depth range.
+    pub fn from_matrix(view_projection: &Mat4) -> Self {
+        let m = view_projection;
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+
+        Self {
+            planes: [
+                // Left, right
+                Plane::from_coefficients(r3 + r0),
+                Plane::from_coefficients(r3 - r0),
+                // Bottom, top (Y is flipped but the pair is the same)
+                Plane::from_coefficients(r3 + r1),
+                Plane::from_coefficients(r3 - r1),
+                // Near, far
+                Plane::from_coefficients(r2),
+                Plane::from_coefficients(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+    }
+
+    /// Conservative: boxes near the corners of the frustum may be reported as visible.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let extents = aabb.extents();
+
+        self.planes.iter().all(|plane| {
+            // Distance of the corner the furthest along the normal
+            let radius = plane.normal.abs().dot(extents);
+            plane.signed_distance(center) >= -radius
+        })
+    }
+}
@@ -0,0 +1,309 @@
+use crate::math::{Quat, Vec3, Vec4};
+use std::ops::Mul;
+
+/// Column major 4x4 matrix, same layout as GLSL so it can be copied to the GPU as is.
+///
+/// Projections follow the Vulkan conventions: right handed view space looking down -Z,
+/// Y pointing down in clip space and depth in the [0, 1] range.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub cols: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    #[inline]
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        cols: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    #[inline]
+    pub const fn from_cols(cols: [[f32; 4]; 4]) -> Self {
+        Self { cols }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        let mut matrix = Mat4::IDENTITY;
+        matrix.cols[3] = [translation.x, translation.y, translation.z, 1.0];
+        matrix
+    }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        let mut matrix = Mat4::IDENTITY;
+        matrix.cols[0][0] = scale.x;
+        matrix.cols[1][1] = scale.y;
+        matrix.cols[2][2] = scale.z;
+        matrix
+    }
+
+    #[inline]
+    pub fn from_rotation(rotation: Quat) -> Self {
+        Mat4::from_scale_rotation_translation(Vec3::ONE, rotation, Vec3::ZERO)
+    }
+
+    /// Scale first, then rotate, then translate.
+    pub fn from_scale_rotation_translation(scale: Vec3, rotation: Quat, translation: Vec3) -> Self {
+        let Quat { x, y, z, w } = rotation;
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        let (xy, xz, yz) = (x * y, x * z, y * z);
+        let (wx, wy, wz) = (w * x, w * y, w * z);
+
+        Mat4::from_cols([
+            [
+                (1.0 - 2.0 * (yy + zz)) * scale.x,
+                2.0 * (xy + wz) * scale.x,
+                2.0 * (xz - wy) * scale.x,
+                0.0,
+            ],
+            [
+                2.0 * (xy - wz) * scale.y,
+                (1.0 - 2.0 * (xx + zz)) * scale.y,
+                2.0 * (yz + wx) * scale.y,
+                0.0,
+            ],
+            [
+                2.0 * (xz + wy) * scale.z,
+                2.0 * (yz - wx) * scale.z,
+                (1.0 - 2.0 * (xx + yy)) * scale.z,
+                0.0,
+            ],
+            [translation.x, translation.y, translation.z, 1.0],
+        ])
+    }
+
+    /// Split an affine matrix back into scale, rotation and translation.
+    /// Shear can't be represented and is lost.
+    pub fn to_scale_rotation_translation(&self) -> (Vec3, Quat, Vec3) {
+        let translation = Vec3::new(self.cols[3][0], self.cols[3][1], self.cols[3][2]);
+
+        let mut scale = Vec3::new(
+            self.column(0).truncate().length(),
+            self.column(1).truncate().length(),
+            self.column(2).truncate().length(),
+        );
+        // A mirrored matrix, put the negative scale on X
+        if self.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let mut rotation = Mat4::IDENTITY;
+        for col in 0..3 {
+            if scale[col] != 0.0 {
+                for row in 0..3 {
+                    rotation.cols[col][row] = self.cols[col][row] / scale[col];
+                }
+            }
+        }
+
+        (scale, Quat::from_rotation_matrix(&rotation), translation)
+    }
+
+    /// Right handed perspective projection, `fov_y` in radians.
+    pub fn perspective(fov_y: f32, aspect_ratio: f32, near: f32, far: f32) -> Self {
+        let f = 1.0 / (fov_y * 0.5).tan();
+
+        Mat4::from_cols([
+            [f / aspect_ratio, 0.0, 0.0, 0.0],
+            [0.0, -f, 0.0, 0.0],
+            [0.0, 0.0, far / (near - far), -1.0],
+            [0.0, 0.0, near * far / (near - far), 0.0],
+        ])
+    }
+
+    /// Right handed orthographic projection.
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Self {
+        Mat4::from_cols([
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
+            [0.0, -2.0 / (top - bottom), 0.0, 0.0],
+            [0.0, 0.0, 1.0 / (near - far), 0.0],
+            [
+                -(right + left) / (right - left),
+                (top + bottom) / (top - bottom),
+                near / (near - far),
+                1.0,
+            ],
+        ])
+    }
+
+    /// Right handed view matrix.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Self {
+        let forward = (target - eye).normalize();
+        let side = forward.cross(up).normalize();
+        let up = side.cross(forward);
+
+        Mat4::from_cols([
+            [side.x, up.x, -forward.x, 0.0],
+            [side.y, up.y, -forward.y, 0.0],
+            [side.z, up.z, -forward.z, 0.0],
+            [-side.dot(eye), -up.dot(eye), forward.dot(eye), 1.0],
+        ])
+    }
+
+    #[inline]
+    pub fn column(&self, index: usize) -> Vec4 {
+        Vec4::from(self.cols[index])
+    }
+
+    #[inline]
+    pub fn row(&self, index: usize) -> Vec4 {
+        Vec4::new(
+            self.cols[0][index],
+            self.cols[1][index],
+            self.cols[2][index],
+            self.cols[3][index],
+        )
+    }
+
+    #[inline]
+    pub fn translation(&self) -> Vec3 {
+        self.column(3).truncate()
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut result = Mat4::IDENTITY;
+        for col in 0..4 {
+            for row in 0..4 {
+                result.cols[col][row] = self.cols[row][col];
+            }
+        }
+        result
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.cols;
+        let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+        let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+        let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+        let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+        let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+        let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+
+        let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+        let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+        let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+        let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+        let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+        let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+        s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0
+    }
+
+    /// Returns the identity for a singular matrix.
+    pub fn inverse(&self) -> Mat4 {
+        let m = &self.cols;
+        let s0 = m[0][0] * m[1][1] - m[1][0] * m[0][1];
+        let s1 = m[0][0] * m[1][2] - m[1][0] * m[0][2];
+        let s2 = m[0][0] * m[1][3] - m[1][0] * m[0][3];
+        let s3 = m[0][1] * m[1][2] - m[1][1] * m[0][2];
+        let s4 = m[0][1] * m[1][3] - m[1][1] * m[0][3];
+        let s5 = m[0][2] * m[1][3] - m[1][2] * m[0][3];
+
+        let c5 = m[2][2] * m[3][3] - m[3][2] * m[2][3];
+        let c4 = m[2][1] * m[3][3] - m[3][1] * m[2][3];
+        let c3 = m[2][1] * m[3][2] - m[3][1] * m[2][2];
+        let c2 = m[2][0] * m[3][3] - m[3][0] * m[2][3];
+        let c1 = m[2][0] * m[3][2] - m[3][0] * m[2][2];
+        let c0 = m[2][0] * m[3][1] - m[3][0] * m[2][1];
+
+        let determinant = s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0;
+        if determinant == 0.0 {
+            return Mat4::IDENTITY;
+        }
+        let inv = 1.0 / determinant;
+
+        Mat4::from_cols([
+            [
+                (m[1][1] * c5 - m[1][2] * c4 + m[1][3] * c3) * inv,
+                (-m[0][1] * c5 + m[0][2] * c4 - m[0][3] * c3) * inv,
+                (m[3][1] * s5 - m[3][2] * s4 + m[3][3] * s3) * inv,
+                (-m[2][1] * s5 + m[2][2] * s4 - m[2][3] * s3) * inv,
+            ],
+            [
+                (-m[1][0] * c5 + m[1][2] * c2 - m[1][3] * c1) * inv,
+                (m[0][0] * c5 - m[0][2] * c2 + m[0][3] * c1) * inv,
+                (-m[3][0] * s5 + m[3][2] * s2 - m[3][3] * s1) * inv,
+                (m[2][0] * s5 - m[2][2] * s2 + m[2][3] * s1) * inv,
+            ],
+            [
+                (m[1][0] * c4 - m[1][1] * c2 + m[1][3] * c0) * inv,
+                (-m[0][0] * c4 + m[0][1] * c2 - m[0][3] * c0) * inv,
+                (m[3][0] * s4 - m[3][1] * s2 + m[3][3] * s0) * inv,
+                (-m[2][0] * s4 + m[2][1] * s2 - m[2][3] * s0) * inv,
+            ],
+            [
+                (-m[1][0] * c3 + m[1][1] * c1 - m[1][2] * c0) * inv,
+                (m[0][0] * c3 - m[0][1] * c1 + m[0][2] * c0) * inv,
+                (-m[3][0] * s3 + m[3][1] * s1 - m[3][2] * s0) * inv,
+                (m[2][0] * s3 - m[2][1] * s1 + m[2][2] * s0) * inv,
+            ],
+        ])
+    }
+
+    /// Transform a position, assumes an affine matrix.
+    #[inline]
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        (*self * point.extend(1.0)).truncate()
+    }
+
+    /// Transform a position with the perspective divide.
+    #[inline]
+    pub fn project_point(&self, point: Vec3) -> Vec3 {
+        let clip = *self * point.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    /// Transform a direction, the translation is ignored.
+    #[inline]
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        (*self * vector.extend(0.0)).truncate()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut result = Mat4::from_cols([[0.0; 4]; 4]);
+        for col in 0..4 {
+            result.cols[col] = (self * other.column(col)).to_array();
+        }
+        result
+    }
+}
+
+impl Mul<Vec4> for Mat4 {
+    type Output = Vec4;
+
+    #[inline]
+    fn mul(self, vector: Vec4) -> Vec4 {
+        self.column(0) * vector.x
+            + self.column(1) * vector.y
+            + self.column(2) * vector.z
+            + self.column(3) * vector.w
+    }
+}
+
+impl From<[[f32; 4]; 4]> for Mat4 {
+    #[inline]
+    fn from(cols: [[f32; 4]; 4]) -> Self {
+        Mat4::from_cols(cols)
+    }
+}
+
+impl From<Mat4> for [[f32; 4]; 4] {
+    #[inline]
+    fn from(matrix: Mat4) -> Self {
+        matrix.cols
+    }
+}
@@ -0,0 +1,232 @@
+use crate::math::{Mat4, Vec3};
+use std::ops::{Mul, Neg};
+
+/// Rotation quaternion, expected to be of unit length.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    #[inline]
+    fn default() -> Self {
+        Quat::IDENTITY
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+        w: 1.0,
+    };
+
+    #[inline]
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self { x, y, z, w }
+    }
+
+    /// Rotation of `angle` radians around `axis`.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// Rotation from euler angles in radians, applied in the yaw (Y), pitch (X), roll (Z) order.
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        Quat::from_axis_angle(Vec3::Y, yaw)
+            * Quat::from_axis_angle(Vec3::X, pitch)
+            * Quat::from_axis_angle(Vec3::Z, roll)
+    }
+
+    /// Smallest rotation that turns `from` into `to`.
+    pub fn from_rotation_arc(from: Vec3, to: Vec3) -> Self {
+        let from = from.normalize();
+        let to = to.normalize();
+        let dot = from.dot(to);
+
+        if dot >= 1.0 - 1e-6 {
+            return Quat::IDENTITY;
+        }
+        if dot <= -1.0 + 1e-6 {
+            // Opposite directions, any perpendicular axis will do
+            let mut axis = Vec3::X.cross(from);
+            if axis.length_squared() < 1e-6 {
+                axis = Vec3::Y.cross(from);
+            }
+            return Quat::from_axis_angle(axis, std::f32::consts::PI);
+        }
+
+        let axis = from.cross(to);
+        Quat::new(axis.x, axis.y, axis.z, 1.0 + dot).normalize()
+    }
+
+    /// Extract the rotation of a matrix without scale.
+    pub fn from_rotation_matrix(matrix: &Mat4) -> Self {
+        let m = &matrix.cols;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quat::new(
+                (m[1][2] - m[2][1]) / s,
+                (m[2][0] - m[0][2]) / s,
+                (m[0][1] - m[1][0]) / s,
+                0.25 * s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Quat::new(
+                0.25 * s,
+                (m[1][0] + m[0][1]) / s,
+                (m[2][0] + m[0][2]) / s,
+                (m[1][2] - m[2][1]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Quat::new(
+                (m[1][0] + m[0][1]) / s,
+                0.25 * s,
+                (m[2][1] + m[1][2]) / s,
+                (m[2][0] - m[0][2]) / s,
+            )
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Quat::new(
+                (m[2][0] + m[0][2]) / s,
+                (m[2][1] + m[1][2]) / s,
+                0.25 * s,
+                (m[0][1] - m[1][0]) / s,
+            )
+        }
+        .normalize()
+    }
+
+    #[inline]
+    pub fn dot(self, other: Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    #[inline]
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Quat {
+        let length = self.length();
+        if length > 0.0 {
+            Quat::new(
+                self.x / length,
+                self.y / length,
+                self.z / length,
+                self.w / length,
+            )
+        } else {
+            Quat::IDENTITY
+        }
+    }
+
+    /// Inverse of a unit quaternion.
+    #[inline]
+    pub fn conjugate(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    #[inline]
+    pub fn rotate(self, vector: Vec3) -> Vec3 {
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = axis.cross(vector) * 2.0;
+        vector + t * self.w + axis.cross(t)
+    }
+
+    /// Normalized linear interpolation, cheaper than slerp and good enough for close rotations.
+    pub fn nlerp(self, other: Quat, t: f32) -> Quat {
+        // Go the short way around
+        let other = if self.dot(other) < 0.0 { -other } else { other };
+        Quat::new(
+            self.x + (other.x - self.x) * t,
+            self.y + (other.y - self.y) * t,
+            self.z + (other.z - self.z) * t,
+            self.w + (other.w - self.w) * t,
+        )
+        .normalize()
+    }
+
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut dot = self.dot(other);
+        let other = if dot < 0.0 {
+            dot = -dot;
+            -other
+        } else {
+            other
+        };
+
+        // Too close, the sine below would be unstable
+        if dot > 0.9995 {
+            return self.nlerp(other, t);
+        }
+
+        let theta = dot.acos();
+        let sin_theta = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin_theta;
+        let b = (t * theta).sin() / sin_theta;
+
+        Quat::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+    }
+
+    #[inline]
+    pub fn to_array(self) -> [f32; 4] {
+        [self.x, self.y, self.z, self.w]
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+
+    /// Combined rotation, `other` is applied first.
+    #[inline]
+    fn mul(self, other: Quat) -> Quat {
+        Quat::new(
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        )
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    #[inline]
+    fn mul(self, vector: Vec3) -> Vec3 {
+        self.rotate(vector)
+    }
+}
+
+impl Neg for Quat {
+    type Output = Quat;
+
+    #[inline]
+    fn neg(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+impl From<[f32; 4]> for Quat {
+    #[inline]
+    fn from(array: [f32; 4]) -> Self {
+        Quat::new(array[0], array[1], array[2], array[3])
+    }
+}
@@ -1,8 +1,40 @@
+pub mod compute;
+pub mod culling;
+pub mod display_output;
+pub mod environment;
+pub mod frame_allocator;
+pub mod frame_stats;
+mod fullscreen;
+pub mod gltf_loader;
+pub mod gpu_profiler;
+mod hdr_target;
+pub mod instancing;
+pub mod lighting;
+pub mod material;
+pub mod memory_tracker;
+pub mod mesh;
+pub mod particles;
+mod pbr_pipeline;
 mod physical_device_selection;
+pub mod post_processing;
+pub mod primitives;
+mod raw_commands;
+pub mod shader_reflection;
+mod shadow_debug;
+pub mod shadows;
+pub mod skinning;
+pub mod sprites;
 mod swapchain_wrapper;
-mod test_material;
+pub mod text;
+pub mod texture;
+pub mod tonemapping;
 pub mod vulkan_app;
 
+pub use frame_allocator::FrameAllocator;
+pub use frame_stats::FrameStats;
+pub use gpu_profiler::GpuProfiler;
+pub use memory_tracker::MemoryTracker;
+pub use mesh::{Mesh, MorphTarget, SkinnedVertex, Vertex};
 pub use vulkan_app::VulkanApplication;
 
 #[cfg(debug_assertions)]
@@ -14,4 +46,7 @@ const VALIDATION_LAYERS: &[&str] = &["VK_LAYER_LUNARG_standard_validation"];
 
 const DIMENSIONS: (u32, u32) = (800, 600);
 
+/// Amount of frames the CPU can record while the GPU is still busy with the previous ones.
+const FRAMES_IN_FLIGHT: usize = 2;
+
 const APPLICATION_NAME: &str = "AL-Engine";
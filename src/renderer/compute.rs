@@ -0,0 +1,667 @@
+use crate::renderer::raw_commands::RawCommands;
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::sync::Arc;
+use vulkano::buffer::{BufferAccess, TypedBufferAccess};
+use vulkano::command_buffer::pool::standard::StandardCommandPoolBuilder;
+use vulkano::command_buffer::sys::{
+    UnsafeCommandBufferBuilder, UnsafeCommandBufferBuilderPipelineBarrier,
+};
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::descriptor::descriptor::{DescriptorDesc, DescriptorType, ShaderStages};
+use vulkano::descriptor::descriptor_set::{
+    DescriptorPool, DescriptorPoolAlloc, DescriptorSetDesc, DescriptorSetsCollection,
+    DescriptorWrite, StdDescriptorPoolAlloc, UnsafeDescriptorSet, UnsafeDescriptorSetLayout,
+};
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::image::ImageViewAccess;
+use vulkano::instance::QueueFamily;
+use vulkano::pipeline::shader::EntryPointAbstract;
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sampler::Sampler;
+use vulkano::sync::{AccessFlagBits, PipelineStages};
+
+/// Where compute work submitted through the application runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ComputeQueue {
+    /// Recorded at the start of the next frame, the draws reading the results wait for it
+    #[default]
+    Graphics,
+    /// Submitted right away on the compute queue, the next frame waits for it to finish.
+    /// Resources used by both queues have to be created for the families of both, see
+    /// [shared_queue_families](crate::renderer::VulkanApplication::shared_queue_families).
+    Async,
+}
+
+/// Compute shader and its pipeline, with the size of its work groups.
+pub struct ComputeProgram {
+    pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+    local_size: [u32; 3],
+}
+
+impl ComputeProgram {
+    /// The local size has to be the one declared in the shader, vulkano doesn't reflect it.
+    pub fn new<Cs>(
+        device: &Arc<Device>,
+        entry_point: &Cs,
+        specialization: &Cs::SpecializationConstants,
+        local_size: [u32; 3],
+    ) -> Self
+    where
+        Cs: EntryPointAbstract,
+        Cs::PipelineLayout: Clone,
+    {
+        Self::with_dynamic_buffers(device, entry_point, specialization, local_size, &[])
+    }
+
+    /// Same as [new](ComputeProgram::new), with the uniform and storage buffers at the given
+    /// `(set, binding)` bound with dynamic offsets, see [ComputeRecorder].
+    pub fn with_dynamic_buffers<Cs>(
+        device: &Arc<Device>,
+        entry_point: &Cs,
+        specialization: &Cs::SpecializationConstants,
+        local_size: [u32; 3],
+        dynamic_buffers: &[(usize, usize)],
+    ) -> Self
+    where
+        Cs: EntryPointAbstract,
+        Cs::PipelineLayout: Clone,
+    {
+        let mut layout = MaterialLayout::merge(&[ShaderReflection::compute(entry_point)])
+            .expect("Failed to reflect compute shader !");
+        for &(set, binding) in dynamic_buffers {
+            layout.set_dynamic(set, binding);
+        }
+        let layout = layout
+            .create_pipeline_layout(device)
+            .expect("Failed to create compute pipeline layout !");
+
+        let pipeline = if dynamic_buffers.is_empty() {
+            ComputePipeline::with_pipeline_layout(
+                device.clone(),
+                entry_point,
+                specialization,
+                layout,
+            )
+        } else {
+            // Vulkano refuses dynamic buffers where the shader declares regular ones, but
+            // shaders can't tell them apart and the rest of the layout is the shader's own
+            unsafe {
+                ComputePipeline::with_unchecked_pipeline_layout(
+                    device.clone(),
+                    entry_point,
+                    specialization,
+                    layout,
+                )
+            }
+        };
+        let pipeline = Arc::new(pipeline.expect("Failed to create compute pipeline !"));
+
+        Self {
+            pipeline,
+            local_size,
+        }
+    }
+
+    #[inline]
+    pub fn pipeline(&self) -> &Arc<dyn ComputePipelineAbstract + Send + Sync> {
+        &self.pipeline
+    }
+
+    #[inline]
+    pub fn local_size(&self) -> [u32; 3] {
+        self.local_size
+    }
+
+    /// Work groups covering every invocation, the last ones may go past it.
+    #[inline]
+    pub fn group_count(&self, invocations: [u32; 3]) -> [u32; 3] {
+        [
+            invocations[0].div_ceil(self.local_size[0]),
+            invocations[1].div_ceil(self.local_size[1]),
+            invocations[2].div_ceil(self.local_size[2]),
+        ]
+    }
+
+    /// Bind the resources of a descriptor set of the shader.
+    pub fn bindings(&self, set: usize) -> ComputeBindings {
+        let layout = self
+            .pipeline
+            .descriptor_set_layout(set)
+            .expect("The compute shader has no such descriptor set !")
+            .clone();
+        ComputeBindings::new(layout)
+    }
+
+    /// Run at least one invocation for each of the given ones, the shader has to ignore
+    /// the ones past the end.
+    pub fn dispatch<S, Pc>(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        invocations: [u32; 3],
+        sets: S,
+        push_constants: Pc,
+    ) -> AutoCommandBufferBuilder
+    where
+        S: DescriptorSetsCollection,
+    {
+        self.dispatch_groups(builder, self.group_count(invocations), sets, push_constants)
+    }
+
+    pub fn dispatch_groups<S, Pc>(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        groups: [u32; 3],
+        sets: S,
+        push_constants: Pc,
+    ) -> AutoCommandBufferBuilder
+    where
+        S: DescriptorSetsCollection,
+    {
+        builder
+            .dispatch(groups, self.pipeline.clone(), sets, push_constants)
+            .expect("Failed to record compute dispatch !")
+    }
+}
+
+/// Records dispatches whose sets are bound with dynamic offsets, which the automatic command
+/// buffer builder can't do, in [raw commands](RawCommands) executed outside of render passes.
+///
+/// Vulkano doesn't synchronize them: the recorded commands wait for everything before them,
+/// [barrier](ComputeRecorder::barrier) separates the ones that depend on each other, and
+/// everything after them waits for them.
+pub struct ComputeRecorder {
+    commands: UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>,
+    resources: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+impl ComputeRecorder {
+    pub fn new(device: &Arc<Device>, queue_family: QueueFamily) -> Self {
+        let mut recorder = Self {
+            commands: RawCommands::begin(device, queue_family),
+            resources: Vec::new(),
+        };
+        recorder.memory_barrier(
+            PipelineStages {
+                all_commands: true,
+                ..PipelineStages::none()
+            },
+            AccessFlagBits {
+                memory_read: true,
+                memory_write: true,
+                ..AccessFlagBits::none()
+            },
+        );
+        recorder
+    }
+
+    pub fn fill_buffer<B>(&mut self, buffer: Arc<B>, data: u32)
+    where
+        B: BufferAccess + Send + Sync + 'static,
+    {
+        unsafe {
+            self.commands.fill_buffer(&*buffer, data);
+        }
+        self.resources.push(buffer);
+    }
+
+    /// Same as [ComputeProgram::dispatch], with an offset for each dynamic buffer of the set
+    /// in binding order.
+    pub fn dispatch<Pc>(
+        &mut self,
+        program: &ComputeProgram,
+        invocations: [u32; 3],
+        set: Arc<ComputeSet>,
+        dynamic_offsets: &[u32],
+        push_constants: Pc,
+    ) {
+        let groups = program.group_count(invocations);
+        self.dispatch_groups(program, groups, set, dynamic_offsets, push_constants);
+    }
+
+    pub fn dispatch_groups<Pc>(
+        &mut self,
+        program: &ComputeProgram,
+        groups: [u32; 3],
+        set: Arc<ComputeSet>,
+        dynamic_offsets: &[u32],
+        push_constants: Pc,
+    ) {
+        let pipeline = program.pipeline();
+        unsafe {
+            self.commands.bind_pipeline_compute(&**pipeline);
+            self.commands.bind_descriptor_sets(
+                false,
+                &**pipeline,
+                0,
+                iter::once(set.inner()),
+                dynamic_offsets.iter().copied(),
+            );
+            let size = mem::size_of::<Pc>();
+            if size > 0 {
+                self.commands.push_constants(
+                    &**pipeline,
+                    ShaderStages::compute(),
+                    0,
+                    size as u32,
+                    &push_constants,
+                );
+            }
+            self.commands.dispatch(groups);
+        }
+        self.resources.push(Arc::new(pipeline.clone()));
+        self.resources.push(set);
+    }
+
+    /// Make what was recorded so far visible to what is recorded next.
+    pub fn barrier(&mut self) {
+        self.memory_barrier(
+            PipelineStages {
+                compute_shader: true,
+                transfer: true,
+                ..PipelineStages::none()
+            },
+            AccessFlagBits {
+                shader_write: true,
+                transfer_write: true,
+                ..AccessFlagBits::none()
+            },
+        );
+    }
+
+    /// Execute the dispatches, outside of any render pass.
+    pub fn execute(mut self, builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        let destination_stages = PipelineStages {
+            all_commands: true,
+            ..PipelineStages::none()
+        };
+        let destination_access = AccessFlagBits {
+            memory_read: true,
+            memory_write: true,
+            ..AccessFlagBits::none()
+        };
+        unsafe {
+            let mut barrier = UnsafeCommandBufferBuilderPipelineBarrier::new();
+            barrier.add_memory_barrier(
+                PipelineStages {
+                    compute_shader: true,
+                    transfer: true,
+                    ..PipelineStages::none()
+                },
+                AccessFlagBits {
+                    shader_write: true,
+                    transfer_write: true,
+                    ..AccessFlagBits::none()
+                },
+                destination_stages,
+                destination_access,
+                false,
+            );
+            self.commands.pipeline_barrier(&barrier);
+        }
+
+        RawCommands::build(self.commands, self.resources).execute(builder)
+    }
+
+    /// Wait for the given accesses before the commands recorded next, which only transfer
+    /// and run compute shaders.
+    fn memory_barrier(&mut self, source_stages: PipelineStages, source_access: AccessFlagBits) {
+        unsafe {
+            let mut barrier = UnsafeCommandBufferBuilderPipelineBarrier::new();
+            barrier.add_memory_barrier(
+                source_stages,
+                source_access,
+                PipelineStages {
+                    compute_shader: true,
+                    transfer: true,
+                    ..PipelineStages::none()
+                },
+                AccessFlagBits {
+                    uniform_read: true,
+                    shader_read: true,
+                    shader_write: true,
+                    transfer_write: true,
+                    ..AccessFlagBits::none()
+                },
+                false,
+            );
+            self.commands.pipeline_barrier(&barrier);
+        }
+    }
+}
+
+/// Why resources can't be bound to a compute shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComputeBindingError {
+    /// The set of the shader has nothing at this binding.
+    NoSuchBinding { binding: u32 },
+    /// The resource is of another type than the descriptor.
+    WrongType {
+        binding: u32,
+        expected: Option<DescriptorType>,
+        provided: DescriptorType,
+    },
+    /// The resource wasn't created with the usage the descriptor needs.
+    MissingUsage { binding: u32, usage: &'static str },
+    /// A descriptor of the shader has nothing bound to it.
+    Unbound { binding: u32 },
+}
+
+impl fmt::Display for ComputeBindingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComputeBindingError::NoSuchBinding { binding } => {
+                write!(f, "the shader has no descriptor at binding {}", binding)
+            }
+            ComputeBindingError::WrongType {
+                binding,
+                expected,
+                provided,
+            } => write!(
+                f,
+                "binding {} expects a {:?} but a {:?} was provided",
+                binding, expected, provided
+            ),
+            ComputeBindingError::MissingUsage { binding, usage } => write!(
+                f,
+                "the resource at binding {} wasn't created with the {} usage",
+                binding, usage
+            ),
+            ComputeBindingError::Unbound { binding } => {
+                write!(f, "nothing is bound at binding {}", binding)
+            }
+        }
+    }
+}
+
+impl Error for ComputeBindingError {}
+
+/// Resources of a descriptor set, checked against the descriptors of the shader as they
+/// are added. The first error is returned when building.
+pub struct ComputeBindings {
+    layout: Arc<UnsafeDescriptorSetLayout>,
+    writes: Vec<DescriptorWrite>,
+    buffers: Vec<(Arc<dyn BufferAccess + Send + Sync>, u32)>,
+    images: Vec<(Arc<dyn ImageViewAccess + Send + Sync>, u32)>,
+    samplers: Vec<Arc<Sampler>>,
+    bound: Vec<bool>,
+    error: Option<ComputeBindingError>,
+}
+
+impl ComputeBindings {
+    pub fn new(layout: Arc<UnsafeDescriptorSetLayout>) -> Self {
+        let bound = vec![false; layout.num_bindings()];
+        Self {
+            layout,
+            writes: Vec::new(),
+            buffers: Vec::new(),
+            images: Vec::new(),
+            samplers: Vec::new(),
+            bound,
+            error: None,
+        }
+    }
+
+    pub fn storage_buffer<B>(mut self, binding: u32, buffer: Arc<B>) -> Self
+    where
+        B: TypedBufferAccess + Send + Sync + 'static,
+    {
+        if self.check(binding, DescriptorType::StorageBuffer) {
+            if !buffer.inner().buffer.usage_storage_buffer() {
+                return self.fail(ComputeBindingError::MissingUsage {
+                    binding,
+                    usage: "storage buffer",
+                });
+            }
+            // Safe, the usage was checked and the buffer is kept alive by the set
+            let write = unsafe { DescriptorWrite::storage_buffer(binding, 0, &buffer) };
+            self.bind_buffer(binding, write, buffer);
+        }
+        self
+    }
+
+    pub fn uniform_buffer<B>(mut self, binding: u32, buffer: Arc<B>) -> Self
+    where
+        B: TypedBufferAccess + Send + Sync + 'static,
+    {
+        if self.check(binding, DescriptorType::UniformBuffer) {
+            if !buffer.inner().buffer.usage_uniform_buffer() {
+                return self.fail(ComputeBindingError::MissingUsage {
+                    binding,
+                    usage: "uniform buffer",
+                });
+            }
+            // Safe, the usage was checked and the buffer is kept alive by the set
+            let write = unsafe { DescriptorWrite::uniform_buffer(binding, 0, &buffer) };
+            self.bind_buffer(binding, write, buffer);
+        }
+        self
+    }
+
+    /// Bound with a dynamic offset, the descriptor covers the size of the data read from
+    /// the start of the buffer, see [dynamic_slice](crate::renderer::frame_allocator::FrameAllocation::dynamic_slice).
+    pub fn dynamic_uniform_buffer<B>(mut self, binding: u32, buffer: Arc<B>) -> Self
+    where
+        B: TypedBufferAccess + Send + Sync + 'static,
+    {
+        if self.check(binding, DescriptorType::UniformBufferDynamic) {
+            if !buffer.inner().buffer.usage_uniform_buffer() {
+                return self.fail(ComputeBindingError::MissingUsage {
+                    binding,
+                    usage: "uniform buffer",
+                });
+            }
+            // Safe, the usage was checked and the buffer is kept alive by the set
+            let write = unsafe { DescriptorWrite::dynamic_uniform_buffer(binding, 0, &buffer) };
+            self.bind_buffer(binding, write, buffer);
+        }
+        self
+    }
+
+    /// Same as [dynamic_uniform_buffer](ComputeBindings::dynamic_uniform_buffer) for a storage
+    /// buffer.
+    pub fn dynamic_storage_buffer<B>(mut self, binding: u32, buffer: Arc<B>) -> Self
+    where
+        B: TypedBufferAccess + Send + Sync + 'static,
+    {
+        if self.check(binding, DescriptorType::StorageBufferDynamic) {
+            if !buffer.inner().buffer.usage_storage_buffer() {
+                return self.fail(ComputeBindingError::MissingUsage {
+                    binding,
+                    usage: "storage buffer",
+                });
+            }
+            // Safe, the usage was checked and the buffer is kept alive by the set
+            let write = unsafe { DescriptorWrite::dynamic_storage_buffer(binding, 0, &buffer) };
+            self.bind_buffer(binding, write, buffer);
+        }
+        self
+    }
+
+    /// Read and written with `imageLoad` and `imageStore`.
+    pub fn storage_image<I>(mut self, binding: u32, image: Arc<I>) -> Self
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        if self.check(binding, DescriptorType::StorageImage) {
+            if !image.inner().usage_storage() {
+                return self.fail(ComputeBindingError::MissingUsage {
+                    binding,
+                    usage: "storage",
+                });
+            }
+            let write = DescriptorWrite::storage_image(binding, 0, &image);
+            self.bind_image(binding, write, image);
+        }
+        self
+    }
+
+    /// Read through a sampler, as a combined image sampler.
+    pub fn sampled_image<I>(mut self, binding: u32, image: Arc<I>, sampler: Arc<Sampler>) -> Self
+    where
+        I: ImageViewAccess + Send + Sync + 'static,
+    {
+        if self.check(binding, DescriptorType::CombinedImageSampler) {
+            if !image.inner().usage_sampled() {
+                return self.fail(ComputeBindingError::MissingUsage {
+                    binding,
+                    usage: "sampled",
+                });
+            }
+            let write = DescriptorWrite::combined_image_sampler(binding, 0, &sampler, &image);
+            self.bind_image(binding, write, image);
+            self.samplers.push(sampler);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Arc<ComputeSet>, ComputeBindingError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        for (binding, bound) in self.bound.iter().enumerate() {
+            if !bound && self.layout.descriptor(binding).is_some() {
+                return Err(ComputeBindingError::Unbound {
+                    binding: binding as u32,
+                });
+            }
+        }
+
+        let mut pool = Device::standard_descriptor_pool(self.layout.device());
+        let inner = unsafe {
+            let mut set = pool
+                .alloc(&self.layout)
+                .expect("Failed to allocate compute descriptor set !");
+            set.inner_mut()
+                .write(pool.device(), self.writes.into_iter());
+            set
+        };
+
+        Ok(Arc::new(ComputeSet {
+            inner,
+            layout: self.layout,
+            buffers: self.buffers,
+            images: self.images,
+            _samplers: self.samplers,
+        }))
+    }
+
+    /// Whether the resource can be bound, the error is kept otherwise.
+    fn check(&mut self, binding: u32, provided: DescriptorType) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+
+        let expected = match self.layout.descriptor(binding as usize) {
+            Some(DescriptorDesc { ty, .. }) => ty.ty(),
+            None => {
+                self.error = Some(ComputeBindingError::NoSuchBinding { binding });
+                return false;
+            }
+        };
+        if expected != Some(provided) {
+            self.error = Some(ComputeBindingError::WrongType {
+                binding,
+                expected,
+                provided,
+            });
+            return false;
+        }
+
+        // Binding again replaces the previous resource
+        self.buffers.retain(|(_, index)| *index != binding);
+        self.images.retain(|(_, index)| *index != binding);
+        self.bound[binding as usize] = true;
+        true
+    }
+
+    fn fail(mut self, err: ComputeBindingError) -> Self {
+        self.error = Some(err);
+        self
+    }
+
+    fn bind_buffer(
+        &mut self,
+        binding: u32,
+        write: DescriptorWrite,
+        buffer: Arc<dyn BufferAccess + Send + Sync>,
+    ) {
+        self.writes.push(write);
+        self.buffers.push((buffer, binding));
+    }
+
+    fn bind_image(
+        &mut self,
+        binding: u32,
+        write: DescriptorWrite,
+        image: Arc<dyn ImageViewAccess + Send + Sync>,
+    ) {
+        self.writes.push(write);
+        self.images.push((image, binding));
+    }
+}
+
+/// Descriptor set built from [ComputeBindings](ComputeBindings).
+/// It lists its resources so the command buffers synchronize the accesses to them.
+pub struct ComputeSet {
+    inner: StdDescriptorPoolAlloc,
+    layout: Arc<UnsafeDescriptorSetLayout>,
+    buffers: Vec<(Arc<dyn BufferAccess + Send + Sync>, u32)>,
+    images: Vec<(Arc<dyn ImageViewAccess + Send + Sync>, u32)>,
+    _samplers: Vec<Arc<Sampler>>,
+}
+
+unsafe impl DescriptorSet for ComputeSet {
+    #[inline]
+    fn inner(&self) -> &UnsafeDescriptorSet {
+        self.inner.inner()
+    }
+
+    #[inline]
+    fn num_buffers(&self) -> usize {
+        self.buffers.len()
+    }
+
+    #[inline]
+    fn buffer(&self, index: usize) -> Option<(&dyn BufferAccess, u32)> {
+        self.buffers
+            .get(index)
+            .map(|(buffer, binding)| (&**buffer as &dyn BufferAccess, *binding))
+    }
+
+    #[inline]
+    fn num_images(&self) -> usize {
+        self.images.len()
+    }
+
+    #[inline]
+    fn image(&self, index: usize) -> Option<(&dyn ImageViewAccess, u32)> {
+        self.images
+            .get(index)
+            .map(|(image, binding)| (&**image as &dyn ImageViewAccess, *binding))
+    }
+}
+
+unsafe impl DescriptorSetDesc for ComputeSet {
+    #[inline]
+    fn num_bindings(&self) -> usize {
+        self.layout.num_bindings()
+    }
+
+    #[inline]
+    fn descriptor(&self, binding: usize) -> Option<DescriptorDesc> {
+        self.layout.descriptor(binding)
+    }
+}
+
+unsafe impl DeviceOwned for ComputeSet {
+    #[inline]
+    fn device(&self) -> &Arc<Device> {
+        self.layout.device()
+    }
+}
@@ -0,0 +1,9 @@
+use crate::math::{Frustum, Mat4};
+use crate::renderer::mesh::MeshBounds;
+
+/// Whether a mesh with the given world matrix can be seen through the frustum.
+pub fn is_visible(frustum: &Frustum, bounds: &MeshBounds, model: &Mat4) -> bool {
+    // The sphere is cheap and rejects most objects, the box is tighter for the rest
+    frustum.intersects_sphere(&bounds.sphere.transform(model))
+        && frustum.intersects_aabb(&bounds.aabb.transform(model))
+}
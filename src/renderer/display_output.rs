@@ -0,0 +1,36 @@
+/// Color space frames are presented in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DisplayOutput {
+    /// 8 bit sRGB, available everywhere
+    #[default]
+    Sdr,
+    /// HDR10, Rec.2020 primaries with the PQ (ST 2084) curve
+    Hdr10,
+    /// Linear extended sRGB in 16 bit floats, 1.0 is 80 nits
+    ScRgb,
+}
+
+impl DisplayOutput {
+    #[inline]
+    pub fn is_hdr(&self) -> bool {
+        *self != DisplayOutput::Sdr
+    }
+}
+
+/// Brightness of HDR output, ignored in SDR.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HdrDisplaySettings {
+    /// Nits of a white surface, what SDR white looks like on the display
+    pub paper_white: f32,
+    /// Nits the brightest highlights are tonemapped to, usually the peak of the display
+    pub max_luminance: f32,
+}
+
+impl Default for HdrDisplaySettings {
+    fn default() -> Self {
+        Self {
+            paper_white: 200.0,
+            max_luminance: 1000.0,
+        }
+    }
+}
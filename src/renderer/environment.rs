@@ -0,0 +1,408 @@
+use crate::renderer::compute::{ComputeProgram, ComputeSet};
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::device::Queue;
+use vulkano::format::Format;
+use vulkano::image::{Dimensions, ImageUsage, ImmutableImage, StorageImage};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::GpuFuture;
+
+pub mod hdr_image;
+mod skybox;
+
+pub use hdr_image::{HdrImage, HdrImageError};
+pub use skybox::{SkyboxDraw, SkyboxPass};
+
+mod equirect_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/equirect_to_cube.comp"
+    }
+}
+
+mod irradiance_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/irradiance.comp"
+    }
+}
+
+mod prefilter_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/prefilter.comp"
+    }
+}
+
+mod brdf_lut_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/brdf_lut.comp"
+    }
+}
+
+/// Roughness levels of the prefiltered specular, evenly spread from 0 to 1.
+/// Same as `PREFILTER_LEVELS` in the PBR shader.
+pub const PREFILTER_LEVELS: usize = 5;
+const ENVIRONMENT_FORMAT: Format = Format::R16G16B16A16Sfloat;
+const BRDF_LUT_SIZE: u32 = 256;
+/// Work group size of the baking shaders.
+const LOCAL_SIZE: [u32; 3] = [8, 8, 1];
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PrefilterPushConstants {
+    roughness: f32,
+    sample_count: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EnvironmentBakeSettings {
+    /// Size of a face of the cubemap drawn as the skybox
+    pub cubemap_size: u32,
+    /// Diffuse lighting is smooth, a small cubemap is enough
+    pub irradiance_size: u32,
+    /// Size of the sharpest level, each rougher one is half the size
+    pub prefiltered_size: u32,
+    /// GGX samples per texel of the prefiltered levels
+    pub prefilter_samples: u32,
+}
+
+impl Default for EnvironmentBakeSettings {
+    fn default() -> Self {
+        Self {
+            cubemap_size: 512,
+            irradiance_size: 32,
+            prefiltered_size: 128,
+            prefilter_samples: 1024,
+        }
+    }
+}
+
+impl EnvironmentBakeSettings {
+    /// Single texel everywhere, for environments that light nothing.
+    fn minimal() -> Self {
+        Self {
+            cubemap_size: 1,
+            irradiance_size: 1,
+            prefiltered_size: 1,
+            prefilter_samples: 1,
+        }
+    }
+}
+
+/// Cubemaps baked from an equirectangular HDR image: the environment itself for the skybox,
+/// its irradiance for diffuse lighting and its prefiltered levels for the specular.
+pub struct Environment {
+    cubemap: Arc<StorageImage<Format>>,
+    irradiance: Arc<StorageImage<Format>>,
+    prefiltered: Vec<Arc<StorageImage<Format>>>,
+    _allocations: Vec<TrackedAllocation>,
+}
+
+impl Environment {
+    #[inline]
+    pub fn cubemap(&self) -> &Arc<StorageImage<Format>> {
+        &self.cubemap
+    }
+
+    #[inline]
+    pub fn irradiance(&self) -> &Arc<StorageImage<Format>> {
+        &self.irradiance
+    }
+
+    /// From smooth to rough, `PREFILTER_LEVELS` of them.
+    #[inline]
+    pub fn prefiltered(&self) -> &[Arc<StorageImage<Format>>] {
+        &self.prefiltered
+    }
+}
+
+/// Bakes environments on the GPU, along with the BRDF lookup table they all share.
+pub struct EnvironmentBaker {
+    queue: Arc<Queue>,
+    memory_tracker: Arc<MemoryTracker>,
+    equirect_program: ComputeProgram,
+    irradiance_program: ComputeProgram,
+    prefilter_program: ComputeProgram,
+    sampler: Arc<Sampler>,
+    brdf_lut: Arc<StorageImage<Format>>,
+    _brdf_lut_allocation: TrackedAllocation,
+}
+
+impl EnvironmentBaker {
+    /// Bakes the BRDF lookup table, waiting for it to finish.
+    pub fn new(queue: &Arc<Queue>, memory_tracker: &Arc<MemoryTracker>) -> Self {
+        let device = queue.device();
+
+        let equirect_shader = equirect_shader::Shader::load(device.clone())
+            .expect("Failed to create compute shader !");
+        let irradiance_shader = irradiance_shader::Shader::load(device.clone())
+            .expect("Failed to create compute shader !");
+        let prefilter_shader = prefilter_shader::Shader::load(device.clone())
+            .expect("Failed to create compute shader !");
+        let brdf_lut_shader = brdf_lut_shader::Shader::load(device.clone())
+            .expect("Failed to create compute shader !");
+
+        let equirect_program =
+            ComputeProgram::new(device, &equirect_shader.main_entry_point(), &(), LOCAL_SIZE);
+        let irradiance_program = ComputeProgram::new(
+            device,
+            &irradiance_shader.main_entry_point(),
+            &(),
+            LOCAL_SIZE,
+        );
+        let prefilter_program = ComputeProgram::new(
+            device,
+            &prefilter_shader.main_entry_point(),
+            &(),
+            LOCAL_SIZE,
+        );
+        let brdf_lut_program =
+            ComputeProgram::new(device, &brdf_lut_shader.main_entry_point(), &(), LOCAL_SIZE);
+
+        // Cubemaps are read in any direction, no seams at the edges of the faces
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .expect("Failed to create environment sampler !");
+
+        let brdf_lut = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+            },
+            ENVIRONMENT_FORMAT,
+            Self::image_usage(),
+            Some(queue.family()),
+        )
+        .expect("Failed to create BRDF lookup table !");
+
+        let set = brdf_lut_program
+            .bindings(0)
+            .storage_image(0, brdf_lut.clone())
+            .build()
+            .expect("Failed to create BRDF lookup table descriptor set !");
+        let builder =
+            AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family())
+                .unwrap();
+        let command_buffer = brdf_lut_program
+            .dispatch(builder, [BRDF_LUT_SIZE, BRDF_LUT_SIZE, 1], set, ())
+            .build()
+            .expect("Failed to build command buffer !");
+        Self::submit(queue, command_buffer);
+
+        Self {
+            queue: queue.clone(),
+            memory_tracker: memory_tracker.clone(),
+            equirect_program,
+            irradiance_program,
+            prefilter_program,
+            sampler,
+            _brdf_lut_allocation: memory_tracker.track_image(MemoryCategory::Texture, &brdf_lut),
+            brdf_lut,
+        }
+    }
+
+    /// Convert the image to cubemaps and convolve them, waiting for the GPU to finish.
+    pub fn bake(&self, image: &HdrImage, settings: &EnvironmentBakeSettings) -> Environment {
+        let device = self.queue.device();
+        let equirect = self.upload(image);
+
+        let mut allocations = Vec::with_capacity(PREFILTER_LEVELS + 2);
+        let mut create_cubemap = |size: u32| {
+            let image = StorageImage::with_usage(
+                device.clone(),
+                Dimensions::Cubemap { size },
+                ENVIRONMENT_FORMAT,
+                Self::image_usage(),
+                Some(self.queue.family()),
+            )
+            .expect("Failed to create environment cubemap !");
+            allocations.push(
+                self.memory_tracker
+                    .track_image(MemoryCategory::Texture, &image),
+            );
+            image
+        };
+
+        let cubemap = create_cubemap(settings.cubemap_size);
+        let irradiance = create_cubemap(settings.irradiance_size);
+        let prefiltered: Vec<_> = (0..PREFILTER_LEVELS)
+            .map(|level| create_cubemap((settings.prefiltered_size >> level).max(1)))
+            .collect();
+
+        let mut builder =
+            AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), self.queue.family())
+                .unwrap();
+
+        let set = self
+            .equirect_program
+            .bindings(0)
+            .sampled_image(0, equirect, self.sampler.clone())
+            .storage_image(1, cubemap.clone())
+            .build()
+            .expect("Failed to create environment descriptor set !");
+        builder = self.equirect_program.dispatch(
+            builder,
+            Self::cube_invocations(settings.cubemap_size),
+            set,
+            (),
+        );
+
+        let set = self.convolution_set(&self.irradiance_program, &cubemap, &irradiance);
+        builder = self.irradiance_program.dispatch(
+            builder,
+            Self::cube_invocations(settings.irradiance_size),
+            set,
+            (),
+        );
+
+        for (level, target) in prefiltered.iter().enumerate() {
+            let roughness = level as f32 / (PREFILTER_LEVELS - 1) as f32;
+            // A perfect mirror only needs the one sample
+            let push_constants = PrefilterPushConstants {
+                roughness,
+                sample_count: if level == 0 {
+                    1
+                } else {
+                    settings.prefilter_samples
+                },
+            };
+            let size = (settings.prefiltered_size >> level).max(1);
+            let set = self.convolution_set(&self.prefilter_program, &cubemap, target);
+            builder = self.prefilter_program.dispatch(
+                builder,
+                Self::cube_invocations(size),
+                set,
+                push_constants,
+            );
+        }
+
+        Self::submit(
+            &self.queue,
+            builder.build().expect("Failed to build command buffer !"),
+        );
+
+        Environment {
+            cubemap,
+            irradiance,
+            prefiltered,
+            _allocations: allocations,
+        }
+    }
+
+    fn convolution_set(
+        &self,
+        program: &ComputeProgram,
+        source: &Arc<StorageImage<Format>>,
+        target: &Arc<StorageImage<Format>>,
+    ) -> Arc<ComputeSet> {
+        program
+            .bindings(0)
+            .sampled_image(0, source.clone(), self.sampler.clone())
+            .storage_image(1, target.clone())
+            .build()
+            .expect("Failed to create environment descriptor set !")
+    }
+
+    /// Half floats are enough for lighting and can always be filtered, unlike floats.
+    fn upload(&self, image: &HdrImage) -> Arc<ImmutableImage<Format>> {
+        let [width, height] = image.dimensions();
+        let (equirect, upload) = ImmutableImage::from_iter(
+            image
+                .pixels()
+                .iter()
+                .map(|&[r, g, b]| [f32_to_f16(r), f32_to_f16(g), f32_to_f16(b), f32_to_f16(1.0)]),
+            Dimensions::Dim2d { width, height },
+            Format::R16G16B16A16Sfloat,
+            self.queue.clone(),
+        )
+        .expect("Failed to create environment image !");
+
+        upload
+            .then_signal_fence_and_flush()
+            .and_then(|fence| fence.wait(None))
+            .expect("Failed to upload environment image !");
+        equirect
+    }
+
+    #[inline]
+    fn image_usage() -> ImageUsage {
+        ImageUsage {
+            storage: true,
+            sampled: true,
+            ..ImageUsage::none()
+        }
+    }
+
+    /// One layer for each face.
+    #[inline]
+    fn cube_invocations(size: u32) -> [u32; 3] {
+        [size, size, 6]
+    }
+
+    fn submit<C>(queue: &Arc<Queue>, command_buffer: C)
+    where
+        C: CommandBuffer + Send + Sync + 'static,
+    {
+        command_buffer
+            .execute(queue.clone())
+            .expect("Failed to execute command buffer !")
+            .then_signal_fence_and_flush()
+            .and_then(|fence| fence.wait(None))
+            .expect("Failed to bake environment !");
+    }
+
+    /// Black everywhere, bound when the scene has no environment.
+    pub fn bake_blank(&self) -> Environment {
+        self.bake(&HdrImage::black(), &EnvironmentBakeSettings::minimal())
+    }
+
+    /// Split sum scale and bias of f0, by NdotV in X and roughness in Y.
+    #[inline]
+    pub fn brdf_lut(&self) -> &Arc<StorageImage<Format>> {
+        &self.brdf_lut
+    }
+
+    /// Linear and clamped, for every environment image.
+    #[inline]
+    pub fn sampler(&self) -> &Arc<Sampler> {
+        &self.sampler
+    }
+}
+
+/// Bits of the closest half float, flushing what is too small to zero and clamping what is
+/// too large to the largest one, light that would turn infinite breaks the convolutions.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    if exponent <= 0 {
+        return sign;
+    }
+    if exponent >= 0x1f {
+        return sign | 0x7bff;
+    }
+
+    let mantissa = bits & 0x7f_ffff;
+    let half = ((exponent as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1);
+    sign | half.min(0x7bff) as u16
+}
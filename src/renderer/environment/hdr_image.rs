@@ -0,0 +1,242 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Larger than the images any device can create, the header must be lying.
+const MAX_DIMENSION: u32 = 16384;
+
+/// Why a Radiance `.hdr` file can't be read.
+#[derive(Debug)]
+pub enum HdrImageError {
+    Io(io::Error),
+    /// Doesn't start with the Radiance magic
+    NotRadiance,
+    /// Only `32-bit_rle_rgbe` is supported, not XYZ
+    UnsupportedFormat(String),
+    /// Only the standard `-Y height +X width` orientation is supported
+    UnsupportedResolution(String),
+    /// Empty or larger than [MAX_DIMENSION] on a side
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    /// The file ends before all the pixels are read
+    Truncated,
+    /// A run length scanline is longer than the image
+    CorruptScanline {
+        row: usize,
+    },
+}
+
+impl fmt::Display for HdrImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HdrImageError::Io(err) => write!(f, "Failed to read HDR image: {}", err),
+            HdrImageError::NotRadiance => write!(f, "Not a Radiance HDR image"),
+            HdrImageError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported HDR pixel format {:?}", format)
+            }
+            HdrImageError::UnsupportedResolution(line) => {
+                write!(f, "Unsupported HDR resolution line {:?}", line)
+            }
+            HdrImageError::InvalidDimensions { width, height } => {
+                write!(f, "Invalid HDR image dimensions {}x{}", width, height)
+            }
+            HdrImageError::Truncated => write!(f, "HDR image is truncated"),
+            HdrImageError::CorruptScanline { row } => {
+                write!(f, "Corrupt run length encoding in HDR scanline {}", row)
+            }
+        }
+    }
+}
+
+impl Error for HdrImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HdrImageError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HdrImageError {
+    fn from(err: io::Error) -> Self {
+        HdrImageError::Io(err)
+    }
+}
+
+/// Linear RGB float pixels of an equirectangular environment, top row first.
+pub struct HdrImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl HdrImage {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, HdrImageError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Decode the content of a Radiance RGBE file, flat or run length encoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, HdrImageError> {
+        let mut cursor = 0;
+        let magic = next_line(bytes, &mut cursor)?;
+        if !magic.starts_with("#?") {
+            return Err(HdrImageError::NotRadiance);
+        }
+
+        // Header variables until an empty line, only the format matters
+        loop {
+            let line = next_line(bytes, &mut cursor)?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(HdrImageError::UnsupportedFormat(format.to_owned()));
+                }
+            }
+        }
+
+        let resolution = next_line(bytes, &mut cursor)?;
+        let (width, height) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => match (width.parse(), height.parse()) {
+                (Ok(width), Ok(height)) => (width, height),
+                _ => return Err(HdrImageError::UnsupportedResolution(resolution)),
+            },
+            _ => return Err(HdrImageError::UnsupportedResolution(resolution)),
+        };
+
+        let invalid = |size: u32| size == 0 || size > MAX_DIMENSION;
+        let pixel_count = (width as usize).checked_mul(height as usize);
+        let pixel_count = match pixel_count {
+            Some(pixel_count) if !invalid(width) && !invalid(height) => pixel_count,
+            _ => return Err(HdrImageError::InvalidDimensions { width, height }),
+        };
+
+        // Run length encoded files hold more pixels than bytes, the vector then grows, but
+        // a truncated file can't make us allocate much more than its size
+        let mut pixels = Vec::with_capacity(pixel_count.min(bytes.len() - cursor));
+        let mut scanline = vec![[0u8; 4]; width as usize];
+        for row in 0..height as usize {
+            read_scanline(bytes, &mut cursor, &mut scanline, row)?;
+            pixels.extend(scanline.iter().map(rgbe_to_rgb));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> [u32; 2] {
+        [self.width, self.height]
+    }
+
+    #[inline]
+    pub fn pixels(&self) -> &[[f32; 3]] {
+        &self.pixels
+    }
+
+    /// Black image of a single pixel, lights nothing.
+    pub fn black() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            pixels: vec![[0.0; 3]],
+        }
+    }
+}
+
+fn next_line(bytes: &[u8], cursor: &mut usize) -> Result<String, HdrImageError> {
+    let rest = &bytes[*cursor..];
+    let end = rest
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .ok_or(HdrImageError::Truncated)?;
+    *cursor += end + 1;
+    Ok(String::from_utf8_lossy(&rest[..end]).trim().to_owned())
+}
+
+fn read_scanline(
+    bytes: &[u8],
+    cursor: &mut usize,
+    scanline: &mut [[u8; 4]],
+    row: usize,
+) -> Result<(), HdrImageError> {
+    let width = scanline.len();
+    let header = bytes
+        .get(*cursor..*cursor + 4)
+        .ok_or(HdrImageError::Truncated)?;
+
+    // Run length encoded scanlines start with 2, 2 and their width, each channel separately
+    let encoded = (8..0x8000).contains(&width)
+        && header[0] == 2
+        && header[1] == 2
+        && ((header[2] as usize) << 8 | header[3] as usize) == width;
+    if !encoded {
+        for pixel in scanline.iter_mut() {
+            let rgbe = bytes
+                .get(*cursor..*cursor + 4)
+                .ok_or(HdrImageError::Truncated)?;
+            pixel.copy_from_slice(rgbe);
+            *cursor += 4;
+        }
+        return Ok(());
+    }
+
+    *cursor += 4;
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *bytes.get(*cursor).ok_or(HdrImageError::Truncated)? as usize;
+            *cursor += 1;
+
+            // Above 128 is a run of the same value, otherwise that many literal values
+            if count > 128 {
+                let count = count - 128;
+                if x + count > width {
+                    return Err(HdrImageError::CorruptScanline { row });
+                }
+                let value = *bytes.get(*cursor).ok_or(HdrImageError::Truncated)?;
+                *cursor += 1;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(HdrImageError::CorruptScanline { row });
+                }
+                let values = bytes
+                    .get(*cursor..*cursor + count)
+                    .ok_or(HdrImageError::Truncated)?;
+                *cursor += count;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shared exponent, a zero one is black.
+#[inline]
+fn rgbe_to_rgb(rgbe: &[u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    let scale = 2f32.powi(rgbe[3] as i32 - 136);
+    [
+        rgbe[0] as f32 * scale,
+        rgbe[1] as f32 * scale,
+        rgbe[2] as f32 * scale,
+    ]
+}
@@ -0,0 +1,148 @@
+use crate::math::Mat4;
+use crate::renderer::environment::Environment;
+use crate::renderer::fullscreen;
+use crate::renderer::fullscreen::FullscreenPipeline;
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use crate::scene::CameraView;
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::depth_stencil::{Compare, DepthBounds, DepthStencil, Stencil};
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/skybox.vert"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/skybox.frag"
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PushConstants {
+    inverse_view_projection: [[f32; 4]; 4],
+    intensity: f32,
+}
+
+/// What the skybox of a frame needs, prepared before recording.
+pub struct SkyboxDraw {
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    push_constants: PushConstants,
+}
+
+/// Draws the environment cubemap where nothing else was drawn.
+pub struct SkyboxPass {
+    pipeline: Arc<FullscreenPipeline>,
+}
+
+impl SkyboxPass {
+    /// Has to be recreated with the HDR render pass.
+    pub fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        // On the far plane, only passes where the depth is still cleared
+        let depth_stencil = DepthStencil {
+            depth_write: false,
+            depth_compare: Compare::LessOrEqual,
+            depth_bounds_test: DepthBounds::Disabled,
+            stencil_front: Stencil::default(),
+            stencil_back: Stencil::default(),
+        };
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create skybox pipeline layout !");
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .depth_stencil(depth_stencil)
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create skybox pipeline !"),
+        );
+
+        Self { pipeline }
+    }
+
+    pub fn prepare(
+        &self,
+        environment: &Environment,
+        sampler: &Arc<Sampler>,
+        camera_view: &CameraView,
+        intensity: f32,
+    ) -> SkyboxDraw {
+        let layout = self.pipeline.descriptor_set_layout(0).unwrap().clone();
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_sampled_image(environment.cubemap().clone(), sampler.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create skybox descriptor set !"),
+        );
+
+        // Only the rotation of the camera
+        let mut view: [[f32; 4]; 4] = camera_view.view.into();
+        view[3] = [0.0, 0.0, 0.0, 1.0];
+        let inverse_view_projection = (camera_view.projection * Mat4::from(view)).inverse();
+
+        SkyboxDraw {
+            descriptor_set,
+            push_constants: PushConstants {
+                inverse_view_projection: inverse_view_projection.into(),
+                intensity,
+            },
+        }
+    }
+
+    /// Has to be inside the HDR render pass, after the opaque geometry.
+    pub fn record(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        draw: &SkyboxDraw,
+        dimensions: [u32; 2],
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        };
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &dynamic_state,
+                fullscreen::triangle(),
+                draw.descriptor_set.clone(),
+                draw.push_constants,
+            )
+            .expect("Failed to record skybox !")
+    }
+}
@@ -0,0 +1,246 @@
+use crate::renderer::memory_tracker::{
+    MemoryCategory, MemoryLocation, MemoryTracker, TrackedAllocation,
+};
+use crate::renderer::FRAMES_IN_FLIGHT;
+use log::{trace, warn};
+use std::mem;
+use std::slice;
+use std::sync::Arc;
+use vulkano::buffer::{BufferSlice, BufferUsage, CpuAccessibleBuffer, TypedBufferAccess};
+use vulkano::device::Device;
+
+/// Size of the buffer of each frame, grows if a frame needs more.
+pub const DEFAULT_FRAME_CAPACITY: usize = 4 * 1024 * 1024;
+
+/// Enough for any vertex attribute format.
+const VERTEX_ALIGNMENT: usize = 16;
+
+type FrameBuffer = Arc<CpuAccessibleBuffer<[u8]>>;
+
+/// Sub-allocates the uniform, storage and per instance vertex data of a frame from one big host visible buffer
+/// per frame in flight, instead of creating a buffer for each update.
+///
+/// The buffer of a frame is only reused once the fence of the frame that last used it has
+/// signaled, see [begin_frame](FrameAllocator::begin_frame).
+///
+/// Uniform and storage allocations are either bound through a set pointing at their
+/// [slice](FrameAllocation::slice), or through a dynamic buffer descriptor pointing at the
+/// [start of the buffer](FrameAllocation::dynamic_slice), bound with their
+/// [dynamic offset](FrameAllocation::dynamic_offset). The second way needs a single set for all
+/// the allocations of a buffer.
+pub struct FrameAllocator {
+    device: Arc<Device>,
+    memory_tracker: Arc<MemoryTracker>,
+    arenas: Vec<Arena>,
+    current: usize,
+
+    uniform_alignment: usize,
+    storage_alignment: usize,
+}
+
+struct Arena {
+    /// Usually only one, more are added when the frame runs out of space
+    chunks: Vec<Chunk>,
+    chunk: usize,
+    head: usize,
+}
+
+struct Chunk {
+    buffer: FrameBuffer,
+    _allocation: TrackedAllocation,
+}
+
+/// A range of a frame buffer, only valid until the same frame comes around again.
+#[derive(Clone)]
+pub struct FrameAllocation {
+    buffer: FrameBuffer,
+    offset: usize,
+    size: usize,
+}
+
+impl FrameAllocation {
+    #[inline]
+    pub fn buffer(&self) -> &FrameBuffer {
+        &self.buffer
+    }
+
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Offset to give when binding a dynamic uniform or storage buffer descriptor
+    /// that points to the [start of the buffer](FrameAllocation::dynamic_slice).
+    #[inline]
+    pub fn dynamic_offset(&self) -> u32 {
+        self.offset as u32
+    }
+
+    /// The start of the buffer with the size of the allocation, for a dynamic descriptor.
+    /// The same descriptor works for every allocation of this size from the same buffer.
+    pub fn dynamic_slice(&self) -> BufferSlice<[u8], FrameBuffer> {
+        BufferSlice::from_typed_buffer_access(self.buffer.clone())
+            .slice(0..self.size)
+            .unwrap()
+    }
+
+    /// The allocation as a buffer slice, for descriptors without dynamic offset or to bind as
+    /// vertex buffer.
+    pub fn slice(&self) -> BufferSlice<[u8], FrameBuffer> {
+        BufferSlice::from_typed_buffer_access(self.buffer.clone())
+            .slice(self.offset..self.offset + self.size)
+            .unwrap()
+    }
+}
+
+impl FrameAllocator {
+    pub fn new(device: &Arc<Device>, memory_tracker: &Arc<MemoryTracker>, capacity: usize) -> Self {
+        let limits = device.physical_device().limits();
+        let uniform_alignment = limits.min_uniform_buffer_offset_alignment() as usize;
+        let storage_alignment = limits.min_storage_buffer_offset_alignment() as usize;
+        trace!(
+            "Frame allocator alignments: uniform {}, storage {}",
+            uniform_alignment,
+            storage_alignment
+        );
+
+        let arenas = (0..FRAMES_IN_FLIGHT)
+            .map(|_| Arena {
+                chunks: vec![Self::create_chunk(device, memory_tracker, capacity)],
+                chunk: 0,
+                head: 0,
+            })
+            .collect();
+
+        Self {
+            device: device.clone(),
+            memory_tracker: memory_tracker.clone(),
+            arenas,
+            current: 0,
+            uniform_alignment,
+            storage_alignment,
+        }
+    }
+
+    /// Start allocating for the given frame, dropping everything it allocated last time.
+    /// The fence of the last submission of this frame must have signaled.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.current = frame;
+        let arena = &mut self.arenas[frame];
+
+        // The frame overflowed last time, replace the chunks by one big enough for all of them
+        if arena.chunks.len() > 1 {
+            let capacity = arena.chunks.iter().map(|chunk| chunk.buffer.len()).sum();
+            warn!(
+                "Frame allocator ran out of space, growing to {} bytes",
+                capacity
+            );
+            // Drop the old ones first, so they don't count against the budget
+            arena.chunks.clear();
+            arena.chunks.push(Self::create_chunk(
+                &self.device,
+                &self.memory_tracker,
+                capacity,
+            ));
+        }
+
+        arena.chunk = 0;
+        arena.head = 0;
+    }
+
+    /// Copy a value to a range suitable for a uniform buffer.
+    pub fn allocate_uniform<T: Copy + 'static>(&mut self, data: &T) -> FrameAllocation {
+        self.allocate(as_bytes(slice::from_ref(data)), self.uniform_alignment)
+    }
+
+    /// Copy an array to a range suitable for a storage buffer.
+    pub fn allocate_storage<T: Copy + 'static>(&mut self, data: &[T]) -> FrameAllocation {
+        self.allocate(as_bytes(data), self.storage_alignment)
+    }
+
+    /// Copy an array to a range usable as a vertex buffer, for per instance data.
+    pub fn allocate_vertices<T: Copy + 'static>(&mut self, data: &[T]) -> FrameAllocation {
+        self.allocate(as_bytes(data), VERTEX_ALIGNMENT)
+    }
+
+    fn allocate(&mut self, bytes: &[u8], alignment: usize) -> FrameAllocation {
+        let size = bytes.len().max(1);
+        let arena = &mut self.arenas[self.current];
+
+        let mut offset = align_up(arena.head, alignment);
+        if offset + size > arena.chunks[arena.chunk].buffer.len() {
+            // Move on to a fresh chunk, big enough for this allocation at least
+            arena.chunk += 1;
+            if arena.chunk == arena.chunks.len() {
+                let capacity = arena.chunks[0].buffer.len().max(size);
+                arena.chunks.push(Self::create_chunk(
+                    &self.device,
+                    &self.memory_tracker,
+                    capacity,
+                ));
+            }
+            offset = 0;
+        }
+        arena.head = offset + size;
+
+        let buffer = arena.chunks[arena.chunk].buffer.clone();
+        {
+            let mut content = buffer
+                .write()
+                .expect("Frame buffer still in use by the GPU !");
+            content[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        FrameAllocation {
+            buffer,
+            offset,
+            size,
+        }
+    }
+
+    fn create_chunk(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        capacity: usize,
+    ) -> Chunk {
+        let buffer = unsafe {
+            CpuAccessibleBuffer::uninitialized_array(
+                device.clone(),
+                capacity,
+                BufferUsage {
+                    uniform_buffer: true,
+                    storage_buffer: true,
+                    vertex_buffer: true,
+                    ..BufferUsage::none()
+                },
+                false,
+            )
+            .expect("Failed to create frame buffer !")
+        };
+
+        Chunk {
+            _allocation: memory_tracker.track_buffer(
+                MemoryCategory::Uniform,
+                MemoryLocation::HostVisible,
+                &buffer,
+            ),
+            buffer,
+        }
+    }
+}
+
+#[inline]
+fn align_up(offset: usize, alignment: usize) -> usize {
+    // Alignments are always powers of two
+    (offset + alignment - 1) & !(alignment - 1)
+}
+
+#[inline]
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) }
+}
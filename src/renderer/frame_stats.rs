@@ -0,0 +1,50 @@
+use log::trace;
+use std::fmt;
+
+/// Counters of what was submitted during a frame.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Objects with a mesh in the scene
+    pub objects: usize,
+    /// Objects dropped because they are outside of the camera's frustum
+    pub culled: usize,
+    /// Visible objects, drawn in instanced batches
+    pub instances: usize,
+    pub draw_calls: usize,
+    /// Drawn in batches sharing a texture, their draws count in `draw_calls`
+    pub sprites: usize,
+    /// Drawn in batches sharing a space and rendering, their draws count in `draw_calls`
+    pub glyphs: usize,
+    /// Simulated on the GPU, each draws its particles in one indirect draw call
+    pub particle_emitters: usize,
+    /// Draws into the shadow maps, their culling isn't counted above
+    pub shadow_draw_calls: usize,
+}
+
+impl FrameStats {
+    #[inline]
+    pub fn visible(&self) -> usize {
+        self.objects - self.culled
+    }
+
+    pub fn log(&self) {
+        trace!("{}", self);
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} objects, {} culled, {} instances, {} sprites, {} glyphs and {} particle emitter(s) in {} draw call(s), {} shadow draw call(s)",
+            self.objects,
+            self.culled,
+            self.instances,
+            self.sprites,
+            self.glyphs,
+            self.particle_emitters,
+            self.draw_calls,
+            self.shadow_draw_calls
+        )
+    }
+}
@@ -0,0 +1,41 @@
+use crate::renderer::shader_reflection::MaterialLayout;
+use std::sync::Arc;
+use vulkano::descriptor::pipeline_layout::PipelineLayout;
+use vulkano::framebuffer::RenderPassAbstract;
+use vulkano::pipeline::vertex::{BufferlessDefinition, BufferlessVertices};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::GraphicsPipeline;
+
+pub mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/fullscreen.vert"
+    }
+}
+
+/// Pipeline of a pass drawing a single triangle over its viewport, bufferless draws
+/// need the concrete vertex definition.
+pub type FullscreenPipeline = GraphicsPipeline<
+    BufferlessDefinition,
+    Arc<PipelineLayout<MaterialLayout>>,
+    Arc<dyn RenderPassAbstract + Send + Sync>,
+>;
+
+/// Vertices of the triangle, generated by the vertex shader.
+#[inline]
+pub fn triangle() -> BufferlessVertices {
+    BufferlessVertices {
+        vertices: 3,
+        instances: 1,
+    }
+}
+
+/// Viewport covering a whole target.
+#[inline]
+pub fn viewport(dimensions: [u32; 2]) -> Viewport {
+    Viewport {
+        origin: [0.0, 0.0],
+        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+        depth_range: 0.0..1.0,
+    }
+}
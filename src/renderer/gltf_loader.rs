@@ -0,0 +1,614 @@
+use crate::math::{Mat4, Vec2, Vec3, Vec4};
+use crate::renderer::material::{MaterialId, PbrMaterial};
+use crate::renderer::memory_tracker::MemoryTracker;
+use crate::renderer::mesh::{generate_tangents, Mesh, MorphTarget, SkinnedVertex, Vertex};
+use crate::renderer::texture::Texture;
+use crate::scene::{
+    AnimationClip, Channel, ChannelProperty, Interpolation, Joint, MeshRenderer, NodeId, Scene,
+    Skin, SkinnedMeshRenderer, Transform,
+};
+use gltf::animation::util::ReadOutputs;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use log::warn;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::device::Queue;
+
+/// Why a glTF file can't be loaded.
+#[derive(Debug)]
+pub enum GltfError {
+    /// Reading or parsing the file or its buffers and images
+    Gltf(gltf::Error),
+    /// Every primitive needs positions
+    MissingPositions { mesh: String },
+    /// Only triangle lists are drawn
+    UnsupportedMode { mesh: String, mode: Mode },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfError::Gltf(err) => write!(f, "Failed to read glTF: {}", err),
+            GltfError::MissingPositions { mesh } => {
+                write!(
+                    f,
+                    "A primitive of the glTF mesh {:?} has no positions",
+                    mesh
+                )
+            }
+            GltfError::UnsupportedMode { mesh, mode } => write!(
+                f,
+                "A primitive of the glTF mesh {:?} is made of {:?}, only triangles are supported",
+                mesh, mode
+            ),
+        }
+    }
+}
+
+impl Error for GltfError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GltfError::Gltf(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfError {
+    fn from(err: gltf::Error) -> Self {
+        GltfError::Gltf(err)
+    }
+}
+
+/// Part of a mesh with its own material.
+pub struct GltfPrimitive {
+    /// Made of [SkinnedVertex] when the mesh is skinned, [Vertex] otherwise
+    pub mesh: Arc<Mesh>,
+    pub material: MaterialId,
+}
+
+/// A node of the file with a mesh.
+pub struct GltfMesh {
+    pub name: String,
+    /// World transform in the file, the skin places skinned meshes instead
+    pub transform: Mat4,
+    pub primitives: Vec<GltfPrimitive>,
+    /// Index in the skins of the model
+    pub skin: Option<usize>,
+    /// Of the morph targets when not animated
+    pub morph_weights: Vec<f32>,
+}
+
+/// A skin with the animations of the file retargeted to its joints.
+/// Meshes with morph targets but no skin get one with a single joint, to go through the
+/// skinned pipeline.
+pub struct GltfSkin {
+    pub skin: Arc<Skin>,
+    /// Only the animations moving some of its joints or the morph targets of its meshes.
+    /// The morph weights are applied to every mesh playing the clip.
+    pub clips: Vec<Arc<AnimationClip>>,
+}
+
+impl GltfSkin {
+    pub fn clip(&self, name: &str) -> Option<&Arc<AnimationClip>> {
+        self.clips.iter().find(|clip| clip.name() == name)
+    }
+}
+
+/// Meshes, materials, skins and animations of a glTF file, uploaded to the GPU.
+///
+/// Cameras and lights are ignored, morph targets are unnamed as the names are in the extras.
+pub struct GltfModel {
+    pub meshes: Vec<GltfMesh>,
+    pub skins: Vec<GltfSkin>,
+}
+
+impl GltfModel {
+    /// Load a `.gltf` or `.glb` file along with its buffers and images.
+    /// Materials are created through the given function, in the order of the file.
+    pub fn load<P, F>(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        path: P,
+        mut create_material: F,
+    ) -> Result<Self, GltfError>
+    where
+        P: AsRef<Path>,
+        F: FnMut(PbrMaterial) -> MaterialId,
+    {
+        let (document, buffers, images) = gltf::import(path)?;
+
+        let mut textures = TextureCache {
+            queue,
+            memory_tracker,
+            images: &images,
+            textures: HashMap::new(),
+        };
+        let materials: Vec<MaterialId> = document
+            .materials()
+            .map(|material| create_material(load_material(&material, &mut textures)))
+            .collect();
+
+        // The world transforms of the nodes, and their parents
+        let mut parents = HashMap::new();
+        let mut world_matrices = HashMap::new();
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next());
+        let mut stack: Vec<(gltf::Node, Mat4)> = scene
+            .iter()
+            .flat_map(|scene| scene.nodes())
+            .map(|node| (node, Mat4::IDENTITY))
+            .collect();
+        while let Some((node, parent_matrix)) = stack.pop() {
+            let world = parent_matrix * Mat4::from(node.transform().matrix());
+            world_matrices.insert(node.index(), world);
+            for child in node.children() {
+                parents.insert(child.index(), node.index());
+                stack.push((child, world));
+            }
+        }
+
+        let mut skins: Vec<GltfSkin> = document
+            .skins()
+            .map(|skin| {
+                let joint_nodes: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+                let joints = skin
+                    .joints()
+                    .map(|joint| Joint {
+                        name: joint.name().unwrap_or_default().to_owned(),
+                        parent: parents
+                            .get(&joint.index())
+                            .and_then(|parent| joint_nodes.iter().position(|node| node == parent)),
+                        rest: Transform::from_matrix(&Mat4::from(joint.transform().matrix())),
+                    })
+                    .collect::<Vec<_>>();
+
+                let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+                let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+                    Some(matrices) => matrices.map(Mat4::from).collect(),
+                    None => vec![Mat4::IDENTITY; joints.len()],
+                };
+
+                // What is above the root joints, usually an armature node
+                let mut roots = joint_nodes
+                    .iter()
+                    .zip(&joints)
+                    .filter(|(_, joint)| joint.parent.is_none())
+                    .map(|(node, _)| {
+                        parents
+                            .get(node)
+                            .and_then(|parent| world_matrices.get(parent))
+                            .copied()
+                            .unwrap_or(Mat4::IDENTITY)
+                    });
+                let root = roots.next().unwrap_or(Mat4::IDENTITY);
+                if roots.any(|other| other != root) {
+                    warn!(
+                        "The root joints of the glTF skin {:?} have different parents, using the first one",
+                        skin.name().unwrap_or_default()
+                    );
+                }
+
+                GltfSkin {
+                    skin: Arc::new(Skin::new(joints, inverse_bind_matrices, root)),
+                    clips: Vec::new(),
+                }
+            })
+            .collect();
+
+        // Skin of each node with a mesh, morphed meshes need one to be deformed
+        let mut node_skins = HashMap::new();
+        for node in document.nodes() {
+            let mesh = match node.mesh() {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            if let Some(skin) = node.skin() {
+                node_skins.insert(node.index(), skin.index());
+            } else if mesh
+                .primitives()
+                .any(|primitive| primitive.morph_targets().next().is_some())
+            {
+                let joint = Joint {
+                    name: node.name().unwrap_or_default().to_owned(),
+                    parent: None,
+                    rest: Transform::IDENTITY,
+                };
+                let world = world_matrices
+                    .get(&node.index())
+                    .copied()
+                    .unwrap_or(Mat4::IDENTITY);
+                node_skins.insert(node.index(), skins.len());
+                skins.push(GltfSkin {
+                    skin: Arc::new(Skin::new(vec![joint], vec![Mat4::IDENTITY], world)),
+                    clips: Vec::new(),
+                });
+            }
+        }
+
+        for animation in document.animations() {
+            let name = animation.name().unwrap_or_default();
+            let mut channels: Vec<Vec<Channel>> = vec![Vec::new(); skins.len()];
+
+            for channel in animation.channels() {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let times: Vec<f32> = match reader.read_inputs() {
+                    Some(times) => times.collect(),
+                    None => continue,
+                };
+                let interpolation = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::Step => Interpolation::Step,
+                    gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                    gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                };
+                let target = channel.target().node().index();
+
+                let (property, values): (_, Vec<Vec4>) = match reader.read_outputs() {
+                    Some(ReadOutputs::Translations(values)) => (
+                        ChannelProperty::Translation,
+                        values.map(|value| Vec3::from(value).extend(0.0)).collect(),
+                    ),
+                    Some(ReadOutputs::Rotations(values)) => (
+                        ChannelProperty::Rotation,
+                        values.into_f32().map(Vec4::from).collect(),
+                    ),
+                    Some(ReadOutputs::Scales(values)) => (
+                        ChannelProperty::Scale,
+                        values.map(|value| Vec3::from(value).extend(0.0)).collect(),
+                    ),
+                    Some(ReadOutputs::MorphTargetWeights(weights)) => {
+                        if let Some(skin) = node_skins.get(&target) {
+                            channels[*skin].extend(morph_weight_channels(
+                                interpolation,
+                                &times,
+                                &weights.into_f32().collect::<Vec<_>>(),
+                            ));
+                        }
+                        continue;
+                    }
+                    None => continue,
+                };
+
+                // Retargeted to every skin the node is a joint of
+                for (skin, gltf_skin) in document.skins().enumerate() {
+                    if let Some(joint) =
+                        gltf_skin.joints().position(|joint| joint.index() == target)
+                    {
+                        channels[skin].push(Channel {
+                            joint,
+                            property,
+                            interpolation,
+                            times: times.clone(),
+                            values: values.clone(),
+                        });
+                    }
+                }
+            }
+
+            for (skin, channels) in skins.iter_mut().zip(channels) {
+                if !channels.is_empty() {
+                    skin.clips
+                        .push(Arc::new(AnimationClip::new(name, channels)));
+                }
+            }
+        }
+
+        let mut meshes = Vec::new();
+        for node in document.nodes() {
+            let mesh = match node.mesh() {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            let name = mesh.name().unwrap_or_default().to_owned();
+            let skin = node_skins.get(&node.index()).copied();
+
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != Mode::Triangles {
+                    return Err(GltfError::UnsupportedMode {
+                        mesh: name,
+                        mode: primitive.mode(),
+                    });
+                }
+
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                    Some(positions) => positions.collect(),
+                    None => return Err(GltfError::MissingPositions { mesh: name }),
+                };
+                let normals: Vec<[f32; 3]> = match reader.read_normals() {
+                    Some(normals) => normals.collect(),
+                    None => vec![[0.0, 1.0, 0.0]; positions.len()],
+                };
+                let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                    Some(uvs) => uvs.into_f32().collect(),
+                    None => vec![[0.0, 0.0]; positions.len()],
+                };
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+
+                let mut vertices: Vec<Vertex> = positions
+                    .iter()
+                    .zip(&normals)
+                    .zip(&uvs)
+                    .map(|((position, normal), uv)| {
+                        Vertex::new(Vec3::from(*position), Vec3::from(*normal), Vec2::from(*uv))
+                    })
+                    .collect();
+                match reader.read_tangents() {
+                    Some(tangents) => {
+                        for (vertex, tangent) in vertices.iter_mut().zip(tangents) {
+                            vertex.tangent = tangent;
+                        }
+                    }
+                    None => generate_tangents(&mut vertices, &indices),
+                }
+
+                let morph_targets: Vec<MorphTarget> = reader
+                    .read_morph_targets()
+                    .map(|(positions, normals, _)| MorphTarget {
+                        name: String::new(),
+                        positions: match positions {
+                            Some(positions) => positions.map(Vec3::from).collect(),
+                            None => vec![Vec3::ZERO; vertices.len()],
+                        },
+                        normals: match normals {
+                            Some(normals) => normals.map(Vec3::from).collect(),
+                            None => Vec::new(),
+                        },
+                    })
+                    .collect();
+
+                let mesh = match skin {
+                    Some(_) => {
+                        // Without joints, everything follows the first one
+                        let joints: Vec<[u32; 4]> = match reader.read_joints(0) {
+                            Some(joints) => joints
+                                .into_u16()
+                                .map(|joints| {
+                                    [
+                                        joints[0] as u32,
+                                        joints[1] as u32,
+                                        joints[2] as u32,
+                                        joints[3] as u32,
+                                    ]
+                                })
+                                .collect(),
+                            None => vec![[0; 4]; vertices.len()],
+                        };
+                        let weights: Vec<[f32; 4]> = match reader.read_weights(0) {
+                            Some(weights) => weights.into_f32().map(normalize_weights).collect(),
+                            None => vec![[1.0, 0.0, 0.0, 0.0]; vertices.len()],
+                        };
+                        let vertices: Vec<SkinnedVertex> = vertices
+                            .iter()
+                            .zip(joints)
+                            .zip(weights)
+                            .map(|((vertex, joints), weights)| {
+                                SkinnedVertex::new(*vertex, joints, weights)
+                            })
+                            .collect();
+                        Mesh::new(queue.device(), memory_tracker, &vertices, Some(&indices))
+                            .with_morph_targets(queue.device(), memory_tracker, &morph_targets)
+                    }
+                    None => Mesh::new(queue.device(), memory_tracker, &vertices, Some(&indices)),
+                };
+
+                primitives.push(GltfPrimitive {
+                    mesh: Arc::new(mesh),
+                    material: primitive
+                        .material()
+                        .index()
+                        .map_or(MaterialId::DEFAULT, |index| materials[index]),
+                });
+            }
+
+            meshes.push(GltfMesh {
+                name,
+                transform: world_matrices
+                    .get(&node.index())
+                    .copied()
+                    .unwrap_or(Mat4::IDENTITY),
+                primitives,
+                skin,
+                morph_weights: node
+                    .weights()
+                    .or_else(|| mesh.weights())
+                    .unwrap_or_default()
+                    .to_vec(),
+            });
+        }
+
+        Ok(Self { meshes, skins })
+    }
+
+    /// Add a node for each primitive under a new root node, the skinned ones get a player
+    /// each.
+    pub fn add_to_scene(&self, scene: &mut Scene, name: &str, transform: Transform) -> NodeId {
+        let root = scene.graph_mut().add_node(name, transform);
+
+        for mesh in &self.meshes {
+            let local = match mesh.skin {
+                Some(_) => Transform::IDENTITY,
+                None => Transform::from_matrix(&mesh.transform),
+            };
+
+            for primitive in &mesh.primitives {
+                let node = scene
+                    .graph_mut()
+                    .add_child(root, &mesh.name, local)
+                    .unwrap();
+                match mesh.skin {
+                    Some(skin) => scene.set_skinned_mesh(
+                        node,
+                        SkinnedMeshRenderer::new(
+                            primitive.mesh.clone(),
+                            self.skins[skin].skin.clone(),
+                        )
+                        .with_material(primitive.material)
+                        .with_morph_weights(mesh.morph_weights.clone()),
+                    ),
+                    None => scene.set_renderable(
+                        node,
+                        MeshRenderer::new(primitive.mesh.clone()).with_material(primitive.material),
+                    ),
+                }
+            }
+        }
+
+        root
+    }
+}
+
+/// Split the weights of every morph target of a mesh into channels of 4 targets.
+fn morph_weight_channels(
+    interpolation: Interpolation,
+    times: &[f32],
+    weights: &[f32],
+) -> Vec<Channel> {
+    let values_per_key = match interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    let target_count = weights.len() / (times.len() * values_per_key).max(1);
+    if target_count == 0 {
+        return Vec::new();
+    }
+
+    (0..target_count)
+        .step_by(4)
+        .map(|first| Channel {
+            joint: 0,
+            property: ChannelProperty::MorphWeights { first },
+            interpolation,
+            times: times.to_vec(),
+            values: weights
+                .chunks_exact(target_count)
+                .map(|key_weights| {
+                    let mut value = [0.0; 4];
+                    for (value, weight) in value.iter_mut().zip(&key_weights[first..]) {
+                        *value = *weight;
+                    }
+                    Vec4::from(value)
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Some exporters don't normalize the weights, the shader expects them to sum to 1.
+fn normalize_weights(weights: [f32; 4]) -> [f32; 4] {
+    let sum: f32 = weights.iter().sum();
+    if sum <= f32::EPSILON {
+        return [1.0, 0.0, 0.0, 0.0];
+    }
+    [
+        weights[0] / sum,
+        weights[1] / sum,
+        weights[2] / sum,
+        weights[3] / sum,
+    ]
+}
+
+fn load_material(material: &gltf::Material, textures: &mut TextureCache) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    PbrMaterial {
+        base_color: Vec4::from(pbr.base_color_factor()),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: Vec3::from(material.emissive_factor()),
+        normal_scale: material
+            .normal_texture()
+            .map_or(1.0, |texture| texture.scale()),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map_or(1.0, |texture| texture.strength()),
+        base_color_texture: pbr
+            .base_color_texture()
+            .and_then(|info| textures.get(&info.texture(), true)),
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .and_then(|info| textures.get(&info.texture(), false)),
+        normal_texture: material
+            .normal_texture()
+            .and_then(|texture| textures.get(&texture.texture(), false)),
+        occlusion_texture: material
+            .occlusion_texture()
+            .and_then(|texture| textures.get(&texture.texture(), false)),
+        emissive_texture: material
+            .emissive_texture()
+            .and_then(|info| textures.get(&info.texture(), true)),
+    }
+}
+
+/// Uploads each image once per color space, materials often share them.
+struct TextureCache<'a> {
+    queue: &'a Arc<Queue>,
+    memory_tracker: &'a Arc<MemoryTracker>,
+    images: &'a [gltf::image::Data],
+    textures: HashMap<(usize, bool), Arc<Texture>>,
+}
+
+impl TextureCache<'_> {
+    fn get(&mut self, texture: &gltf::Texture, srgb: bool) -> Option<Arc<Texture>> {
+        let index = texture.source().index();
+        if let Some(texture) = self.textures.get(&(index, srgb)) {
+            return Some(texture.clone());
+        }
+
+        let image = &self.images[index];
+        let pixels = match to_rgba8(image) {
+            Some(pixels) => pixels,
+            None => {
+                warn!(
+                    "Unsupported glTF image format {:?}, the texture is ignored",
+                    image.format
+                );
+                return None;
+            }
+        };
+        let texture = Arc::new(Texture::from_rgba8(
+            self.queue,
+            self.memory_tracker,
+            [image.width, image.height],
+            &pixels,
+            srgb,
+        ));
+        self.textures.insert((index, srgb), texture.clone());
+        Some(texture)
+    }
+}
+
+/// 16 bit images aren't supported.
+fn to_rgba8(image: &gltf::image::Data) -> Option<Vec<u8>> {
+    let pixels = &image.pixels;
+    let rgba = match image.format {
+        Format::R8 => pixels.iter().flat_map(|r| vec![*r, *r, *r, 255]).collect(),
+        Format::R8G8 => pixels
+            .chunks_exact(2)
+            .flat_map(|p| vec![p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8G8B8 => pixels
+            .chunks_exact(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        Format::R8G8B8A8 => pixels.clone(),
+        Format::B8G8R8 => pixels
+            .chunks_exact(3)
+            .flat_map(|p| vec![p[2], p[1], p[0], 255])
+            .collect(),
+        Format::B8G8R8A8 => pixels
+            .chunks_exact(4)
+            .flat_map(|p| vec![p[2], p[1], p[0], p[3]])
+            .collect(),
+        _ => return None,
+    };
+    Some(rgba)
+}
@@ -0,0 +1,552 @@
+use crate::renderer::memory_tracker::{
+    MemoryCategory, MemoryLocation, MemoryTracker, TrackedAllocation,
+};
+use crate::renderer::raw_commands::RawCommands;
+use crate::renderer::FRAMES_IN_FLIGHT;
+use log::{info, warn};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::CStr;
+use std::mem;
+use std::sync::Arc;
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::pool::standard::StandardCommandPoolBuilder;
+use vulkano::command_buffer::sys::UnsafeCommandBufferBuilder;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::device::Device;
+use vulkano::instance::loader;
+use vulkano::instance::QueueFamily;
+use vulkano::query::{QueryType, UnsafeQueriesRange, UnsafeQueryPool};
+use vulkano::sync::PipelineStages;
+use vulkano::VulkanObject;
+
+/// Amount of frames between the recording of a scope and the read back of its result.
+/// Must be at least the number of frames in flight, otherwise the results aren't there yet.
+pub const FRAME_LATENCY: usize = FRAMES_IN_FLIGHT + 1;
+
+/// Maximum amount of scopes that can be recorded in a single frame.
+pub const MAX_SCOPES_PER_FRAME: u32 = 64;
+
+/// Amount of samples kept for each scope.
+pub const HISTORY_LENGTH: usize = 120;
+
+// Two timestamps per scope
+const QUERIES_PER_FRAME: u32 = MAX_SCOPES_PER_FRAME * 2;
+
+// Each query is read back as its 64 bits value followed by its availability
+const VALUES_PER_QUERY: usize = 2;
+
+// Flags of vkCmdCopyQueryPoolResults, vulkano only copies 32 bits values without availability
+const QUERY_RESULT_64_BIT: u32 = 0x1;
+const QUERY_RESULT_WITH_AVAILABILITY_BIT: u32 = 0x4;
+
+type CmdCopyQueryPoolResults = extern "system" fn(usize, u64, u32, u32, u64, u64, u64, u32);
+
+/// Records timestamps around named scopes and reads them back [FRAME_LATENCY](FRAME_LATENCY)
+/// frames later, so the CPU never has to wait on the GPU.
+///
+/// Scopes are recorded outside of render passes: the queries go in secondary command buffers,
+/// which a render pass begun for inline commands can't execute.
+pub struct GpuProfiler {
+    device: Arc<Device>,
+    queue_family: u32,
+    query_pool: Option<Arc<UnsafeQueryPool>>,
+    readback: Option<Arc<CpuAccessibleBuffer<[u64]>>>,
+    _readback_allocation: Option<TrackedAllocation>,
+    copy_query_pool_results: Option<CmdCopyQueryPoolResults>,
+
+    /// Nanoseconds per tick
+    timestamp_period: f32,
+    /// Mask of the bits the queue family actually writes
+    timestamp_mask: u64,
+
+    frames: Vec<FrameScopes>,
+    current_frame: usize,
+
+    last_frame: Vec<ScopeTiming>,
+    history: HashMap<String, ScopeHistory>,
+}
+
+#[derive(Default)]
+struct FrameScopes {
+    scopes: Vec<PendingScope>,
+    open_scopes: Vec<usize>,
+    next_query: u32,
+    resolved: bool,
+}
+
+struct PendingScope {
+    name: String,
+    depth: usize,
+    begin_query: u32,
+    end_query: Option<u32>,
+}
+
+/// Result of a scope, as read back from the GPU.
+#[derive(Debug, Clone)]
+pub struct ScopeTiming {
+    pub name: String,
+    /// Nesting level of the scope, 0 for top level scopes
+    pub depth: usize,
+    pub milliseconds: f32,
+}
+
+/// The last [HISTORY_LENGTH](HISTORY_LENGTH) samples of a scope, in milliseconds.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeHistory {
+    samples: VecDeque<f32>,
+}
+
+impl ScopeHistory {
+    fn push(&mut self, milliseconds: f32) {
+        if self.samples.len() == HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(milliseconds);
+    }
+
+    #[inline]
+    pub fn samples(&self) -> impl Iterator<Item = &f32> {
+        self.samples.iter()
+    }
+
+    #[inline]
+    pub fn last(&self) -> Option<f32> {
+        self.samples.back().copied()
+    }
+
+    pub fn average(&self) -> Option<f32> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(self.samples.iter().sum::<f32>() / self.samples.len() as f32)
+        }
+    }
+
+    pub fn min(&self) -> Option<f32> {
+        self.samples.iter().copied().fold(None, |min, sample| {
+            Some(min.map_or(sample, |min: f32| min.min(sample)))
+        })
+    }
+
+    pub fn max(&self) -> Option<f32> {
+        self.samples.iter().copied().fold(None, |max, sample| {
+            Some(max.map_or(sample, |max: f32| max.max(sample)))
+        })
+    }
+}
+
+impl GpuProfiler {
+    /// Create a profiler for command buffers submitted to a queue of the given family.
+    /// If the family doesn't support timestamps, the profiler does nothing.
+    pub fn new(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        queue_family: QueueFamily,
+    ) -> Self {
+        let timestamp_period = device.physical_device().limits().timestamp_period();
+
+        let (query_pool, readback, timestamp_mask) = match queue_family.timestamp_valid_bits() {
+            Some(valid_bits) => {
+                // Queries are reset and copied by range, and vulkano refuses a range ending
+                // on the last slot of the pool, so keep one spare slot at the end
+                let query_pool = Arc::new(
+                    UnsafeQueryPool::new(
+                        device.clone(),
+                        QueryType::Timestamp,
+                        QUERIES_PER_FRAME * FRAME_LATENCY as u32 + 1,
+                    )
+                    .expect("Failed to create timestamp query pool !"),
+                );
+
+                let readback = unsafe {
+                    CpuAccessibleBuffer::uninitialized_array(
+                        device.clone(),
+                        QUERIES_PER_FRAME as usize * FRAME_LATENCY * VALUES_PER_QUERY,
+                        BufferUsage {
+                            transfer_destination: true,
+                            ..BufferUsage::none()
+                        },
+                        true,
+                    )
+                    .expect("Failed to create timestamp readback buffer !")
+                };
+
+                (Some(query_pool), Some(readback), timestamp_mask(valid_bits))
+            }
+            None => {
+                warn!("Queue family doesn't support timestamps, GPU profiling disabled");
+                (None, None, 0)
+            }
+        };
+
+        let _readback_allocation = readback.as_ref().map(|readback| {
+            memory_tracker.track_buffer(
+                MemoryCategory::Staging,
+                MemoryLocation::HostVisible,
+                readback,
+            )
+        });
+
+        let copy_query_pool_results = query_pool
+            .as_ref()
+            .map(|_| load_copy_query_pool_results(device));
+
+        Self {
+            device: device.clone(),
+            queue_family: queue_family.id(),
+            query_pool,
+            readback,
+            _readback_allocation,
+            copy_query_pool_results,
+            timestamp_period,
+            timestamp_mask,
+            frames: (0..FRAME_LATENCY).map(|_| FrameScopes::default()).collect(),
+            current_frame: 0,
+            last_frame: Vec::new(),
+            history: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.query_pool.is_some()
+    }
+
+    /// Move to the next frame, reading back the results of the frame that used the same slot.
+    /// The fence of that frame must have been signaled.
+    pub fn begin_frame(&mut self) {
+        self.current_frame = (self.current_frame + 1) % FRAME_LATENCY;
+        self.collect_results();
+
+        let frame = &mut self.frames[self.current_frame];
+        frame.scopes.clear();
+        frame.open_scopes.clear();
+        frame.next_query = 0;
+        frame.resolved = false;
+    }
+
+    /// Reset the queries of the current frame, before any scope of the frame.
+    pub fn reset(&self, builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        let query_pool = match &self.query_pool {
+            Some(query_pool) => query_pool,
+            None => return builder,
+        };
+
+        let queries = query_pool
+            .queries_range(self.first_query(), QUERIES_PER_FRAME)
+            .unwrap();
+        self.record_queries(builder, None, |commands| unsafe {
+            commands.reset_query_pool(queries)
+        })
+    }
+
+    /// Write the timestamp that opens a scope, scopes can be nested.
+    pub fn begin_scope(
+        &mut self,
+        builder: AutoCommandBufferBuilder,
+        name: &str,
+    ) -> AutoCommandBufferBuilder {
+        let query_pool = match &self.query_pool {
+            Some(query_pool) => query_pool.clone(),
+            None => return builder,
+        };
+
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current_frame];
+        if frame.next_query + 2 > QUERIES_PER_FRAME {
+            warn!("Too many GPU scopes in a frame, ignoring {:?}", name);
+            // Still track it so the matching end_scope stays balanced
+            frame.open_scopes.push(usize::MAX);
+            return builder;
+        }
+
+        let begin_query = frame.next_query;
+        frame.next_query += 2;
+        frame.open_scopes.push(frame.scopes.len());
+        frame.scopes.push(PendingScope {
+            name: name.to_owned(),
+            depth: frame.open_scopes.len() - 1,
+            begin_query,
+            end_query: None,
+        });
+
+        let query = query_pool.query(first_query + begin_query).unwrap();
+        self.record_queries(builder, None, |commands| unsafe {
+            commands.write_timestamp(
+                query,
+                PipelineStages {
+                    top_of_pipe: true,
+                    ..PipelineStages::none()
+                },
+            )
+        })
+    }
+
+    /// Write the timestamp that closes the last opened scope.
+    pub fn end_scope(&mut self, builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        let query_pool = match &self.query_pool {
+            Some(query_pool) => query_pool.clone(),
+            None => return builder,
+        };
+
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current_frame];
+        let scope = match frame.open_scopes.pop() {
+            Some(scope) if scope != usize::MAX => &mut frame.scopes[scope],
+            Some(_) => return builder,
+            None => {
+                warn!("GPU scope closed without being opened");
+                return builder;
+            }
+        };
+
+        let end_query = scope.begin_query + 1;
+        scope.end_query = Some(end_query);
+
+        let query = query_pool.query(first_query + end_query).unwrap();
+        self.record_queries(builder, None, |commands| unsafe {
+            commands.write_timestamp(
+                query,
+                PipelineStages {
+                    bottom_of_pipe: true,
+                    ..PipelineStages::none()
+                },
+            )
+        })
+    }
+
+    /// Copy the timestamps of the current frame to the read back buffer, after the last scope
+    /// of the frame.
+    pub fn resolve(&mut self, builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        let (query_pool, readback, copy_query_pool_results) = match (
+            &self.query_pool,
+            &self.readback,
+            self.copy_query_pool_results,
+        ) {
+            (Some(query_pool), Some(readback), Some(copy)) => (query_pool, readback.clone(), copy),
+            _ => return builder,
+        };
+
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current_frame];
+        if !frame.open_scopes.is_empty() {
+            warn!(
+                "{} GPU scope(s) still open at the end of the frame",
+                frame.open_scopes.len()
+            );
+        }
+        if frame.next_query == 0 {
+            return builder;
+        }
+        frame.resolved = true;
+
+        let queries = query_pool
+            .queries_range(first_query, frame.next_query)
+            .unwrap();
+        let destination = readback.clone();
+        self.record_queries(builder, Some(readback), |commands| unsafe {
+            copy_results(commands, copy_query_pool_results, queries, &destination)
+        })
+    }
+
+    /// Timings of the most recent frame that was read back.
+    #[inline]
+    pub fn last_frame(&self) -> &[ScopeTiming] {
+        &self.last_frame
+    }
+
+    #[inline]
+    pub fn history(&self, name: &str) -> Option<&ScopeHistory> {
+        self.history.get(name)
+    }
+
+    #[inline]
+    pub fn histories(&self) -> impl Iterator<Item = (&String, &ScopeHistory)> {
+        self.history.iter()
+    }
+
+    /// Log the timings of the last frame, indented by scope.
+    pub fn log_last_frame(&self) {
+        for timing in &self.last_frame {
+            info!(
+                "[GPU] {:indent$}{}: {:.3} ms",
+                "",
+                timing.name,
+                timing.milliseconds,
+                indent = timing.depth * 2
+            );
+        }
+    }
+
+    /// The automatic command buffer builder can't record queries, so they are recorded in
+    /// raw commands that the builder executes.
+    fn record_queries<F>(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        readback: Option<Arc<CpuAccessibleBuffer<[u64]>>>,
+        record: F,
+    ) -> AutoCommandBufferBuilder
+    where
+        F: FnOnce(&mut UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>),
+    {
+        let queue_family = self
+            .device
+            .physical_device()
+            .queue_family_by_id(self.queue_family)
+            .unwrap();
+
+        let mut commands = RawCommands::begin(&self.device, queue_family);
+        record(&mut commands);
+
+        let mut resources: Vec<Arc<dyn Any + Send + Sync>> = vec![self.query_pool.clone().unwrap()];
+        if let Some(readback) = readback {
+            resources.push(readback);
+        }
+        RawCommands::build(commands, resources).execute(builder)
+    }
+
+    #[inline]
+    fn first_query(&self) -> u32 {
+        self.current_frame as u32 * QUERIES_PER_FRAME
+    }
+
+    fn collect_results(&mut self) {
+        let readback = match &self.readback {
+            Some(readback) => readback,
+            None => return,
+        };
+
+        let frame = &self.frames[self.current_frame];
+        if !frame.resolved {
+            return;
+        }
+
+        let values = readback
+            .read()
+            .expect("Failed to read back GPU timestamps !");
+        let first_query = self.first_query() as usize;
+
+        self.last_frame.clear();
+        for scope in &frame.scopes {
+            let end_query = match scope.end_query {
+                Some(end_query) => end_query,
+                None => continue,
+            };
+
+            let result = |query: u32| {
+                let index = (first_query + query as usize) * VALUES_PER_QUERY;
+                // The availability follows the value, zero if it was never written
+                match values[index + 1] {
+                    0 => None,
+                    _ => Some(values[index]),
+                }
+            };
+            let (begin, end) = match (result(scope.begin_query), result(end_query)) {
+                (Some(begin), Some(end)) => (begin, end),
+                _ => {
+                    warn!("GPU timestamps of {:?} aren't available", scope.name);
+                    continue;
+                }
+            };
+            let milliseconds =
+                ticks_to_milliseconds(begin, end, self.timestamp_mask, self.timestamp_period);
+
+            self.history
+                .entry(scope.name.clone())
+                .or_default()
+                .push(milliseconds);
+            self.last_frame.push(ScopeTiming {
+                name: scope.name.clone(),
+                depth: scope.depth,
+                milliseconds,
+            });
+        }
+    }
+}
+
+/// Mask of the bits written by a queue family with the given amount of valid timestamp bits.
+#[inline]
+fn timestamp_mask(valid_bits: u32) -> u64 {
+    if valid_bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << valid_bits) - 1
+    }
+}
+
+/// Duration between two raw timestamps, the counter may have wrapped around in between.
+#[inline]
+fn ticks_to_milliseconds(begin: u64, end: u64, timestamp_mask: u64, timestamp_period: f32) -> f32 {
+    let ticks = (end & timestamp_mask).wrapping_sub(begin & timestamp_mask) & timestamp_mask;
+    (ticks as f64 * f64::from(timestamp_period) / 1_000_000.0) as f32
+}
+
+/// Vulkano doesn't give access to the function pointers of the device, so load
+/// vkCmdCopyQueryPoolResults through the loader vulkano uses.
+fn load_copy_query_pool_results(device: &Arc<Device>) -> CmdCopyQueryPoolResults {
+    let name = CStr::from_bytes_with_nul(b"vkCmdCopyQueryPoolResults\0").unwrap();
+    let function = loader::auto_loader()
+        .expect("Failed to load Vulkan !")
+        .get_instance_proc_addr(device.instance().internal_object(), name.as_ptr());
+    unsafe { mem::transmute::<extern "system" fn(), CmdCopyQueryPoolResults>(function) }
+}
+
+/// Copy the results of the queries to their place in the read back buffer, as 64 bits values
+/// with their availability. Doesn't wait, the frame is only read back once its fence signaled.
+unsafe fn copy_results(
+    commands: &mut UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>,
+    copy_query_pool_results: CmdCopyQueryPoolResults,
+    queries: UnsafeQueriesRange,
+    readback: &CpuAccessibleBuffer<[u64]>,
+) {
+    let stride = (VALUES_PER_QUERY * mem::size_of::<u64>()) as u64;
+    let destination = readback.inner();
+    copy_query_pool_results(
+        commands.internal_object(),
+        queries.pool().internal_object(),
+        queries.first_index(),
+        queries.count(),
+        destination.buffer.internal_object(),
+        destination.offset as u64 + u64::from(queries.first_index()) * stride,
+        stride,
+        QUERY_RESULT_64_BIT | QUERY_RESULT_WITH_AVAILABILITY_BIT,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_are_converted_with_the_timestamp_period() {
+        // 1.5 nanoseconds per tick
+        let milliseconds = ticks_to_milliseconds(1_000, 2_001_000, u64::MAX, 1.5);
+        assert!((milliseconds - 3.0).abs() < 1e-6);
+        assert_eq!(ticks_to_milliseconds(42, 42, u64::MAX, 1.0), 0.0);
+    }
+
+    #[test]
+    fn timestamp_mask_keeps_the_valid_bits() {
+        assert_eq!(timestamp_mask(36), 0xf_ffff_ffff);
+        assert_eq!(timestamp_mask(64), u64::MAX);
+    }
+
+    #[test]
+    fn wrapped_timestamps_stay_positive() {
+        let mask = timestamp_mask(36);
+        // The counter wrapped around between the two scopes
+        let milliseconds = ticks_to_milliseconds(mask - 999_999, 1_000_000, mask, 1.0);
+        assert!((milliseconds - 2.0).abs() < 1e-6);
+
+        // Bits above the valid ones are garbage and ignored
+        let milliseconds = ticks_to_milliseconds(1 << 40, (1 << 40) + 1_000_000, mask, 1.0);
+        assert!((milliseconds - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn full_width_timestamps_wrap_too() {
+        let milliseconds = ticks_to_milliseconds(u64::MAX, 999_999, u64::MAX, 1.0);
+        assert!((milliseconds - 1.0).abs() < 1e-6);
+    }
+}
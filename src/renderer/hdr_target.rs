@@ -0,0 +1,100 @@
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
+use log::warn;
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract};
+use vulkano::image::{AttachmentImage, ImageAccess};
+use vulkano::single_pass_renderpass;
+
+/// Linear color with enough range for bright lights, tonemapped before reaching the screen.
+pub const HDR_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+/// Offscreen color and depth the scene is rendered to, same size as the swap chain.
+pub struct HdrTarget {
+    color: Arc<AttachmentImage>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+
+    _color_allocation: TrackedAllocation,
+    _depth_allocation: TrackedAllocation,
+}
+
+impl HdrTarget {
+    pub fn new(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        dimensions: [u32; 2],
+    ) -> Self {
+        let color = AttachmentImage::sampled(device.clone(), dimensions, HDR_FORMAT)
+            .expect("Failed to create HDR color target !");
+        let depth = Self::create_depth_buffer(device, dimensions);
+
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: HDR_FORMAT,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Clear,
+                        store: DontCare,
+                        format: depth.format(),
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            )
+            .unwrap(),
+        );
+
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(color.clone())
+                .unwrap()
+                .add(depth.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create HDR framebuffer !"),
+        );
+
+        Self {
+            _color_allocation: memory_tracker.track_image(MemoryCategory::RenderTarget, &color),
+            _depth_allocation: memory_tracker.track_image(MemoryCategory::RenderTarget, &depth),
+            color,
+            render_pass,
+            framebuffer,
+        }
+    }
+
+    fn create_depth_buffer(device: &Arc<Device>, dimensions: [u32; 2]) -> Arc<AttachmentImage> {
+        // D32Sfloat is the most precise but not mandatory, D16Unorm always is
+        AttachmentImage::transient(device.clone(), dimensions, Format::D32Sfloat)
+            .or_else(|_| {
+                warn!("Depth format D32Sfloat not available, falling back to D16Unorm");
+                AttachmentImage::transient(device.clone(), dimensions, Format::D16Unorm)
+            })
+            .expect("Failed to create depth buffer !")
+    }
+
+    #[inline]
+    pub fn color(&self) -> &Arc<AttachmentImage> {
+        &self.color
+    }
+
+    #[inline]
+    pub fn render_pass(&self) -> &Arc<dyn RenderPassAbstract + Send + Sync> {
+        &self.render_pass
+    }
+
+    #[inline]
+    pub fn framebuffer(&self) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+        self.framebuffer.clone()
+    }
+}
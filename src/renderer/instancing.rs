@@ -0,0 +1,144 @@
+use crate::math::Frustum;
+use crate::renderer::culling::is_visible;
+use crate::renderer::frame_allocator::FrameAllocator;
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::material::MaterialId;
+use crate::renderer::mesh::Mesh;
+use crate::scene::{NodeId, Scene};
+use log::trace;
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::buffer::BufferAccess;
+use vulkano::impl_vertex;
+
+/// Per instance vertex attributes, read from the second vertex binding.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+    pub tint: [f32; 4],
+    pub params: [f32; 4],
+}
+
+impl_vertex!(InstanceData, model, tint, params);
+
+/// Visible instances of a mesh with a material, drawn with a single call.
+pub struct DrawBatch {
+    pub mesh: Arc<Mesh>,
+    pub material: MaterialId,
+    pub instances: Arc<dyn BufferAccess + Send + Sync>,
+    pub instance_count: usize,
+}
+
+/// Objects sharing a mesh and a material.
+struct Group {
+    mesh: Arc<Mesh>,
+    material: MaterialId,
+    nodes: Vec<NodeId>,
+}
+
+/// Groups the renderables of a scene into instanced draws.
+///
+/// Grouping is only redone when the renderables of the scene change, moving objects just
+/// changes the instance data which is gathered again every frame.
+#[derive(Default)]
+pub struct InstanceBatcher {
+    groups: Vec<Group>,
+    revision: Option<u64>,
+    /// Reused between frames to avoid allocating
+    instances: Vec<InstanceData>,
+}
+
+impl InstanceBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cull the scene and build the batches of the frame, the instance data goes into the
+    /// frame allocator.
+    pub fn prepare(
+        &mut self,
+        scene: &mut Scene,
+        frustum: &Frustum,
+        frame_allocator: &mut FrameAllocator,
+        stats: &mut FrameStats,
+    ) -> Vec<DrawBatch> {
+        if self.revision != Some(scene.renderables_revision()) {
+            self.rebuild_groups(scene);
+        }
+
+        scene.graph_mut().update();
+
+        let mut batches = Vec::with_capacity(self.groups.len());
+        for group in &self.groups {
+            self.instances.clear();
+
+            for node in &group.nodes {
+                // Removed directly from the graph, the renderable is gone at the next rebuild
+                if !scene.graph().contains(*node) {
+                    continue;
+                }
+                // Up to date after the update, unless something refreshed only part of it
+                let model = &scene.graph_mut().world_matrix(*node);
+                let renderable = scene.renderable(*node).unwrap();
+                stats.objects += 1;
+
+                if !is_visible(frustum, group.mesh.bounds(), model) {
+                    stats.culled += 1;
+                    continue;
+                }
+
+                self.instances.push(InstanceData {
+                    model: (*model).into(),
+                    tint: renderable.tint.to_array(),
+                    params: renderable.params.to_array(),
+                });
+            }
+
+            if self.instances.is_empty() {
+                continue;
+            }
+            stats.instances += self.instances.len();
+
+            let allocation = frame_allocator.allocate_vertices(&self.instances);
+            batches.push(DrawBatch {
+                mesh: group.mesh.clone(),
+                material: group.material,
+                instances: Arc::new(allocation.slice()),
+                instance_count: self.instances.len(),
+            });
+        }
+
+        batches
+    }
+
+    fn rebuild_groups(&mut self, scene: &Scene) {
+        let mut groups: HashMap<(MaterialId, usize), Group> = HashMap::new();
+
+        for (node, renderable) in scene.renderables() {
+            // Renderables left behind by nodes removed directly from the graph
+            if !scene.graph().contains(node) {
+                continue;
+            }
+
+            let key = (renderable.material, Arc::as_ptr(&renderable.mesh) as usize);
+            groups
+                .entry(key)
+                .or_insert_with(|| Group {
+                    mesh: renderable.mesh.clone(),
+                    material: renderable.material,
+                    nodes: Vec::new(),
+                })
+                .nodes
+                .push(node);
+        }
+
+        // Sorted by material to limit pipeline switches
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by_key(|(key, _)| *key);
+        self.groups = groups.into_iter().map(|(_, group)| group).collect();
+        self.revision = Some(scene.renderables_revision());
+
+        trace!("Rebuilt {} instance batch(es)", self.groups.len());
+    }
+}
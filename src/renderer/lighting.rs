@@ -0,0 +1,178 @@
+use crate::math::Vec3;
+use crate::scene::{CameraView, LightKind, LightShadow, Scene};
+use log::warn;
+use std::cmp::Ordering;
+
+/// Descriptor set of the per frame data in the lit shaders.
+pub const FRAME_SET: usize = 0;
+
+/// Lights uploaded when nothing says otherwise.
+pub const DEFAULT_MAX_LIGHTS: usize = 64;
+
+const LIGHT_DIRECTIONAL: f32 = 0.0;
+const LIGHT_POINT: f32 = 1.0;
+const LIGHT_SPOT: f32 = 2.0;
+
+/// A light as laid out in the light storage buffer, std430.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct GpuLight {
+    /// World position, range in W (0 for directional lights)
+    pub position_range: [f32; 4],
+    /// World direction the light shines to, type in W
+    pub direction_type: [f32; 4],
+    /// Linear color premultiplied by the intensity
+    pub color: [f32; 4],
+    /// Cosines of the inner and outer cone angles of spot lights
+    pub spot_cos: [f32; 4],
+    /// Index of the first shadow map (-1 without shadows), depth bias, normal bias and
+    /// number of shadow maps, one per cascade
+    pub shadow: [f32; 4],
+}
+
+/// A gathered light that wants a shadow map.
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowCaster {
+    /// Index in the gathered lights
+    pub light_index: usize,
+    pub kind: LightKind,
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub shadow: LightShadow,
+}
+
+/// Uniform block of the frame set, std140.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct FrameUniforms {
+    pub view_projection: [[f32; 4]; 4],
+    pub camera_position: [f32; 4],
+    /// Direction the camera looks at, to measure distances for the shadow cascades
+    pub camera_forward: [f32; 4],
+    /// Hemisphere ambient, premultiplied by its intensity
+    pub ambient_sky: [f32; 4],
+    pub ambient_ground: [f32; 4],
+    /// Number of lights in X
+    pub light_count: [u32; 4],
+    /// Texel size of the shadow atlas in X, PCF radius in texels in Y, cascade blend in Z
+    pub shadow_params: [f32; 4],
+    /// Intensity of the environment in X, 1 in Y when there is one, replacing the hemisphere
+    pub environment_params: [f32; 4],
+}
+
+/// Collects the lights of the scene every frame, keeping the most relevant ones when
+/// there are more than the shaders are allowed to handle.
+pub struct LightGatherer {
+    max_lights: usize,
+    lights: Vec<GpuLight>,
+    shadow_casters: Vec<ShadowCaster>,
+    warned: bool,
+}
+
+impl LightGatherer {
+    pub fn new(max_lights: usize) -> Self {
+        Self {
+            max_lights,
+            lights: Vec::new(),
+            shadow_casters: Vec::new(),
+            warned: false,
+        }
+    }
+
+    #[inline]
+    pub fn max_lights(&self) -> usize {
+        self.max_lights
+    }
+
+    #[inline]
+    pub fn set_max_lights(&mut self, max_lights: usize) {
+        self.max_lights = max_lights;
+        self.warned = false;
+    }
+
+    /// Lights of the frame, directional ones first then the closest to the camera.
+    pub fn gather(&mut self, scene: &mut Scene, camera_view: &CameraView) -> &mut [GpuLight] {
+        let lights: Vec<_> = scene
+            .lights()
+            .filter(|(node, _)| scene.graph().contains(*node))
+            .map(|(node, light)| (node, *light))
+            .collect();
+
+        let mut lights: Vec<_> = lights
+            .into_iter()
+            .map(|(node, light)| {
+                let world = scene.graph_mut().world_matrix(node);
+                let position = world.translation();
+                let direction = world.transform_vector(-Vec3::Z).normalize();
+                let color = (light.color * light.intensity).extend(0.0).to_array();
+
+                let (kind, range, spot_cos) = match light.kind {
+                    LightKind::Directional => (LIGHT_DIRECTIONAL, 0.0, [0.0; 4]),
+                    LightKind::Point { range } => (LIGHT_POINT, range, [0.0; 4]),
+                    LightKind::Spot {
+                        range,
+                        inner_angle,
+                        outer_angle,
+                    } => (
+                        LIGHT_SPOT,
+                        range,
+                        [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0],
+                    ),
+                };
+
+                // Directional lights affect everything, keep them first
+                let distance = if light.kind == LightKind::Directional {
+                    -1.0
+                } else {
+                    position.distance(camera_view.position)
+                };
+
+                let gpu_light = GpuLight {
+                    position_range: position.extend(range).to_array(),
+                    direction_type: direction.extend(kind).to_array(),
+                    color,
+                    spot_cos,
+                    shadow: [-1.0, 0.0, 0.0, 0.0],
+                };
+                (distance, gpu_light, light, position, direction)
+            })
+            .collect();
+
+        if lights.len() > self.max_lights {
+            if !self.warned {
+                warn!(
+                    "{} lights in the scene, only the {} most relevant are used",
+                    lights.len(),
+                    self.max_lights
+                );
+                self.warned = true;
+            }
+            lights.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            lights.truncate(self.max_lights);
+        }
+
+        self.lights.clear();
+        self.shadow_casters.clear();
+        for (_, gpu_light, light, position, direction) in lights {
+            match (light.kind, light.shadow) {
+                (LightKind::Point { .. }, _) | (_, None) => {}
+                (kind, Some(shadow)) => self.shadow_casters.push(ShadowCaster {
+                    light_index: self.lights.len(),
+                    kind,
+                    position,
+                    direction,
+                    shadow,
+                }),
+            }
+            self.lights.push(gpu_light);
+        }
+
+        &mut self.lights
+    }
+
+    /// Gathered lights casting shadows, in the same order as the lights.
+    #[inline]
+    pub fn shadow_casters(&self) -> &[ShadowCaster] {
+        &self.shadow_casters
+    }
+}
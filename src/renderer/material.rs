@@ -0,0 +1,258 @@
+use crate::math::{Vec3, Vec4};
+use crate::renderer::memory_tracker::{
+    MemoryCategory, MemoryLocation, MemoryTracker, TrackedAllocation,
+};
+use crate::renderer::texture::Texture;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
+use vulkano::device::{Device, Queue};
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::sampler::Sampler;
+
+/// Descriptor set of the material in the PBR shaders.
+pub const MATERIAL_SET: usize = 1;
+
+/// Identifies a material of the renderer, objects sharing one can be batched together.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct MaterialId(pub u32);
+
+impl MaterialId {
+    pub const DEFAULT: MaterialId = MaterialId(0);
+}
+
+/// Metallic-roughness material, same model and conventions as glTF.
+/// Each factor is multiplied with its texture when there is one.
+#[derive(Clone)]
+pub struct PbrMaterial {
+    /// Linear RGB and alpha
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Linear RGB
+    pub emissive: Vec3,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+
+    /// sRGB
+    pub base_color_texture: Option<Arc<Texture>>,
+    /// Roughness in G, metalness in B, linear
+    pub metallic_roughness_texture: Option<Arc<Texture>>,
+    /// Tangent space, linear
+    pub normal_texture: Option<Arc<Texture>>,
+    /// Occlusion in R, linear
+    pub occlusion_texture: Option<Arc<Texture>>,
+    /// sRGB
+    pub emissive_texture: Option<Arc<Texture>>,
+}
+
+impl Default for PbrMaterial {
+    /// A white dielectric, glTF defaults to a fully rough metal which is black without IBL.
+    fn default() -> Self {
+        Self {
+            base_color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
+            emissive: Vec3::ZERO,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+/// Uniform block of the material set, std140.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct MaterialParams {
+    base_color: [f32; 4],
+    /// Emissive in RGB, normal scale in A
+    emissive_normal_scale: [f32; 4],
+    /// Metallic, roughness, occlusion strength
+    metallic_roughness_occlusion: [f32; 4],
+}
+
+struct Material {
+    description: PbrMaterial,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    _params: Arc<CpuAccessibleBuffer<MaterialParams>>,
+    _params_allocation: TrackedAllocation,
+}
+
+/// Owns the materials and their descriptor sets.
+pub struct MaterialLibrary {
+    device: Arc<Device>,
+    memory_tracker: Arc<MemoryTracker>,
+    sampler: Arc<Sampler>,
+
+    // Bound in place of the missing textures, neutral for their factor
+    white_srgb: Arc<Texture>,
+    white_linear: Arc<Texture>,
+    flat_normal: Arc<Texture>,
+
+    materials: Vec<Material>,
+}
+
+impl MaterialLibrary {
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+    ) -> Self {
+        Self {
+            device: device.clone(),
+            memory_tracker: memory_tracker.clone(),
+            sampler: Sampler::simple_repeat_linear(device.clone()),
+            white_srgb: Arc::new(Texture::solid(
+                queue,
+                memory_tracker,
+                [255, 255, 255, 255],
+                true,
+            )),
+            white_linear: Arc::new(Texture::solid(
+                queue,
+                memory_tracker,
+                [255, 255, 255, 255],
+                false,
+            )),
+            flat_normal: Arc::new(Texture::solid(
+                queue,
+                memory_tracker,
+                [128, 128, 255, 255],
+                false,
+            )),
+            materials: Vec::new(),
+        }
+    }
+
+    /// Register a material for the given pipeline, the first one becomes the default.
+    pub fn create(
+        &mut self,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        description: PbrMaterial,
+    ) -> MaterialId {
+        let material = self.build(pipeline, description);
+        self.materials.push(material);
+        MaterialId(self.materials.len() as u32 - 1)
+    }
+
+    /// Replace the parameters and textures of a material.
+    pub fn update(
+        &mut self,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        id: MaterialId,
+        description: PbrMaterial,
+    ) {
+        self.materials[id.0 as usize] = self.build(pipeline, description);
+    }
+
+    #[inline]
+    pub fn get(&self, id: MaterialId) -> Option<&PbrMaterial> {
+        self.materials
+            .get(id.0 as usize)
+            .map(|material| &material.description)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// Unknown materials fall back to the default one.
+    pub fn descriptor_set(&self, id: MaterialId) -> Arc<dyn DescriptorSet + Send + Sync> {
+        self.materials
+            .get(id.0 as usize)
+            .unwrap_or(&self.materials[MaterialId::DEFAULT.0 as usize])
+            .descriptor_set
+            .clone()
+    }
+
+    fn build(
+        &self,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        description: PbrMaterial,
+    ) -> Material {
+        let params = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::uniform_buffer(),
+            false,
+            MaterialParams {
+                base_color: description.base_color.to_array(),
+                emissive_normal_scale: description
+                    .emissive
+                    .extend(description.normal_scale)
+                    .to_array(),
+                metallic_roughness_occlusion: [
+                    description.metallic,
+                    description.roughness,
+                    description.occlusion_strength,
+                    0.0,
+                ],
+            },
+        )
+        .expect("Failed to create material buffer !");
+        let params_allocation = self.memory_tracker.track_buffer(
+            MemoryCategory::Uniform,
+            MemoryLocation::HostVisible,
+            &params,
+        );
+
+        let texture = |texture: &Option<Arc<Texture>>, fallback: &Arc<Texture>| {
+            texture.as_ref().unwrap_or(fallback).image().clone()
+        };
+
+        let layout = pipeline
+            .descriptor_set_layout(MATERIAL_SET)
+            .expect("The pipeline has no material set !")
+            .clone();
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_buffer(params.clone())
+                .unwrap()
+                .add_sampled_image(
+                    texture(&description.base_color_texture, &self.white_srgb),
+                    self.sampler.clone(),
+                )
+                .unwrap()
+                .add_sampled_image(
+                    texture(&description.metallic_roughness_texture, &self.white_linear),
+                    self.sampler.clone(),
+                )
+                .unwrap()
+                .add_sampled_image(
+                    texture(&description.normal_texture, &self.flat_normal),
+                    self.sampler.clone(),
+                )
+                .unwrap()
+                .add_sampled_image(
+                    texture(&description.occlusion_texture, &self.white_linear),
+                    self.sampler.clone(),
+                )
+                .unwrap()
+                .add_sampled_image(
+                    texture(&description.emissive_texture, &self.white_srgb),
+                    self.sampler.clone(),
+                )
+                .unwrap()
+                .build()
+                .expect("Failed to create material descriptor set !"),
+        );
+
+        Material {
+            description,
+            descriptor_set,
+            _params: params,
+            _params_allocation: params_allocation,
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use log::{info, warn};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use vulkano::buffer::BufferAccess;
+use vulkano::image::ImageAccess;
+use vulkano::instance::PhysicalDevice;
+
+/// Fraction of each heap the engine allows itself to use before complaining.
+pub const DEFAULT_BUDGET_RATIO: f64 = 0.9;
+
+/// What a tracked resource is used for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    Mesh,
+    Texture,
+    RenderTarget,
+    Staging,
+    Uniform,
+    Other,
+}
+
+impl MemoryCategory {
+    pub const ALL: [MemoryCategory; 6] = [
+        MemoryCategory::Mesh,
+        MemoryCategory::Texture,
+        MemoryCategory::RenderTarget,
+        MemoryCategory::Staging,
+        MemoryCategory::Uniform,
+        MemoryCategory::Other,
+    ];
+
+    #[inline]
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Where the memory of a resource lives, used to find out its heap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryLocation {
+    /// Images and device local buffers
+    DeviceLocal,
+    /// CPU accessible buffers
+    HostVisible,
+}
+
+/// Keeps count of the memory used by every buffer and image of the engine,
+/// per category and per heap, and warns when a heap goes over its budget.
+pub struct MemoryTracker {
+    heaps: Vec<HeapInfo>,
+    device_local_heap: usize,
+    host_visible_heap: usize,
+    usage: Mutex<Usage>,
+}
+
+#[derive(Debug, Clone)]
+struct HeapInfo {
+    size: u64,
+    device_local: bool,
+}
+
+struct Usage {
+    /// Bytes used, indexed by heap then by category
+    bytes: Vec<[u64; MemoryCategory::ALL.len()]>,
+    allocations: Vec<[usize; MemoryCategory::ALL.len()]>,
+    peaks: Vec<u64>,
+    budgets: Vec<u64>,
+    over_budget: Vec<bool>,
+}
+
+/// Handle of a tracked resource, keep it alongside the resource.
+/// The memory is considered freed when it is dropped.
+pub struct TrackedAllocation {
+    tracker: Arc<MemoryTracker>,
+    category: MemoryCategory,
+    heap: usize,
+    size: u64,
+}
+
+impl Drop for TrackedAllocation {
+    fn drop(&mut self) {
+        self.tracker.release(self.category, self.heap, self.size);
+    }
+}
+
+impl TrackedAllocation {
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    #[inline]
+    pub fn category(&self) -> MemoryCategory {
+        self.category
+    }
+}
+
+impl MemoryTracker {
+    pub fn new(physical_device: &PhysicalDevice) -> Arc<Self> {
+        let heaps: Vec<_> = physical_device
+            .memory_heaps()
+            .map(|heap| HeapInfo {
+                size: heap.size() as u64,
+                device_local: heap.is_device_local(),
+            })
+            .collect();
+
+        // Mimic the memory pool: the first memory type with the right properties wins
+        let heap_of = |predicate: &dyn Fn(&vulkano::instance::MemoryType) -> bool| {
+            physical_device
+                .memory_types()
+                .find(|ty| predicate(ty))
+                .map(|ty| ty.heap().id() as usize)
+                .unwrap_or(0)
+        };
+        let device_local_heap = heap_of(&|ty| ty.is_device_local());
+        let host_visible_heap = heap_of(&|ty| ty.is_host_visible());
+
+        let budgets = heaps
+            .iter()
+            .map(|heap| (heap.size as f64 * DEFAULT_BUDGET_RATIO) as u64)
+            .collect();
+
+        Arc::new(Self {
+            usage: Mutex::new(Usage {
+                bytes: vec![[0; MemoryCategory::ALL.len()]; heaps.len()],
+                allocations: vec![[0; MemoryCategory::ALL.len()]; heaps.len()],
+                peaks: vec![0; heaps.len()],
+                budgets,
+                over_budget: vec![false; heaps.len()],
+            }),
+            heaps,
+            device_local_heap,
+            host_visible_heap,
+        })
+    }
+
+    /// Override the budget of a heap, in bytes.
+    pub fn set_budget(&self, heap: usize, bytes: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.budgets[heap] = bytes;
+        usage.over_budget[heap] = false;
+    }
+
+    /// Set the budget of every heap as a fraction of its size.
+    pub fn set_budget_ratio(&self, ratio: f64) {
+        for (heap, info) in self.heaps.iter().enumerate() {
+            self.set_budget(heap, (info.size as f64 * ratio) as u64);
+        }
+    }
+
+    pub fn track(
+        self: &Arc<Self>,
+        category: MemoryCategory,
+        location: MemoryLocation,
+        size: u64,
+    ) -> TrackedAllocation {
+        let heap = match location {
+            MemoryLocation::DeviceLocal => self.device_local_heap,
+            MemoryLocation::HostVisible => self.host_visible_heap,
+        };
+
+        {
+            let mut usage = self.usage.lock().unwrap();
+            usage.bytes[heap][category.index()] += size;
+            usage.allocations[heap][category.index()] += 1;
+
+            let used = usage.bytes[heap].iter().sum::<u64>();
+            usage.peaks[heap] = usage.peaks[heap].max(used);
+
+            // Only warn when crossing the budget, not for every allocation after that
+            if used > usage.budgets[heap] && !usage.over_budget[heap] {
+                usage.over_budget[heap] = true;
+                warn!(
+                    "Memory heap {} over budget: {} used, budget is {} ({} total)",
+                    heap,
+                    format_bytes(used),
+                    format_bytes(usage.budgets[heap]),
+                    format_bytes(self.heaps[heap].size)
+                );
+            }
+        }
+
+        TrackedAllocation {
+            tracker: self.clone(),
+            category,
+            heap,
+            size,
+        }
+    }
+
+    pub fn track_buffer<B>(
+        self: &Arc<Self>,
+        category: MemoryCategory,
+        location: MemoryLocation,
+        buffer: &B,
+    ) -> TrackedAllocation
+    where
+        B: BufferAccess + ?Sized,
+    {
+        self.track(category, location, buffer.size() as u64)
+    }
+
+    /// Images are always device local, their size is estimated from their dimensions and format.
+    pub fn track_image<I>(
+        self: &Arc<Self>,
+        category: MemoryCategory,
+        image: &I,
+    ) -> TrackedAllocation
+    where
+        I: ImageAccess + ?Sized,
+    {
+        let dimensions = image.dimensions();
+        let texel_size = image.format().size().unwrap_or(4) as u64;
+
+        // Each mip level is a quarter of the previous one
+        let base = u64::from(dimensions.num_texels()) * texel_size * u64::from(image.samples());
+        let size = (0..image.mipmap_levels())
+            .map(|level| base >> (2 * level))
+            .sum();
+
+        self.track(category, MemoryLocation::DeviceLocal, size)
+    }
+
+    fn release(&self, category: MemoryCategory, heap: usize, size: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        usage.bytes[heap][category.index()] -= size;
+        usage.allocations[heap][category.index()] -= 1;
+
+        if usage.bytes[heap].iter().sum::<u64>() <= usage.budgets[heap] {
+            usage.over_budget[heap] = false;
+        }
+    }
+
+    /// Copy of the current usage, to log or attach to bug reports.
+    pub fn snapshot(&self) -> MemorySnapshot {
+        let usage = self.usage.lock().unwrap();
+
+        MemorySnapshot {
+            heaps: self
+                .heaps
+                .iter()
+                .enumerate()
+                .map(|(heap, info)| HeapSnapshot {
+                    size: info.size,
+                    device_local: info.device_local,
+                    budget: usage.budgets[heap],
+                    peak: usage.peaks[heap],
+                    categories: MemoryCategory::ALL
+                        .iter()
+                        .map(|category| CategorySnapshot {
+                            category: *category,
+                            bytes: usage.bytes[heap][category.index()],
+                            allocations: usage.allocations[heap][category.index()],
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// State of the memory at a given time.
+#[derive(Debug, Clone)]
+pub struct MemorySnapshot {
+    pub heaps: Vec<HeapSnapshot>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeapSnapshot {
+    pub size: u64,
+    pub device_local: bool,
+    pub budget: u64,
+    pub peak: u64,
+    pub categories: Vec<CategorySnapshot>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CategorySnapshot {
+    pub category: MemoryCategory,
+    pub bytes: u64,
+    pub allocations: usize,
+}
+
+impl HeapSnapshot {
+    pub fn used(&self) -> u64 {
+        self.categories.iter().map(|category| category.bytes).sum()
+    }
+}
+
+impl MemorySnapshot {
+    pub fn total_used(&self) -> u64 {
+        self.heaps.iter().map(HeapSnapshot::used).sum()
+    }
+
+    pub fn log(&self) {
+        for line in self.to_string().lines() {
+            info!("{}", line);
+        }
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for MemorySnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "GPU memory: {} used", format_bytes(self.total_used()))?;
+
+        for (index, heap) in self.heaps.iter().enumerate() {
+            writeln!(
+                f,
+                "Heap {} ({}): {} / {} used, budget {}, peak {}",
+                index,
+                if heap.device_local {
+                    "device local"
+                } else {
+                    "host"
+                },
+                format_bytes(heap.used()),
+                format_bytes(heap.size),
+                format_bytes(heap.budget),
+                format_bytes(heap.peak)
+            )?;
+
+            for category in heap.categories.iter().filter(|c| c.allocations > 0) {
+                writeln!(
+                    f,
+                    "  {:?}: {} in {} allocation(s)",
+                    category.category,
+                    format_bytes(category.bytes),
+                    category.allocations
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.2} {}", value, UNITS[unit])
+}
@@ -0,0 +1,312 @@
+use crate::math::{Aabb, BoundingSphere, Vec2, Vec3};
+use crate::renderer::memory_tracker::{
+    MemoryCategory, MemoryLocation, MemoryTracker, TrackedAllocation,
+};
+use std::sync::Arc;
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer};
+use vulkano::device::Device;
+use vulkano::impl_vertex;
+
+/// Vertices that can be put in a [Mesh], the position is needed for the bounds.
+pub trait MeshVertex: Copy + Send + Sync + 'static {
+    fn position(&self) -> Vec3;
+}
+
+/// Standard vertex of the engine, what every lit material expects.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// XYZ is the direction of increasing U, W the handedness of the bitangent
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+}
+
+impl_vertex!(Vertex, position, normal, tangent, uv);
+
+impl Vertex {
+    /// The tangent is left undefined, see [generate_tangents].
+    pub fn new(position: Vec3, normal: Vec3, uv: Vec2) -> Self {
+        Self {
+            position: position.to_array(),
+            normal: normal.to_array(),
+            tangent: [1.0, 0.0, 0.0, 1.0],
+            uv: uv.to_array(),
+        }
+    }
+}
+
+impl MeshVertex for Vertex {
+    #[inline]
+    fn position(&self) -> Vec3 {
+        Vec3::from(self.position)
+    }
+}
+
+/// Vertex of a mesh deformed by the joints of a skin, the standard one plus its joints.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// XYZ is the direction of increasing U, W the handedness of the bitangent
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+    /// Indices in the joints of the skin
+    pub joints: [u32; 4],
+    /// Sum to 1
+    pub weights: [f32; 4],
+}
+
+impl_vertex!(
+    SkinnedVertex,
+    position,
+    normal,
+    tangent,
+    uv,
+    joints,
+    weights
+);
+
+impl SkinnedVertex {
+    pub fn new(vertex: Vertex, joints: [u32; 4], weights: [f32; 4]) -> Self {
+        Self {
+            position: vertex.position,
+            normal: vertex.normal,
+            tangent: vertex.tangent,
+            uv: vertex.uv,
+            joints,
+            weights,
+        }
+    }
+}
+
+impl MeshVertex for SkinnedVertex {
+    #[inline]
+    fn position(&self) -> Vec3 {
+        Vec3::from(self.position)
+    }
+}
+
+/// Compute the tangents from the positions and texture coordinates, for the meshes that
+/// don't come with them. Needed by normal mapping.
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let edge1 = Vec3::from(vertices[b].position) - Vec3::from(vertices[a].position);
+        let edge2 = Vec3::from(vertices[c].position) - Vec3::from(vertices[a].position);
+        let delta_uv1 = Vec2::from(vertices[b].uv) - Vec2::from(vertices[a].uv);
+        let delta_uv2 = Vec2::from(vertices[c].uv) - Vec2::from(vertices[a].uv);
+
+        let determinant = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if determinant.abs() < 1e-8 {
+            // Degenerate texture coordinates
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) * r;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) * r;
+
+        for index in &[a, b, c] {
+            tangents[*index] += tangent;
+            bitangents[*index] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = Vec3::from(vertex.normal);
+        // Gram-Schmidt, make it orthogonal to the normal
+        let mut tangent = (tangents[i] - normal * normal.dot(tangents[i])).normalize();
+        if tangent == Vec3::ZERO {
+            // Anything perpendicular will do
+            tangent = normal.cross(Vec3::X);
+            if tangent.length_squared() < 1e-6 {
+                tangent = normal.cross(Vec3::Y);
+            }
+            tangent = tangent.normalize();
+        }
+
+        let handedness = if normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = tangent.extend(handedness).to_array();
+    }
+}
+
+/// Bounds in the mesh's local space, computed when it is created.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshBounds {
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+/// Displacements of the vertices of a mesh, blended in by a weight (blend shape).
+#[derive(Debug, Clone, Default)]
+pub struct MorphTarget {
+    pub name: String,
+    /// One per vertex
+    pub positions: Vec<Vec3>,
+    /// One per vertex, or empty to leave the normals alone
+    pub normals: Vec<Vec3>,
+}
+
+/// Geometry uploaded to the GPU, shared between every object that displays it.
+pub struct Mesh {
+    vertex_buffer: Arc<dyn BufferAccess + Send + Sync>,
+    vertex_count: u32,
+    index_buffer: Option<Arc<CpuAccessibleBuffer<[u32]>>>,
+    bounds: MeshBounds,
+    /// Position then normal displacement of each vertex, target after target
+    morph_buffer: Option<Arc<CpuAccessibleBuffer<[[f32; 4]]>>>,
+    morph_target_names: Vec<String>,
+
+    _allocations: Vec<TrackedAllocation>,
+}
+
+impl Mesh {
+    pub fn new<V: MeshVertex>(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        vertices: &[V],
+        indices: Option<&[u32]>,
+    ) -> Self {
+        let positions = vertices.iter().map(MeshVertex::position);
+        let bounds = MeshBounds {
+            aabb: Aabb::from_points(positions.clone()).expect("Can't create an empty mesh !"),
+            sphere: BoundingSphere::from_points(positions).unwrap(),
+        };
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            vertices.iter().copied(),
+        )
+        .expect("Failed to create vertex buffer !");
+        let mut allocations = vec![memory_tracker.track_buffer(
+            MemoryCategory::Mesh,
+            MemoryLocation::HostVisible,
+            &vertex_buffer,
+        )];
+
+        let index_buffer = indices.map(|indices| {
+            let index_buffer = CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::index_buffer(),
+                false,
+                indices.iter().copied(),
+            )
+            .expect("Failed to create index buffer !");
+            allocations.push(memory_tracker.track_buffer(
+                MemoryCategory::Mesh,
+                MemoryLocation::HostVisible,
+                &index_buffer,
+            ));
+            index_buffer
+        });
+
+        Self {
+            vertex_buffer,
+            vertex_count: vertices.len() as u32,
+            index_buffer,
+            bounds,
+            morph_buffer: None,
+            morph_target_names: Vec::new(),
+            _allocations: allocations,
+        }
+    }
+
+    /// Upload morph targets, they are only applied by the skinned pipeline.
+    /// The bounds don't account for them.
+    pub fn with_morph_targets(
+        mut self,
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        targets: &[MorphTarget],
+    ) -> Self {
+        if targets.is_empty() {
+            return self;
+        }
+
+        let vertex_count = self.vertex_count as usize;
+        let mut displacements = Vec::with_capacity(targets.len() * vertex_count * 2);
+        for target in targets {
+            assert_eq!(
+                target.positions.len(),
+                vertex_count,
+                "A morph target needs a displacement per vertex !"
+            );
+            for (index, position) in target.positions.iter().enumerate() {
+                let normal = target.normals.get(index).copied().unwrap_or(Vec3::ZERO);
+                displacements.push(position.extend(0.0).to_array());
+                displacements.push(normal.extend(0.0).to_array());
+            }
+        }
+
+        let morph_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::none()
+            },
+            false,
+            displacements.into_iter(),
+        )
+        .expect("Failed to create morph target buffer !");
+        self._allocations.push(memory_tracker.track_buffer(
+            MemoryCategory::Mesh,
+            MemoryLocation::HostVisible,
+            &morph_buffer,
+        ));
+
+        self.morph_buffer = Some(morph_buffer);
+        self.morph_target_names = targets.iter().map(|target| target.name.clone()).collect();
+        self
+    }
+
+    #[inline]
+    pub fn vertex_buffer(&self) -> Arc<dyn BufferAccess + Send + Sync> {
+        self.vertex_buffer.clone()
+    }
+
+    #[inline]
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    #[inline]
+    pub fn index_buffer(&self) -> Option<&Arc<CpuAccessibleBuffer<[u32]>>> {
+        self.index_buffer.as_ref()
+    }
+
+    #[inline]
+    pub fn bounds(&self) -> &MeshBounds {
+        &self.bounds
+    }
+
+    #[inline]
+    pub fn morph_buffer(&self) -> Option<&Arc<CpuAccessibleBuffer<[[f32; 4]]>>> {
+        self.morph_buffer.as_ref()
+    }
+
+    #[inline]
+    pub fn morph_target_count(&self) -> usize {
+        self.morph_target_names.len()
+    }
+
+    pub fn find_morph_target(&self, name: &str) -> Option<usize> {
+        self.morph_target_names
+            .iter()
+            .position(|target| target == name)
+    }
+}
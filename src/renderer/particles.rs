@@ -0,0 +1,764 @@
+use crate::math::{Mat4, Vec3, Vec4};
+use crate::renderer::compute::{ComputeProgram, ComputeRecorder, ComputeSet};
+use crate::renderer::frame_allocator::{FrameAllocation, FrameAllocator};
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::fullscreen;
+use crate::renderer::memory_tracker::{
+    MemoryCategory, MemoryLocation, MemoryTracker, TrackedAllocation,
+};
+use crate::renderer::mesh::Mesh;
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use crate::renderer::sprites::QuadVertex;
+use crate::renderer::texture::Texture;
+use crate::renderer::FRAMES_IN_FLIGHT;
+use crate::scene::{
+    CameraView, EmitterShape, NodeId, ParticleBlend, ParticleEffect, ParticleEmitter, Scene,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DrawIndexedIndirectCommand, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::{Device, Queue};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::depth_stencil::{Compare, DepthBounds, DepthStencil, Stencil};
+use vulkano::pipeline::vertex::SingleBufferDefinition;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+mod simulate_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/particle_simulate.comp"
+    }
+}
+
+mod spawn_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/particle_spawn.comp"
+    }
+}
+
+mod finalize_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "shaders/particle_finalize.comp"
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/particle.vert"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/particle.frag"
+    }
+}
+
+/// Samples of the curves over the life of the particles.
+/// Same as `CURVE_SAMPLES` in the particle shaders.
+const CURVE_SAMPLES: usize = 32;
+/// Work group size of the spawn and simulation shaders.
+const LOCAL_SIZE: [u32; 3] = [64, 1, 1];
+/// Longest time step simulated at once, long frames slow the effects down instead of
+/// making them jump
+const MAX_TIME_STEP: f32 = 0.1;
+const DOT_TEXTURE_SIZE: u32 = 32;
+/// Binding of the emitter uniforms in the simulation and spawn shaders.
+const SIMULATE_UNIFORMS: u32 = 3;
+const SPAWN_UNIFORMS: u32 = 2;
+/// Frame buffers an emitter keeps compute sets for, one per frame in flight and a spare for
+/// frames that run out of space.
+const CACHED_UNIFORM_BUFFERS: usize = FRAMES_IN_FLIGHT + 1;
+
+/// Same layout as `Particle` in the particle shaders.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct GpuParticle {
+    position_size: [f32; 4],
+    velocity_age: [f32; 4],
+    color: [f32; 4],
+    params: [f32; 4],
+}
+
+/// Same layout as `Emitter` in the particle shaders.
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct EmitterUniforms {
+    transform: [[f32; 4]; 4],
+    shape: [f32; 4],
+    velocity: [f32; 4],
+    variation: [f32; 4],
+    gravity: [f32; 4],
+    wind: [f32; 4],
+    attractor: [f32; 4],
+    time: [f32; 4],
+    counts: [u32; 4],
+    color_curve: [[f32; 4]; CURVE_SAMPLES],
+    scalar_curve: [[f32; 4]; CURVE_SAMPLES],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct FinalizePushConstants {
+    capacity: u32,
+    destination: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct DrawPushConstants {
+    view_projection: [[f32; 4]; 4],
+    camera_right: [f32; 4],
+    camera_up: [f32; 4],
+}
+
+/// GPU side of an emitter, its particles are ping-ponged between two buffers.
+struct EmitterState {
+    capacity: u32,
+    particles: [Arc<DeviceLocalBuffer<[GpuParticle]>>; 2],
+    /// Alive particles in each buffer
+    counters: Arc<DeviceLocalBuffer<[u32]>>,
+    draw_args: Arc<DeviceLocalBuffer<[DrawIndexedIndirectCommand]>>,
+    /// Buffer holding the particles of the last frame
+    source: usize,
+    /// The counters hold garbage until the first frame clears them
+    cleared: bool,
+    /// Fraction of particle left to spawn
+    spawn_accumulator: f32,
+    /// Drawn from the destination buffer, for both destinations
+    draw_sets: [Arc<dyn DescriptorSet + Send + Sync>; 2],
+    finalize_set: Arc<ComputeSet>,
+    /// Most recently used last
+    compute_sets: Vec<EmitterSets>,
+    texture: Arc<Texture>,
+    _allocations: Vec<TrackedAllocation>,
+}
+
+/// Compute sets of an emitter reading its uniforms from a frame buffer, the uniforms of each
+/// frame are picked with a dynamic offset.
+struct EmitterSets {
+    uniforms: Arc<CpuAccessibleBuffer<[u8]>>,
+    /// By source buffer
+    simulate: [Arc<ComputeSet>; 2],
+    /// By destination buffer
+    spawn: [Arc<ComputeSet>; 2],
+}
+
+/// The work of an emitter for a frame.
+struct EmitterJob {
+    uniform_offset: u32,
+    simulate_set: Arc<ComputeSet>,
+    spawn_set: Arc<ComputeSet>,
+    finalize_set: Arc<ComputeSet>,
+    counters: Option<Arc<DeviceLocalBuffer<[u32]>>>,
+    capacity: u32,
+    destination: usize,
+    spawn_count: u32,
+    draw_args: Arc<DeviceLocalBuffer<[DrawIndexedIndirectCommand]>>,
+    draw_set: Arc<dyn DescriptorSet + Send + Sync>,
+    additive: bool,
+    /// From the camera, sorted back to front
+    distance: f32,
+}
+
+/// What the particles of a frame need, prepared before recording.
+pub struct ParticleFrame {
+    jobs: Vec<EmitterJob>,
+    view_projection: Mat4,
+    camera_right: Vec3,
+    camera_up: Vec3,
+}
+
+impl ParticleFrame {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+}
+
+/// Spawns, simulates and compacts the particles of the emitters of the scene in compute
+/// shaders, then draws them as camera facing quads. Nothing about the particles goes
+/// through the CPU, only the settings of the emitters.
+pub struct ParticleSystem {
+    queue: Arc<Queue>,
+    memory_tracker: Arc<MemoryTracker>,
+    simulate_program: ComputeProgram,
+    spawn_program: ComputeProgram,
+    finalize_program: ComputeProgram,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    quad: Mesh,
+    sampler: Arc<Sampler>,
+    /// Used by the effects without texture
+    dot_texture: Arc<Texture>,
+    emitters: HashMap<NodeId, EmitterState>,
+    last_update: Option<Instant>,
+    /// Seconds since the first frame, animates the turbulence
+    elapsed: f32,
+    /// Changes every frame so the particles spawned get different random values
+    seed: u32,
+}
+
+impl ParticleSystem {
+    /// The particles are drawn in the HDR render pass.
+    pub fn new(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        hdr_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let device = queue.device();
+
+        let simulate_shader = simulate_shader::Shader::load(device.clone())
+            .expect("Failed to create compute shader !");
+        let spawn_shader =
+            spawn_shader::Shader::load(device.clone()).expect("Failed to create compute shader !");
+        let finalize_shader = finalize_shader::Shader::load(device.clone())
+            .expect("Failed to create compute shader !");
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .expect("Failed to create particle sampler !");
+
+        Self {
+            queue: queue.clone(),
+            memory_tracker: memory_tracker.clone(),
+            simulate_program: ComputeProgram::with_dynamic_buffers(
+                device,
+                &simulate_shader.main_entry_point(),
+                &(),
+                LOCAL_SIZE,
+                &[(0, SIMULATE_UNIFORMS as usize)],
+            ),
+            spawn_program: ComputeProgram::with_dynamic_buffers(
+                device,
+                &spawn_shader.main_entry_point(),
+                &(),
+                LOCAL_SIZE,
+                &[(0, SPAWN_UNIFORMS as usize)],
+            ),
+            finalize_program: ComputeProgram::new(
+                device,
+                &finalize_shader.main_entry_point(),
+                &(),
+                [1, 1, 1],
+            ),
+            pipeline: Self::create_pipeline(device, hdr_render_pass),
+            quad: QuadVertex::unit_quad(device, memory_tracker),
+            sampler,
+            dot_texture: Arc::new(Self::create_dot_texture(queue, memory_tracker)),
+            emitters: HashMap::new(),
+            last_update: None,
+            elapsed: 0.0,
+            seed: 0,
+        }
+    }
+
+    /// The pipeline is built against the render pass, the particles are kept.
+    pub fn set_render_pass(&mut self, hdr_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>) {
+        self.pipeline = Self::create_pipeline(self.queue.device(), hdr_render_pass);
+    }
+
+    fn create_pipeline(
+        device: &Arc<Device>,
+        hdr_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        // Additive particles write no alpha, the same blending does both
+        let premultiplied = AttachmentBlend {
+            enabled: true,
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_source: BlendFactor::One,
+            alpha_destination: BlendFactor::OneMinusSrcAlpha,
+            ..AttachmentBlend::pass_through()
+        };
+        // Hidden by the scene, without hiding each other
+        let depth_stencil = DepthStencil {
+            depth_write: false,
+            depth_compare: Compare::Less,
+            depth_bounds_test: DepthBounds::Disabled,
+            stencil_front: Stencil::default(),
+            stencil_back: Stencil::default(),
+        };
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create particle pipeline layout !");
+
+        Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(SingleBufferDefinition::<QuadVertex>::new())
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .cull_mode_disabled()
+                .depth_stencil(depth_stencil)
+                .blend_collective(premultiplied)
+                .render_pass(Subpass::from(hdr_render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create particle pipeline !"),
+        )
+    }
+
+    /// White disc fading out towards its edge.
+    fn create_dot_texture(queue: &Arc<Queue>, memory_tracker: &Arc<MemoryTracker>) -> Texture {
+        let size = DOT_TEXTURE_SIZE;
+        let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let dx = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let dy = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let falloff = (1.0 - (dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+                let alpha = (falloff * falloff * 255.0).round() as u8;
+                pixels.extend_from_slice(&[255, 255, 255, alpha]);
+            }
+        }
+        Texture::from_rgba8(queue, memory_tracker, [size, size], &pixels, false)
+    }
+
+    /// Advance the time and prepare the work of every emitter of the scene, the uniforms go
+    /// into the frame allocator. The bursts of the emitters are consumed.
+    pub fn prepare(
+        &mut self,
+        scene: &mut Scene,
+        camera_view: &CameraView,
+        frame_allocator: &mut FrameAllocator,
+        stats: &mut FrameStats,
+    ) -> ParticleFrame {
+        let now = Instant::now();
+        let time_step = self.last_update.map_or(0.0, |last| {
+            now.duration_since(last).as_secs_f32().min(MAX_TIME_STEP)
+        });
+        self.last_update = Some(now);
+        self.elapsed += time_step;
+
+        // The particles of removed emitters go with them
+        self.emitters
+            .retain(|node, _| scene.particle_emitter(*node).is_some());
+
+        let emitters: Vec<(NodeId, ParticleEmitter)> = scene
+            .particle_emitters()
+            .map(|(node, emitter)| (node, emitter.clone()))
+            .collect();
+
+        let mut jobs = Vec::with_capacity(emitters.len());
+        for (node, emitter) in emitters {
+            let effect = &emitter.effect;
+            let transform = scene.graph_mut().world_matrix(node);
+            if let Some(emitter) = scene.particle_emitter_mut(node) {
+                emitter.burst = 0;
+            }
+
+            self.seed = self.seed.wrapping_add(1);
+            let texture = effect
+                .texture
+                .clone()
+                .unwrap_or_else(|| self.dot_texture.clone());
+            self.update_emitter_state(node, effect, &texture);
+            let state = self.emitters.get_mut(&node).unwrap();
+
+            let mut spawn_count = emitter.burst;
+            if emitter.emitting {
+                state.spawn_accumulator += effect.spawn_rate * time_step;
+                spawn_count += state.spawn_accumulator as u32;
+                state.spawn_accumulator = state.spawn_accumulator.fract();
+            }
+            let spawn_count = spawn_count.min(state.capacity);
+
+            let source = state.source;
+            let destination = 1 - source;
+            state.source = destination;
+            let counters = if state.cleared {
+                None
+            } else {
+                state.cleared = true;
+                Some(state.counters.clone())
+            };
+
+            let uniforms = emitter_uniforms(
+                effect,
+                &transform,
+                [time_step, self.elapsed],
+                [state.capacity, spawn_count, self.seed, source as u32],
+            );
+            let uniforms = frame_allocator.allocate_uniform(&uniforms);
+            self.update_emitter_sets(node, &uniforms);
+            let state = &self.emitters[&node];
+            let sets = state.compute_sets.last().unwrap();
+
+            jobs.push(EmitterJob {
+                uniform_offset: uniforms.dynamic_offset(),
+                simulate_set: sets.simulate[source].clone(),
+                spawn_set: sets.spawn[destination].clone(),
+                finalize_set: state.finalize_set.clone(),
+                counters,
+                capacity: state.capacity,
+                destination,
+                spawn_count,
+                draw_args: state.draw_args.clone(),
+                draw_set: state.draw_sets[destination].clone(),
+                additive: effect.blend == ParticleBlend::Additive,
+                distance: transform.translation().distance(camera_view.position),
+            });
+        }
+
+        // Back to front, the particles of an emitter aren't sorted
+        jobs.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        stats.particle_emitters += jobs.len();
+
+        let camera = camera_view.view.inverse();
+        ParticleFrame {
+            jobs,
+            view_projection: camera_view.view_projection,
+            camera_right: camera.transform_vector(Vec3::X).normalize(),
+            camera_up: camera.transform_vector(Vec3::Y).normalize(),
+        }
+    }
+
+    /// Buffers of the emitter, recreated when its capacity or texture changes.
+    fn update_emitter_state(
+        &mut self,
+        node: NodeId,
+        effect: &ParticleEffect,
+        texture: &Arc<Texture>,
+    ) {
+        let outdated = self.emitters.get(&node).is_none_or(|state| {
+            state.capacity != effect.capacity || !Arc::ptr_eq(&state.texture, texture)
+        });
+        if outdated {
+            let state = self.create_emitter_state(effect.capacity, texture);
+            self.emitters.insert(node, state);
+        }
+    }
+
+    /// Move the compute sets of the emitter reading from the frame buffer of the uniforms
+    /// last, they are created the first time the emitter meets that buffer.
+    fn update_emitter_sets(&mut self, node: NodeId, uniforms: &FrameAllocation) {
+        let state = &self.emitters[&node];
+        match state
+            .compute_sets
+            .iter()
+            .position(|sets| Arc::ptr_eq(&sets.uniforms, uniforms.buffer()))
+        {
+            Some(index) => {
+                let state = self.emitters.get_mut(&node).unwrap();
+                let sets = state.compute_sets.remove(index);
+                state.compute_sets.push(sets);
+            }
+            None => {
+                let sets = self.create_emitter_sets(state, uniforms);
+                let state = self.emitters.get_mut(&node).unwrap();
+                if state.compute_sets.len() == CACHED_UNIFORM_BUFFERS {
+                    state.compute_sets.remove(0);
+                }
+                state.compute_sets.push(sets);
+            }
+        }
+    }
+
+    fn create_emitter_sets(&self, state: &EmitterState, uniforms: &FrameAllocation) -> EmitterSets {
+        let uniform_slice = Arc::new(uniforms.dynamic_slice());
+        let create_simulate_set = |source: usize| {
+            self.simulate_program
+                .bindings(0)
+                .storage_buffer(0, state.particles[source].clone())
+                .storage_buffer(1, state.particles[1 - source].clone())
+                .storage_buffer(2, state.counters.clone())
+                .dynamic_uniform_buffer(SIMULATE_UNIFORMS, uniform_slice.clone())
+                .build()
+                .expect("Failed to create particle simulation descriptor set !")
+        };
+        let create_spawn_set = |destination: usize| {
+            self.spawn_program
+                .bindings(0)
+                .storage_buffer(0, state.particles[destination].clone())
+                .storage_buffer(1, state.counters.clone())
+                .dynamic_uniform_buffer(SPAWN_UNIFORMS, uniform_slice.clone())
+                .build()
+                .expect("Failed to create particle spawn descriptor set !")
+        };
+
+        EmitterSets {
+            uniforms: uniforms.buffer().clone(),
+            simulate: [create_simulate_set(0), create_simulate_set(1)],
+            spawn: [create_spawn_set(0), create_spawn_set(1)],
+        }
+    }
+
+    fn create_emitter_state(&self, capacity: u32, texture: &Arc<Texture>) -> EmitterState {
+        let device = self.queue.device();
+        let families = Some(self.queue.family());
+        let storage = BufferUsage {
+            storage_buffer: true,
+            ..BufferUsage::none()
+        };
+
+        let create_particles = || {
+            DeviceLocalBuffer::<[GpuParticle]>::array(
+                device.clone(),
+                capacity.max(1) as usize,
+                storage,
+                families,
+            )
+            .expect("Failed to create particle buffer !")
+        };
+        let particles = [create_particles(), create_particles()];
+        let counters = DeviceLocalBuffer::<[u32]>::array(
+            device.clone(),
+            2,
+            BufferUsage {
+                storage_buffer: true,
+                transfer_destination: true,
+                ..BufferUsage::none()
+            },
+            families,
+        )
+        .expect("Failed to create particle counters !");
+        let draw_args = DeviceLocalBuffer::<[DrawIndexedIndirectCommand]>::array(
+            device.clone(),
+            1,
+            BufferUsage {
+                storage_buffer: true,
+                indirect_buffer: true,
+                ..BufferUsage::none()
+            },
+            families,
+        )
+        .expect("Failed to create particle draw arguments !");
+
+        let allocations = vec![
+            self.track(&*particles[0]),
+            self.track(&*particles[1]),
+            self.track(&*counters),
+            self.track(&*draw_args),
+        ];
+
+        let layout = self.pipeline.descriptor_set_layout(0).unwrap().clone();
+        let create_draw_set = |buffer: &Arc<DeviceLocalBuffer<[GpuParticle]>>| {
+            Arc::new(
+                PersistentDescriptorSet::start(layout.clone())
+                    .add_buffer(buffer.clone())
+                    .unwrap()
+                    .add_sampled_image(texture.image().clone(), self.sampler.clone())
+                    .unwrap()
+                    .build()
+                    .expect("Failed to create particle descriptor set !"),
+            ) as Arc<dyn DescriptorSet + Send + Sync>
+        };
+        let draw_sets = [
+            create_draw_set(&particles[0]),
+            create_draw_set(&particles[1]),
+        ];
+        let finalize_set = self
+            .finalize_program
+            .bindings(0)
+            .storage_buffer(0, counters.clone())
+            .storage_buffer(1, draw_args.clone())
+            .build()
+            .expect("Failed to create particle finalize descriptor set !");
+
+        EmitterState {
+            capacity,
+            particles,
+            counters,
+            draw_args,
+            source: 0,
+            cleared: false,
+            spawn_accumulator: 0.0,
+            draw_sets,
+            finalize_set,
+            compute_sets: Vec::new(),
+            texture: texture.clone(),
+            _allocations: allocations,
+        }
+    }
+
+    #[inline]
+    fn track<B: vulkano::buffer::BufferAccess>(&self, buffer: &B) -> TrackedAllocation {
+        self.memory_tracker
+            .track_buffer(MemoryCategory::Other, MemoryLocation::DeviceLocal, buffer)
+    }
+
+    /// Has to be outside of any render pass, before the particles are drawn.
+    pub fn record_simulation(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        frame: &ParticleFrame,
+    ) -> AutoCommandBufferBuilder {
+        if frame.jobs.is_empty() {
+            return builder;
+        }
+
+        // Each step of every emitter at once, so only one barrier separates two steps
+        let mut recorder = ComputeRecorder::new(self.queue.device(), self.queue.family());
+        for job in &frame.jobs {
+            if let Some(counters) = &job.counters {
+                recorder.fill_buffer(counters.clone(), 0);
+            }
+        }
+        recorder.barrier();
+
+        // Survivors first, so spawning never overwrites a live particle
+        for job in &frame.jobs {
+            recorder.dispatch(
+                &self.simulate_program,
+                [job.capacity, 1, 1],
+                job.simulate_set.clone(),
+                &[job.uniform_offset],
+                (),
+            );
+        }
+        recorder.barrier();
+
+        for job in frame.jobs.iter().filter(|job| job.spawn_count > 0) {
+            recorder.dispatch(
+                &self.spawn_program,
+                [job.spawn_count, 1, 1],
+                job.spawn_set.clone(),
+                &[job.uniform_offset],
+                (),
+            );
+        }
+        recorder.barrier();
+
+        for job in &frame.jobs {
+            recorder.dispatch_groups(
+                &self.finalize_program,
+                [1, 1, 1],
+                job.finalize_set.clone(),
+                &[],
+                FinalizePushConstants {
+                    capacity: job.capacity,
+                    destination: job.destination as u32,
+                },
+            );
+        }
+
+        recorder.execute(builder)
+    }
+
+    /// Has to be inside the HDR render pass, after the opaque geometry.
+    pub fn record_draw(
+        &self,
+        mut builder: AutoCommandBufferBuilder,
+        frame: &ParticleFrame,
+        dimensions: [u32; 2],
+        stats: &mut FrameStats,
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        };
+        let index_buffer = self.quad.index_buffer().unwrap();
+
+        for job in &frame.jobs {
+            let push_constants = DrawPushConstants {
+                view_projection: frame.view_projection.into(),
+                camera_right: frame.camera_right.extend(0.0).to_array(),
+                camera_up: frame
+                    .camera_up
+                    .extend(job.additive as u32 as f32)
+                    .to_array(),
+            };
+            builder = builder
+                .draw_indexed_indirect(
+                    self.pipeline.clone(),
+                    &dynamic_state,
+                    vec![self.quad.vertex_buffer()],
+                    index_buffer.clone(),
+                    job.draw_args.clone(),
+                    job.draw_set.clone(),
+                    push_constants,
+                )
+                .expect("Failed to record particle draw !");
+            stats.draw_calls += 1;
+        }
+
+        builder
+    }
+}
+
+fn emitter_uniforms(
+    effect: &ParticleEffect,
+    transform: &Mat4,
+    time: [f32; 2],
+    counts: [u32; 4],
+) -> EmitterUniforms {
+    let shape = match effect.shape {
+        EmitterShape::Point => [0.0; 4],
+        EmitterShape::Sphere { radius } => [1.0, radius, 0.0, 0.0],
+        EmitterShape::Box { half_extents } => [2.0, half_extents.x, half_extents.y, half_extents.z],
+    };
+    let forces = &effect.forces;
+    let attractor = match forces.attractor {
+        Some((position, strength)) => position.extend(strength).to_array(),
+        None => [0.0; 4],
+    };
+
+    let mut color_curve = [[0.0; 4]; CURVE_SAMPLES];
+    let mut scalar_curve = [[0.0; 4]; CURVE_SAMPLES];
+    for i in 0..CURVE_SAMPLES {
+        let t = i as f32 / (CURVE_SAMPLES - 1) as f32;
+        color_curve[i] = effect.color_over_life.sample(t).to_array();
+        scalar_curve[i] = Vec4::new(
+            effect.size_over_life.sample(t),
+            effect.speed_over_life.sample(t),
+            0.0,
+            0.0,
+        )
+        .to_array();
+    }
+
+    EmitterUniforms {
+        transform: (*transform).into(),
+        shape,
+        velocity: effect.velocity.extend(effect.spread).to_array(),
+        variation: [
+            effect.lifetime.0,
+            effect.lifetime.1,
+            effect.speed_variation,
+            effect.spin,
+        ],
+        gravity: forces.gravity.extend(forces.drag).to_array(),
+        wind: forces.wind.extend(forces.turbulence).to_array(),
+        attractor,
+        time: [time[0], time[1], 0.0, 0.0],
+        counts,
+        color_curve,
+        scalar_curve,
+    }
+}
@@ -0,0 +1,130 @@
+use crate::renderer::instancing::InstanceData;
+use crate::renderer::mesh::{SkinnedVertex, Vertex};
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderInterfaceError, ShaderReflection};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/pbr.vert"
+    }
+}
+
+mod skinned_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/pbr_skinned.vert"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/pbr.frag"
+    }
+}
+
+/// Forward metallic-roughness pipeline shared by every [PbrMaterial](crate::renderer::material::PbrMaterial),
+/// they only differ by their descriptor set.
+pub struct PbrPipeline {
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    /// Same shading and sets, plus the joint matrices of a skin
+    skinned_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+impl PbrPipeline {
+    pub fn new(
+        device: &Arc<Device>,
+        swap_chain_extent: [u32; 2],
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<Self, ShaderInterfaceError> {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let skinned_vert_shader = skinned_vertex_shader::Shader::load(device.clone())
+            .expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        // Catch interface mismatches before vulkano or the validation layers do
+        let vertex_reflection = ShaderReflection::graphics(&vert_shader.main_entry_point());
+        vertex_reflection.check_instanced_vertex_input::<Vertex, InstanceData>()?;
+        let layout = MaterialLayout::merge(&[
+            vertex_reflection,
+            ShaderReflection::graphics(&frag_shader.main_entry_point()),
+        ])?;
+        let skinned_vertex_reflection =
+            ShaderReflection::graphics(&skinned_vert_shader.main_entry_point());
+        skinned_vertex_reflection.check_instanced_vertex_input::<SkinnedVertex, InstanceData>()?;
+        // The joint matrices and morph targets come on top of the sets of the material
+        let skinned_layout = MaterialLayout::merge(&[
+            skinned_vertex_reflection,
+            ShaderReflection::graphics(&frag_shader.main_entry_point()),
+        ])?;
+
+        let dimensions = [swap_chain_extent[0] as f32, swap_chain_extent[1] as f32];
+        let viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions,
+            depth_range: 0.0..1.0,
+        };
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<Vertex, InstanceData>::new())
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .primitive_restart(false)
+                .viewports(vec![viewport.clone()])
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .depth_clamp(false)
+                .depth_stencil_simple_depth()
+                .polygon_mode_fill()
+                .cull_mode_back()
+                // Meshes are counter clockwise, like glTF, the projection flips Y
+                .front_face_counter_clockwise()
+                .blend_pass_through()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout.create_pipeline_layout(device)?)?,
+        );
+        let skinned_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<SkinnedVertex, InstanceData>::new())
+                .vertex_shader(skinned_vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .primitive_restart(false)
+                .viewports(vec![viewport])
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .depth_clamp(false)
+                .depth_stencil_simple_depth()
+                .polygon_mode_fill()
+                .cull_mode_back()
+                .front_face_counter_clockwise()
+                .blend_pass_through()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(
+                    device.clone(),
+                    skinned_layout.create_pipeline_layout(device)?,
+                )?,
+        );
+
+        Ok(Self {
+            pipeline,
+            skinned_pipeline,
+        })
+    }
+
+    pub fn pipeline(&self) -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
+        self.pipeline.clone()
+    }
+
+    /// For the meshes made of [SkinnedVertex], the joint matrices go in the
+    /// [JOINT_SET](crate::renderer::skinning::JOINT_SET).
+    pub fn skinned_pipeline(&self) -> Arc<dyn GraphicsPipelineAbstract + Send + Sync> {
+        self.skinned_pipeline.clone()
+    }
+}
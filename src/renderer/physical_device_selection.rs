@@ -8,13 +8,16 @@ use winit::window::Window;
 pub struct QueueFamilyId {
     pub graphics: u32,
     pub presentation: u32,
+    /// The graphics family when there is no dedicated compute one
+    pub compute: u32,
 }
 
 impl QueueFamilyId {
-    pub fn new(graphics: u32, presentation: u32) -> Self {
+    pub fn new(graphics: u32, presentation: u32, compute: u32) -> Self {
         Self {
             graphics,
             presentation,
+            compute,
         }
     }
 }
@@ -24,6 +27,8 @@ struct QueueFamilyIdBuilder {
     graphics_score: usize,
     presentation: Option<u32>,
     presentation_score: usize,
+    compute: Option<u32>,
+    compute_score: usize,
 }
 
 impl QueueFamilyIdBuilder {
@@ -33,6 +38,8 @@ impl QueueFamilyIdBuilder {
             graphics_score: 0,
             presentation: None,
             presentation_score: 0,
+            compute: None,
+            compute_score: 0,
         }
     }
 
@@ -66,14 +73,28 @@ impl QueueFamilyIdBuilder {
         }
     }
 
+    /// Only families without graphics, they run alongside the graphics queue.
+    pub fn try_set_compute(&mut self, family: &QueueFamily) {
+        let score = Self::rank_queue(family) + 1;
+        if score > self.compute_score {
+            self.compute_score = score;
+            self.compute = Some(family.id());
+        }
+    }
+
     pub fn is_complete(&self) -> bool {
         self.graphics.is_some() && self.presentation.is_some()
     }
 }
 
-impl Into<QueueFamilyId> for QueueFamilyIdBuilder {
-    fn into(self) -> QueueFamilyId {
-        QueueFamilyId::new(self.graphics.unwrap(), self.presentation.unwrap())
+impl From<QueueFamilyIdBuilder> for QueueFamilyId {
+    fn from(builder: QueueFamilyIdBuilder) -> Self {
+        let graphics = builder.graphics.unwrap();
+        QueueFamilyId::new(
+            graphics,
+            builder.presentation.unwrap(),
+            builder.compute.unwrap_or(graphics),
+        )
     }
 }
 
@@ -81,10 +102,10 @@ pub fn pick_physical_device<'a>(
     instance: &'a Arc<Instance>,
     surface: &Arc<Surface<Window>>,
 ) -> PhysicalDevice<'a> {
-    PhysicalDevice::enumerate(&instance)
+    PhysicalDevice::enumerate(instance)
         .filter(|device| {
             trace!("Trying device: {:?}", device.name());
-            is_device_suitable(surface, &device)
+            is_device_suitable(surface, device)
         })
         .map(|device| {
             let score = score_device(&device);
@@ -153,8 +174,8 @@ pub fn find_queue_families(
             families_id.try_set_presentation(&queue_family)
         }
 
-        if families_id.is_complete() {
-            break;
+        if queue_family.supports_compute() && !queue_family.supports_graphics() {
+            families_id.try_set_compute(&queue_family);
         }
     }
 
@@ -0,0 +1,517 @@
+use crate::renderer::display_output::{DisplayOutput, HdrDisplaySettings};
+use crate::renderer::fullscreen::{self, vertex_shader, FullscreenPipeline};
+use crate::renderer::gpu_profiler::GpuProfiler;
+use crate::renderer::hdr_target::{HdrTarget, HDR_FORMAT};
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use crate::renderer::swapchain_wrapper::SwapChainWrapper;
+use crate::renderer::tonemapping::{TonemapPass, TonemapSettings};
+use bloom::Bloom;
+use output::OutputPass;
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::format::ClearValue;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::AttachmentImage;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::single_pass_renderpass;
+
+mod bloom;
+mod output;
+
+mod effects_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/post_effects.frag"
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    /// Brightness above which pixels bloom
+    pub threshold: f32,
+    /// Softens the threshold, as a fraction of it
+    pub knee: f32,
+    /// How much of the bloom is added to the scene
+    pub intensity: f32,
+    /// Spread of the upsampling filter, in texels
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.04,
+            radius: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FxaaSettings {
+    pub enabled: bool,
+    /// How much aliasing smaller than a pixel is removed, from 0 to 1
+    pub subpixel: f32,
+    /// Contrast an edge needs, relative to its brightest side
+    pub edge_threshold: f32,
+    /// Contrast below which dark edges are ignored
+    pub edge_threshold_min: f32,
+}
+
+impl Default for FxaaSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            subpixel: 0.75,
+            edge_threshold: 0.166,
+            edge_threshold_min: 0.0833,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChromaticAberrationSettings {
+    pub enabled: bool,
+    /// Split between the channels, grows towards the edges of the screen
+    pub intensity: f32,
+}
+
+impl Default for ChromaticAberrationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.02,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VignetteSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    /// Higher values darken the corners faster
+    pub smoothness: f32,
+    /// 1 is a circle, 0 follows the shape of the screen
+    pub roundness: f32,
+}
+
+impl Default for VignetteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            intensity: 0.35,
+            smoothness: 1.5,
+            roundness: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FilmGrainSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    /// How much less grain there is in the highlights, from 0 to 1
+    pub response: f32,
+}
+
+impl Default for FilmGrainSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.04,
+            response: 0.8,
+        }
+    }
+}
+
+/// Full screen pass applied on the tonemapped image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PostEffect {
+    Fxaa(FxaaSettings),
+    ChromaticAberration(ChromaticAberrationSettings),
+    Vignette(VignetteSettings),
+    FilmGrain(FilmGrainSettings),
+}
+
+impl PostEffect {
+    #[inline]
+    pub fn enabled(&self) -> bool {
+        match self {
+            PostEffect::Fxaa(settings) => settings.enabled,
+            PostEffect::ChromaticAberration(settings) => settings.enabled,
+            PostEffect::Vignette(settings) => settings.enabled,
+            PostEffect::FilmGrain(settings) => settings.enabled,
+        }
+    }
+
+    /// Effect id and parameters, as laid out in the shader.
+    fn shader_params(&self) -> (u32, [f32; 4]) {
+        match self {
+            PostEffect::Fxaa(settings) => (
+                0,
+                [
+                    settings.subpixel,
+                    settings.edge_threshold,
+                    settings.edge_threshold_min,
+                    0.0,
+                ],
+            ),
+            PostEffect::ChromaticAberration(settings) => (1, [settings.intensity, 0.0, 0.0, 0.0]),
+            PostEffect::Vignette(settings) => (
+                2,
+                [
+                    settings.intensity,
+                    settings.smoothness,
+                    settings.roundness,
+                    0.0,
+                ],
+            ),
+            PostEffect::FilmGrain(settings) => {
+                (3, [settings.intensity, settings.response, 0.0, 0.0])
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessSettings {
+    /// Applied on the HDR scene, before tonemapping
+    pub bloom: BloomSettings,
+    /// Applied in order after tonemapping, the disabled ones are skipped
+    pub effects: Vec<PostEffect>,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            bloom: BloomSettings::default(),
+            effects: vec![
+                PostEffect::Fxaa(FxaaSettings::default()),
+                PostEffect::ChromaticAberration(ChromaticAberrationSettings::default()),
+                PostEffect::Vignette(VignetteSettings::default()),
+                PostEffect::FilmGrain(FilmGrainSettings::default()),
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct EffectPushConstants {
+    params: [f32; 4],
+    texel_size: [f32; 2],
+    effect: u32,
+    seed: f32,
+}
+
+/// Filtered and clamped, shared by the passes reading another target.
+fn linear_sampler(device: &Arc<Device>) -> Arc<Sampler> {
+    Sampler::new(
+        device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .expect("Failed to create post-processing sampler !")
+}
+
+/// Target the effects ping-pong between, display referred but still linear.
+struct PostTarget {
+    image: Arc<AttachmentImage>,
+    framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    /// Keeps the content, to draw on top of it
+    overlay_framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    /// Reads the target in the effects pipeline
+    effect_set: Arc<dyn DescriptorSet + Send + Sync>,
+    _allocation: TrackedAllocation,
+}
+
+/// Everything between the HDR target and the swap chain: bloom, tonemapping, the effects
+/// and the encoding for the display.
+pub struct PostProcessor {
+    bloom: Bloom,
+    tonemap_pass: TonemapPass,
+    effects_pipeline: Arc<FullscreenPipeline>,
+    targets: [PostTarget; 2],
+    overlay_render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    output_pass: OutputPass,
+    output: DisplayOutput,
+    dimensions: [u32; 2],
+    /// Target holding the end of the chain of the last recorded frame
+    last_target: usize,
+    /// Changes the grain every frame
+    frame_index: u32,
+}
+
+impl PostProcessor {
+    /// Has to be recreated with the swap chain and the HDR target.
+    pub fn new(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        swap_chain: &SwapChainWrapper,
+        hdr_target: &HdrTarget,
+    ) -> Self {
+        let dimensions = swap_chain.dimensions();
+        let sampler = linear_sampler(device);
+
+        // Nothing is blended, every pixel is overwritten
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: DontCare,
+                        store: Store,
+                        format: HDR_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )
+            .unwrap(),
+        );
+
+        // Blended over the end of the chain
+        let overlay_render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Load,
+                        store: Store,
+                        format: HDR_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )
+            .unwrap(),
+        );
+
+        let bloom = Bloom::new(device, memory_tracker, hdr_target.color(), dimensions);
+        let tonemap_pass =
+            TonemapPass::new(device, &render_pass, hdr_target, bloom.image(), &sampler);
+        let effects_pipeline = Self::create_effects_pipeline(device, &render_pass);
+
+        let create_target = || {
+            let image = AttachmentImage::sampled(device.clone(), dimensions, HDR_FORMAT)
+                .expect("Failed to create post-processing target !");
+            let framebuffer = Arc::new(
+                Framebuffer::start(render_pass.clone())
+                    .add(image.clone())
+                    .unwrap()
+                    .build()
+                    .expect("Failed to create post-processing framebuffer !"),
+            );
+            let overlay_framebuffer = Arc::new(
+                Framebuffer::start(overlay_render_pass.clone())
+                    .add(image.clone())
+                    .unwrap()
+                    .build()
+                    .expect("Failed to create overlay framebuffer !"),
+            );
+            let layout = effects_pipeline.descriptor_set_layout(0).unwrap().clone();
+            let effect_set = Arc::new(
+                PersistentDescriptorSet::start(layout)
+                    .add_sampled_image(image.clone(), sampler.clone())
+                    .unwrap()
+                    .build()
+                    .expect("Failed to create post-processing descriptor set !"),
+            );
+
+            PostTarget {
+                _allocation: memory_tracker.track_image(MemoryCategory::RenderTarget, &image),
+                image,
+                framebuffer,
+                overlay_framebuffer,
+                effect_set,
+            }
+        };
+        let targets = [create_target(), create_target()];
+
+        let output_pass = OutputPass::new(
+            device,
+            swap_chain.render_pass(),
+            [&targets[0].image, &targets[1].image],
+            &sampler,
+            swap_chain.output(),
+            swap_chain.needs_srgb_encoding(),
+        );
+
+        Self {
+            bloom,
+            tonemap_pass,
+            effects_pipeline,
+            targets,
+            overlay_render_pass,
+            output_pass,
+            output: swap_chain.output(),
+            dimensions,
+            last_target: 0,
+            frame_index: 0,
+        }
+    }
+
+    fn create_effects_pipeline(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Arc<FullscreenPipeline> {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = effects_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create post effects pipeline layout !");
+
+        Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create post effects pipeline !"),
+        )
+    }
+
+    /// Bloom, tonemapping and the effects, outside of any render pass. The output pass
+    /// then copies the result to the screen.
+    pub fn record(
+        &mut self,
+        mut builder: AutoCommandBufferBuilder,
+        profiler: &mut GpuProfiler,
+        settings: &PostProcessSettings,
+        tonemap_settings: &TonemapSettings,
+        hdr_settings: &HdrDisplaySettings,
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(self.dimensions)]),
+            ..DynamicState::none()
+        };
+
+        builder = profiler.begin_scope(builder, "Bloom");
+        builder = self.bloom.record(builder, &settings.bloom);
+        builder = profiler.end_scope(builder);
+
+        // HDR displays get highlights brighter than paper white
+        let peak = if self.output.is_hdr() {
+            hdr_settings.max_luminance.max(hdr_settings.paper_white) / hdr_settings.paper_white
+        } else {
+            1.0
+        };
+        let bloom_intensity = if settings.bloom.enabled {
+            settings.bloom.intensity
+        } else {
+            0.0
+        };
+        builder = profiler.begin_scope(builder, "Tonemap");
+        builder = builder
+            .begin_render_pass(
+                self.targets[0].framebuffer.clone(),
+                false,
+                vec![ClearValue::None],
+            )
+            .unwrap();
+        builder = self.tonemap_pass.record(
+            builder,
+            tonemap_settings,
+            bloom_intensity,
+            peak,
+            self.dimensions,
+        );
+        builder = builder.end_render_pass().unwrap();
+        builder = profiler.end_scope(builder);
+
+        let texel_size = [
+            1.0 / self.dimensions[0] as f32,
+            1.0 / self.dimensions[1] as f32,
+        ];
+        let seed = (self.frame_index % 256) as f32;
+        let mut source = 0;
+        for effect in settings.effects.iter().filter(|effect| effect.enabled()) {
+            let (id, params) = effect.shader_params();
+            let push_constants = EffectPushConstants {
+                params,
+                texel_size,
+                effect: id,
+                seed,
+            };
+
+            let destination = 1 - source;
+            builder = builder
+                .begin_render_pass(
+                    self.targets[destination].framebuffer.clone(),
+                    false,
+                    vec![ClearValue::None],
+                )
+                .unwrap()
+                .draw(
+                    self.effects_pipeline.clone(),
+                    &dynamic_state,
+                    fullscreen::triangle(),
+                    self.targets[source].effect_set.clone(),
+                    push_constants,
+                )
+                .expect("Failed to record post effect !")
+                .end_render_pass()
+                .unwrap();
+            source = destination;
+        }
+
+        self.last_target = source;
+        self.frame_index = self.frame_index.wrapping_add(1);
+        builder
+    }
+
+    /// Render pass drawing over the processed frame, in linear colors where 1 is paper white.
+    #[inline]
+    pub fn overlay_render_pass(&self) -> &Arc<dyn RenderPassAbstract + Send + Sync> {
+        &self.overlay_render_pass
+    }
+
+    /// Target holding the processed frame, only valid between `record` and `record_output`.
+    #[inline]
+    pub fn overlay_framebuffer(&self) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+        self.targets[self.last_target].overlay_framebuffer.clone()
+    }
+
+    /// Has to be inside the swap chain render pass, after `record`.
+    pub fn record_output(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        hdr_settings: &HdrDisplaySettings,
+    ) -> AutoCommandBufferBuilder {
+        self.output_pass
+            .record(builder, self.last_target, hdr_settings, self.dimensions)
+    }
+}
@@ -0,0 +1,359 @@
+use crate::renderer::fullscreen::{self, vertex_shader, FullscreenPipeline};
+use crate::renderer::hdr_target::HDR_FORMAT;
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
+use crate::renderer::post_processing::{linear_sampler, BloomSettings};
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::format::ClearValue;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::AttachmentImage;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+use vulkano::single_pass_renderpass;
+
+mod downsample_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/bloom_downsample.frag"
+    }
+}
+
+mod upsample_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/bloom_upsample.frag"
+    }
+}
+
+/// Mips below the HDR target, the first one is half its size.
+const MAX_MIPS: usize = 6;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct DownsamplePushConstants {
+    texel_size: [f32; 2],
+    threshold: f32,
+    knee: f32,
+    prefilter: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct UpsamplePushConstants {
+    texel_size: [f32; 2],
+    radius: f32,
+}
+
+struct BloomMip {
+    image: Arc<AttachmentImage>,
+    dimensions: [u32; 2],
+    /// Overwrites the mip with the one above it
+    downsample_framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    downsample_set: Arc<dyn DescriptorSet + Send + Sync>,
+    /// Adds the mip below it on top
+    upsample_framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    upsample_set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    _allocation: TrackedAllocation,
+}
+
+/// Bright parts of the HDR target blurred over a chain of smaller and smaller mips, then
+/// accumulated back up into the first one.
+pub struct Bloom {
+    downsample_pipeline: Arc<FullscreenPipeline>,
+    upsample_pipeline: Arc<FullscreenPipeline>,
+    source_dimensions: [u32; 2],
+    mips: Vec<BloomMip>,
+}
+
+impl Bloom {
+    /// Has to be recreated with the HDR target.
+    pub fn new(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        source: &Arc<AttachmentImage>,
+        source_dimensions: [u32; 2],
+    ) -> Self {
+        let downsample_render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: HDR_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )
+            .unwrap(),
+        );
+        let upsample_render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    color: {
+                        load: Load,
+                        store: Store,
+                        format: HDR_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )
+            .unwrap(),
+        );
+
+        let downsample_pipeline = Self::create_downsample_pipeline(device, &downsample_render_pass);
+        let upsample_pipeline = Self::create_upsample_pipeline(device, &upsample_render_pass);
+        let sampler = linear_sampler(device);
+
+        let mut mips: Vec<BloomMip> = Vec::with_capacity(MAX_MIPS);
+        let mut dimensions = Self::half(source_dimensions);
+        loop {
+            let image = AttachmentImage::sampled(device.clone(), dimensions, HDR_FORMAT)
+                .expect("Failed to create bloom mip !");
+            let above = mips.last().map_or(source, |mip| &mip.image);
+
+            mips.push(BloomMip {
+                downsample_framebuffer: Self::create_framebuffer(&downsample_render_pass, &image),
+                downsample_set: Self::create_set(&downsample_pipeline, above, &sampler),
+                upsample_framebuffer: Self::create_framebuffer(&upsample_render_pass, &image),
+                upsample_set: None,
+                _allocation: memory_tracker.track_image(MemoryCategory::RenderTarget, &image),
+                image,
+                dimensions,
+            });
+
+            if mips.len() == MAX_MIPS || dimensions[0] < 2 || dimensions[1] < 2 {
+                break;
+            }
+            dimensions = Self::half(dimensions);
+        }
+
+        // Each mip but the smallest accumulates the one below
+        for i in 0..mips.len() - 1 {
+            let set = Self::create_set(&upsample_pipeline, &mips[i + 1].image, &sampler);
+            mips[i].upsample_set = Some(set);
+        }
+
+        Self {
+            downsample_pipeline,
+            upsample_pipeline,
+            source_dimensions,
+            mips,
+        }
+    }
+
+    fn create_downsample_pipeline(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Arc<FullscreenPipeline> {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = downsample_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create bloom downsample pipeline layout !");
+
+        Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create bloom downsample pipeline !"),
+        )
+    }
+
+    fn create_upsample_pipeline(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Arc<FullscreenPipeline> {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = upsample_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        // Added to what the downsampling left in the mip
+        let additive = AttachmentBlend {
+            enabled: true,
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::One,
+            alpha_source: BlendFactor::One,
+            alpha_destination: BlendFactor::Zero,
+            ..AttachmentBlend::pass_through()
+        };
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create bloom upsample pipeline layout !");
+
+        Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .blend_collective(additive)
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create bloom upsample pipeline !"),
+        )
+    }
+
+    fn create_framebuffer(
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        image: &Arc<AttachmentImage>,
+    ) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+        Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create bloom framebuffer !"),
+        )
+    }
+
+    fn create_set(
+        pipeline: &Arc<FullscreenPipeline>,
+        source: &Arc<AttachmentImage>,
+        sampler: &Arc<Sampler>,
+    ) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_sampled_image(source.clone(), sampler.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create bloom descriptor set !"),
+        )
+    }
+
+    #[inline]
+    fn half(dimensions: [u32; 2]) -> [u32; 2] {
+        [(dimensions[0] / 2).max(1), (dimensions[1] / 2).max(1)]
+    }
+
+    #[inline]
+    fn texel_size(dimensions: [u32; 2]) -> [f32; 2] {
+        [1.0 / dimensions[0] as f32, 1.0 / dimensions[1] as f32]
+    }
+
+    /// Accumulated bloom, half the size of the HDR target.
+    #[inline]
+    pub fn image(&self) -> &Arc<AttachmentImage> {
+        &self.mips[0].image
+    }
+
+    pub fn record(
+        &self,
+        mut builder: AutoCommandBufferBuilder,
+        settings: &BloomSettings,
+    ) -> AutoCommandBufferBuilder {
+        if !settings.enabled {
+            // Still cleared, the tonemapping samples it either way
+            return builder
+                .begin_render_pass(
+                    self.mips[0].downsample_framebuffer.clone(),
+                    false,
+                    vec![[0.0, 0.0, 0.0, 1.0].into()],
+                )
+                .unwrap()
+                .end_render_pass()
+                .unwrap();
+        }
+
+        let mut source_dimensions = self.source_dimensions;
+        for (i, mip) in self.mips.iter().enumerate() {
+            let push_constants = DownsamplePushConstants {
+                texel_size: Self::texel_size(source_dimensions),
+                threshold: settings.threshold,
+                knee: settings.knee,
+                // Only the first pass keeps the bright parts alone
+                prefilter: (i == 0) as u32,
+            };
+            builder = builder
+                .begin_render_pass(
+                    mip.downsample_framebuffer.clone(),
+                    false,
+                    vec![[0.0, 0.0, 0.0, 1.0].into()],
+                )
+                .unwrap()
+                .draw(
+                    self.downsample_pipeline.clone(),
+                    &Self::dynamic_state(mip.dimensions),
+                    fullscreen::triangle(),
+                    mip.downsample_set.clone(),
+                    push_constants,
+                )
+                .expect("Failed to record bloom downsample !")
+                .end_render_pass()
+                .unwrap();
+            source_dimensions = mip.dimensions;
+        }
+
+        for pair in self.mips.windows(2).rev() {
+            let (mip, below) = (&pair[0], &pair[1]);
+            let push_constants = UpsamplePushConstants {
+                texel_size: Self::texel_size(below.dimensions),
+                radius: settings.radius,
+            };
+            builder = builder
+                .begin_render_pass(
+                    mip.upsample_framebuffer.clone(),
+                    false,
+                    vec![ClearValue::None],
+                )
+                .unwrap()
+                .draw(
+                    self.upsample_pipeline.clone(),
+                    &Self::dynamic_state(mip.dimensions),
+                    fullscreen::triangle(),
+                    mip.upsample_set.clone().unwrap(),
+                    push_constants,
+                )
+                .expect("Failed to record bloom upsample !")
+                .end_render_pass()
+                .unwrap();
+        }
+
+        builder
+    }
+
+    #[inline]
+    fn dynamic_state(dimensions: [u32; 2]) -> DynamicState {
+        DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        }
+    }
+}
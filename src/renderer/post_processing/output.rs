@@ -0,0 +1,139 @@
+use crate::renderer::display_output::{DisplayOutput, HdrDisplaySettings};
+use crate::renderer::fullscreen::{self, vertex_shader, FullscreenPipeline};
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::AttachmentImage;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/output.frag"
+    }
+}
+
+/// How the final color is written to the swap chain.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum OutputEncoding {
+    /// Linear, the swap chain format does the sRGB encoding
+    Linear = 0,
+    Srgb = 1,
+    /// Rec.2020 with the ST 2084 curve, for HDR10
+    Pq = 2,
+    /// Linear extended sRGB, 1.0 is 80 nits
+    ScRgb = 3,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PushConstants {
+    encoding: u32,
+    paper_white: f32,
+}
+
+/// Copies the end of the post-processing chain to the screen, encoded for the display.
+pub struct OutputPass {
+    pipeline: Arc<FullscreenPipeline>,
+    /// One for each post-processing target
+    descriptor_sets: [Arc<dyn DescriptorSet + Send + Sync>; 2],
+    encoding: OutputEncoding,
+}
+
+impl OutputPass {
+    /// Has to be recreated with the swap chain, `encode_srgb` when its format doesn't.
+    pub fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        sources: [&Arc<AttachmentImage>; 2],
+        sampler: &Arc<Sampler>,
+        output: DisplayOutput,
+        encode_srgb: bool,
+    ) -> Self {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create output pipeline layout !");
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create output pipeline !"),
+        );
+
+        let create_set = |source: &Arc<AttachmentImage>| {
+            let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+            Arc::new(
+                PersistentDescriptorSet::start(layout)
+                    .add_sampled_image(source.clone(), sampler.clone())
+                    .unwrap()
+                    .build()
+                    .expect("Failed to create output descriptor set !"),
+            ) as Arc<dyn DescriptorSet + Send + Sync>
+        };
+        let descriptor_sets = [create_set(sources[0]), create_set(sources[1])];
+
+        let encoding = match output {
+            DisplayOutput::Hdr10 => OutputEncoding::Pq,
+            DisplayOutput::ScRgb => OutputEncoding::ScRgb,
+            DisplayOutput::Sdr if encode_srgb => OutputEncoding::Srgb,
+            DisplayOutput::Sdr => OutputEncoding::Linear,
+        };
+
+        Self {
+            pipeline,
+            descriptor_sets,
+            encoding,
+        }
+    }
+
+    /// Has to be inside the swap chain render pass.
+    pub fn record(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        source: usize,
+        hdr_settings: &HdrDisplaySettings,
+        dimensions: [u32; 2],
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        };
+        let push_constants = PushConstants {
+            encoding: self.encoding as u32,
+            paper_white: hdr_settings.paper_white,
+        };
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &dynamic_state,
+                fullscreen::triangle(),
+                self.descriptor_sets[source].clone(),
+                push_constants,
+            )
+            .expect("Failed to record output pass !")
+    }
+}
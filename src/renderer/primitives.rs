@@ -0,0 +1,90 @@
+use crate::math::{Vec2, Vec3};
+use crate::renderer::mesh::{generate_tangents, Vertex};
+use std::f32::consts::PI;
+
+/// Vertices and indices of a procedural shape, counter clockwise when seen from outside.
+pub type Geometry = (Vec<Vertex>, Vec<u32>);
+
+/// Flat square on the XZ plane, facing +Y.
+pub fn plane(size: f32) -> Geometry {
+    let half = size * 0.5;
+    let mut vertices = vec![
+        Vertex::new(Vec3::new(-half, 0.0, half), Vec3::Y, Vec2::new(0.0, 1.0)),
+        Vertex::new(Vec3::new(half, 0.0, half), Vec3::Y, Vec2::new(1.0, 1.0)),
+        Vertex::new(Vec3::new(half, 0.0, -half), Vec3::Y, Vec2::new(1.0, 0.0)),
+        Vertex::new(Vec3::new(-half, 0.0, -half), Vec3::Y, Vec2::new(0.0, 0.0)),
+    ];
+    let indices = vec![0, 1, 2, 0, 2, 3];
+
+    generate_tangents(&mut vertices, &indices);
+    (vertices, indices)
+}
+
+/// Cube centered on the origin, each face has its own vertices for sharp normals.
+pub fn cube(size: f32) -> Geometry {
+    let half = size * 0.5;
+    // Normal, right and up of each face, with right x up = normal
+    let faces = [
+        (Vec3::X, -Vec3::Z, Vec3::Y),
+        (-Vec3::X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, -Vec3::Z),
+        (-Vec3::Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (-Vec3::Z, -Vec3::X, Vec3::Y),
+    ];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+    for (normal, right, up) in faces.iter() {
+        let first = vertices.len() as u32;
+        let center = *normal * half;
+        let corners = [
+            (-1.0, -1.0, Vec2::new(0.0, 1.0)),
+            (1.0, -1.0, Vec2::new(1.0, 1.0)),
+            (1.0, 1.0, Vec2::new(1.0, 0.0)),
+            (-1.0, 1.0, Vec2::new(0.0, 0.0)),
+        ];
+        for (x, y, uv) in corners.iter() {
+            let position = center + (*right * *x + *up * *y) * half;
+            vertices.push(Vertex::new(position, *normal, *uv));
+        }
+
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    generate_tangents(&mut vertices, &indices);
+    (vertices, indices)
+}
+
+/// Sphere made of `rings` horizontal bands of `segments` quads.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Geometry {
+    let segments = segments.max(3);
+    let rings = rings.max(2);
+
+    let mut vertices = Vec::with_capacity(((segments + 1) * (rings + 1)) as usize);
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        // From the top pole to the bottom one
+        let (sin_phi, cos_phi) = (v * PI).sin_cos();
+
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_theta, cos_theta) = (u * 2.0 * PI).sin_cos();
+
+            let normal = Vec3::new(sin_phi * cos_theta, cos_phi, sin_phi * sin_theta);
+            vertices.push(Vertex::new(normal * radius, normal, Vec2::new(u, v)));
+        }
+    }
+
+    let mut indices = Vec::with_capacity((segments * rings * 6) as usize);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = ring * (segments + 1) + segment;
+            let b = a + segments + 1;
+            indices.extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+        }
+    }
+
+    generate_tangents(&mut vertices, &indices);
+    (vertices, indices)
+}
@@ -0,0 +1,113 @@
+use std::any::Any;
+use std::sync::Arc;
+use vulkano::buffer::BufferAccess;
+use vulkano::command_buffer::pool::standard::{
+    StandardCommandPoolAlloc, StandardCommandPoolBuilder,
+};
+use vulkano::command_buffer::sys::{
+    Flags, Kind, KindOcclusionQuery, UnsafeCommandBuffer, UnsafeCommandBufferBuilder,
+};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer, CommandBufferExecError};
+use vulkano::device::{Device, DeviceOwned, Queue};
+use vulkano::image::{ImageAccess, ImageLayout};
+use vulkano::instance::QueueFamily;
+use vulkano::query::QueryPipelineStatisticFlags;
+use vulkano::sync::{AccessCheckError, AccessFlagBits, GpuFuture, PipelineStages};
+
+/// Commands the automatic command buffer builder can't record, recorded in a secondary
+/// command buffer that a frame's command buffer executes outside of any render pass.
+///
+/// Vulkano tracks nothing about them: they have to synchronize themselves with barriers,
+/// and keep the resources they use alive until the frame's command buffer is dropped.
+pub struct RawCommands {
+    inner: UnsafeCommandBuffer<StandardCommandPoolAlloc>,
+    _resources: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+impl RawCommands {
+    /// Start recording for a queue of the given family.
+    pub fn begin(
+        device: &Arc<Device>,
+        queue_family: QueueFamily,
+    ) -> UnsafeCommandBufferBuilder<StandardCommandPoolBuilder> {
+        let pool = Device::standard_command_pool(device, queue_family);
+        unsafe {
+            UnsafeCommandBufferBuilder::new(
+                &pool,
+                Kind::secondary(
+                    KindOcclusionQuery::Forbidden,
+                    QueryPipelineStatisticFlags::none(),
+                ),
+                Flags::OneTimeSubmit,
+            )
+            .expect("Failed to allocate raw command buffer !")
+        }
+    }
+
+    pub fn build(
+        commands: UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>,
+        resources: Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Self {
+        Self {
+            inner: commands
+                .build()
+                .expect("Failed to build raw command buffer !"),
+            _resources: resources,
+        }
+    }
+
+    /// Has to be outside of any render pass.
+    pub fn execute(self, builder: AutoCommandBufferBuilder) -> AutoCommandBufferBuilder {
+        unsafe {
+            builder
+                .execute_commands(self)
+                .expect("Failed to execute raw commands !")
+        }
+    }
+}
+
+unsafe impl DeviceOwned for RawCommands {
+    #[inline]
+    fn device(&self) -> &Arc<Device> {
+        self.inner.device()
+    }
+}
+
+// Only ever executed from a primary command buffer, which does the locking
+unsafe impl CommandBuffer for RawCommands {
+    type PoolAlloc = StandardCommandPoolAlloc;
+
+    #[inline]
+    fn inner(&self) -> &UnsafeCommandBuffer<StandardCommandPoolAlloc> {
+        &self.inner
+    }
+
+    #[inline]
+    fn lock_submit(&self, _: &dyn GpuFuture, _: &Queue) -> Result<(), CommandBufferExecError> {
+        Ok(())
+    }
+
+    #[inline]
+    unsafe fn unlock(&self) {}
+
+    #[inline]
+    fn check_buffer_access(
+        &self,
+        _: &dyn BufferAccess,
+        _: bool,
+        _: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+
+    #[inline]
+    fn check_image_access(
+        &self,
+        _: &dyn ImageAccess,
+        _: ImageLayout,
+        _: bool,
+        _: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+}
@@ -0,0 +1,550 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use vulkano::descriptor::descriptor::{DescriptorDesc, DescriptorDescTy, ShaderStages};
+use vulkano::descriptor::pipeline_layout::{
+    PipelineLayout, PipelineLayoutCreationError, PipelineLayoutDesc, PipelineLayoutDescPcRange,
+};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::pipeline::shader::{
+    EntryPointAbstract, GraphicsEntryPointAbstract, GraphicsShaderType, ShaderInterfaceDef,
+};
+use vulkano::pipeline::vertex::{Vertex, VertexMemberInfo, VertexMemberTy};
+use vulkano::pipeline::GraphicsPipelineCreationError;
+
+/// Interface of a single shader stage, as reflected from its SPIR-V by vulkano-shaders.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    stage: &'static str,
+    descriptors: Vec<ReflectedDescriptor>,
+    push_constants: Vec<PipelineLayoutDescPcRange>,
+    inputs: Vec<ReflectedInput>,
+}
+
+#[derive(Debug, Clone)]
+struct ReflectedDescriptor {
+    set: usize,
+    binding: usize,
+    desc: DescriptorDesc,
+}
+
+#[derive(Debug, Clone)]
+struct ReflectedInput {
+    location: u32,
+    num_locations: u32,
+    format: Format,
+    name: Option<String>,
+}
+
+/// Descriptor sets and push constants of all the stages of a material merged together,
+/// the pipelines of the material are created with the layout built from it.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialLayout {
+    sets: Vec<Vec<Option<DescriptorDesc>>>,
+    push_constants: Vec<PipelineLayoutDescPcRange>,
+}
+
+/// Why the shaders of a material can't be used together.
+#[derive(Debug)]
+pub enum ShaderInterfaceError {
+    /// Two stages declare the same binding with different types.
+    DescriptorConflict {
+        set: usize,
+        binding: usize,
+        first_stage: &'static str,
+        second_stage: &'static str,
+    },
+    /// The vertex shader reads an attribute that the vertex type doesn't have.
+    MissingVertexAttribute { name: String, location: u32 },
+    /// The vertex shader reads an attribute with a different type than the one in the vertex.
+    VertexAttributeMismatch {
+        name: String,
+        location: u32,
+        shader_format: Format,
+        vertex_type: VertexMemberTy,
+        vertex_array_size: usize,
+    },
+    /// Vertex inputs are matched by name, so they can't be anonymous.
+    UnnamedVertexInput { location: u32 },
+    /// The merged layout can't be turned into a pipeline layout.
+    Layout(PipelineLayoutCreationError),
+    /// Vulkano refused the pipeline for another reason.
+    Pipeline(GraphicsPipelineCreationError),
+}
+
+impl fmt::Display for ShaderInterfaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderInterfaceError::DescriptorConflict {
+                set,
+                binding,
+                first_stage,
+                second_stage,
+            } => write!(
+                f,
+                "descriptor (set = {}, binding = {}) has a different type in the {} shader and in the {} shader",
+                set, binding, first_stage, second_stage
+            ),
+            ShaderInterfaceError::MissingVertexAttribute { name, location } => write!(
+                f,
+                "vertex shader input `{}` (location = {}) has no matching member in the vertex type",
+                name, location
+            ),
+            ShaderInterfaceError::VertexAttributeMismatch {
+                name,
+                location,
+                shader_format,
+                vertex_type,
+                vertex_array_size,
+            } => write!(
+                f,
+                "vertex shader input `{}` (location = {}) expects {:?} but the vertex provides [{:?}; {}]",
+                name, location, shader_format, vertex_type, vertex_array_size
+            ),
+            ShaderInterfaceError::UnnamedVertexInput { location } => write!(
+                f,
+                "vertex shader input at location {} has no name, it can't be matched with the vertex type",
+                location
+            ),
+            ShaderInterfaceError::Layout(err) => {
+                write!(f, "pipeline layout creation failed: {}", err)
+            }
+            ShaderInterfaceError::Pipeline(err) => write!(f, "pipeline creation failed: {}", err),
+        }
+    }
+}
+
+impl Error for ShaderInterfaceError {}
+
+impl From<PipelineLayoutCreationError> for ShaderInterfaceError {
+    fn from(err: PipelineLayoutCreationError) -> Self {
+        ShaderInterfaceError::Layout(err)
+    }
+}
+
+impl From<GraphicsPipelineCreationError> for ShaderInterfaceError {
+    fn from(err: GraphicsPipelineCreationError) -> Self {
+        ShaderInterfaceError::Pipeline(err)
+    }
+}
+
+impl ShaderReflection {
+    /// Reflect the interface of a graphics shader stage.
+    pub fn graphics<E>(entry_point: &E) -> Self
+    where
+        E: GraphicsEntryPointAbstract,
+    {
+        let stage = match entry_point.ty() {
+            GraphicsShaderType::Vertex => "vertex",
+            GraphicsShaderType::TessellationControl => "tessellation control",
+            GraphicsShaderType::TessellationEvaluation => "tessellation evaluation",
+            GraphicsShaderType::Geometry(_) => "geometry",
+            GraphicsShaderType::Fragment => "fragment",
+        };
+
+        let mut reflection = Self::from_layout(stage, entry_point.layout());
+        reflection.inputs = entry_point
+            .input()
+            .elements()
+            .map(|element| ReflectedInput {
+                location: element.location.start,
+                num_locations: element.location.end - element.location.start,
+                format: element.format,
+                name: element.name.map(|name| name.into_owned()),
+            })
+            .collect();
+
+        reflection
+    }
+
+    /// Reflect the interface of a compute shader.
+    pub fn compute<E>(entry_point: &E) -> Self
+    where
+        E: EntryPointAbstract,
+    {
+        Self::from_layout("compute", entry_point.layout())
+    }
+
+    /// Reflect the descriptors and push constants of a stage, whatever its type.
+    pub fn from_layout<L>(stage: &'static str, layout: &L) -> Self
+    where
+        L: PipelineLayoutDesc + ?Sized,
+    {
+        let mut descriptors = Vec::new();
+        for set in 0..layout.num_sets() {
+            for binding in 0..layout.num_bindings_in_set(set).unwrap_or(0) {
+                if let Some(desc) = layout.descriptor(set, binding) {
+                    descriptors.push(ReflectedDescriptor { set, binding, desc });
+                }
+            }
+        }
+
+        let push_constants = (0..layout.num_push_constants_ranges())
+            .filter_map(|num| layout.push_constants_range(num))
+            .collect();
+
+        Self {
+            stage,
+            descriptors,
+            push_constants,
+            inputs: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub fn stage(&self) -> &'static str {
+        self.stage
+    }
+
+    /// Check that every input of this (vertex) shader is provided by the vertex type `V`,
+    /// with a compatible type.
+    pub fn check_vertex_input<V: Vertex>(&self) -> Result<(), ShaderInterfaceError> {
+        self.check_inputs(V::member)
+    }
+
+    /// Same as [check_vertex_input](ShaderReflection::check_vertex_input) with a per vertex
+    /// type `V` and a per instance type `I`, `V` is looked up first like vulkano does.
+    pub fn check_instanced_vertex_input<V: Vertex, I: Vertex>(
+        &self,
+    ) -> Result<(), ShaderInterfaceError> {
+        self.check_inputs(|name| V::member(name).or_else(|| I::member(name)))
+    }
+
+    fn check_inputs<F>(&self, member: F) -> Result<(), ShaderInterfaceError>
+    where
+        F: Fn(&str) -> Option<VertexMemberInfo>,
+    {
+        for input in &self.inputs {
+            let name = input
+                .name
+                .as_ref()
+                .ok_or(ShaderInterfaceError::UnnamedVertexInput {
+                    location: input.location,
+                })?;
+
+            let member =
+                member(name).ok_or_else(|| ShaderInterfaceError::MissingVertexAttribute {
+                    name: name.clone(),
+                    location: input.location,
+                })?;
+
+            if !member
+                .ty
+                .matches(member.array_size, input.format, input.num_locations)
+            {
+                return Err(ShaderInterfaceError::VertexAttributeMismatch {
+                    name: name.clone(),
+                    location: input.location,
+                    shader_format: input.format,
+                    vertex_type: member.ty,
+                    vertex_array_size: member.array_size,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl MaterialLayout {
+    /// Merge the interfaces of all the stages of a material.
+    /// The same binding can be used by several stages as long as they agree on its type.
+    pub fn merge(stages: &[ShaderReflection]) -> Result<Self, ShaderInterfaceError> {
+        let mut layout = Self::default();
+        // Remember who declared what, to blame the right stages
+        let mut owners: Vec<(usize, usize, &'static str)> = Vec::new();
+
+        for stage in stages {
+            for descriptor in &stage.descriptors {
+                if layout.sets.len() <= descriptor.set {
+                    layout.sets.resize(descriptor.set + 1, Vec::new());
+                }
+                let set = &mut layout.sets[descriptor.set];
+                if set.len() <= descriptor.binding {
+                    set.resize(descriptor.binding + 1, None);
+                }
+
+                let slot = &mut set[descriptor.binding];
+                *slot = match slot {
+                    None => {
+                        owners.push((descriptor.set, descriptor.binding, stage.stage));
+                        Some(descriptor.desc.clone())
+                    }
+                    Some(existing) => Some(existing.union(&descriptor.desc).ok_or_else(|| {
+                        let first_stage = owners
+                            .iter()
+                            .find(|(set, binding, _)| {
+                                *set == descriptor.set && *binding == descriptor.binding
+                            })
+                            .map(|(_, _, stage)| *stage)
+                            .unwrap_or("previous");
+
+                        ShaderInterfaceError::DescriptorConflict {
+                            set: descriptor.set,
+                            binding: descriptor.binding,
+                            first_stage,
+                            second_stage: stage.stage,
+                        }
+                    })?),
+                };
+            }
+
+            for range in &stage.push_constants {
+                layout.add_push_constants(*range);
+            }
+        }
+
+        Ok(layout)
+    }
+
+    /// Turn a uniform or storage buffer binding into its dynamic variant, so it can be pointed
+    /// at [frame allocations](crate::renderer::frame_allocator::FrameAllocation) with dynamic
+    /// offsets. Does nothing if the binding isn't a buffer.
+    pub fn set_dynamic(&mut self, set: usize, binding: usize) {
+        let desc = self
+            .sets
+            .get_mut(set)
+            .and_then(|set| set.get_mut(binding))
+            .and_then(|desc| desc.as_mut());
+
+        if let Some(DescriptorDesc {
+            ty: DescriptorDescTy::Buffer(buffer),
+            ..
+        }) = desc
+        {
+            buffer.dynamic = Some(true);
+        }
+    }
+
+    /// Create the pipeline layout to build the pipelines of the material with.
+    pub fn create_pipeline_layout(
+        &self,
+        device: &Arc<Device>,
+    ) -> Result<Arc<PipelineLayout<MaterialLayout>>, ShaderInterfaceError> {
+        Ok(Arc::new(self.clone().build(device.clone())?))
+    }
+
+    /// Merge the stages and create their pipeline layout at once, for the pipelines that
+    /// don't need the merged layout afterwards.
+    pub fn pipeline_layout(
+        device: &Arc<Device>,
+        stages: &[ShaderReflection],
+    ) -> Result<Arc<PipelineLayout<MaterialLayout>>, ShaderInterfaceError> {
+        Self::merge(stages)?.create_pipeline_layout(device)
+    }
+
+    /// A stage can only be in one range, so overlapping ranges become one range covering
+    /// all of them, visible to all their stages.
+    fn add_push_constants(&mut self, range: PipelineLayoutDescPcRange) {
+        let mut merged = range;
+        while let Some(index) = self
+            .push_constants
+            .iter()
+            .position(|existing| overlap(existing, &merged))
+        {
+            let existing = self.push_constants.swap_remove(index);
+            let end = (existing.offset + existing.size).max(merged.offset + merged.size);
+            merged.offset = merged.offset.min(existing.offset);
+            merged.size = end - merged.offset;
+            merged.stages = merged.stages | existing.stages;
+        }
+        self.push_constants.push(merged);
+    }
+
+    /// Stages that can access the push constants, to use when pushing them.
+    pub fn push_constant_stages(&self) -> ShaderStages {
+        self.push_constants
+            .iter()
+            .fold(ShaderStages::none(), |stages, range| stages | range.stages)
+    }
+}
+
+#[inline]
+fn overlap(first: &PipelineLayoutDescPcRange, second: &PipelineLayoutDescPcRange) -> bool {
+    first.offset < second.offset + second.size && second.offset < first.offset + first.size
+}
+
+unsafe impl PipelineLayoutDesc for MaterialLayout {
+    #[inline]
+    fn num_sets(&self) -> usize {
+        self.sets.len()
+    }
+
+    #[inline]
+    fn num_bindings_in_set(&self, set: usize) -> Option<usize> {
+        self.sets.get(set).map(|set| set.len())
+    }
+
+    #[inline]
+    fn descriptor(&self, set: usize, binding: usize) -> Option<DescriptorDesc> {
+        self.sets.get(set)?.get(binding)?.clone()
+    }
+
+    #[inline]
+    fn num_push_constants_ranges(&self) -> usize {
+        self.push_constants.len()
+    }
+
+    #[inline]
+    fn push_constants_range(&self, num: usize) -> Option<PipelineLayoutDescPcRange> {
+        self.push_constants.get(num).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulkano::descriptor::descriptor::DescriptorBufferDesc;
+    use vulkano::descriptor::pipeline_layout::RuntimePipelineDesc;
+
+    fn buffer(storage: bool, stages: ShaderStages) -> DescriptorDesc {
+        DescriptorDesc {
+            ty: DescriptorDescTy::Buffer(DescriptorBufferDesc {
+                dynamic: Some(false),
+                storage,
+            }),
+            array_count: 1,
+            stages,
+            readonly: true,
+        }
+    }
+
+    fn push_constants(
+        offset: usize,
+        size: usize,
+        stages: ShaderStages,
+    ) -> PipelineLayoutDescPcRange {
+        PipelineLayoutDescPcRange {
+            offset,
+            size,
+            stages,
+        }
+    }
+
+    fn stage(
+        name: &'static str,
+        sets: Vec<Vec<Option<DescriptorDesc>>>,
+        ranges: Vec<PipelineLayoutDescPcRange>,
+    ) -> ShaderReflection {
+        let desc = RuntimePipelineDesc::new(sets, ranges).unwrap();
+        ShaderReflection::from_layout(name, &desc)
+    }
+
+    fn vertex() -> ShaderStages {
+        ShaderStages {
+            vertex: true,
+            ..ShaderStages::none()
+        }
+    }
+
+    fn fragment() -> ShaderStages {
+        ShaderStages {
+            fragment: true,
+            ..ShaderStages::none()
+        }
+    }
+
+    #[test]
+    fn shared_bindings_are_visible_to_both_stages() {
+        let layout = MaterialLayout::merge(&[
+            stage("vertex", vec![vec![Some(buffer(false, vertex()))]], vec![]),
+            stage(
+                "fragment",
+                vec![vec![
+                    Some(buffer(false, fragment())),
+                    Some(buffer(true, fragment())),
+                ]],
+                vec![],
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(layout.num_sets(), 1);
+        assert_eq!(layout.num_bindings_in_set(0), Some(2));
+        let shared = layout.descriptor(0, 0).unwrap();
+        assert!(shared.stages.vertex && shared.stages.fragment);
+        assert!(!layout.descriptor(0, 1).unwrap().stages.vertex);
+    }
+
+    #[test]
+    fn different_types_at_the_same_binding_conflict() {
+        let err = MaterialLayout::merge(&[
+            stage(
+                "vertex",
+                vec![vec![], vec![Some(buffer(false, vertex()))]],
+                vec![],
+            ),
+            stage(
+                "fragment",
+                vec![vec![], vec![Some(buffer(true, fragment()))]],
+                vec![],
+            ),
+        ])
+        .unwrap_err();
+
+        match err {
+            ShaderInterfaceError::DescriptorConflict {
+                set,
+                binding,
+                first_stage,
+                second_stage,
+            } => {
+                assert_eq!((set, binding), (1, 0));
+                assert_eq!((first_stage, second_stage), ("vertex", "fragment"));
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn overlapping_push_constants_are_coalesced() {
+        let layout = MaterialLayout::merge(&[
+            stage("vertex", vec![], vec![push_constants(0, 64, vertex())]),
+            stage("fragment", vec![], vec![push_constants(48, 32, fragment())]),
+        ])
+        .unwrap();
+
+        assert_eq!(layout.num_push_constants_ranges(), 1);
+        let range = layout.push_constants_range(0).unwrap();
+        assert_eq!((range.offset, range.size), (0, 80));
+        assert!(range.stages.vertex && range.stages.fragment);
+        assert_eq!(layout.push_constant_stages(), vertex() | fragment());
+    }
+
+    #[test]
+    fn disjoint_push_constants_stay_apart() {
+        let mut layout = MaterialLayout::default();
+        layout.add_push_constants(push_constants(0, 16, vertex()));
+        layout.add_push_constants(push_constants(16, 16, fragment()));
+        assert_eq!(layout.num_push_constants_ranges(), 2);
+
+        // Bridging both ranges merges all three
+        layout.add_push_constants(push_constants(8, 16, ShaderStages::compute()));
+        assert_eq!(layout.num_push_constants_ranges(), 1);
+        let range = layout.push_constants_range(0).unwrap();
+        assert_eq!((range.offset, range.size), (0, 32));
+        assert!(range.stages.vertex && range.stages.fragment && range.stages.compute);
+    }
+
+    #[test]
+    fn only_buffers_become_dynamic() {
+        let mut layout = MaterialLayout::merge(&[stage(
+            "compute",
+            vec![vec![Some(buffer(false, ShaderStages::compute())), None]],
+            vec![],
+        )])
+        .unwrap();
+
+        layout.set_dynamic(0, 0);
+        // Missing bindings are ignored
+        layout.set_dynamic(0, 1);
+        layout.set_dynamic(3, 0);
+
+        match layout.descriptor(0, 0).unwrap().ty {
+            DescriptorDescTy::Buffer(buffer) => assert_eq!(buffer.dynamic, Some(true)),
+            ty => panic!("unexpected descriptor {:?}", ty),
+        }
+        assert!(layout.descriptor(0, 1).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::renderer::fullscreen::{self, vertex_shader, FullscreenPipeline};
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::AttachmentImage;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::Sampler;
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/shadow_debug.frag"
+    }
+}
+
+/// Fraction of the smallest side of the screen taken by the atlas.
+const SCREEN_FRACTION: f32 = 0.4;
+
+/// Draws the shadow atlas in the bottom right corner of the screen.
+pub struct ShadowAtlasDebugView {
+    pipeline: Arc<FullscreenPipeline>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+impl ShadowAtlasDebugView {
+    /// Has to be recreated with the render pass or the atlas.
+    pub fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        atlas: &Arc<AttachmentImage>,
+    ) -> Self {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create shadow debug pipeline layout !");
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create shadow debug pipeline !"),
+        );
+
+        // Raw depth, without the comparison of the lit shaders
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_sampled_image(atlas.clone(), Sampler::simple_repeat_linear(device.clone()))
+                .unwrap()
+                .build()
+                .expect("Failed to create shadow debug descriptor set !"),
+        );
+
+        Self {
+            pipeline,
+            descriptor_set,
+        }
+    }
+
+    pub fn record(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        screen_dimensions: [u32; 2],
+    ) -> AutoCommandBufferBuilder {
+        let size = screen_dimensions[0].min(screen_dimensions[1]) as f32 * SCREEN_FRACTION;
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [
+                    screen_dimensions[0] as f32 - size,
+                    screen_dimensions[1] as f32 - size,
+                ],
+                dimensions: [size, size],
+                depth_range: 0.0..1.0,
+            }]),
+            ..DynamicState::none()
+        };
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &dynamic_state,
+                fullscreen::triangle(),
+                self.descriptor_set.clone(),
+                (),
+            )
+            .expect("Failed to record shadow atlas debug view !")
+    }
+}
@@ -0,0 +1,559 @@
+use crate::math::{BoundingSphere, Frustum, Mat4, Vec3};
+use crate::renderer::frame_allocator::FrameAllocator;
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::instancing::{DrawBatch, InstanceBatcher, InstanceData};
+use crate::renderer::lighting::{GpuLight, ShadowCaster};
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
+use crate::renderer::mesh::Vertex;
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderInterfaceError, ShaderReflection};
+use crate::scene::{CameraView, LightKind, Scene};
+use log::warn;
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass};
+use vulkano::image::{AttachmentImage, ImageAccess};
+use vulkano::pipeline::depth_stencil::Compare;
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{BorderColor, Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::single_pass_renderpass;
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/shadow.vert"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/shadow.frag"
+    }
+}
+
+/// Near plane of the spot light projections.
+const SPOT_NEAR: f32 = 0.05;
+
+/// Cascades a directional light can be split into.
+pub const MAX_CASCADES: u32 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowAtlasSettings {
+    /// Width and height of the atlas in texels
+    pub size: u32,
+    /// Width and height of the shadow map of each light in the atlas
+    pub tile_size: u32,
+    /// Radius of the PCF kernel in texels, 0 for hard shadows
+    pub pcf_radius: u32,
+    /// Distance from the camera covered by directional shadows, split between the cascades
+    pub max_distance: f32,
+    /// Slices of the camera frustum shadowed by directional lights, each takes a tile,
+    /// up to [MAX_CASCADES]
+    pub cascade_count: u32,
+    /// Split distribution between linear (0) and logarithmic (1)
+    pub cascade_split_lambda: f32,
+    /// Fraction at the end of each cascade blended with the next one
+    pub cascade_blend: f32,
+}
+
+impl Default for ShadowAtlasSettings {
+    fn default() -> Self {
+        Self {
+            size: 4096,
+            tile_size: 1024,
+            pcf_radius: 1,
+            max_distance: 50.0,
+            cascade_count: 3,
+            cascade_split_lambda: 0.75,
+            cascade_blend: 0.1,
+        }
+    }
+}
+
+impl ShadowAtlasSettings {
+    #[inline]
+    pub fn tiles_per_row(&self) -> u32 {
+        (self.size / self.tile_size).max(1)
+    }
+
+    /// Shadow maps that fit in the atlas, lights past that don't cast shadows.
+    #[inline]
+    pub fn tile_count(&self) -> usize {
+        (self.tiles_per_row() * self.tiles_per_row()) as usize
+    }
+}
+
+/// A shadow map as laid out in the shadow storage buffer, std430.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct GpuShadow {
+    pub view_projection: [[f32; 4]; 4],
+    /// Offset in XY and size in ZW of the tile, in atlas UVs
+    pub atlas_rect: [f32; 4],
+    /// Camera distances covered by a cascade in XY
+    pub split: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PushConstants {
+    light_view_projection: [[f32; 4]; 4],
+}
+
+/// A shadow map to render this frame.
+pub struct ShadowPass {
+    view_projection: Mat4,
+    viewport: Viewport,
+    batches: Vec<DrawBatch>,
+}
+
+/// Renders the shadow maps of the lights into tiles of a single depth atlas.
+pub struct ShadowRenderer {
+    settings: ShadowAtlasSettings,
+    atlas: Arc<AttachmentImage>,
+    framebuffer: Arc<dyn FramebufferAbstract + Send + Sync>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    /// Compares with the depth in the atlas, filtered by the hardware
+    sampler: Arc<Sampler>,
+
+    instance_batcher: InstanceBatcher,
+    warned: bool,
+    _allocation: TrackedAllocation,
+}
+
+impl ShadowRenderer {
+    pub fn new(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        settings: ShadowAtlasSettings,
+    ) -> Self {
+        let dimensions = [settings.size, settings.size];
+        // Same as the swap chain depth buffer, but sampled afterwards
+        let atlas = AttachmentImage::sampled(device.clone(), dimensions, Format::D32Sfloat)
+            .or_else(|_| {
+                warn!("Shadow atlas format D32Sfloat not available, falling back to D16Unorm");
+                AttachmentImage::sampled(device.clone(), dimensions, Format::D16Unorm)
+            })
+            .expect("Failed to create shadow atlas !");
+        let allocation = memory_tracker.track_image(MemoryCategory::RenderTarget, &atlas);
+
+        let render_pass: Arc<dyn RenderPassAbstract + Send + Sync> = Arc::new(
+            single_pass_renderpass!(device.clone(),
+                attachments: {
+                    depth: {
+                        load: Clear,
+                        store: Store,
+                        format: atlas.format(),
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [],
+                    depth_stencil: {depth}
+                }
+            )
+            .unwrap(),
+        );
+
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(atlas.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create shadow framebuffer !"),
+        );
+
+        let pipeline = Self::create_pipeline(device, &render_pass)
+            .unwrap_or_else(|err| panic!("Failed to create shadow pipeline: {}", err));
+
+        // Outside of the atlas counts as lit
+        let border = SamplerAddressMode::ClampToBorder(BorderColor::FloatOpaqueWhite);
+        let sampler = Sampler::compare(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            border,
+            border,
+            border,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+            Compare::LessOrEqual,
+        )
+        .expect("Failed to create shadow sampler !");
+
+        Self {
+            settings,
+            atlas,
+            framebuffer,
+            pipeline,
+            sampler,
+            instance_batcher: InstanceBatcher::new(),
+            warned: false,
+            _allocation: allocation,
+        }
+    }
+
+    fn create_pipeline(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>, ShaderInterfaceError> {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let vertex_reflection = ShaderReflection::graphics(&vert_shader.main_entry_point());
+        vertex_reflection.check_instanced_vertex_input::<Vertex, InstanceData>()?;
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                vertex_reflection,
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )?;
+
+        Ok(Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<Vertex, InstanceData>::new())
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .primitive_restart(false)
+                // Each light renders to its own tile
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .depth_clamp(false)
+                .depth_stencil_simple_depth()
+                .polygon_mode_fill()
+                .cull_mode_back()
+                .front_face_counter_clockwise()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)?,
+        ))
+    }
+
+    #[inline]
+    pub fn settings(&self) -> &ShadowAtlasSettings {
+        &self.settings
+    }
+
+    /// Change the settings that don't need a new atlas, see [ShadowAtlasSettings::size].
+    pub fn set_settings(&mut self, settings: ShadowAtlasSettings) {
+        assert_eq!(
+            settings.size, self.settings.size,
+            "The atlas size can't change without recreating it !"
+        );
+        self.settings = settings;
+        self.warned = false;
+    }
+
+    #[inline]
+    pub fn atlas(&self) -> &Arc<AttachmentImage> {
+        &self.atlas
+    }
+
+    #[inline]
+    pub fn sampler(&self) -> &Arc<Sampler> {
+        &self.sampler
+    }
+
+    /// Texel size, PCF radius and cascade blend, as expected in the frame uniforms.
+    pub fn shader_params(&self) -> [f32; 4] {
+        [
+            1.0 / self.settings.size as f32,
+            self.settings.pcf_radius as f32,
+            self.settings.cascade_blend,
+            0.0,
+        ]
+    }
+
+    /// Assign a tile to each shadow caster, point their light at it and cull the scene
+    /// from its point of view.
+    pub fn prepare(
+        &mut self,
+        scene: &mut Scene,
+        camera_view: &CameraView,
+        casters: &[ShadowCaster],
+        lights: &mut [GpuLight],
+        frame_allocator: &mut FrameAllocator,
+        stats: &mut FrameStats,
+    ) -> (Vec<ShadowPass>, Vec<GpuShadow>) {
+        let tile_count = self.settings.tile_count();
+        let cascade_count = self.settings.cascade_count.clamp(1, MAX_CASCADES);
+
+        let mut passes = Vec::new();
+        let mut shadows: Vec<GpuShadow> = Vec::new();
+        for caster in casters {
+            // View projection and camera distances covered of each shadow map of the light
+            let views = match caster.kind {
+                LightKind::Directional => self.cascade_views(caster, camera_view, cascade_count),
+                LightKind::Spot {
+                    range, outer_angle, ..
+                } => vec![(spot_view_projection(caster, range, outer_angle), [0.0; 4])],
+                // Filtered out by the light gatherer
+                LightKind::Point { .. } => continue,
+            };
+
+            if shadows.len() + views.len() > tile_count {
+                if !self.warned {
+                    warn!(
+                        "Shadow atlas full with {} tiles, some lights don't cast shadows",
+                        tile_count
+                    );
+                    self.warned = true;
+                }
+                continue;
+            }
+
+            lights[caster.light_index].shadow = [
+                shadows.len() as f32,
+                caster.shadow.depth_bias,
+                caster.shadow.normal_bias,
+                views.len() as f32,
+            ];
+
+            for (view_projection, split) in views {
+                let (viewport, atlas_rect) = self.tile(shadows.len() as u32);
+                shadows.push(GpuShadow {
+                    view_projection: view_projection.into(),
+                    atlas_rect,
+                    split,
+                });
+
+                // The casters are culled but not counted with the objects of the camera
+                let mut shadow_stats = FrameStats::default();
+                let batches = self.instance_batcher.prepare(
+                    scene,
+                    &Frustum::from_matrix(&view_projection),
+                    frame_allocator,
+                    &mut shadow_stats,
+                );
+                stats.shadow_draw_calls += batches.len();
+
+                passes.push(ShadowPass {
+                    view_projection,
+                    viewport,
+                    batches,
+                });
+            }
+        }
+
+        (passes, shadows)
+    }
+
+    /// Render the shadow maps, the atlas is cleared even without any so it can be sampled.
+    pub fn record(
+        &self,
+        mut builder: AutoCommandBufferBuilder,
+        passes: &[ShadowPass],
+    ) -> AutoCommandBufferBuilder {
+        builder = builder
+            .begin_render_pass(self.framebuffer.clone(), false, vec![1f32.into()])
+            .unwrap();
+
+        for pass in passes {
+            let dynamic_state = DynamicState {
+                viewports: Some(vec![pass.viewport.clone()]),
+                ..DynamicState::none()
+            };
+            let push_constants = PushConstants {
+                light_view_projection: pass.view_projection.into(),
+            };
+
+            for batch in &pass.batches {
+                let vertex_buffers = vec![batch.mesh.vertex_buffer(), batch.instances.clone()];
+
+                builder = match batch.mesh.index_buffer() {
+                    Some(index_buffer) => builder
+                        .draw_indexed(
+                            self.pipeline.clone(),
+                            &dynamic_state,
+                            vertex_buffers,
+                            index_buffer.clone(),
+                            (),
+                            push_constants,
+                        )
+                        .expect("Failed to record indexed shadow draw !"),
+                    None => builder
+                        .draw(
+                            self.pipeline.clone(),
+                            &dynamic_state,
+                            vertex_buffers,
+                            (),
+                            push_constants,
+                        )
+                        .expect("Failed to record shadow draw !"),
+                };
+            }
+        }
+
+        builder.end_render_pass().unwrap()
+    }
+
+    /// Viewport and UV rect of a tile, row by row.
+    fn tile(&self, tile: u32) -> (Viewport, [f32; 4]) {
+        let tiles_per_row = self.settings.tiles_per_row();
+        let tile_size = self.settings.tile_size as f32;
+        let origin = [
+            (tile % tiles_per_row) as f32 * tile_size,
+            (tile / tiles_per_row) as f32 * tile_size,
+        ];
+
+        let viewport = Viewport {
+            origin,
+            dimensions: [tile_size, tile_size],
+            depth_range: 0.0..1.0,
+        };
+
+        let atlas_size = self.settings.size as f32;
+        let atlas_rect = [
+            origin[0] / atlas_size,
+            origin[1] / atlas_size,
+            tile_size / atlas_size,
+            tile_size / atlas_size,
+        ];
+
+        (viewport, atlas_rect)
+    }
+
+    /// Split the camera frustum up to `max_distance` and fit an orthographic projection
+    /// around each slice.
+    fn cascade_views(
+        &self,
+        caster: &ShadowCaster,
+        camera_view: &CameraView,
+        cascade_count: u32,
+    ) -> Vec<(Mat4, [f32; 4])> {
+        let corners = frustum_corners(camera_view);
+        let camera_range = depth_range(camera_view, &corners);
+        let (near, far) = camera_range;
+        let far = far.min(self.settings.max_distance).max(near);
+        let lambda = self.settings.cascade_split_lambda;
+
+        let mut views = Vec::with_capacity(cascade_count as usize);
+        let mut split_near = near;
+        for i in 1..=cascade_count {
+            let fraction = i as f32 / cascade_count as f32;
+            let linear = near + (far - near) * fraction;
+            // Only defined in front of the camera, orthographic views can start at 0 or behind
+            let logarithmic = if near > 0.0 {
+                near * (far / near).powf(fraction)
+            } else {
+                linear
+            };
+            let split_far = lambda * logarithmic + (1.0 - lambda) * linear;
+
+            let slice = frustum_slice(&corners, camera_range, split_near, split_far);
+            views.push((
+                self.cascade_view_projection(caster.direction, &slice),
+                [split_near, split_far, 0.0, 0.0],
+            ));
+            split_near = split_far;
+        }
+
+        views
+    }
+
+    /// Orthographic projection around a slice of the camera frustum, stable while the
+    /// camera moves and turns.
+    fn cascade_view_projection(&self, direction: Vec3, corners: &[Vec3; 8]) -> Mat4 {
+        let sphere = BoundingSphere::from_points(corners.iter().copied()).unwrap();
+        // The sphere is the same whatever the camera orientation, quantize it against
+        // floating point noise
+        let radius = (sphere.radius * 16.0).ceil() / 16.0;
+
+        // Anchored at the origin so only the texel snapping below moves the projection
+        let view = Mat4::look_at(Vec3::ZERO, direction, up_vector(direction));
+        let center = view.transform_point(sphere.center);
+
+        // Move by whole texels to keep the shadow edges from shimmering
+        let texel = radius * 2.0 / self.settings.tile_size as f32;
+        let x = (center.x / texel).floor() * texel;
+        let y = (center.y / texel).floor() * texel;
+
+        // Pulled back so casters up to a radius outside of the sphere still land in the map
+        let depth = -center.z;
+        let projection = Mat4::orthographic(
+            x - radius,
+            x + radius,
+            y - radius,
+            y + radius,
+            depth - radius * 2.0,
+            depth + radius,
+        );
+
+        projection * view
+    }
+}
+
+fn spot_view_projection(caster: &ShadowCaster, range: f32, outer_angle: f32) -> Mat4 {
+    let view = Mat4::look_at(
+        caster.position,
+        caster.position + caster.direction,
+        up_vector(caster.direction),
+    );
+    let projection = Mat4::perspective(outer_angle * 2.0, 1.0, SPOT_NEAR, range);
+
+    projection * view
+}
+
+/// Any up vector that isn't parallel to the direction.
+fn up_vector(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
+
+/// World corners of the camera frustum, near plane first.
+fn frustum_corners(camera_view: &CameraView) -> [Vec3; 8] {
+    let inverse = camera_view.view_projection.inverse();
+    let mut corners = [Vec3::ZERO; 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+        let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+        let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+        *corner = inverse.project_point(Vec3::new(x, y, z));
+    }
+
+    corners
+}
+
+/// Distances of the near and far planes of the camera.
+fn depth_range(camera_view: &CameraView, corners: &[Vec3; 8]) -> (f32, f32) {
+    (
+        -camera_view.view.transform_point(corners[0]).z,
+        -camera_view.view.transform_point(corners[4]).z,
+    )
+}
+
+/// World corners of the part of the camera frustum between two distances.
+fn frustum_slice(
+    corners: &[Vec3; 8],
+    (camera_near, camera_far): (f32, f32),
+    near: f32,
+    far: f32,
+) -> [Vec3; 8] {
+    let depth = (camera_far - camera_near).max(f32::EPSILON);
+    let near = (near - camera_near) / depth;
+    let far = (far - camera_near) / depth;
+
+    // Slide along the edges of the frustum
+    let mut slice = *corners;
+    let (near_corners, far_corners) = slice.split_at_mut(4);
+    for (near_corner, far_corner) in near_corners.iter_mut().zip(far_corners.iter_mut()) {
+        let edge = (*near_corner, *far_corner);
+        *near_corner = edge.0.lerp(edge.1, near);
+        *far_corner = edge.0.lerp(edge.1, far);
+    }
+
+    slice
+}
@@ -0,0 +1,184 @@
+use crate::math::Mat4;
+use crate::renderer::frame_allocator::FrameAllocator;
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::instancing::InstanceData;
+use crate::renderer::material::MaterialId;
+use crate::renderer::mesh::Mesh;
+use crate::scene::{Scene, Transform};
+use std::sync::Arc;
+use vulkano::buffer::BufferAccess;
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::pipeline::GraphicsPipelineAbstract;
+
+/// Descriptor set of the joint matrices and morph targets in the skinned PBR pipeline.
+pub const JOINT_SET: usize = 2;
+
+/// How many morph targets can deform a mesh at once, the ones with the largest weights win.
+pub const DEFAULT_MAX_MORPH_TARGETS: usize = 8;
+
+/// A skinned mesh in its current pose, drawn on its own.
+pub struct SkinnedDraw {
+    pub mesh: Arc<Mesh>,
+    pub material: MaterialId,
+    /// A single instance
+    pub instance: Arc<dyn BufferAccess + Send + Sync>,
+    pub joints: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+/// Poses the skinned meshes of a scene on the CPU, the vertices are morphed and skinned in
+/// the vertex shader.
+pub struct SkinPoser {
+    max_morph_targets: usize,
+
+    /// Reused between meshes to avoid allocating
+    pose: Vec<Transform>,
+    matrices: Vec<Mat4>,
+    joints: Vec<[[f32; 4]; 4]>,
+    morph_weights: Vec<f32>,
+    active_targets: Vec<(usize, f32)>,
+    /// Target count and vertex count, then the index and the bits of the weight of each
+    /// active target
+    morph_data: Vec<u32>,
+}
+
+impl Default for SkinPoser {
+    fn default() -> Self {
+        Self {
+            max_morph_targets: DEFAULT_MAX_MORPH_TARGETS,
+            pose: Vec::new(),
+            matrices: Vec::new(),
+            joints: Vec::new(),
+            morph_weights: Vec::new(),
+            active_targets: Vec::new(),
+            morph_data: Vec::new(),
+        }
+    }
+}
+
+impl SkinPoser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn max_morph_targets(&self) -> usize {
+        self.max_morph_targets
+    }
+
+    #[inline]
+    pub fn set_max_morph_targets(&mut self, max_morph_targets: usize) {
+        self.max_morph_targets = max_morph_targets;
+    }
+
+    /// Sample the animators or players and prepare the draws, the joint matrices go into the
+    /// frame allocator.
+    ///
+    /// Skinned meshes aren't culled, a pose can move the vertices far out of the bounds of
+    /// the bind pose.
+    pub fn prepare(
+        &mut self,
+        scene: &mut Scene,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        frame_allocator: &mut FrameAllocator,
+        stats: &mut FrameStats,
+    ) -> Vec<SkinnedDraw> {
+        scene.graph_mut().update();
+        let layout = pipeline
+            .descriptor_set_layout(JOINT_SET)
+            .expect("The pipeline has no joint set !");
+
+        let mut draws = Vec::new();
+        let nodes: Vec<_> = scene.skinned_meshes().map(|(node, _)| node).collect();
+        for node in nodes {
+            // Removed directly from the graph
+            if !scene.graph().contains(node) {
+                continue;
+            }
+            let model = scene.graph_mut().world_matrix(node);
+            let skinned_mesh = scene.skinned_mesh(node).unwrap();
+            stats.objects += 1;
+
+            let mesh = &skinned_mesh.mesh;
+            self.morph_weights.clear();
+            self.morph_weights
+                .extend_from_slice(&skinned_mesh.morph_weights);
+            self.morph_weights.resize(mesh.morph_target_count(), 0.0);
+
+            match &skinned_mesh.animator {
+                Some(animator) => {
+                    animator.pose(&skinned_mesh.skin, &mut self.pose, &mut self.morph_weights)
+                }
+                None => skinned_mesh.player.pose(
+                    &skinned_mesh.skin,
+                    &mut self.pose,
+                    &mut self.morph_weights,
+                ),
+            }
+            skinned_mesh
+                .skin
+                .joint_matrices(&self.pose, &mut self.matrices);
+            self.joints.clear();
+            self.joints.extend(
+                self.matrices
+                    .iter()
+                    .map(|matrix| <[[f32; 4]; 4]>::from(*matrix)),
+            );
+
+            let joints = frame_allocator.allocate_storage(&self.joints);
+
+            self.active_targets.clear();
+            self.active_targets.extend(
+                self.morph_weights
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .filter(|(_, weight)| *weight != 0.0),
+            );
+            self.active_targets
+                .sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+            self.active_targets.truncate(self.max_morph_targets);
+
+            self.morph_data.clear();
+            self.morph_data
+                .extend_from_slice(&[self.active_targets.len() as u32, mesh.vertex_count()]);
+            for (target, weight) in &self.active_targets {
+                self.morph_data
+                    .extend_from_slice(&[*target as u32, weight.to_bits()]);
+            }
+            let morph_weights = frame_allocator.allocate_storage(&self.morph_data);
+            // The set needs a buffer even without morph targets, the shader doesn't read it
+            let morph_targets: Arc<dyn BufferAccess + Send + Sync> = match mesh.morph_buffer() {
+                Some(buffer) => buffer.clone(),
+                None => Arc::new(frame_allocator.allocate_storage(&[[0.0f32; 4]]).slice()),
+            };
+            let instance = frame_allocator.allocate_vertices(&[InstanceData {
+                model: model.into(),
+                tint: skinned_mesh.tint.to_array(),
+                params: skinned_mesh.params.to_array(),
+            }]);
+            stats.instances += 1;
+
+            draws.push(SkinnedDraw {
+                mesh: skinned_mesh.mesh.clone(),
+                material: skinned_mesh.material,
+                instance: Arc::new(instance.slice()),
+                joints: Arc::new(
+                    PersistentDescriptorSet::start(layout.clone())
+                        .add_buffer(joints.slice())
+                        .unwrap()
+                        .add_buffer(morph_targets)
+                        .unwrap()
+                        .add_buffer(morph_weights.slice())
+                        .unwrap()
+                        .build()
+                        .expect("Failed to create joint descriptor set !"),
+                ),
+            });
+        }
+
+        // Sorted by material to limit descriptor set switches
+        draws.sort_by_key(|draw| draw.material);
+        draws
+    }
+}
@@ -0,0 +1,561 @@
+use crate::math::{Mat4, Vec2, Vec3, Vec4};
+use crate::renderer::frame_allocator::FrameAllocator;
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::fullscreen;
+use crate::renderer::memory_tracker::MemoryTracker;
+use crate::renderer::mesh::{Mesh, MeshVertex};
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use crate::renderer::texture::Texture;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use vulkano::buffer::BufferAccess;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/sprite.vert"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/sprite.frag"
+    }
+}
+
+/// Region of a texture, in normalized coordinates from the top left corner.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UvRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl UvRect {
+    pub const FULL: UvRect = UvRect {
+        min: Vec2::ZERO,
+        max: Vec2::ONE,
+    };
+
+    #[inline]
+    pub const fn new(min: Vec2, max: Vec2) -> Self {
+        Self { min, max }
+    }
+
+    /// Region of a texture given in texels, like a frame of a sprite sheet.
+    pub fn from_texels(texture_dimensions: [u32; 2], position: [u32; 2], size: [u32; 2]) -> Self {
+        let width = texture_dimensions[0] as f32;
+        let height = texture_dimensions[1] as f32;
+        Self {
+            min: Vec2::new(position[0] as f32 / width, position[1] as f32 / height),
+            max: Vec2::new(
+                (position[0] + size[0]) as f32 / width,
+                (position[1] + size[1]) as f32 / height,
+            ),
+        }
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+}
+
+impl Default for UvRect {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Textured quad, one texel of its region is one world unit before scaling.
+///
+/// World coordinates are in pixels at a zoom of 1, with Y pointing down.
+#[derive(Clone)]
+pub struct Sprite {
+    pub texture: Arc<Texture>,
+    pub position: Vec2,
+    /// In radians, clockwise on the screen
+    pub rotation: f32,
+    /// Negative to flip the sprite
+    pub scale: Vec2,
+    /// Point put at the position and rotated around, from the top left (0, 0) to the
+    /// bottom right (1, 1)
+    pub origin: Vec2,
+    pub uv_rect: UvRect,
+    /// Multiplies the texture, alpha included
+    pub tint: Vec4,
+    /// Higher layers are drawn over lower ones
+    pub layer: i32,
+}
+
+impl Sprite {
+    /// The whole texture, centered on the origin of the world.
+    pub fn new(texture: Arc<Texture>) -> Self {
+        Self {
+            texture,
+            position: Vec2::ZERO,
+            rotation: 0.0,
+            scale: Vec2::ONE,
+            origin: Vec2::new(0.5, 0.5),
+            uv_rect: UvRect::FULL,
+            tint: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            layer: 0,
+        }
+    }
+
+    #[inline]
+    pub fn with_position(mut self, position: Vec2) -> Self {
+        self.position = position;
+        self
+    }
+
+    #[inline]
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    #[inline]
+    pub fn with_scale(mut self, scale: Vec2) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    #[inline]
+    pub fn with_origin(mut self, origin: Vec2) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    #[inline]
+    pub fn with_uv_rect(mut self, uv_rect: UvRect) -> Self {
+        self.uv_rect = uv_rect;
+        self
+    }
+
+    #[inline]
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    #[inline]
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Size in world units, scale included.
+    pub fn size(&self) -> Vec2 {
+        let dimensions = self.texture.dimensions();
+        let texels = self.uv_rect.size();
+        Vec2::new(
+            texels.x * dimensions[0] as f32 * self.scale.x,
+            texels.y * dimensions[1] as f32 * self.scale.y,
+        )
+    }
+
+    fn instance_data(&self) -> SpriteInstance {
+        let size = self.size();
+        let x_axis = Vec2::new(size.x, 0.0).rotate(self.rotation);
+        let y_axis = Vec2::new(0.0, size.y).rotate(self.rotation);
+        let translation = self.position - x_axis * self.origin.x - y_axis * self.origin.y;
+
+        SpriteInstance {
+            axes: [x_axis.x, x_axis.y, y_axis.x, y_axis.y],
+            translation: translation.to_array(),
+            region: [
+                self.uv_rect.min.x,
+                self.uv_rect.min.y,
+                self.uv_rect.max.x,
+                self.uv_rect.max.y,
+            ],
+            tint: self.tint.to_array(),
+        }
+    }
+}
+
+/// Orthographic camera of the sprites.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpriteCamera {
+    /// World position at the center of the screen
+    pub position: Vec2,
+    /// Screen pixels per world unit
+    pub zoom: f32,
+    /// Snap to the pixel grid and sample without filtering, for pixel art.
+    /// The zoom is then rounded down to a whole number.
+    pub pixel_perfect: bool,
+}
+
+impl Default for SpriteCamera {
+    fn default() -> Self {
+        Self {
+            position: Vec2::ZERO,
+            zoom: 1.0,
+            pixel_perfect: true,
+        }
+    }
+}
+
+impl SpriteCamera {
+    /// The zoom actually used.
+    #[inline]
+    pub fn effective_zoom(&self) -> f32 {
+        if self.pixel_perfect {
+            self.zoom.floor().max(1.0)
+        } else {
+            self.zoom
+        }
+    }
+
+    /// World position of the top left corner of the screen.
+    fn top_left(&self, viewport: [u32; 2]) -> Vec2 {
+        let zoom = self.effective_zoom();
+        let half_viewport = Vec2::new(viewport[0] as f32, viewport[1] as f32) * 0.5;
+        let mut top_left = self.position * zoom - half_viewport;
+        if self.pixel_perfect {
+            // World units land on whole screen pixels
+            top_left = Vec2::new(top_left.x.round(), top_left.y.round());
+        }
+        top_left / zoom
+    }
+
+    pub fn view_projection(&self, viewport: [u32; 2]) -> Mat4 {
+        let zoom = self.effective_zoom();
+        let top_left = self.top_left(viewport);
+        let right = top_left.x + viewport[0] as f32 / zoom;
+        let bottom = top_left.y + viewport[1] as f32 / zoom;
+        // Bottom and top swapped, Y points down
+        Mat4::orthographic(top_left.x, right, bottom, top_left.y, -1.0, 1.0)
+    }
+
+    /// World position under a point of the screen given in pixels, like the cursor.
+    pub fn screen_to_world(&self, screen: Vec2, viewport: [u32; 2]) -> Vec2 {
+        self.top_left(viewport) + screen / self.effective_zoom()
+    }
+}
+
+/// Sprites to draw in the next frame, emptied once it is rendered.
+///
+/// Sprites are drawn by layer, then grouped by texture. Those of a layer using the same texture
+/// keep the order they were added in, use different layers for overlapping sprites with
+/// different textures.
+#[derive(Default)]
+pub struct SpriteBatch {
+    sprites: Vec<Sprite>,
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+}
+
+/// Corner of the unit quad every sprite is drawn with, also used by the glyphs.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct QuadVertex {
+    pub corner: [f32; 2],
+}
+
+impl_vertex!(QuadVertex, corner);
+
+impl QuadVertex {
+    /// From (0, 0) to (1, 1), indexed.
+    pub fn unit_quad(device: &Arc<Device>, memory_tracker: &Arc<MemoryTracker>) -> Mesh {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let vertices: Vec<_> = corners
+            .iter()
+            .map(|corner| QuadVertex { corner: *corner })
+            .collect();
+        Mesh::new(device, memory_tracker, &vertices, Some(&[0, 1, 2, 2, 3, 0]))
+    }
+}
+
+impl MeshVertex for QuadVertex {
+    #[inline]
+    fn position(&self) -> Vec3 {
+        Vec3::new(self.corner[0], self.corner[1], 0.0)
+    }
+}
+
+/// Per instance vertex attributes of a sprite.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct SpriteInstance {
+    /// X axis then Y axis, scaled by the size
+    pub axes: [f32; 4],
+    /// Top left corner
+    pub translation: [f32; 2],
+    /// Min UV then max UV
+    pub region: [f32; 4],
+    pub tint: [f32; 4],
+}
+
+impl_vertex!(SpriteInstance, axes, translation, region, tint);
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PushConstants {
+    view_projection: [[f32; 4]; 4],
+    viewport_size: [f32; 2],
+    pixel_snap: u32,
+}
+
+/// Consecutive sprites sharing a texture, drawn with a single call.
+pub struct SpriteDraw {
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    instances: Arc<dyn BufferAccess + Send + Sync>,
+}
+
+/// What the sprites of a frame need, prepared before recording.
+pub struct SpriteFrame {
+    draws: Vec<SpriteDraw>,
+    push_constants: PushConstants,
+}
+
+impl SpriteFrame {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+}
+
+/// Descriptor set of a texture, dropped with it.
+struct CachedSet {
+    texture: Weak<Texture>,
+    set: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+/// Draws batched sprites over the processed frame, with premultiplied alpha blending.
+pub struct SpriteRenderer {
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    quad: Mesh,
+    linear_sampler: Arc<Sampler>,
+    nearest_sampler: Arc<Sampler>,
+    /// By texture and whether it is filtered
+    descriptor_sets: HashMap<(usize, bool), CachedSet>,
+    /// Reused between frames to avoid allocating
+    instances: Vec<SpriteInstance>,
+}
+
+impl SpriteRenderer {
+    /// Has to be recreated with the render pass.
+    pub fn new(
+        device: &Arc<Device>,
+        memory_tracker: &Arc<MemoryTracker>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let premultiplied = AttachmentBlend {
+            enabled: true,
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_source: BlendFactor::One,
+            alpha_destination: BlendFactor::OneMinusSrcAlpha,
+            ..AttachmentBlend::pass_through()
+        };
+
+        // Flipped sprites are back facing, nothing is culled
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create sprite pipeline layout !");
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<QuadVertex, SpriteInstance>::new())
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .cull_mode_disabled()
+                .blend_collective(premultiplied)
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create sprite pipeline !"),
+        );
+
+        Self {
+            pipeline,
+            quad: QuadVertex::unit_quad(device, memory_tracker),
+            linear_sampler: Self::create_sampler(device, Filter::Linear),
+            nearest_sampler: Self::create_sampler(device, Filter::Nearest),
+            descriptor_sets: HashMap::new(),
+            instances: Vec::new(),
+        }
+    }
+
+    fn create_sampler(device: &Arc<Device>, filter: Filter) -> Arc<Sampler> {
+        // Clamped, neighbouring regions of an atlas shouldn't bleed in
+        Sampler::new(
+            device.clone(),
+            filter,
+            filter,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .expect("Failed to create sprite sampler !")
+    }
+
+    /// Sort and batch the sprites, the instance data goes into the frame allocator.
+    /// The batch is emptied.
+    pub fn prepare(
+        &mut self,
+        batch: &mut SpriteBatch,
+        camera: &SpriteCamera,
+        viewport: [u32; 2],
+        frame_allocator: &mut FrameAllocator,
+        stats: &mut FrameStats,
+    ) -> SpriteFrame {
+        // Sets of textures that were dropped would keep them alive, and their address may
+        // be reused by a new one
+        self.descriptor_sets
+            .retain(|_, cached| cached.texture.strong_count() > 0);
+
+        // Stable, the order within a layer and texture is kept
+        let sprites = &mut batch.sprites;
+        sprites.sort_by_key(|sprite| (sprite.layer, Arc::as_ptr(&sprite.texture) as usize));
+
+        let mut draws = Vec::new();
+        let mut start = 0;
+        while start < sprites.len() {
+            let texture = &sprites[start].texture;
+            let end = sprites[start..]
+                .iter()
+                .position(|sprite| !Arc::ptr_eq(&sprite.texture, texture))
+                .map_or(sprites.len(), |count| start + count);
+
+            self.instances.clear();
+            self.instances
+                .extend(sprites[start..end].iter().map(Sprite::instance_data));
+            let allocation = frame_allocator.allocate_vertices(&self.instances);
+
+            draws.push(SpriteDraw {
+                descriptor_set: self.descriptor_set(texture, !camera.pixel_perfect),
+                instances: Arc::new(allocation.slice()),
+            });
+            start = end;
+        }
+        stats.sprites += sprites.len();
+        sprites.clear();
+
+        SpriteFrame {
+            draws,
+            push_constants: PushConstants {
+                view_projection: camera.view_projection(viewport).into(),
+                viewport_size: [viewport[0] as f32, viewport[1] as f32],
+                pixel_snap: camera.pixel_perfect as u32,
+            },
+        }
+    }
+
+    fn descriptor_set(
+        &mut self,
+        texture: &Arc<Texture>,
+        filtered: bool,
+    ) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let key = (Arc::as_ptr(texture) as usize, filtered);
+        if let Some(cached) = self.descriptor_sets.get(&key) {
+            return cached.set.clone();
+        }
+
+        let sampler = if filtered {
+            &self.linear_sampler
+        } else {
+            &self.nearest_sampler
+        };
+        let layout = self.pipeline.descriptor_set_layout(0).unwrap().clone();
+        let set: Arc<dyn DescriptorSet + Send + Sync> = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_sampled_image(texture.image().clone(), sampler.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create sprite descriptor set !"),
+        );
+        self.descriptor_sets.insert(
+            key,
+            CachedSet {
+                texture: Arc::downgrade(texture),
+                set: set.clone(),
+            },
+        );
+        set
+    }
+
+    /// Has to be inside the overlay render pass of the post processor.
+    pub fn record(
+        &self,
+        mut builder: AutoCommandBufferBuilder,
+        frame: &SpriteFrame,
+        dimensions: [u32; 2],
+        stats: &mut FrameStats,
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        };
+        let index_buffer = self.quad.index_buffer().unwrap();
+
+        for draw in &frame.draws {
+            builder = builder
+                .draw_indexed(
+                    self.pipeline.clone(),
+                    &dynamic_state,
+                    vec![self.quad.vertex_buffer(), draw.instances.clone()],
+                    index_buffer.clone(),
+                    draw.descriptor_set.clone(),
+                    frame.push_constants,
+                )
+                .expect("Failed to record sprite draw !");
+            stats.draw_calls += 1;
+        }
+
+        builder
+    }
+}
@@ -1,11 +1,12 @@
+use crate::renderer::display_output::DisplayOutput;
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
 use crate::renderer::DIMENSIONS;
-use log::warn;
+use log::{info, warn};
 use std::sync::Arc;
 use vulkano::device::{Device, DeviceOwned, Queue};
 use vulkano::format::Format;
-use vulkano::framebuffer::RenderPassAbstract;
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract};
 use vulkano::image::{ImageUsage, SwapchainImage};
-use vulkano::instance::{Instance, PhysicalDevice};
 use vulkano::single_pass_renderpass;
 use vulkano::swapchain::{
     Capabilities, ColorSpace, CompositeAlpha, FullscreenExclusive, PresentMode,
@@ -16,27 +17,33 @@ use winit::window::Window;
 
 pub struct SwapChainWrapper {
     swap_chain: Arc<Swapchain<Window>>,
-    images: Vec<Arc<SwapchainImage<Window>>>,
+    /// Not exposed by the swap chain
+    color_space: ColorSpace,
+    output: DisplayOutput,
+    _images: Vec<Arc<SwapchainImage<Window>>>,
     render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
+
+    memory_tracker: Arc<MemoryTracker>,
+    _image_allocations: Vec<TrackedAllocation>,
 }
 
 impl SwapChainWrapper {
     pub fn create(
-        instance: &Arc<Instance>,
         surface: &Arc<Surface<Window>>,
-        physical_device: usize,
         device: &Arc<Device>,
         graphics_queue: &Arc<Queue>,
         presentation_queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        preferred_output: DisplayOutput,
     ) -> Self {
-        let physical_device = PhysicalDevice::from_index(&instance, physical_device).unwrap();
         let capabilities = surface
-            .capabilities(physical_device)
+            .capabilities(device.physical_device())
             .expect("Failed to get surface capabilities !");
 
         // Screen related config
-        let (surface_format, surface_color_space) =
-            Self::choose_surface_format(&capabilities.supported_formats);
+        let (surface_format, surface_color_space, output) =
+            Self::choose_surface_format(&capabilities.supported_formats, preferred_output);
         let presentation_mode = Self::choose_presentation_mode(capabilities.present_modes);
         let extent = Self::choose_extent(&capabilities);
 
@@ -84,13 +91,23 @@ impl SwapChainWrapper {
         )
         .expect("Failed to create swap chain !");
 
+        let render_pass = Self::create_render_pass(device, surface_format);
+        let framebuffers = Self::create_framebuffers(&images, &render_pass);
+        let _image_allocations = Self::track_images(&images, memory_tracker);
+
         Self {
             swap_chain,
-            images,
-            render_pass: Self::create_render_pass(device, surface_format),
+            color_space: surface_color_space,
+            output,
+            _images: images,
+            render_pass,
+            framebuffers,
+            memory_tracker: memory_tracker.clone(),
+            _image_allocations,
         }
     }
 
+    /// Final pass writing to the screen, the scene itself is rendered offscreen.
     fn create_render_pass(
         device: &Arc<Device>,
         color_format: Format,
@@ -99,7 +116,7 @@ impl SwapChainWrapper {
             single_pass_renderpass!(device.clone(),
                 attachments: {
                     color: {
-                        load: Clear,
+                        load: DontCare,
                         store: Store,
                         format: color_format,
                         samples: 1,
@@ -114,21 +131,134 @@ impl SwapChainWrapper {
         )
     }
 
+    fn create_framebuffers(
+        images: &[Arc<SwapchainImage<Window>>],
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
+        images
+            .iter()
+            .map(|image| {
+                Arc::new(
+                    Framebuffer::start(render_pass.clone())
+                        .add(image.clone())
+                        .unwrap()
+                        .build()
+                        .expect("Failed to create framebuffer !"),
+                ) as Arc<dyn FramebufferAbstract + Send + Sync>
+            })
+            .collect()
+    }
+
+    /// Owned by the presentation engine, but they still take room in the heaps.
+    fn track_images(
+        images: &[Arc<SwapchainImage<Window>>],
+        memory_tracker: &Arc<MemoryTracker>,
+    ) -> Vec<TrackedAllocation> {
+        images
+            .iter()
+            .map(|image| memory_tracker.track_image(MemoryCategory::RenderTarget, image))
+            .collect()
+    }
+
+    fn choose_surface_format(
+        available_formats: &[(Format, ColorSpace)],
+        preferred_output: DisplayOutput,
+    ) -> (Format, ColorSpace, DisplayOutput) {
+        let hdr_format = match preferred_output {
+            DisplayOutput::Sdr => None,
+            DisplayOutput::Hdr10 => Self::find_surface_format(
+                available_formats,
+                &[
+                    Format::A2B10G10R10UnormPack32,
+                    Format::A2R10G10B10UnormPack32,
+                ],
+                ColorSpace::Hdr10St2084,
+            ),
+            DisplayOutput::ScRgb => Self::find_surface_format(
+                available_formats,
+                &[Format::R16G16B16A16Sfloat],
+                ColorSpace::ExtendedSrgbLinear,
+            ),
+        };
+
+        if let Some((format, color_space)) = hdr_format {
+            info!("Presenting in {:?} with {:?}", color_space, format);
+            return (format, color_space, preferred_output);
+        }
+        if preferred_output.is_hdr() {
+            info!(
+                "No surface format for {:?} output, falling back to SDR",
+                preferred_output
+            );
+        }
+
+        let (format, color_space) = Self::choose_sdr_surface_format(available_formats);
+        (format, color_space, DisplayOutput::Sdr)
+    }
+
+    #[inline]
+    fn find_surface_format(
+        available_formats: &[(Format, ColorSpace)],
+        preferred_formats: &[Format],
+        color_space: ColorSpace,
+    ) -> Option<(Format, ColorSpace)> {
+        preferred_formats.iter().find_map(|preferred| {
+            available_formats
+                .iter()
+                .find(|available| *available == &(*preferred, color_space))
+                .copied()
+        })
+    }
+
     #[inline]
-    fn choose_surface_format(available_formats: &[(Format, ColorSpace)]) -> (Format, ColorSpace) {
-        // Always choose B8G8R8A8Unorm and SrgbNonLinear or fallback to whatever is available
-        // TODO improve this
-        *available_formats.iter()
-            .find(|(format, color_space)| {
-                *format == Format::B8G8R8A8Unorm && *color_space == ColorSpace::SrgbNonLinear
+    fn choose_sdr_surface_format(
+        available_formats: &[(Format, ColorSpace)],
+    ) -> (Format, ColorSpace) {
+        // Prefer sRGB formats so the hardware does the encoding, then Unorm ones which are
+        // encoded by the tonemapping pass, or fallback to whatever is available
+        let preferred = [
+            Format::B8G8R8A8Srgb,
+            Format::R8G8B8A8Srgb,
+            Format::B8G8R8A8Unorm,
+            Format::R8G8B8A8Unorm,
+        ];
+
+        *preferred
+            .iter()
+            .find_map(|preferred| {
+                available_formats.iter().find(|(format, color_space)| {
+                    format == preferred && *color_space == ColorSpace::SrgbNonLinear
+                })
             })
             .unwrap_or_else(|| {
                 let format = &available_formats[0];
-                warn!("Can't find surface format B8G8R8A8Unorm and SrgbNonLinear, falling back to {:?} and {:?}", format.0, format.1);
+                warn!(
+                    "Can't find an 8 bit surface format with SrgbNonLinear, falling back to {:?} and {:?}",
+                    format.0, format.1
+                );
                 format
             })
     }
 
+    /// What the swap chain ended up presenting, SDR when the preferred output isn't supported.
+    #[inline]
+    pub fn output(&self) -> DisplayOutput {
+        self.output
+    }
+
+    /// Whether the shaders have to encode sRGB themselves when writing to the screen.
+    #[inline]
+    pub fn needs_srgb_encoding(&self) -> bool {
+        match self.swap_chain.format() {
+            Format::B8G8R8A8Srgb
+            | Format::R8G8B8A8Srgb
+            | Format::A8B8G8R8SrgbPack32
+            | Format::R8G8B8Srgb
+            | Format::B8G8R8Srgb => false,
+            _ => self.color_space == ColorSpace::SrgbNonLinear,
+        }
+    }
+
     #[inline]
     fn choose_presentation_mode(
         available_presentation_modes: SupportedPresentModes,
@@ -170,17 +300,41 @@ impl SwapChainWrapper {
         self.swap_chain.clone()
     }
 
+    #[inline]
+    pub fn render_pass(&self) -> &Arc<dyn RenderPassAbstract + Send + Sync> {
+        &self.render_pass
+    }
+
+    #[inline]
+    pub fn framebuffer(&self, image_index: usize) -> Arc<dyn FramebufferAbstract + Send + Sync> {
+        self.framebuffers[image_index].clone()
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.swap_chain.dimensions()
+    }
+
     #[inline]
     pub fn recreate(self) -> Self {
         let (swap_chain, images) = self
             .swap_chain
             .recreate()
             .expect("Failed to recreate swap chain !");
+        let render_pass = Self::create_render_pass(swap_chain.device(), swap_chain.format());
+        let framebuffers = Self::create_framebuffers(&images, &render_pass);
+        // The old images are released when the old allocations are dropped
+        let _image_allocations = Self::track_images(&images, &self.memory_tracker);
 
         Self {
             swap_chain,
-            images,
-            render_pass: Self::create_render_pass(swap_chain.device(), swap_chain.format()),
+            color_space: self.color_space,
+            output: self.output,
+            _images: images,
+            render_pass,
+            framebuffers,
+            memory_tracker: self.memory_tracker,
+            _image_allocations,
         }
     }
 }
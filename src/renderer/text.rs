@@ -0,0 +1,599 @@
+use crate::math::{Mat4, Vec2, Vec3, Vec4};
+use crate::renderer::frame_allocator::FrameAllocator;
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::fullscreen;
+use crate::renderer::memory_tracker::MemoryTracker;
+use crate::renderer::mesh::Mesh;
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use crate::renderer::sprites::QuadVertex;
+use crate::renderer::texture::Texture;
+use crate::scene::CameraView;
+use atlas::{GlyphAtlas, SDF_SIZE};
+use log::warn;
+use std::sync::Arc;
+use vulkano::buffer::BufferAccess;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::{Device, Queue};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor};
+use vulkano::pipeline::depth_stencil::{Compare, DepthBounds, DepthStencil, Stencil};
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+mod atlas;
+mod font;
+mod layout;
+
+pub use font::{Font, FontError};
+pub use layout::{LayoutGlyph, LayoutSettings, TextAlignment, TextLayout};
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/text.vert"
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/text.frag"
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum GlyphRendering {
+    /// Coverage rasterized at the size of the text, the sharpest at that size
+    #[default]
+    Bitmap,
+    /// Signed distance field of a single size, stays sharp when scaled like in world space.
+    /// Corners get slightly rounded.
+    Sdf,
+}
+
+#[derive(Clone)]
+pub struct TextStyle {
+    pub font: Arc<Font>,
+    /// Height of a line in pixels
+    pub size: f32,
+    /// Linear, alpha included
+    pub color: Vec4,
+    pub rendering: GlyphRendering,
+}
+
+impl TextStyle {
+    /// White bitmap glyphs.
+    pub fn new(font: Arc<Font>, size: f32) -> Self {
+        Self {
+            font,
+            size,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            rendering: GlyphRendering::Bitmap,
+        }
+    }
+
+    #[inline]
+    pub fn with_color(mut self, color: Vec4) -> Self {
+        self.color = color;
+        self
+    }
+
+    #[inline]
+    pub fn with_rendering(mut self, rendering: GlyphRendering) -> Self {
+        self.rendering = rendering;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextPlacement {
+    /// Top left corner, in pixels from the top left of the screen. Drawn over everything.
+    Screen(Vec2),
+    /// Transform of the text in the scene, hidden by what is in front of it.
+    /// The text faces +Z with its top left corner at the origin, one pixel of the
+    /// layout being one unit.
+    World(Mat4),
+}
+
+struct QueuedText {
+    layout: TextLayout,
+    color: Vec4,
+    rendering: GlyphRendering,
+    placement: TextPlacement,
+}
+
+/// Text to draw in the next frame, emptied once it is rendered.
+#[derive(Default)]
+pub struct TextBatch {
+    texts: Vec<QueuedText>,
+}
+
+impl TextBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lay out and draw text, see [TextLayout::new].
+    pub fn draw(
+        &mut self,
+        text: &str,
+        style: &TextStyle,
+        settings: &LayoutSettings,
+        placement: TextPlacement,
+    ) {
+        let layout = TextLayout::new(text, &style.font, style.size, settings);
+        self.draw_layout(layout, style.color, style.rendering, placement);
+    }
+
+    /// Draw text laid out beforehand, like text that doesn't change.
+    pub fn draw_layout(
+        &mut self,
+        layout: TextLayout,
+        color: Vec4,
+        rendering: GlyphRendering,
+        placement: TextPlacement,
+    ) {
+        self.texts.push(QueuedText {
+            layout,
+            color,
+            rendering,
+            placement,
+        });
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.texts.clear();
+    }
+}
+
+/// Per instance vertex attributes of a glyph.
+#[repr(C)]
+#[derive(Default, Copy, Clone)]
+pub struct GlyphInstance {
+    /// Top left corner
+    pub origin: [f32; 3],
+    /// Top edge, going right
+    pub right: [f32; 3],
+    /// Left edge, going down
+    pub down: [f32; 3],
+    /// Min UV then max UV
+    pub region: [f32; 4],
+    pub color: [f32; 4],
+}
+
+impl_vertex!(GlyphInstance, origin, right, down, region, color);
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PushConstants {
+    view_projection: [[f32; 4]; 4],
+    sdf: u32,
+}
+
+/// Glyphs of the same space and rendering, drawn with a single call.
+struct TextDraw {
+    instances: Arc<dyn BufferAccess + Send + Sync>,
+    push_constants: PushConstants,
+}
+
+/// What the text of a frame needs, prepared before recording.
+pub struct TextFrame {
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    screen: Vec<TextDraw>,
+    world: Vec<TextDraw>,
+}
+
+impl TextFrame {
+    #[inline]
+    pub fn has_screen(&self) -> bool {
+        !self.screen.is_empty()
+    }
+
+    #[inline]
+    pub fn has_world(&self) -> bool {
+        !self.world.is_empty()
+    }
+}
+
+/// Rasterizes glyphs into the atlas as they are needed and draws them as instanced quads.
+pub struct TextRenderer {
+    queue: Arc<Queue>,
+    memory_tracker: Arc<MemoryTracker>,
+    screen_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    world_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    quad: Mesh,
+    sampler: Arc<Sampler>,
+    atlas: GlyphAtlas,
+    /// Reads the current atlas texture, replaced with it
+    descriptor_set: Option<(Arc<Texture>, Arc<dyn DescriptorSet + Send + Sync>)>,
+    /// By world space then distance field, reused between frames to avoid allocating
+    instances: [[Vec<GlyphInstance>; 2]; 2],
+}
+
+impl TextRenderer {
+    /// Screen space text is drawn in the overlay render pass, world space text in the HDR one.
+    pub fn new(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        overlay_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        hdr_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let device = queue.device();
+        let (screen_pipeline, world_pipeline) =
+            Self::create_pipelines(device, overlay_render_pass, hdr_render_pass);
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .expect("Failed to create glyph sampler !");
+
+        Self {
+            queue: queue.clone(),
+            memory_tracker: memory_tracker.clone(),
+            screen_pipeline,
+            world_pipeline,
+            quad: QuadVertex::unit_quad(device, memory_tracker),
+            sampler,
+            atlas: GlyphAtlas::new(),
+            descriptor_set: None,
+            instances: Default::default(),
+        }
+    }
+
+    /// The pipelines are built against the render passes, the glyphs are kept.
+    pub fn set_render_passes(
+        &mut self,
+        overlay_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        hdr_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        let (screen_pipeline, world_pipeline) =
+            Self::create_pipelines(self.queue.device(), overlay_render_pass, hdr_render_pass);
+        self.screen_pipeline = screen_pipeline;
+        self.world_pipeline = world_pipeline;
+    }
+
+    fn create_pipelines(
+        device: &Arc<Device>,
+        overlay_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        hdr_render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> (
+        Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    ) {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let premultiplied = AttachmentBlend {
+            enabled: true,
+            color_source: BlendFactor::One,
+            color_destination: BlendFactor::OneMinusSrcAlpha,
+            alpha_source: BlendFactor::One,
+            alpha_destination: BlendFactor::OneMinusSrcAlpha,
+            ..AttachmentBlend::pass_through()
+        };
+
+        // Both pipelines draw the same shaders
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create text pipeline layout !");
+
+        // Mirrored text is back facing, nothing is culled
+        let screen_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<QuadVertex, GlyphInstance>::new())
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .cull_mode_disabled()
+                .blend_collective(premultiplied.clone())
+                .render_pass(Subpass::from(overlay_render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout.clone())
+                .expect("Failed to create screen text pipeline !"),
+        );
+
+        // Transparent, tested against the scene without hiding what is drawn after it
+        let depth_stencil = DepthStencil {
+            depth_write: false,
+            depth_compare: Compare::Less,
+            depth_bounds_test: DepthBounds::Disabled,
+            stencil_front: Stencil::default(),
+            stencil_back: Stencil::default(),
+        };
+        let world_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<QuadVertex, GlyphInstance>::new())
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .cull_mode_disabled()
+                .depth_stencil(depth_stencil)
+                .blend_collective(premultiplied)
+                .render_pass(Subpass::from(hdr_render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create world text pipeline !"),
+        );
+
+        (screen_pipeline, world_pipeline)
+    }
+
+    /// Rasterize the missing glyphs and batch the text, the instance data goes into the
+    /// frame allocator. The batch is emptied, world space text is dropped without camera.
+    pub fn prepare(
+        &mut self,
+        batch: &mut TextBatch,
+        camera_view: Option<&CameraView>,
+        viewport: [u32; 2],
+        frame_allocator: &mut FrameAllocator,
+        stats: &mut FrameStats,
+    ) -> TextFrame {
+        if camera_view.is_none() {
+            batch
+                .texts
+                .retain(|text| matches!(text.placement, TextPlacement::Screen(_)));
+        }
+        self.cache_glyphs(&batch.texts);
+
+        for group in self.instances.iter_mut().flatten() {
+            group.clear();
+        }
+        let atlas_dimensions = self.atlas.dimensions();
+        let atlas_size = Vec2::new(atlas_dimensions[0] as f32, atlas_dimensions[1] as f32);
+        for text in &batch.texts {
+            let sdf = text.rendering == GlyphRendering::Sdf;
+            let world = matches!(text.placement, TextPlacement::World(_));
+            // Distance fields have a single size, scaled to the one of the text
+            let scale = if sdf {
+                text.layout.size() / SDF_SIZE
+            } else {
+                1.0
+            };
+
+            for layout_glyph in text.layout.glyphs() {
+                let glyph = match self.atlas.glyph(
+                    text.layout.font(),
+                    layout_glyph.glyph,
+                    text.layout.size(),
+                    sdf,
+                ) {
+                    Ok(Some(glyph)) => glyph,
+                    // Nothing to draw, or left out of a full atlas
+                    Ok(None) | Err(_) => continue,
+                };
+
+                let position = Vec2::new(glyph.position[0] as f32, glyph.position[1] as f32);
+                let texels = Vec2::new(glyph.size[0] as f32, glyph.size[1] as f32);
+                let top_left = layout_glyph.position + glyph.offset * scale;
+                let size = texels * scale;
+
+                let (origin, right, down) = match text.placement {
+                    TextPlacement::Screen(screen) => {
+                        let mut origin = screen + top_left;
+                        if !sdf {
+                            // Texels on whole pixels
+                            origin = Vec2::new(origin.x.round(), origin.y.round());
+                        }
+                        (
+                            Vec3::new(origin.x, origin.y, 0.0),
+                            Vec3::new(size.x, 0.0, 0.0),
+                            Vec3::new(0.0, size.y, 0.0),
+                        )
+                    }
+                    // Y of the layout points down, the one of the text up
+                    TextPlacement::World(transform) => (
+                        transform.transform_point(Vec3::new(top_left.x, -top_left.y, 0.0)),
+                        transform.transform_vector(Vec3::new(size.x, 0.0, 0.0)),
+                        transform.transform_vector(Vec3::new(0.0, -size.y, 0.0)),
+                    ),
+                };
+
+                let min_uv = Vec2::new(position.x / atlas_size.x, position.y / atlas_size.y);
+                let max_uv = Vec2::new(
+                    (position.x + texels.x) / atlas_size.x,
+                    (position.y + texels.y) / atlas_size.y,
+                );
+                self.instances[world as usize][sdf as usize].push(GlyphInstance {
+                    origin: origin.to_array(),
+                    right: right.to_array(),
+                    down: down.to_array(),
+                    region: [min_uv.x, min_uv.y, max_uv.x, max_uv.y],
+                    color: text.color.to_array(),
+                });
+            }
+        }
+        batch.clear();
+
+        let screen_projection =
+            Mat4::orthographic(0.0, viewport[0] as f32, viewport[1] as f32, 0.0, -1.0, 1.0);
+        let mut screen = Vec::new();
+        let mut world = Vec::new();
+        for (is_world, groups) in self.instances.iter().enumerate() {
+            for (is_sdf, instances) in groups.iter().enumerate() {
+                if instances.is_empty() {
+                    continue;
+                }
+                stats.glyphs += instances.len();
+
+                let view_projection = if is_world == 1 {
+                    camera_view.unwrap().view_projection
+                } else {
+                    screen_projection
+                };
+                let allocation = frame_allocator.allocate_vertices(instances);
+                let draw = TextDraw {
+                    instances: Arc::new(allocation.slice()),
+                    push_constants: PushConstants {
+                        view_projection: view_projection.into(),
+                        sdf: is_sdf as u32,
+                    },
+                };
+                if is_world == 1 {
+                    world.push(draw);
+                } else {
+                    screen.push(draw);
+                }
+            }
+        }
+
+        TextFrame {
+            descriptor_set: self.atlas_descriptor_set(),
+            screen,
+            world,
+        }
+    }
+
+    /// Make room by clearing the atlas when the glyphs of the frame don't fit.
+    fn cache_glyphs(&mut self, texts: &[QueuedText]) {
+        for attempt in 0..2 {
+            let mut full = false;
+            for text in texts {
+                let sdf = text.rendering == GlyphRendering::Sdf;
+                for glyph in text.layout.glyphs() {
+                    full |= self
+                        .atlas
+                        .glyph(text.layout.font(), glyph.glyph, text.layout.size(), sdf)
+                        .is_err();
+                }
+            }
+
+            if !full {
+                return;
+            }
+            if attempt == 0 {
+                warn!("Glyph atlas is full, clearing it");
+                self.atlas.clear();
+            } else {
+                warn!(
+                    "Glyph atlas is too small for the text of the frame, some glyphs are left out"
+                );
+            }
+        }
+    }
+
+    fn atlas_descriptor_set(&mut self) -> Arc<dyn DescriptorSet + Send + Sync> {
+        let texture = self.atlas.upload(&self.queue, &self.memory_tracker);
+        if let Some((current, set)) = &self.descriptor_set {
+            if Arc::ptr_eq(current, texture) {
+                return set.clone();
+            }
+        }
+
+        // Both pipelines have the same layout
+        let layout = self
+            .screen_pipeline
+            .descriptor_set_layout(0)
+            .unwrap()
+            .clone();
+        let set: Arc<dyn DescriptorSet + Send + Sync> = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_sampled_image(texture.image().clone(), self.sampler.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create glyph atlas descriptor set !"),
+        );
+        self.descriptor_set = Some((texture.clone(), set.clone()));
+        set
+    }
+
+    /// Has to be inside the overlay render pass of the post processor.
+    pub fn record_screen(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        frame: &TextFrame,
+        dimensions: [u32; 2],
+        stats: &mut FrameStats,
+    ) -> AutoCommandBufferBuilder {
+        self.record(
+            builder,
+            &self.screen_pipeline,
+            &frame.screen,
+            &frame.descriptor_set,
+            dimensions,
+            stats,
+        )
+    }
+
+    /// Has to be inside the HDR render pass, after the opaque geometry.
+    pub fn record_world(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        frame: &TextFrame,
+        dimensions: [u32; 2],
+        stats: &mut FrameStats,
+    ) -> AutoCommandBufferBuilder {
+        self.record(
+            builder,
+            &self.world_pipeline,
+            &frame.world,
+            &frame.descriptor_set,
+            dimensions,
+            stats,
+        )
+    }
+
+    fn record(
+        &self,
+        mut builder: AutoCommandBufferBuilder,
+        pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        draws: &[TextDraw],
+        descriptor_set: &Arc<dyn DescriptorSet + Send + Sync>,
+        dimensions: [u32; 2],
+        stats: &mut FrameStats,
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        };
+        let index_buffer = self.quad.index_buffer().unwrap();
+
+        for draw in draws {
+            builder = builder
+                .draw_indexed(
+                    pipeline.clone(),
+                    &dynamic_state,
+                    vec![self.quad.vertex_buffer(), draw.instances.clone()],
+                    index_buffer.clone(),
+                    descriptor_set.clone(),
+                    draw.push_constants,
+                )
+                .expect("Failed to record text draw !");
+            stats.draw_calls += 1;
+        }
+
+        builder
+    }
+}
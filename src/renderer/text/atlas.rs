@@ -0,0 +1,270 @@
+use crate::math::Vec2;
+use crate::renderer::memory_tracker::MemoryTracker;
+use crate::renderer::text::font::Font;
+use crate::renderer::texture::Texture;
+use log::trace;
+use rusttype::{point, GlyphId, Scale};
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::device::Queue;
+
+/// Size distance field glyphs are rasterized at, they are scaled from it when drawn.
+pub const SDF_SIZE: f32 = 48.0;
+/// Pixels of distance stored on each side of the outlines, at `SDF_SIZE`.
+pub const SDF_SPREAD: u32 = 6;
+
+const WIDTH: u32 = 1024;
+const INITIAL_HEIGHT: u32 = 256;
+const MAX_HEIGHT: u32 = 4096;
+/// Empty texels between glyphs, so filtering doesn't pick up the neighbours
+const PADDING: u32 = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: usize,
+    glyph: u16,
+    /// Bits of the pixel size, zero for distance fields which have a single size
+    size: u32,
+    sdf: bool,
+}
+
+/// Where a glyph is in the atlas.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasGlyph {
+    /// Top left corner, in texels
+    pub position: [u32; 2],
+    pub size: [u32; 2],
+    /// From the pen position on the baseline to the top left corner, in pixels of the
+    /// size it was rasterized at
+    pub offset: Vec2,
+}
+
+/// The atlas has no space left, even at its largest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AtlasFull;
+
+/// Row of glyphs of about the same height.
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Start of the free space
+    x: u32,
+}
+
+/// Single channel texture the glyphs are rasterized into when first drawn, either as
+/// coverage or as signed distance.
+///
+/// It grows in height when full, the positions of the glyphs already in it stay valid.
+pub struct GlyphAtlas {
+    height: u32,
+    pixels: Vec<u8>,
+    shelves: Vec<Shelf>,
+    /// Glyphs without outline, like spaces, are cached as `None`
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    texture: Option<Arc<Texture>>,
+    /// Glyphs were added since the last upload
+    dirty: bool,
+}
+
+impl GlyphAtlas {
+    pub fn new() -> Self {
+        Self {
+            height: INITIAL_HEIGHT,
+            pixels: vec![0; (WIDTH * INITIAL_HEIGHT) as usize],
+            shelves: Vec::new(),
+            glyphs: HashMap::new(),
+            texture: None,
+            dirty: true,
+        }
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> [u32; 2] {
+        [WIDTH, self.height]
+    }
+
+    /// Rasterize the glyph if it isn't in the atlas yet, `None` if it has nothing to draw.
+    pub fn glyph(
+        &mut self,
+        font: &Font,
+        glyph: GlyphId,
+        size: f32,
+        sdf: bool,
+    ) -> Result<Option<AtlasGlyph>, AtlasFull> {
+        let key = GlyphKey {
+            font: font.id(),
+            glyph: glyph.0,
+            size: if sdf { 0 } else { size.to_bits() },
+            sdf,
+        };
+        if let Some(cached) = self.glyphs.get(&key) {
+            return Ok(*cached);
+        }
+
+        let rasterized = if sdf {
+            rasterize_sdf(font, glyph)
+        } else {
+            rasterize(font, glyph, size, 0)
+        };
+        let atlas_glyph = match rasterized {
+            Some((coverage, size, offset)) => {
+                let position = self.allocate(size)?;
+                for row in 0..size[1] {
+                    let source = (row * size[0]) as usize;
+                    let destination = ((position[1] + row) * WIDTH + position[0]) as usize;
+                    self.pixels[destination..destination + size[0] as usize]
+                        .copy_from_slice(&coverage[source..source + size[0] as usize]);
+                }
+                self.dirty = true;
+
+                Some(AtlasGlyph {
+                    position,
+                    size,
+                    offset,
+                })
+            }
+            None => None,
+        };
+
+        self.glyphs.insert(key, atlas_glyph);
+        Ok(atlas_glyph)
+    }
+
+    /// Forget every glyph, when the atlas is full of ones that aren't drawn anymore.
+    pub fn clear(&mut self) {
+        trace!("Clearing glyph atlas of {} glyph(s)", self.glyphs.len());
+        self.glyphs.clear();
+        self.shelves.clear();
+        self.pixels.iter_mut().for_each(|pixel| *pixel = 0);
+        self.dirty = true;
+    }
+
+    /// The texture with every glyph added so far, replaced when there are new ones.
+    pub fn upload(
+        &mut self,
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+    ) -> &Arc<Texture> {
+        if self.dirty || self.texture.is_none() {
+            // Frames in flight keep the previous one alive
+            self.texture = Some(Arc::new(Texture::from_r8(
+                queue,
+                memory_tracker,
+                self.dimensions(),
+                &self.pixels,
+            )));
+            self.dirty = false;
+        }
+        self.texture.as_ref().unwrap()
+    }
+
+    /// Shelf packing, a new shelf is started when no existing one fits.
+    fn allocate(&mut self, size: [u32; 2]) -> Result<[u32; 2], AtlasFull> {
+        let width = size[0] + PADDING;
+        let height = size[1] + PADDING;
+        if width > WIDTH {
+            return Err(AtlasFull);
+        }
+
+        // Shelves much taller than the glyph would waste space
+        let shelf = self.shelves.iter_mut().find(|shelf| {
+            shelf.height >= height && shelf.height <= height * 3 / 2 + 1 && shelf.x + width <= WIDTH
+        });
+        if let Some(shelf) = shelf {
+            let position = [shelf.x, shelf.y];
+            shelf.x += width;
+            return Ok(position);
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map_or(0, |shelf| shelf.y + shelf.height);
+        while y + height > self.height {
+            if self.height >= MAX_HEIGHT {
+                return Err(AtlasFull);
+            }
+            // Rows are added at the end, nothing moves
+            self.height *= 2;
+            self.pixels.resize((WIDTH * self.height) as usize, 0);
+            trace!("Glyph atlas grown to {}x{}", WIDTH, self.height);
+        }
+
+        self.shelves.push(Shelf {
+            y,
+            height,
+            x: width,
+        });
+        Ok([0, y])
+    }
+}
+
+/// Coverage of a glyph with a margin around it, its size and its offset from the pen position.
+fn rasterize(
+    font: &Font,
+    glyph: GlyphId,
+    size: f32,
+    margin: u32,
+) -> Option<(Vec<u8>, [u32; 2], Vec2)> {
+    let glyph = font
+        .inner()
+        .glyph(glyph)
+        .scaled(Scale::uniform(size))
+        .positioned(point(0.0, 0.0));
+    let bounds = glyph.pixel_bounding_box()?;
+
+    let width = bounds.width() as u32 + margin * 2;
+    let height = bounds.height() as u32 + margin * 2;
+    let mut coverage = vec![0; (width * height) as usize];
+    glyph.draw(|x, y, value| {
+        let index = ((y + margin) * width + x + margin) as usize;
+        coverage[index] = (value.min(1.0) * 255.0).round() as u8;
+    });
+
+    let offset = Vec2::new(
+        (bounds.min.x - margin as i32) as f32,
+        (bounds.min.y - margin as i32) as f32,
+    );
+    Some((coverage, [width, height], offset))
+}
+
+/// Signed distance to the outline mapped to 0-255, 128 being on the outline and larger
+/// values inside.
+fn rasterize_sdf(font: &Font, glyph: GlyphId) -> Option<(Vec<u8>, [u32; 2], Vec2)> {
+    let (coverage, size, offset) = rasterize(font, glyph, SDF_SIZE, SDF_SPREAD)?;
+    let [width, height] = [size[0] as i32, size[1] as i32];
+    let spread = SDF_SPREAD as i32;
+    let inside = |x: i32, y: i32| coverage[(y * width + x) as usize] >= 128;
+
+    // Closest texel on the other side of the outline, within the spread
+    let mut distances = vec![0; coverage.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let texel_inside = inside(x, y);
+            let mut closest = (spread * spread) as f32;
+            for dy in -spread..=spread {
+                for dx in -spread..=spread {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                        // Outside of the bitmap is outside of the glyph
+                        if texel_inside {
+                            closest = closest.min((dx * dx + dy * dy) as f32);
+                        }
+                        continue;
+                    }
+                    if inside(sx, sy) != texel_inside {
+                        closest = closest.min((dx * dx + dy * dy) as f32);
+                    }
+                }
+            }
+
+            // The outline is half way between the two texels
+            let distance = closest.sqrt() - 0.5;
+            let signed = if texel_inside { distance } else { -distance };
+            let value = 0.5 + signed / (2.0 * SDF_SPREAD as f32);
+            distances[(y * width + x) as usize] = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    }
+
+    Some((distances, size, offset))
+}
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_FONT_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(io::Error),
+    /// Neither a TrueType nor an OpenType font
+    Unsupported,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Io(err) => write!(f, "Failed to read font: {}", err),
+            FontError::Unsupported => write!(f, "Unsupported font format"),
+        }
+    }
+}
+
+impl Error for FontError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FontError::Io(err) => Some(err),
+            FontError::Unsupported => None,
+        }
+    }
+}
+
+impl From<io::Error> for FontError {
+    fn from(err: io::Error) -> Self {
+        FontError::Io(err)
+    }
+}
+
+/// TrueType or OpenType font, with TrueType or CFF outlines.
+pub struct Font {
+    font: rusttype::Font<'static>,
+    /// Identifies its glyphs in the atlas
+    id: usize,
+}
+
+impl Font {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FontError> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, FontError> {
+        let font = rusttype::Font::try_from_vec(bytes).ok_or(FontError::Unsupported)?;
+        Ok(Self {
+            font,
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    #[inline]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &rusttype::Font<'static> {
+        &self.font
+    }
+}
@@ -0,0 +1,503 @@
+use crate::math::Vec2;
+use crate::renderer::text::font::Font;
+use rusttype::{GlyphId, Scale};
+use std::sync::Arc;
+
+/// Spaces a tab advances by.
+const TAB_WIDTH: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TextAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LayoutSettings {
+    /// Lines are broken between words to fit, or inside of them when a word alone doesn't
+    pub max_width: Option<f32>,
+    /// Within the max width if there is one, otherwise within the widest line
+    pub alignment: TextAlignment,
+    /// Multiplies the line height of the font
+    pub line_spacing: f32,
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            alignment: TextAlignment::Left,
+            line_spacing: 1.0,
+        }
+    }
+}
+
+/// A glyph placed by the layout.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LayoutGlyph {
+    pub glyph: GlyphId,
+    /// Pen position on the baseline, in pixels from the top left corner of the text
+    pub position: Vec2,
+}
+
+/// Glyph of a line being laid out.
+struct LineGlyph {
+    glyph: LayoutGlyph,
+    advance: f32,
+    whitespace: bool,
+    /// Combining character, over its base
+    mark: bool,
+}
+
+/// Text broken into lines and positioned, in pixels with Y pointing down.
+#[derive(Clone)]
+pub struct TextLayout {
+    font: Arc<Font>,
+    size: f32,
+    glyphs: Vec<LayoutGlyph>,
+    dimensions: Vec2,
+}
+
+impl TextLayout {
+    /// Lay out UTF-8 text with a font, the size being the height of a line in pixels.
+    /// Combining characters are put over the character they follow.
+    pub fn new(text: &str, font: &Arc<Font>, size: f32, settings: &LayoutSettings) -> Self {
+        let metrics = ScaledFont {
+            font,
+            scale: Scale::uniform(size),
+        };
+        let (glyphs, dimensions) = lay_out(text, metrics, settings);
+
+        Self {
+            font: font.clone(),
+            size,
+            glyphs,
+            dimensions,
+        }
+    }
+
+    #[inline]
+    pub fn font(&self) -> &Arc<Font> {
+        &self.font
+    }
+
+    #[inline]
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Glyphs with something to draw, whitespace is left out.
+    #[inline]
+    pub fn glyphs(&self) -> &[LayoutGlyph] {
+        &self.glyphs
+    }
+
+    /// Width of the widest line, or the max width when given, and height of every line.
+    #[inline]
+    pub fn dimensions(&self) -> Vec2 {
+        self.dimensions
+    }
+}
+
+/// What the layout needs from a font at the size of the text, in pixels.
+trait GlyphMetrics {
+    /// Glyph of a character and its advance.
+    fn glyph(&self, c: char) -> (GlyphId, f32);
+    fn kerning(&self, previous: GlyphId, glyph: GlyphId) -> f32;
+    /// Ascent, descent and line gap.
+    fn v_metrics(&self) -> (f32, f32, f32);
+}
+
+struct ScaledFont<'a> {
+    font: &'a Font,
+    scale: Scale,
+}
+
+impl GlyphMetrics for ScaledFont<'_> {
+    fn glyph(&self, c: char) -> (GlyphId, f32) {
+        let glyph = self.font.inner().glyph(c);
+        (
+            glyph.id(),
+            glyph.scaled(self.scale).h_metrics().advance_width,
+        )
+    }
+
+    fn kerning(&self, previous: GlyphId, glyph: GlyphId) -> f32 {
+        self.font.inner().pair_kerning(self.scale, previous, glyph)
+    }
+
+    fn v_metrics(&self) -> (f32, f32, f32) {
+        let metrics = self.font.inner().v_metrics(self.scale);
+        (metrics.ascent, metrics.descent, metrics.line_gap)
+    }
+}
+
+/// The glyphs of the text and the dimensions of the layout.
+fn lay_out<M: GlyphMetrics>(
+    text: &str,
+    metrics: M,
+    settings: &LayoutSettings,
+) -> (Vec<LayoutGlyph>, Vec2) {
+    let mut builder = LayoutBuilder::new(metrics, settings);
+    for (i, paragraph) in text.split('\n').enumerate() {
+        if i > 0 {
+            builder.finish_line();
+        }
+        for c in paragraph.trim_end_matches('\r').chars() {
+            builder.push(c);
+        }
+    }
+    builder.finish_line();
+    builder.build()
+}
+
+struct LayoutBuilder<'a, M> {
+    metrics: M,
+    settings: &'a LayoutSettings,
+    ascent: f32,
+    line_height: f32,
+
+    lines: Vec<(Vec<LineGlyph>, f32)>,
+    line: Vec<LineGlyph>,
+    pen: f32,
+    /// Glyph kerned against the next one
+    previous: Option<GlyphId>,
+    /// Where the current line can be broken, after the last whitespace
+    break_index: Option<usize>,
+    /// Start of the glyph the next combining characters go over, and its advance
+    base: Option<(f32, f32)>,
+}
+
+impl<'a, M: GlyphMetrics> LayoutBuilder<'a, M> {
+    fn new(metrics: M, settings: &'a LayoutSettings) -> Self {
+        let (ascent, descent, line_gap) = metrics.v_metrics();
+
+        Self {
+            metrics,
+            settings,
+            ascent,
+            line_height: (ascent - descent + line_gap) * settings.line_spacing,
+            lines: Vec::new(),
+            line: Vec::new(),
+            pen: 0.0,
+            previous: None,
+            break_index: None,
+            base: None,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        if is_ignorable(c) {
+            return;
+        }
+        // Without a base a mark is laid out on its own, like any other character
+        if is_combining_mark(c) && self.base.is_some() {
+            self.push_mark(c);
+            return;
+        }
+
+        let whitespace = c.is_whitespace();
+        let (id, mut advance) = self.metrics.glyph(if c == '\t' { ' ' } else { c });
+        if c == '\t' {
+            advance *= TAB_WIDTH;
+        }
+
+        let mut x = self.pen + self.kerning(id);
+        if let Some(max_width) = self.settings.max_width {
+            if !whitespace && x + advance > max_width && !self.line.is_empty() {
+                self.wrap();
+                x = self.pen + self.kerning(id);
+            }
+        }
+
+        self.line.push(LineGlyph {
+            glyph: LayoutGlyph {
+                glyph: id,
+                position: Vec2::new(x, 0.0),
+            },
+            advance,
+            whitespace,
+            mark: false,
+        });
+        self.pen = x + advance;
+        self.previous = Some(id);
+        self.base = Some((x, advance));
+        if whitespace {
+            self.break_index = Some(self.line.len());
+        }
+    }
+
+    fn kerning(&self, glyph: GlyphId) -> f32 {
+        self.previous
+            .map_or(0.0, |previous| self.metrics.kerning(previous, glyph))
+    }
+
+    /// Over the base glyph, without advancing.
+    fn push_mark(&mut self, c: char) {
+        let (id, advance) = self.metrics.glyph(c);
+        let (base_x, base_advance) = self.base.unwrap();
+        let x = if advance == 0.0 {
+            // Zero width marks are drawn from the end of their base, like the fonts expect
+            base_x + base_advance
+        } else {
+            base_x + (base_advance - advance) * 0.5
+        };
+
+        self.line.push(LineGlyph {
+            glyph: LayoutGlyph {
+                glyph: id,
+                position: Vec2::new(x, 0.0),
+            },
+            advance: 0.0,
+            whitespace: false,
+            mark: true,
+        });
+    }
+
+    /// Move what follows the last break opportunity to a new line, or the glyph being
+    /// added when the line is a single word.
+    fn wrap(&mut self) {
+        let rest = match self.break_index.take() {
+            Some(index) if index < self.line.len() => self.line.split_off(index),
+            Some(_) | None => Vec::new(),
+        };
+        self.finish_line();
+
+        let offset = rest.first().map_or(0.0, |first| first.glyph.position.x);
+        for mut glyph in rest {
+            glyph.glyph.position.x -= offset;
+            if !glyph.mark {
+                self.pen = glyph.glyph.position.x + glyph.advance;
+                self.base = Some((glyph.glyph.position.x, glyph.advance));
+                self.previous = Some(glyph.glyph.glyph);
+            }
+            self.line.push(glyph);
+        }
+    }
+
+    fn finish_line(&mut self) {
+        // Trailing whitespace doesn't count for the alignment
+        let width = self
+            .line
+            .iter()
+            .filter(|glyph| !glyph.whitespace)
+            .map(|glyph| glyph.glyph.position.x + glyph.advance)
+            .fold(0.0, f32::max);
+        let line = std::mem::take(&mut self.line);
+        self.lines.push((line, width));
+
+        self.pen = 0.0;
+        self.previous = None;
+        self.break_index = None;
+        self.base = None;
+    }
+
+    fn build(self) -> (Vec<LayoutGlyph>, Vec2) {
+        let widest = self
+            .lines
+            .iter()
+            .map(|(_, width)| *width)
+            .fold(0.0, f32::max);
+        let width = self.settings.max_width.unwrap_or(widest);
+
+        let mut glyphs = Vec::new();
+        for (i, (line, line_width)) in self.lines.iter().enumerate() {
+            let offset = match self.settings.alignment {
+                TextAlignment::Left => 0.0,
+                TextAlignment::Center => (width - line_width) * 0.5,
+                TextAlignment::Right => width - line_width,
+            };
+            let baseline = self.ascent + i as f32 * self.line_height;
+
+            glyphs.extend(
+                line.iter()
+                    .filter(|glyph| !glyph.whitespace)
+                    .map(|glyph| LayoutGlyph {
+                        glyph: glyph.glyph.glyph,
+                        position: Vec2::new(glyph.glyph.position.x + offset, baseline),
+                    }),
+            );
+        }
+
+        let height = self.lines.len() as f32 * self.line_height;
+        (glyphs, Vec2::new(width, height))
+    }
+}
+
+/// Characters drawn over the previous one: the combining diacritical marks blocks and the
+/// common nonspacing marks of other scripts. Spacing marks advance like any character.
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c as u32,
+        // Combining diacritical marks and their extensions
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F
+        // Cyrillic titlos
+        | 0x0483..=0x0489
+        // Hebrew points
+        | 0x0591..=0x05BD | 0x05BF | 0x05C1..=0x05C2 | 0x05C4..=0x05C5 | 0x05C7
+        // Arabic harakat and Quranic marks
+        | 0x0610..=0x061A | 0x064B..=0x065F | 0x0670 | 0x06D6..=0x06DC | 0x06DF..=0x06E4
+        | 0x06E7..=0x06E8 | 0x06EA..=0x06ED
+        // Devanagari signs and vowels drawn over or under
+        | 0x0900..=0x0902 | 0x093A | 0x093C | 0x0941..=0x0948 | 0x094D | 0x0951..=0x0957
+        | 0x0962..=0x0963
+        // Thai vowels and tone marks
+        | 0x0E31 | 0x0E34..=0x0E3A | 0x0E47..=0x0E4E
+        // Kana voicing marks
+        | 0x3099..=0x309A
+    )
+}
+
+/// Joiners and variation selectors, they have nothing to draw.
+fn is_ignorable(c: char) -> bool {
+    matches!(c as u32, 0x200B..=0x200D | 0xFE00..=0xFE0F | 0xFEFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every glyph 10 pixels wide except the marks, "AV" kerned together.
+    struct Monospace;
+
+    impl GlyphMetrics for Monospace {
+        fn glyph(&self, c: char) -> (GlyphId, f32) {
+            let advance = if c == '\u{301}' { 0.0 } else { 10.0 };
+            (GlyphId(c as u16), advance)
+        }
+
+        fn kerning(&self, previous: GlyphId, glyph: GlyphId) -> f32 {
+            if (previous, glyph) == (GlyphId(b'A' as u16), GlyphId(b'V' as u16)) {
+                -2.0
+            } else {
+                0.0
+            }
+        }
+
+        fn v_metrics(&self) -> (f32, f32, f32) {
+            (8.0, -2.0, 2.0)
+        }
+    }
+
+    fn layout(text: &str, settings: &LayoutSettings) -> (Vec<LayoutGlyph>, Vec2) {
+        lay_out(text, Monospace, settings)
+    }
+
+    /// Characters and pen positions of the glyphs.
+    fn positions(glyphs: &[LayoutGlyph]) -> Vec<(char, f32, f32)> {
+        glyphs
+            .iter()
+            .map(|glyph| {
+                let c = std::char::from_u32(glyph.glyph.0 as u32).unwrap();
+                (c, glyph.position.x, glyph.position.y)
+            })
+            .collect()
+    }
+
+    fn wrapped(max_width: f32) -> LayoutSettings {
+        LayoutSettings {
+            max_width: Some(max_width),
+            ..LayoutSettings::default()
+        }
+    }
+
+    #[test]
+    fn pairs_are_kerned() {
+        let (glyphs, dimensions) = layout("AVA", &LayoutSettings::default());
+        assert_eq!(
+            positions(&glyphs),
+            [('A', 0.0, 8.0), ('V', 8.0, 8.0), ('A', 18.0, 8.0)]
+        );
+        assert_eq!(dimensions, Vec2::new(28.0, 12.0));
+    }
+
+    #[test]
+    fn lines_break_between_words() {
+        let (glyphs, dimensions) = layout("ab cd ef", &wrapped(55.0));
+        assert_eq!(
+            positions(&glyphs),
+            [
+                ('a', 0.0, 8.0),
+                ('b', 10.0, 8.0),
+                ('c', 30.0, 8.0),
+                ('d', 40.0, 8.0),
+                ('e', 0.0, 20.0),
+                ('f', 10.0, 20.0),
+            ]
+        );
+        assert_eq!(dimensions, Vec2::new(55.0, 24.0));
+    }
+
+    #[test]
+    fn long_words_break_inside() {
+        let (glyphs, _) = layout("abcd", &wrapped(25.0));
+        assert_eq!(
+            positions(&glyphs),
+            [
+                ('a', 0.0, 8.0),
+                ('b', 10.0, 8.0),
+                ('c', 0.0, 20.0),
+                ('d', 10.0, 20.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_are_aligned_without_trailing_whitespace() {
+        let settings = LayoutSettings {
+            alignment: TextAlignment::Right,
+            ..LayoutSettings::default()
+        };
+        let (glyphs, dimensions) = layout("abc\nd ", &settings);
+        assert_eq!(dimensions.x, 30.0);
+        assert_eq!(positions(&glyphs)[3], ('d', 20.0, 20.0));
+
+        let settings = LayoutSettings {
+            alignment: TextAlignment::Center,
+            ..wrapped(50.0)
+        };
+        let (glyphs, _) = layout("ab", &settings);
+        assert_eq!(positions(&glyphs)[0], ('a', 15.0, 8.0));
+    }
+
+    #[test]
+    fn marks_go_over_their_base() {
+        // Zero width acute accent and a 10 pixels wide Hebrew point
+        let (glyphs, dimensions) = layout("e\u{301}\u{5B8}x", &LayoutSettings::default());
+        assert_eq!(
+            positions(&glyphs),
+            [
+                ('e', 0.0, 8.0),
+                ('\u{301}', 10.0, 8.0),
+                ('\u{5B8}', 0.0, 8.0),
+                ('x', 10.0, 8.0),
+            ]
+        );
+        assert_eq!(dimensions.x, 20.0);
+    }
+
+    #[test]
+    fn marks_without_base_stand_alone() {
+        let (glyphs, _) = layout("\u{5B8}\u{301}V\nA", &LayoutSettings::default());
+        assert_eq!(
+            positions(&glyphs),
+            [
+                ('\u{5B8}', 0.0, 8.0),
+                ('\u{301}', 10.0, 8.0),
+                ('V', 10.0, 8.0),
+                ('A', 0.0, 20.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_follow_their_base_to_the_next_line() {
+        let (glyphs, _) = layout("ab e\u{301}", &wrapped(35.0));
+        assert_eq!(
+            positions(&glyphs)[2..],
+            [('e', 0.0, 20.0), ('\u{301}', 10.0, 20.0)]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::renderer::memory_tracker::{MemoryCategory, MemoryTracker, TrackedAllocation};
+use std::sync::Arc;
+use vulkano::device::Queue;
+use vulkano::format::{AcceptsPixels, Format};
+use vulkano::image::{Dimensions, ImmutableImage};
+use vulkano::sync::GpuFuture;
+
+/// Image sampled by the shaders, uploaded once.
+pub struct Texture {
+    image: Arc<ImmutableImage<Format>>,
+    dimensions: [u32; 2],
+    _allocation: TrackedAllocation,
+}
+
+impl Texture {
+    /// Upload tightly packed RGBA8 pixels, waiting for the transfer to finish.
+    /// Colors are stored as sRGB, data like normals or roughness as linear.
+    pub fn from_rgba8(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        dimensions: [u32; 2],
+        pixels: &[u8],
+        srgb: bool,
+    ) -> Self {
+        assert_eq!(
+            pixels.len(),
+            (dimensions[0] * dimensions[1] * 4) as usize,
+            "Pixel data doesn't match the dimensions !"
+        );
+
+        let format = if srgb {
+            Format::R8G8B8A8Srgb
+        } else {
+            Format::R8G8B8A8Unorm
+        };
+        Self::upload(
+            queue,
+            memory_tracker,
+            dimensions,
+            pixels
+                .chunks_exact(4)
+                .map(|pixel| [pixel[0], pixel[1], pixel[2], pixel[3]]),
+            format,
+        )
+    }
+
+    /// Upload tightly packed single channel pixels, like glyph coverage, waiting for the
+    /// transfer to finish. Sampled as red.
+    pub fn from_r8(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        dimensions: [u32; 2],
+        pixels: &[u8],
+    ) -> Self {
+        assert_eq!(
+            pixels.len(),
+            (dimensions[0] * dimensions[1]) as usize,
+            "Pixel data doesn't match the dimensions !"
+        );
+
+        Self::upload(
+            queue,
+            memory_tracker,
+            dimensions,
+            pixels.iter().copied(),
+            Format::R8Unorm,
+        )
+    }
+
+    fn upload<P, I>(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        dimensions: [u32; 2],
+        pixels: I,
+        format: Format,
+    ) -> Self
+    where
+        P: Send + Sync + Clone + 'static,
+        I: ExactSizeIterator<Item = P>,
+        Format: AcceptsPixels<P>,
+    {
+        let (image, upload) = ImmutableImage::from_iter(
+            pixels,
+            Dimensions::Dim2d {
+                width: dimensions[0],
+                height: dimensions[1],
+            },
+            format,
+            queue.clone(),
+        )
+        .expect("Failed to create texture !");
+
+        upload
+            .then_signal_fence_and_flush()
+            .and_then(|fence| fence.wait(None))
+            .expect("Failed to upload texture !");
+
+        Self {
+            _allocation: memory_tracker.track_image(MemoryCategory::Texture, &image),
+            image,
+            dimensions,
+        }
+    }
+
+    /// 1x1 texture of a single color, used in place of missing textures.
+    pub fn solid(
+        queue: &Arc<Queue>,
+        memory_tracker: &Arc<MemoryTracker>,
+        rgba: [u8; 4],
+        srgb: bool,
+    ) -> Self {
+        Self::from_rgba8(queue, memory_tracker, [1, 1], &rgba, srgb)
+    }
+
+    #[inline]
+    pub fn image(&self) -> &Arc<ImmutableImage<Format>> {
+        &self.image
+    }
+
+    /// Width and height in texels.
+    #[inline]
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+}
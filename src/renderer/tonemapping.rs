@@ -0,0 +1,154 @@
+use crate::renderer::fullscreen::{self, vertex_shader, FullscreenPipeline};
+use crate::renderer::hdr_target::HdrTarget;
+use crate::renderer::shader_reflection::{MaterialLayout, ShaderReflection};
+use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::AttachmentImage;
+use vulkano::pipeline::vertex::BufferlessDefinition;
+use vulkano::pipeline::GraphicsPipeline;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "shaders/tonemap.frag"
+    }
+}
+
+/// Curve mapping the HDR scene to the displayable range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TonemapOperator {
+    /// Filmic, contrasty and slightly saturated
+    #[default]
+    Aces,
+    /// Simple and desaturates the highlights the least
+    Reinhard,
+    /// Filmic with softer shoulder, from Uncharted 2
+    Uncharted2,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TonemapSettings {
+    pub operator: TonemapOperator,
+    /// In stops, each one doubles the brightness
+    pub exposure: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[allow(dead_code)] // Only read by the GPU
+struct PushConstants {
+    exposure: f32,
+    operator: u32,
+    bloom_intensity: f32,
+    peak: f32,
+}
+
+/// Resolves the HDR target and its bloom to the start of the post-processing chain.
+pub struct TonemapPass {
+    pipeline: Arc<FullscreenPipeline>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+impl TonemapPass {
+    /// Has to be recreated with the HDR target.
+    pub fn new(
+        device: &Arc<Device>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        hdr_target: &HdrTarget,
+        bloom: &Arc<AttachmentImage>,
+        bloom_sampler: &Arc<Sampler>,
+    ) -> Self {
+        let vert_shader =
+            vertex_shader::Shader::load(device.clone()).expect("Failed to create vertex shader !");
+        let frag_shader = fragment_shader::Shader::load(device.clone())
+            .expect("Failed to create fragment shader !");
+
+        let layout = MaterialLayout::pipeline_layout(
+            device,
+            &[
+                ShaderReflection::graphics(&vert_shader.main_entry_point()),
+                ShaderReflection::graphics(&frag_shader.main_entry_point()),
+            ],
+        )
+        .expect("Failed to create tonemapping pipeline layout !");
+
+        let pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(BufferlessDefinition)
+                .vertex_shader(vert_shader.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(frag_shader.main_entry_point(), ())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .with_pipeline_layout(device.clone(), layout)
+                .expect("Failed to create tonemapping pipeline !"),
+        );
+
+        // Same size as the screen, one texel per pixel
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Nearest,
+            Filter::Nearest,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .expect("Failed to create tonemapping sampler !");
+
+        let layout = pipeline.descriptor_set_layout(0).unwrap().clone();
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_sampled_image(hdr_target.color().clone(), sampler)
+                .unwrap()
+                .add_sampled_image(bloom.clone(), bloom_sampler.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create tonemapping descriptor set !"),
+        );
+
+        Self {
+            pipeline,
+            descriptor_set,
+        }
+    }
+
+    pub fn record(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        settings: &TonemapSettings,
+        bloom_intensity: f32,
+        peak: f32,
+        dimensions: [u32; 2],
+    ) -> AutoCommandBufferBuilder {
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![fullscreen::viewport(dimensions)]),
+            ..DynamicState::none()
+        };
+        let push_constants = PushConstants {
+            exposure: settings.exposure.exp2(),
+            operator: settings.operator as u32,
+            bloom_intensity,
+            peak,
+        };
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &dynamic_state,
+                fullscreen::triangle(),
+                self.descriptor_set.clone(),
+                push_constants,
+            )
+            .expect("Failed to record tonemapping !")
+    }
+}
@@ -1,34 +1,136 @@
+use crate::math::Vec3;
+use crate::renderer::compute::ComputeQueue;
+use crate::renderer::display_output::{DisplayOutput, HdrDisplaySettings};
+use crate::renderer::environment::{
+    Environment, EnvironmentBakeSettings, EnvironmentBaker, HdrImage, HdrImageError, SkyboxDraw,
+    SkyboxPass,
+};
+use crate::renderer::frame_allocator::{FrameAllocator, DEFAULT_FRAME_CAPACITY};
+use crate::renderer::frame_stats::FrameStats;
+use crate::renderer::gltf_loader::{GltfError, GltfModel};
+use crate::renderer::gpu_profiler::GpuProfiler;
+use crate::renderer::hdr_target::HdrTarget;
+use crate::renderer::instancing::{DrawBatch, InstanceBatcher};
+use crate::renderer::lighting::{
+    FrameUniforms, GpuLight, LightGatherer, DEFAULT_MAX_LIGHTS, FRAME_SET,
+};
+use crate::renderer::material::{MaterialId, MaterialLibrary, PbrMaterial};
+use crate::renderer::memory_tracker::{MemorySnapshot, MemoryTracker};
+use crate::renderer::mesh::{Mesh, MeshVertex};
+use crate::renderer::particles::{ParticleFrame, ParticleSystem};
+use crate::renderer::pbr_pipeline::PbrPipeline;
 use crate::renderer::physical_device_selection::{
     find_queue_families, pick_physical_device, required_extensions,
 };
-use crate::renderer::{APPLICATION_NAME, DIMENSIONS, ENABLE_VALIDATION_LAYERS, VALIDATION_LAYERS};
+use crate::renderer::post_processing::{PostProcessSettings, PostProcessor};
+use crate::renderer::shadow_debug::ShadowAtlasDebugView;
+use crate::renderer::shadows::{GpuShadow, ShadowAtlasSettings, ShadowPass, ShadowRenderer};
+use crate::renderer::skinning::{SkinPoser, SkinnedDraw};
+use crate::renderer::sprites::{SpriteBatch, SpriteCamera, SpriteFrame, SpriteRenderer};
+use crate::renderer::swapchain_wrapper::SwapChainWrapper;
+use crate::renderer::text::{TextBatch, TextFrame, TextRenderer};
+use crate::renderer::texture::Texture;
+use crate::renderer::tonemapping::TonemapSettings;
+use crate::renderer::{
+    APPLICATION_NAME, DIMENSIONS, ENABLE_VALIDATION_LAYERS, FRAMES_IN_FLIGHT, VALIDATION_LAYERS,
+};
+use crate::scene::{CameraView, Scene};
 use log::{error, info, trace, warn};
 use std::collections::HashSet;
 use std::iter::FromIterator;
+use std::path::Path;
 use std::sync::Arc;
+use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::DescriptorSet;
 use vulkano::device::{Device, Features, Queue};
+use vulkano::format::ClearValue;
 use vulkano::instance::debug::{DebugCallback, MessageSeverity, MessageType};
 use vulkano::instance::{
-    layers_list, ApplicationInfo, Instance, InstanceExtensions, PhysicalDevice, Version,
+    layers_list, ApplicationInfo, Instance, InstanceExtensions, PhysicalDevice, QueueFamily,
+    Version,
 };
-use vulkano::swapchain::Surface;
+use vulkano::swapchain::{acquire_next_image, AcquireError, Surface};
+use vulkano::sync::{self, FenceSignalFuture, FlushError, GpuFuture};
 use vulkano_win::VkSurfaceBuild;
 use winit::dpi::LogicalSize;
 use winit::event_loop::EventLoop;
 use winit::window::{Window, WindowBuilder};
 
+/// Records compute work into a command buffer.
+type ComputeRecorder = Box<dyn FnOnce(AutoCommandBufferBuilder) -> AutoCommandBufferBuilder>;
+
+/// Everything a frame draws, prepared before recording it.
+struct PreparedFrame {
+    /// Only there with a camera
+    frame_set: Option<Arc<dyn DescriptorSet + Send + Sync>>,
+    shadow_passes: Vec<ShadowPass>,
+    batches: Vec<DrawBatch>,
+    skinned: Vec<SkinnedDraw>,
+    skybox: Option<SkyboxDraw>,
+    particles: Option<ParticleFrame>,
+    sprites: SpriteFrame,
+    text: TextFrame,
+}
+
 pub struct VulkanApplication {
-    instance: Arc<Instance>,
+    _instance: Arc<Instance>,
     #[cfg(debug_assertions)]
-    debug_callback: DebugCallback,
+    _debug_callback: DebugCallback,
 
     surface: Arc<Surface<Window>>,
-    physical_device_id: usize,
 
     device: Arc<Device>,
 
     graphics_queue: Arc<Queue>,
     presentation_queue: Arc<Queue>,
+    /// Same as the graphics queue when there is no dedicated compute family
+    compute_queue: Arc<Queue>,
+
+    swap_chain: Option<SwapChainWrapper>,
+    swap_chain_outdated: bool,
+    /// Set when the output changes, the swap chain can't simply be recreated with a new format
+    swap_chain_format_outdated: bool,
+    preferred_output: DisplayOutput,
+    hdr_display_settings: HdrDisplaySettings,
+    /// The scene is rendered to it, then tonemapped to the swap chain
+    hdr_target: HdrTarget,
+    post_processor: PostProcessor,
+    post_settings: PostProcessSettings,
+    tonemap_settings: TonemapSettings,
+
+    pbr_pipeline: PbrPipeline,
+    material_library: MaterialLibrary,
+    instance_batcher: InstanceBatcher,
+    skin_poser: SkinPoser,
+    light_gatherer: LightGatherer,
+    shadow_renderer: ShadowRenderer,
+    environment_baker: EnvironmentBaker,
+    /// Bound when the scene has no environment
+    blank_environment: Arc<Environment>,
+    skybox_pass: SkyboxPass,
+    particle_system: ParticleSystem,
+    sprite_renderer: SpriteRenderer,
+    sprite_batch: SpriteBatch,
+    sprite_camera: SpriteCamera,
+    text_renderer: TextRenderer,
+    text_batch: TextBatch,
+    /// Only there while the debug view is enabled
+    shadow_debug_view: Option<ShadowAtlasDebugView>,
+
+    /// Recorded at the start of the next frame
+    frame_compute: Vec<ComputeRecorder>,
+    /// Submitted on the compute queue, the next frame waits for it
+    async_compute: Option<Box<dyn GpuFuture>>,
+
+    /// Signaled when the GPU is done with the last submission of each frame
+    frame_fences: Vec<Option<FenceSignalFuture<Box<dyn GpuFuture>>>>,
+    current_frame: usize,
+
+    memory_tracker: Arc<MemoryTracker>,
+    frame_allocator: FrameAllocator,
+    gpu_profiler: GpuProfiler,
+    frame_stats: FrameStats,
 }
 
 impl VulkanApplication {
@@ -47,29 +149,927 @@ impl VulkanApplication {
 
         // Create device
         let physical_device_id = pick_physical_device(&instance, &surface).index();
-        let (device, graphics_queue, presentation_queue) =
+        let (device, graphics_queue, presentation_queue, compute_queue) =
             Self::create_logical_device(&instance, &surface, physical_device_id);
 
+        // Everything that allocates GPU memory reports to it
+        let memory_tracker = MemoryTracker::new(&device.physical_device());
+
+        let swap_chain = SwapChainWrapper::create(
+            &surface,
+            &device,
+            &graphics_queue,
+            &presentation_queue,
+            &memory_tracker,
+            DisplayOutput::Sdr,
+        );
+
+        let hdr_target = HdrTarget::new(&device, &memory_tracker, swap_chain.dimensions());
+        let post_processor = PostProcessor::new(&device, &memory_tracker, &swap_chain, &hdr_target);
+
+        let pbr_pipeline = Self::create_pbr_pipeline(&device, &swap_chain, &hdr_target);
+
+        // Unknown materials fall back to the first one
+        let mut material_library = MaterialLibrary::new(&device, &graphics_queue, &memory_tracker);
+        material_library.create(&pbr_pipeline.pipeline(), PbrMaterial::default());
+
+        let shadow_renderer =
+            ShadowRenderer::new(&device, &memory_tracker, ShadowAtlasSettings::default());
+
+        let environment_baker = EnvironmentBaker::new(&graphics_queue, &memory_tracker);
+        let blank_environment = Arc::new(environment_baker.bake_blank());
+        let skybox_pass = SkyboxPass::new(&device, hdr_target.render_pass());
+        let particle_system =
+            ParticleSystem::new(&graphics_queue, &memory_tracker, hdr_target.render_pass());
+
+        // Drawn over the processed frame
+        let sprite_renderer = SpriteRenderer::new(
+            &device,
+            &memory_tracker,
+            post_processor.overlay_render_pass(),
+        );
+        // Over the processed frame, or in the scene
+        let text_renderer = TextRenderer::new(
+            &graphics_queue,
+            &memory_tracker,
+            post_processor.overlay_render_pass(),
+            hdr_target.render_pass(),
+        );
+
+        let frame_allocator = FrameAllocator::new(&device, &memory_tracker, DEFAULT_FRAME_CAPACITY);
+
+        // Timestamps are written in the command buffers of the graphics queue
+        let gpu_profiler = GpuProfiler::new(&device, &memory_tracker, graphics_queue.family());
+
         (
             Self {
-                instance,
+                _instance: instance,
                 #[cfg(debug_assertions)]
-                debug_callback,
+                _debug_callback: debug_callback,
                 surface,
-                physical_device_id,
                 device,
                 graphics_queue,
                 presentation_queue,
+                compute_queue,
+                frame_compute: Vec::new(),
+                async_compute: None,
+                swap_chain: Some(swap_chain),
+                swap_chain_outdated: false,
+                swap_chain_format_outdated: false,
+                preferred_output: DisplayOutput::Sdr,
+                hdr_display_settings: HdrDisplaySettings::default(),
+                hdr_target,
+                post_processor,
+                post_settings: PostProcessSettings::default(),
+                tonemap_settings: TonemapSettings::default(),
+                pbr_pipeline,
+                material_library,
+                instance_batcher: InstanceBatcher::new(),
+                skin_poser: SkinPoser::new(),
+                light_gatherer: LightGatherer::new(DEFAULT_MAX_LIGHTS),
+                shadow_renderer,
+                environment_baker,
+                blank_environment,
+                skybox_pass,
+                particle_system,
+                sprite_renderer,
+                sprite_batch: SpriteBatch::new(),
+                sprite_camera: SpriteCamera::default(),
+                text_renderer,
+                text_batch: TextBatch::new(),
+                shadow_debug_view: None,
+                frame_fences: (0..FRAMES_IN_FLIGHT).map(|_| None).collect(),
+                current_frame: 0,
+                memory_tracker,
+                frame_allocator,
+                gpu_profiler,
+                frame_stats: FrameStats::default(),
             },
             event_loop,
         )
     }
 
     pub fn window(&self) -> &Window {
-        return self.surface.window();
+        self.surface.window()
+    }
+
+    #[inline]
+    pub fn gpu_profiler(&self) -> &GpuProfiler {
+        &self.gpu_profiler
+    }
+
+    #[inline]
+    pub fn memory_tracker(&self) -> &Arc<MemoryTracker> {
+        &self.memory_tracker
+    }
+
+    /// Current GPU memory usage, can be written to a file for bug reports.
+    #[inline]
+    pub fn memory_snapshot(&self) -> MemorySnapshot {
+        self.memory_tracker.snapshot()
+    }
+
+    /// Per frame uniform and storage data, reset at the start of every frame.
+    #[inline]
+    pub fn frame_allocator(&mut self) -> &mut FrameAllocator {
+        &mut self.frame_allocator
+    }
+
+    /// Counters of the last rendered frame.
+    #[inline]
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    #[inline]
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    #[inline]
+    pub fn graphics_queue(&self) -> &Arc<Queue> {
+        &self.graphics_queue
+    }
+
+    #[inline]
+    pub fn compute_queue(&self) -> &Arc<Queue> {
+        &self.compute_queue
+    }
+
+    /// Families of the graphics and compute queues, to create the buffers and images used
+    /// by both with.
+    pub fn shared_queue_families(&self) -> Vec<QueueFamily<'_>> {
+        let mut families = vec![self.graphics_queue.family()];
+        if self.compute_queue.family().id() != self.graphics_queue.family().id() {
+            families.push(self.compute_queue.family());
+        }
+        families
+    }
+
+    /// Run compute work before the next frame is drawn, see [ComputeQueue].
+    /// The frame reads the results only once the work is done.
+    pub fn compute<F>(&mut self, queue: ComputeQueue, record: F)
+    where
+        F: FnOnce(AutoCommandBufferBuilder) -> AutoCommandBufferBuilder + 'static,
+    {
+        match queue {
+            // Barriers are inserted between the dispatches and the draws of the frame
+            ComputeQueue::Graphics => self.frame_compute.push(Box::new(record)),
+            ComputeQueue::Async => {
+                let builder = AutoCommandBufferBuilder::primary_one_time_submit(
+                    self.device.clone(),
+                    self.compute_queue.family(),
+                )
+                .unwrap();
+                let command_buffer = record(builder)
+                    .build()
+                    .expect("Failed to build compute command buffer !");
+
+                // After the work submitted before it, the frame waits on the semaphore
+                let previous = self
+                    .async_compute
+                    .take()
+                    .unwrap_or_else(|| Box::new(sync::now(self.device.clone())));
+                let future = previous
+                    .then_execute(self.compute_queue.clone(), command_buffer)
+                    .expect("Failed to execute compute command buffer !")
+                    .then_signal_semaphore_and_flush()
+                    .expect("Failed to submit compute work !");
+                self.async_compute = Some(Box::new(future));
+            }
+        }
+    }
+
+    /// Upload a mesh, its bounds are computed from the vertices.
+    pub fn create_mesh<V: MeshVertex>(&self, vertices: &[V], indices: Option<&[u32]>) -> Arc<Mesh> {
+        Arc::new(Mesh::new(
+            &self.device,
+            &self.memory_tracker,
+            vertices,
+            indices,
+        ))
+    }
+
+    /// Upload RGBA8 pixels, see [Texture::from_rgba8].
+    pub fn create_texture(&self, dimensions: [u32; 2], pixels: &[u8], srgb: bool) -> Arc<Texture> {
+        Arc::new(Texture::from_rgba8(
+            &self.graphics_queue,
+            &self.memory_tracker,
+            dimensions,
+            pixels,
+            srgb,
+        ))
+    }
+
+    /// Load an equirectangular Radiance `.hdr` image and bake it, see [create_environment](Self::create_environment).
+    pub fn load_environment<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Arc<Environment>, HdrImageError> {
+        let image = HdrImage::load(path)?;
+        Ok(self.create_environment(&image, &EnvironmentBakeSettings::default()))
+    }
+
+    /// Bake the skybox cubemap and the lighting of an equirectangular image, waiting for the
+    /// GPU to finish.
+    pub fn create_environment(
+        &self,
+        image: &HdrImage,
+        settings: &EnvironmentBakeSettings,
+    ) -> Arc<Environment> {
+        Arc::new(self.environment_baker.bake(image, settings))
+    }
+
+    /// Load the meshes, materials, skins and animations of a `.gltf` or `.glb` file, see
+    /// [GltfModel::add_to_scene] to display it.
+    pub fn load_gltf<P: AsRef<Path>>(&mut self, path: P) -> Result<GltfModel, GltfError> {
+        let material_library = &mut self.material_library;
+        let pipeline = self.pbr_pipeline.pipeline();
+        GltfModel::load(
+            &self.graphics_queue,
+            &self.memory_tracker,
+            path,
+            |material| material_library.create(&pipeline, material),
+        )
+    }
+
+    pub fn create_material(&mut self, material: PbrMaterial) -> MaterialId {
+        self.material_library
+            .create(&self.pbr_pipeline.pipeline(), material)
+    }
+
+    /// Replace a material, objects using it pick the change up on the next frame.
+    pub fn update_material(&mut self, id: MaterialId, material: PbrMaterial) {
+        self.material_library
+            .update(&self.pbr_pipeline.pipeline(), id, material);
+    }
+
+    #[inline]
+    pub fn material(&self, id: MaterialId) -> Option<&PbrMaterial> {
+        self.material_library.get(id)
+    }
+
+    /// Lights shading each frame, the closest to the camera are kept when there are more.
+    #[inline]
+    pub fn max_lights(&self) -> usize {
+        self.light_gatherer.max_lights()
+    }
+
+    #[inline]
+    pub fn set_max_lights(&mut self, max_lights: usize) {
+        self.light_gatherer.set_max_lights(max_lights);
+    }
+
+    /// Morph targets deforming each skinned mesh, the largest weights are kept when there are
+    /// more.
+    #[inline]
+    pub fn max_morph_targets(&self) -> usize {
+        self.skin_poser.max_morph_targets()
+    }
+
+    #[inline]
+    pub fn set_max_morph_targets(&mut self, max_morph_targets: usize) {
+        self.skin_poser.set_max_morph_targets(max_morph_targets);
+    }
+
+    #[inline]
+    pub fn tonemap_settings(&self) -> &TonemapSettings {
+        &self.tonemap_settings
+    }
+
+    /// Operator and exposure, applied from the next frame.
+    #[inline]
+    pub fn set_tonemap_settings(&mut self, settings: TonemapSettings) {
+        self.tonemap_settings = settings;
+    }
+
+    /// What the frames are presented in, may be SDR even when HDR was requested.
+    #[inline]
+    pub fn display_output(&self) -> DisplayOutput {
+        self.swap_chain.as_ref().unwrap().output()
+    }
+
+    /// The one last requested, what is actually presented may differ.
+    #[inline]
+    pub fn preferred_display_output(&self) -> DisplayOutput {
+        self.preferred_output
+    }
+
+    /// Falls back to SDR if the surface has no format for it, applied from the next frame.
+    pub fn set_display_output(&mut self, output: DisplayOutput) {
+        if output != self.preferred_output {
+            self.preferred_output = output;
+            self.swap_chain_outdated = true;
+            self.swap_chain_format_outdated = true;
+        }
+    }
+
+    #[inline]
+    pub fn hdr_display_settings(&self) -> &HdrDisplaySettings {
+        &self.hdr_display_settings
+    }
+
+    /// Paper white and peak brightness, only used by HDR outputs.
+    #[inline]
+    pub fn set_hdr_display_settings(&mut self, settings: HdrDisplaySettings) {
+        self.hdr_display_settings = settings;
+    }
+
+    #[inline]
+    pub fn post_settings(&self) -> &PostProcessSettings {
+        &self.post_settings
+    }
+
+    /// Bloom and the order and parameters of the effects, applied from the next frame.
+    #[inline]
+    pub fn set_post_settings(&mut self, settings: PostProcessSettings) {
+        self.post_settings = settings;
+    }
+
+    /// Sprites drawn over the next frame, it is emptied once the frame is rendered.
+    #[inline]
+    pub fn sprites(&mut self) -> &mut SpriteBatch {
+        &mut self.sprite_batch
+    }
+
+    #[inline]
+    pub fn sprite_camera(&self) -> &SpriteCamera {
+        &self.sprite_camera
+    }
+
+    #[inline]
+    pub fn set_sprite_camera(&mut self, camera: SpriteCamera) {
+        self.sprite_camera = camera;
+    }
+
+    /// Text drawn in the next frame, it is emptied once the frame is rendered.
+    #[inline]
+    pub fn text(&mut self) -> &mut TextBatch {
+        &mut self.text_batch
+    }
+
+    #[inline]
+    pub fn shadow_settings(&self) -> &ShadowAtlasSettings {
+        self.shadow_renderer.settings()
     }
 
-    pub fn draw_frame(&mut self) {}
+    /// Cascades, filtering and tiling apply on the next frame, the atlas is only recreated
+    /// when its size changes.
+    pub fn set_shadow_settings(&mut self, settings: ShadowAtlasSettings) {
+        if settings.size == self.shadow_renderer.settings().size {
+            self.shadow_renderer.set_settings(settings);
+            return;
+        }
+
+        self.shadow_renderer = ShadowRenderer::new(&self.device, &self.memory_tracker, settings);
+        if self.shadow_debug_view.is_some() {
+            self.shadow_debug_view = Some(self.create_shadow_debug_view());
+        }
+    }
+
+    #[inline]
+    pub fn shadow_debug_view(&self) -> bool {
+        self.shadow_debug_view.is_some()
+    }
+
+    /// Show the shadow atlas in a corner of the screen.
+    pub fn set_shadow_debug_view(&mut self, enabled: bool) {
+        self.shadow_debug_view = if enabled {
+            Some(self.create_shadow_debug_view())
+        } else {
+            None
+        };
+    }
+
+    fn create_shadow_debug_view(&self) -> ShadowAtlasDebugView {
+        ShadowAtlasDebugView::new(
+            &self.device,
+            self.swap_chain.as_ref().unwrap().render_pass(),
+            self.shadow_renderer.atlas(),
+        )
+    }
+
+    /// Wait for the GPU and log what is still allocated.
+    pub fn shutdown(&mut self) {
+        trace!("Shutting down vulkan app");
+
+        // Let the GPU finish before tearing anything down
+        self.wait_for_frames();
+
+        self.memory_tracker.snapshot().log();
+    }
+
+    fn wait_for_frames(&mut self) {
+        for fence in self.frame_fences.iter_mut().filter_map(Option::take) {
+            if let Err(err) = fence.wait(None) {
+                warn!("Failed to wait for frame: {:?}", err);
+            }
+        }
+    }
+
+    pub fn draw_frame(&mut self, scene: &mut Scene) {
+        // Everything of this frame slot can be reused once the GPU is done with it
+        if let Some(fence) = self.frame_fences[self.current_frame].take() {
+            fence.wait(None).expect("Failed to wait for frame fence !");
+        }
+        self.frame_allocator.begin_frame(self.current_frame);
+        self.gpu_profiler.begin_frame();
+
+        if self.swap_chain_outdated {
+            self.recreate_swap_chain();
+        }
+
+        let swap_chain = self.swap_chain.as_ref().unwrap().swap_chain();
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(swap_chain.clone(), None) {
+                Ok(acquired) => acquired,
+                Err(AcquireError::OutOfDate) => {
+                    self.swap_chain_outdated = true;
+                    // Dropped with the frame, they would be drawn twice otherwise
+                    self.sprite_batch.clear();
+                    self.text_batch.clear();
+                    return;
+                }
+                Err(err) => panic!("Failed to acquire next image: {:?}", err),
+            };
+        // Still usable, but recreate it next frame
+        self.swap_chain_outdated |= suboptimal;
+
+        // Drop what the camera can't see before recording anything
+        self.frame_stats = FrameStats::default();
+        let dimensions = self.swap_chain.as_ref().unwrap().dimensions();
+        let aspect_ratio = dimensions[0] as f32 / dimensions[1] as f32;
+        let camera_view = scene.active_camera_view(aspect_ratio);
+        let (frame_set, shadow_passes, batches, skinned, skybox, particles) = match &camera_view {
+            Some(camera_view) => {
+                let (frame_set, shadow_passes) = self.prepare_lights(scene, camera_view);
+                let batches = self.instance_batcher.prepare(
+                    scene,
+                    &camera_view.frustum,
+                    &mut self.frame_allocator,
+                    &mut self.frame_stats,
+                );
+                let skinned = self.skin_poser.prepare(
+                    scene,
+                    &self.pbr_pipeline.skinned_pipeline(),
+                    &mut self.frame_allocator,
+                    &mut self.frame_stats,
+                );
+                let skybox = scene
+                    .environment()
+                    .filter(|environment| environment.skybox)
+                    .map(|environment| {
+                        self.skybox_pass.prepare(
+                            &environment.environment,
+                            self.environment_baker.sampler(),
+                            camera_view,
+                            environment.intensity,
+                        )
+                    });
+                let particles = self.particle_system.prepare(
+                    scene,
+                    camera_view,
+                    &mut self.frame_allocator,
+                    &mut self.frame_stats,
+                );
+                (
+                    Some(frame_set),
+                    shadow_passes,
+                    batches,
+                    skinned,
+                    skybox,
+                    Some(particles),
+                )
+            }
+            None => (None, Vec::new(), Vec::new(), Vec::new(), None, None),
+        };
+
+        let sprites = self.sprite_renderer.prepare(
+            &mut self.sprite_batch,
+            &self.sprite_camera,
+            dimensions,
+            &mut self.frame_allocator,
+            &mut self.frame_stats,
+        );
+        let text = self.text_renderer.prepare(
+            &mut self.text_batch,
+            camera_view.as_ref(),
+            dimensions,
+            &mut self.frame_allocator,
+            &mut self.frame_stats,
+        );
+
+        let command_buffer = self.record_command_buffer(
+            image_index,
+            PreparedFrame {
+                frame_set,
+                shadow_passes,
+                batches,
+                skinned,
+                skybox,
+                particles,
+                sprites,
+                text,
+            },
+        );
+
+        let mut wait: Box<dyn GpuFuture> = Box::new(acquire_future);
+        if let Some(compute) = self.async_compute.take() {
+            wait = Box::new(wait.join(compute));
+        }
+        let future: Box<dyn GpuFuture> = Box::new(
+            wait.then_execute(self.graphics_queue.clone(), command_buffer)
+                .expect("Failed to execute command buffer !")
+                .then_swapchain_present(self.presentation_queue.clone(), swap_chain, image_index),
+        );
+
+        match future.then_signal_fence_and_flush() {
+            Ok(fence) => self.frame_fences[self.current_frame] = Some(fence),
+            Err(FlushError::OutOfDate) => self.swap_chain_outdated = true,
+            Err(err) => error!("Failed to flush frame: {:?}", err),
+        }
+
+        self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT;
+    }
+
+    /// Gather the lights and their shadow maps, the frame set holds them along with the
+    /// camera and ambient, shared by every draw.
+    fn prepare_lights(
+        &mut self,
+        scene: &mut Scene,
+        camera_view: &CameraView,
+    ) -> (Arc<dyn DescriptorSet + Send + Sync>, Vec<ShadowPass>) {
+        let mut lights = self.light_gatherer.gather(scene, camera_view).to_vec();
+        let (shadow_passes, mut shadows) = self.shadow_renderer.prepare(
+            scene,
+            camera_view,
+            self.light_gatherer.shadow_casters(),
+            &mut lights,
+            &mut self.frame_allocator,
+            &mut self.frame_stats,
+        );
+
+        let light_count = lights.len() as u32;
+        // Empty storage buffers can't be bound
+        if lights.is_empty() {
+            lights.push(GpuLight::default());
+        }
+        if shadows.is_empty() {
+            shadows.push(GpuShadow::default());
+        }
+
+        let ambient = scene.ambient();
+        let (environment, environment_intensity, has_environment) = match scene.environment() {
+            Some(environment) => (&environment.environment, environment.intensity, true),
+            None => (&self.blank_environment, 0.0, false),
+        };
+        let sampler = self.environment_baker.sampler();
+        let uniforms = FrameUniforms {
+            view_projection: camera_view.view_projection.into(),
+            camera_position: camera_view.position.extend(1.0).to_array(),
+            camera_forward: camera_view
+                .view
+                .inverse()
+                .transform_vector(-Vec3::Z)
+                .normalize()
+                .extend(0.0)
+                .to_array(),
+            ambient_sky: (ambient.sky_color * ambient.intensity)
+                .extend(0.0)
+                .to_array(),
+            ambient_ground: (ambient.ground_color * ambient.intensity)
+                .extend(0.0)
+                .to_array(),
+            light_count: [light_count, 0, 0, 0],
+            shadow_params: self.shadow_renderer.shader_params(),
+            environment_params: [
+                environment_intensity,
+                has_environment as u32 as f32,
+                0.0,
+                0.0,
+            ],
+        };
+
+        let uniforms = self.frame_allocator.allocate_uniform(&uniforms);
+        let lights = self.frame_allocator.allocate_storage(&lights);
+        let shadows = self.frame_allocator.allocate_storage(&shadows);
+
+        let layout = self
+            .pbr_pipeline
+            .pipeline()
+            .descriptor_set_layout(FRAME_SET)
+            .expect("The pipeline has no frame set !")
+            .clone();
+        let frame_set = Arc::new(
+            PersistentDescriptorSet::start(layout)
+                .add_buffer(uniforms.slice())
+                .unwrap()
+                .add_buffer(lights.slice())
+                .unwrap()
+                .add_sampled_image(
+                    self.shadow_renderer.atlas().clone(),
+                    self.shadow_renderer.sampler().clone(),
+                )
+                .unwrap()
+                .add_buffer(shadows.slice())
+                .unwrap()
+                .add_sampled_image(environment.irradiance().clone(), sampler.clone())
+                .unwrap()
+                .enter_array()
+                .unwrap()
+                .add_sampled_image(environment.prefiltered()[0].clone(), sampler.clone())
+                .unwrap()
+                .add_sampled_image(environment.prefiltered()[1].clone(), sampler.clone())
+                .unwrap()
+                .add_sampled_image(environment.prefiltered()[2].clone(), sampler.clone())
+                .unwrap()
+                .add_sampled_image(environment.prefiltered()[3].clone(), sampler.clone())
+                .unwrap()
+                .add_sampled_image(environment.prefiltered()[4].clone(), sampler.clone())
+                .unwrap()
+                .leave_array()
+                .unwrap()
+                .add_sampled_image(self.environment_baker.brdf_lut().clone(), sampler.clone())
+                .unwrap()
+                .build()
+                .expect("Failed to create frame descriptor set !"),
+        );
+
+        (frame_set, shadow_passes)
+    }
+
+    fn record_command_buffer(
+        &mut self,
+        image_index: usize,
+        frame: PreparedFrame,
+    ) -> AutoCommandBuffer {
+        let PreparedFrame {
+            frame_set,
+            shadow_passes,
+            batches,
+            skinned,
+            skybox,
+            particles,
+            sprites,
+            text,
+        } = frame;
+        let swap_chain = self.swap_chain.as_ref().unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.graphics_queue.family(),
+        )
+        .unwrap();
+        // Timestamps are written between the render passes, each scope covers whole passes
+        builder = self.gpu_profiler.reset(builder);
+        builder = self.gpu_profiler.begin_scope(builder, "Frame");
+
+        let frame_compute = std::mem::take(&mut self.frame_compute);
+        if !frame_compute.is_empty() {
+            builder = self.gpu_profiler.begin_scope(builder, "Compute");
+            for record in frame_compute {
+                builder = record(builder);
+            }
+            builder = self.gpu_profiler.end_scope(builder);
+        }
+
+        builder = self.gpu_profiler.begin_scope(builder, "Shadows");
+        builder = self.shadow_renderer.record(builder, &shadow_passes);
+        builder = self.gpu_profiler.end_scope(builder);
+        // Simulated before the render pass they are drawn in
+        if let Some(particles) = &particles {
+            builder = self
+                .gpu_profiler
+                .begin_scope(builder, "Particle simulation");
+            builder = self.particle_system.record_simulation(builder, particles);
+            builder = self.gpu_profiler.end_scope(builder);
+        }
+
+        // Opaque geometry, skybox, particles and world text share the HDR render pass
+        builder = self.gpu_profiler.begin_scope(builder, "Scene");
+        builder = builder
+            .begin_render_pass(
+                self.hdr_target.framebuffer(),
+                false,
+                vec![[0.0, 0.0, 0.0, 1.0].into(), 1f32.into()],
+            )
+            .unwrap();
+
+        if let Some(frame_set) = frame_set {
+            let pipeline = self.pbr_pipeline.pipeline();
+
+            for batch in &batches {
+                let sets = (
+                    frame_set.clone(),
+                    self.material_library.descriptor_set(batch.material),
+                );
+                let vertex_buffers = vec![batch.mesh.vertex_buffer(), batch.instances.clone()];
+
+                builder = match batch.mesh.index_buffer() {
+                    Some(index_buffer) => builder
+                        .draw_indexed(
+                            pipeline.clone(),
+                            &DynamicState::none(),
+                            vertex_buffers,
+                            index_buffer.clone(),
+                            sets,
+                            (),
+                        )
+                        .expect("Failed to record indexed draw !"),
+                    None => builder
+                        .draw(
+                            pipeline.clone(),
+                            &DynamicState::none(),
+                            vertex_buffers,
+                            sets,
+                            (),
+                        )
+                        .expect("Failed to record draw !"),
+                };
+                self.frame_stats.draw_calls += 1;
+            }
+
+            let skinned_pipeline = self.pbr_pipeline.skinned_pipeline();
+            for draw in &skinned {
+                let sets = (
+                    frame_set.clone(),
+                    self.material_library.descriptor_set(draw.material),
+                    draw.joints.clone(),
+                );
+                let vertex_buffers = vec![draw.mesh.vertex_buffer(), draw.instance.clone()];
+
+                builder = match draw.mesh.index_buffer() {
+                    Some(index_buffer) => builder
+                        .draw_indexed(
+                            skinned_pipeline.clone(),
+                            &DynamicState::none(),
+                            vertex_buffers,
+                            index_buffer.clone(),
+                            sets,
+                            (),
+                        )
+                        .expect("Failed to record indexed draw !"),
+                    None => builder
+                        .draw(
+                            skinned_pipeline.clone(),
+                            &DynamicState::none(),
+                            vertex_buffers,
+                            sets,
+                            (),
+                        )
+                        .expect("Failed to record draw !"),
+                };
+                self.frame_stats.draw_calls += 1;
+            }
+        }
+
+        // Behind everything, only drawn where the geometry wasn't
+        if let Some(skybox) = &skybox {
+            builder = self
+                .skybox_pass
+                .record(builder, skybox, swap_chain.dimensions());
+        }
+        if let Some(particles) = particles.as_ref().filter(|particles| !particles.is_empty()) {
+            builder = self.particle_system.record_draw(
+                builder,
+                particles,
+                swap_chain.dimensions(),
+                &mut self.frame_stats,
+            );
+        }
+        // Blended over the scene, after everything opaque
+        if text.has_world() {
+            builder = self.text_renderer.record_world(
+                builder,
+                &text,
+                swap_chain.dimensions(),
+                &mut self.frame_stats,
+            );
+        }
+
+        builder = builder.end_render_pass().unwrap();
+        builder = self.gpu_profiler.end_scope(builder);
+
+        builder = self.gpu_profiler.begin_scope(builder, "Post processing");
+        builder = self.post_processor.record(
+            builder,
+            &mut self.gpu_profiler,
+            &self.post_settings,
+            &self.tonemap_settings,
+            &self.hdr_display_settings,
+        );
+        builder = self.gpu_profiler.end_scope(builder);
+
+        // Over the effects, but still encoded for the display like the rest
+        if !sprites.is_empty() || text.has_screen() {
+            builder = self.gpu_profiler.begin_scope(builder, "Overlay");
+            builder = builder
+                .begin_render_pass(
+                    self.post_processor.overlay_framebuffer(),
+                    false,
+                    vec![ClearValue::None],
+                )
+                .unwrap();
+            builder = self.sprite_renderer.record(
+                builder,
+                &sprites,
+                swap_chain.dimensions(),
+                &mut self.frame_stats,
+            );
+            // Text goes over the sprites, like labels
+            builder = self.text_renderer.record_screen(
+                builder,
+                &text,
+                swap_chain.dimensions(),
+                &mut self.frame_stats,
+            );
+            builder = builder.end_render_pass().unwrap();
+            builder = self.gpu_profiler.end_scope(builder);
+        }
+
+        // Every pixel is written, nothing to clear
+        builder = self.gpu_profiler.begin_scope(builder, "Output");
+        builder = builder
+            .begin_render_pass(
+                swap_chain.framebuffer(image_index),
+                false,
+                vec![ClearValue::None],
+            )
+            .unwrap();
+        builder = self
+            .post_processor
+            .record_output(builder, &self.hdr_display_settings);
+
+        if let Some(debug_view) = &self.shadow_debug_view {
+            builder = debug_view.record(builder, swap_chain.dimensions());
+        }
+
+        builder = builder.end_render_pass().unwrap();
+        builder = self.gpu_profiler.end_scope(builder);
+
+        builder = self.gpu_profiler.end_scope(builder);
+        builder = self.gpu_profiler.resolve(builder);
+        builder.build().expect("Failed to build command buffer !")
+    }
+
+    fn recreate_swap_chain(&mut self) {
+        trace!("Recreating swap chain");
+
+        let swap_chain = if self.swap_chain_format_outdated {
+            // The surface only takes one swap chain at a time, the old one has to go first
+            self.wait_for_frames();
+            self.swap_chain = None;
+            self.swap_chain_format_outdated = false;
+            SwapChainWrapper::create(
+                &self.surface,
+                &self.device,
+                &self.graphics_queue,
+                &self.presentation_queue,
+                &self.memory_tracker,
+                self.preferred_output,
+            )
+        } else {
+            self.swap_chain.take().unwrap().recreate()
+        };
+        self.hdr_target =
+            HdrTarget::new(&self.device, &self.memory_tracker, swap_chain.dimensions());
+        self.post_processor = PostProcessor::new(
+            &self.device,
+            &self.memory_tracker,
+            &swap_chain,
+            &self.hdr_target,
+        );
+        self.sprite_renderer = SpriteRenderer::new(
+            &self.device,
+            &self.memory_tracker,
+            self.post_processor.overlay_render_pass(),
+        );
+        self.text_renderer.set_render_passes(
+            self.post_processor.overlay_render_pass(),
+            self.hdr_target.render_pass(),
+        );
+        // Material sets stay valid, the new pipeline has the same layout
+        self.skybox_pass = SkyboxPass::new(&self.device, self.hdr_target.render_pass());
+        self.particle_system
+            .set_render_pass(self.hdr_target.render_pass());
+        self.pbr_pipeline = Self::create_pbr_pipeline(&self.device, &swap_chain, &self.hdr_target);
+        self.swap_chain = Some(swap_chain);
+        self.swap_chain_outdated = false;
+
+        // Built against the old render pass
+        if self.shadow_debug_view.is_some() {
+            self.shadow_debug_view = Some(self.create_shadow_debug_view());
+        }
+    }
+
+    fn create_pbr_pipeline(
+        device: &Arc<Device>,
+        swap_chain: &SwapChainWrapper,
+        hdr_target: &HdrTarget,
+    ) -> PbrPipeline {
+        PbrPipeline::new(device, swap_chain.dimensions(), hdr_target.render_pass())
+            .unwrap_or_else(|err| panic!("Failed to create PBR pipeline: {}", err))
+    }
 
     fn create_instance() -> Arc<Instance> {
         if ENABLE_VALIDATION_LAYERS && !check_validation_layer_support() {
@@ -131,13 +1131,13 @@ impl VulkanApplication {
         instance: &Arc<Instance>,
         surface: &Arc<Surface<Window>>,
         physical_device_index: usize,
-    ) -> (Arc<Device>, Arc<Queue>, Arc<Queue>) {
+    ) -> (Arc<Device>, Arc<Queue>, Arc<Queue>, Arc<Queue>) {
         trace!("Creating logical device");
 
-        let physical_device = PhysicalDevice::from_index(&instance, physical_device_index).unwrap();
+        let physical_device = PhysicalDevice::from_index(instance, physical_device_index).unwrap();
         let indices = find_queue_families(surface, &physical_device).unwrap();
 
-        let families = [indices.graphics, indices.presentation];
+        let families = [indices.graphics, indices.presentation, indices.compute];
         let unique_queue_families: HashSet<&u32> = HashSet::from_iter(families.iter());
 
         let queue_priority = 1.0;
@@ -148,7 +1148,7 @@ impl VulkanApplication {
             )
         });
 
-        let (device, mut queues) = Device::new(
+        let (device, queues) = Device::new(
             physical_device,
             &Features::none(),
             &required_extensions(),
@@ -156,10 +1156,20 @@ impl VulkanApplication {
         )
         .expect("Failed to create logical device !");
 
-        let graphics_queue = queues.next().unwrap();
-        let presentation_queue = queues.next().unwrap_or_else(|| graphics_queue.clone());
+        // One queue per unique family, in no particular order
+        let queues: Vec<_> = queues.collect();
+        let queue_of = |family: u32| {
+            queues
+                .iter()
+                .find(|queue| queue.family().id() == family)
+                .unwrap()
+                .clone()
+        };
+        let graphics_queue = queue_of(indices.graphics);
+        let presentation_queue = queue_of(indices.presentation);
+        let compute_queue = queue_of(indices.compute);
 
-        (device, graphics_queue, presentation_queue)
+        (device, graphics_queue, presentation_queue, compute_queue)
     }
 
     #[cfg(debug_assertions)]
@@ -179,7 +1189,7 @@ impl VulkanApplication {
             verbose: true,
         };
 
-        DebugCallback::new(&instance, severities, msg_types, |msg| {
+        DebugCallback::new(instance, severities, msg_types, |msg| {
             if msg.severity.error {
                 error!(
                     "[Validation Layer] [{}] {}",
@@ -223,6 +1233,13 @@ fn get_required_extensions() -> InstanceExtensions {
     if ENABLE_VALIDATION_LAYERS {
         extensions.ext_debug_utils = true;
     }
+    // Exposes the HDR color spaces of the surfaces, when the loader has it
+    if InstanceExtensions::supported_by_core()
+        .map(|supported| supported.ext_swapchain_colorspace)
+        .unwrap_or(false)
+    {
+        extensions.ext_swapchain_colorspace = true;
+    }
 
     extensions
 }
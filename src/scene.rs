@@ -0,0 +1,348 @@
+pub mod animation;
+pub mod animator;
+pub mod audio_emitter;
+pub mod camera;
+pub mod light;
+pub mod mesh_renderer;
+pub mod particle_emitter;
+pub mod scene_graph;
+pub mod skinned_mesh_renderer;
+pub mod transform;
+pub mod tween;
+
+pub use animation::{
+    AnimationClip, AnimationPlayer, Channel, ChannelProperty, Interpolation, Joint, Skin,
+};
+pub use animator::{Animator, AnimatorController, AnimatorError, ParameterValue};
+pub use audio_emitter::AudioEmitter;
+pub use camera::{Camera, CameraView, Projection};
+pub use light::{EnvironmentLight, HemisphereLight, Light, LightKind, LightShadow};
+pub use mesh_renderer::MeshRenderer;
+pub use particle_emitter::{
+    Curve, CurveValue, EmitterShape, ParticleBlend, ParticleEffect, ParticleEmitter, ParticleForces,
+};
+pub use scene_graph::{NodeId, SceneGraph, SceneGraphError};
+pub use skinned_mesh_renderer::SkinnedMeshRenderer;
+pub use transform::Transform;
+pub use tween::{Easing, Repeat, Tween, TweenId, Tweenable, Tweener};
+
+use crate::math::Vec4;
+use std::collections::HashMap;
+
+/// Everything that gets rendered: the transform hierarchy and what is attached to its nodes.
+#[derive(Default)]
+pub struct Scene {
+    graph: SceneGraph,
+    cameras: HashMap<NodeId, Camera>,
+    active_camera: Option<NodeId>,
+    renderables: HashMap<NodeId, MeshRenderer>,
+    skinned_meshes: HashMap<NodeId, SkinnedMeshRenderer>,
+    lights: HashMap<NodeId, Light>,
+    particle_emitters: HashMap<NodeId, ParticleEmitter>,
+    audio_emitters: HashMap<NodeId, AudioEmitter>,
+    ambient: HemisphereLight,
+    environment: Option<EnvironmentLight>,
+    tweens: Tweener,
+    /// Bumped every time a renderable is added, removed or changed
+    renderables_revision: u64,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn graph(&self) -> &SceneGraph {
+        &self.graph
+    }
+
+    #[inline]
+    pub fn graph_mut(&mut self) -> &mut SceneGraph {
+        &mut self.graph
+    }
+
+    /// Remove a node and its subtree, along with everything attached to them.
+    pub fn remove_node(&mut self, id: NodeId) -> Result<(), SceneGraphError> {
+        let removed: Vec<_> = self.graph.iter_subtree(id).map(|(node, _)| node).collect();
+        self.graph.remove(id)?;
+
+        for node in removed {
+            self.cameras.remove(&node);
+            self.lights.remove(&node);
+            self.particle_emitters.remove(&node);
+            self.audio_emitters.remove(&node);
+            self.skinned_meshes.remove(&node);
+            if self.renderables.remove(&node).is_some() {
+                self.renderables_revision += 1;
+            }
+            if self.active_camera == Some(node) {
+                self.active_camera = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn set_camera(&mut self, node: NodeId, camera: Camera) {
+        self.cameras.insert(node, camera);
+    }
+
+    #[inline]
+    pub fn camera(&self, node: NodeId) -> Option<&Camera> {
+        self.cameras.get(&node)
+    }
+
+    #[inline]
+    pub fn camera_mut(&mut self, node: NodeId) -> Option<&mut Camera> {
+        self.cameras.get_mut(&node)
+    }
+
+    /// The camera the scene is rendered from, the node must have a camera.
+    pub fn set_active_camera(&mut self, node: Option<NodeId>) {
+        if let Some(node) = node {
+            assert!(
+                self.cameras.contains_key(&node),
+                "The active camera node has no camera !"
+            );
+        }
+        self.active_camera = node;
+    }
+
+    #[inline]
+    pub fn active_camera(&self) -> Option<NodeId> {
+        self.active_camera
+    }
+
+    /// Matrices and frustum of the active camera, if any.
+    pub fn active_camera_view(&mut self, aspect_ratio: f32) -> Option<CameraView> {
+        let node = self.active_camera?;
+        let camera = *self.cameras.get(&node)?;
+        let world = self.graph.world_matrix(node);
+
+        Some(CameraView::new(&camera, &world, aspect_ratio))
+    }
+
+    pub fn set_renderable(&mut self, node: NodeId, renderable: MeshRenderer) {
+        self.renderables.insert(node, renderable);
+        self.renderables_revision += 1;
+    }
+
+    pub fn remove_renderable(&mut self, node: NodeId) -> Option<MeshRenderer> {
+        let removed = self.renderables.remove(&node);
+        if removed.is_some() {
+            self.renderables_revision += 1;
+        }
+        removed
+    }
+
+    #[inline]
+    pub fn renderable(&self, node: NodeId) -> Option<&MeshRenderer> {
+        self.renderables.get(&node)
+    }
+
+    /// Assumes the renderable is changed, the batches using it will be rebuilt.
+    pub fn renderable_mut(&mut self, node: NodeId) -> Option<&mut MeshRenderer> {
+        let renderable = self.renderables.get_mut(&node);
+        if renderable.is_some() {
+            self.renderables_revision += 1;
+        }
+        renderable
+    }
+
+    /// Tint of the mesh or skinned mesh of a node, read every frame so the batches aren't
+    /// rebuilt. Returns false when the node has neither.
+    pub fn set_tint(&mut self, node: NodeId, tint: Vec4) -> bool {
+        if let Some(renderable) = self.renderables.get_mut(&node) {
+            renderable.tint = tint;
+        } else if let Some(skinned_mesh) = self.skinned_meshes.get_mut(&node) {
+            skinned_mesh.tint = tint;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Material parameters of the mesh or skinned mesh of a node, same as
+    /// [set_tint](Scene::set_tint).
+    pub fn set_params(&mut self, node: NodeId, params: Vec4) -> bool {
+        if let Some(renderable) = self.renderables.get_mut(&node) {
+            renderable.params = params;
+        } else if let Some(skinned_mesh) = self.skinned_meshes.get_mut(&node) {
+            skinned_mesh.params = params;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Every node with a mesh.
+    pub fn renderables(&self) -> impl Iterator<Item = (NodeId, &MeshRenderer)> {
+        self.renderables
+            .iter()
+            .map(|(node, renderable)| (*node, renderable))
+    }
+
+    pub fn set_skinned_mesh(&mut self, node: NodeId, skinned_mesh: SkinnedMeshRenderer) {
+        self.skinned_meshes.insert(node, skinned_mesh);
+    }
+
+    pub fn remove_skinned_mesh(&mut self, node: NodeId) -> Option<SkinnedMeshRenderer> {
+        self.skinned_meshes.remove(&node)
+    }
+
+    #[inline]
+    pub fn skinned_mesh(&self, node: NodeId) -> Option<&SkinnedMeshRenderer> {
+        self.skinned_meshes.get(&node)
+    }
+
+    /// Skinned meshes aren't batched, changing them doesn't rebuild anything.
+    #[inline]
+    pub fn skinned_mesh_mut(&mut self, node: NodeId) -> Option<&mut SkinnedMeshRenderer> {
+        self.skinned_meshes.get_mut(&node)
+    }
+
+    /// Every node with a skinned mesh.
+    pub fn skinned_meshes(&self) -> impl Iterator<Item = (NodeId, &SkinnedMeshRenderer)> {
+        self.skinned_meshes
+            .iter()
+            .map(|(node, skinned_mesh)| (*node, skinned_mesh))
+    }
+
+    /// Move the tweens then the animations forward by a number of seconds, once per frame.
+    pub fn update(&mut self, time_step: f32) {
+        self.update_tweens(time_step);
+        self.update_animations(time_step);
+    }
+
+    #[inline]
+    pub fn tweens(&self) -> &Tweener {
+        &self.tweens
+    }
+
+    #[inline]
+    pub fn tweens_mut(&mut self) -> &mut Tweener {
+        &mut self.tweens
+    }
+
+    /// Move the tweens forward by a number of seconds, calling the callbacks of the completed
+    /// ones.
+    pub fn update_tweens(&mut self, time_step: f32) {
+        // Taken out to be given the scene, the callbacks still see them through the scene
+        let mut tweens = std::mem::take(&mut self.tweens);
+        tweens.advance(self, time_step);
+        self.tweens = tweens;
+    }
+
+    /// Move the animation players of the skinned meshes forward by a number of seconds.
+    pub fn update_animations(&mut self, time_step: f32) {
+        for skinned_mesh in self.skinned_meshes.values_mut() {
+            match &mut skinned_mesh.animator {
+                Some(animator) => animator.advance(time_step),
+                None => skinned_mesh.player.advance(time_step),
+            }
+        }
+    }
+
+    pub fn set_light(&mut self, node: NodeId, light: Light) {
+        self.lights.insert(node, light);
+    }
+
+    pub fn remove_light(&mut self, node: NodeId) -> Option<Light> {
+        self.lights.remove(&node)
+    }
+
+    #[inline]
+    pub fn light(&self, node: NodeId) -> Option<&Light> {
+        self.lights.get(&node)
+    }
+
+    #[inline]
+    pub fn light_mut(&mut self, node: NodeId) -> Option<&mut Light> {
+        self.lights.get_mut(&node)
+    }
+
+    /// Every node with a light.
+    pub fn lights(&self) -> impl Iterator<Item = (NodeId, &Light)> {
+        self.lights.iter().map(|(node, light)| (*node, light))
+    }
+
+    pub fn set_particle_emitter(&mut self, node: NodeId, emitter: ParticleEmitter) {
+        self.particle_emitters.insert(node, emitter);
+    }
+
+    /// Its particles disappear with it.
+    pub fn remove_particle_emitter(&mut self, node: NodeId) -> Option<ParticleEmitter> {
+        self.particle_emitters.remove(&node)
+    }
+
+    #[inline]
+    pub fn particle_emitter(&self, node: NodeId) -> Option<&ParticleEmitter> {
+        self.particle_emitters.get(&node)
+    }
+
+    #[inline]
+    pub fn particle_emitter_mut(&mut self, node: NodeId) -> Option<&mut ParticleEmitter> {
+        self.particle_emitters.get_mut(&node)
+    }
+
+    /// Every node with a particle emitter.
+    pub fn particle_emitters(&self) -> impl Iterator<Item = (NodeId, &ParticleEmitter)> {
+        self.particle_emitters
+            .iter()
+            .map(|(node, emitter)| (*node, emitter))
+    }
+
+    pub fn set_audio_emitter(&mut self, node: NodeId, emitter: AudioEmitter) {
+        self.audio_emitters.insert(node, emitter);
+    }
+
+    /// Its sound stops on the next audio update.
+    pub fn remove_audio_emitter(&mut self, node: NodeId) -> Option<AudioEmitter> {
+        self.audio_emitters.remove(&node)
+    }
+
+    #[inline]
+    pub fn audio_emitter(&self, node: NodeId) -> Option<&AudioEmitter> {
+        self.audio_emitters.get(&node)
+    }
+
+    #[inline]
+    pub fn audio_emitter_mut(&mut self, node: NodeId) -> Option<&mut AudioEmitter> {
+        self.audio_emitters.get_mut(&node)
+    }
+
+    /// Every node with an audio emitter.
+    pub fn audio_emitters(&self) -> impl Iterator<Item = (NodeId, &AudioEmitter)> {
+        self.audio_emitters
+            .iter()
+            .map(|(node, emitter)| (*node, emitter))
+    }
+
+    #[inline]
+    pub fn ambient(&self) -> &HemisphereLight {
+        &self.ambient
+    }
+
+    #[inline]
+    pub fn set_ambient(&mut self, ambient: HemisphereLight) {
+        self.ambient = ambient;
+    }
+
+    #[inline]
+    pub fn environment(&self) -> Option<&EnvironmentLight> {
+        self.environment.as_ref()
+    }
+
+    /// Lights the scene in place of the hemisphere ambient when there is one.
+    #[inline]
+    pub fn set_environment(&mut self, environment: Option<EnvironmentLight>) {
+        self.environment = environment;
+    }
+
+    /// Changes when renderables are added, removed or modified, used to know when to rebatch.
+    #[inline]
+    pub fn renderables_revision(&self) -> u64 {
+        self.renderables_revision
+    }
+}
@@ -0,0 +1,410 @@
+use crate::math::{Mat4, Quat, Vec4};
+use crate::scene::Transform;
+use std::sync::Arc;
+
+/// How the values of a channel go from one key to the next, same as glTF.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Holds the value of the previous key
+    Step,
+    /// Rotations are slerped
+    #[default]
+    Linear,
+    /// Hermite spline, each key has an in tangent, a value and an out tangent
+    CubicSpline,
+}
+
+/// Part of the local transform of a joint, or morph target weights, animated by a channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChannelProperty {
+    Translation,
+    Rotation,
+    Scale,
+    /// Up to 4 weights, starting from the given morph target
+    MorphWeights {
+        first: usize,
+    },
+}
+
+/// Keys animating one property of one joint.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    /// Index in the joints of the skin, ignored for morph weights
+    pub joint: usize,
+    pub property: ChannelProperty,
+    pub interpolation: Interpolation,
+    /// Seconds, increasing
+    pub times: Vec<f32>,
+    /// XYZ for translations and scales, XYZW for rotations and morph weights.
+    /// Three per key for cubic splines: in tangent, value, out tangent.
+    pub values: Vec<Vec4>,
+}
+
+impl Channel {
+    /// Before the first key and after the last one, the value of the closest key.
+    pub fn sample(&self, time: f32) -> Vec4 {
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        let value = |key: usize| {
+            if cubic {
+                self.values[key * 3 + 1]
+            } else {
+                self.values[key]
+            }
+        };
+
+        let next = self.times.partition_point(|key_time| *key_time <= time);
+        if next == 0 {
+            return value(0);
+        }
+        if next == self.times.len() {
+            return value(next - 1);
+        }
+
+        let previous = next - 1;
+        let step = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / step;
+        match self.interpolation {
+            Interpolation::Step => value(previous),
+            Interpolation::Linear if self.property == ChannelProperty::Rotation => {
+                let start = Quat::from(value(previous).to_array());
+                let end = Quat::from(value(next).to_array());
+                let rotation = start.slerp(end, t);
+                Vec4::new(rotation.x, rotation.y, rotation.z, rotation.w)
+            }
+            Interpolation::Linear => value(previous).lerp(value(next), t),
+            Interpolation::CubicSpline => {
+                let out_tangent = self.values[previous * 3 + 2] * step;
+                let in_tangent = self.values[next * 3] * step;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + value(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2)
+            }
+        }
+    }
+
+    /// Overwrite the property of the joint in the pose, or the weights of the morph targets.
+    /// Weights past the morph targets of the mesh are dropped.
+    fn apply(&self, time: f32, pose: &mut [Transform], morph_weights: &mut [f32]) {
+        let value = self.sample(time);
+        match self.property {
+            ChannelProperty::Translation => pose[self.joint].translation = value.truncate(),
+            ChannelProperty::Rotation => {
+                pose[self.joint].rotation = Quat::from(value.to_array()).normalize()
+            }
+            ChannelProperty::Scale => pose[self.joint].scale = value.truncate(),
+            ChannelProperty::MorphWeights { first } => {
+                let weights = morph_weights.iter_mut().skip(first);
+                for (weight, value) in weights.zip(&value.to_array()) {
+                    *weight = *value;
+                }
+            }
+        }
+    }
+}
+
+/// Animation of the joints of a skin, sampled on the CPU.
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    name: String,
+    /// Seconds, time of the last key
+    duration: f32,
+    channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+
+        Self {
+            name: name.to_owned(),
+            duration,
+            channels,
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    #[inline]
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    /// Overwrite the animated properties of the pose and morph weights, the others keep
+    /// their value.
+    pub fn sample(&self, time: f32, pose: &mut [Transform], morph_weights: &mut [f32]) {
+        for channel in &self.channels {
+            channel.apply(time, pose, morph_weights);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    /// Index in the joints of the skin
+    pub parent: Option<usize>,
+    /// Local transform when not animated
+    pub rest: Transform,
+}
+
+/// Hierarchy of joints deforming a skinned mesh.
+#[derive(Debug, Clone)]
+pub struct Skin {
+    joints: Vec<Joint>,
+    /// From the space of the mesh to the space of each joint in the bind pose
+    inverse_bind_matrices: Vec<Mat4>,
+    /// Transform of the root joints, from what is above them but not part of the skin
+    root: Mat4,
+    /// Joints sorted with the parents before their children
+    order: Vec<usize>,
+}
+
+impl Skin {
+    /// The joints can be in any order, as long as they form a hierarchy.
+    pub fn new(joints: Vec<Joint>, inverse_bind_matrices: Vec<Mat4>, root: Mat4) -> Self {
+        assert_eq!(
+            joints.len(),
+            inverse_bind_matrices.len(),
+            "Every joint needs an inverse bind matrix !"
+        );
+
+        let mut order = Vec::with_capacity(joints.len());
+        let mut placed = vec![false; joints.len()];
+        while order.len() < joints.len() {
+            let before = order.len();
+            for (index, joint) in joints.iter().enumerate() {
+                if !placed[index] && joint.parent.is_none_or(|parent| placed[parent]) {
+                    placed[index] = true;
+                    order.push(index);
+                }
+            }
+            assert!(order.len() > before, "The joints of a skin form a cycle !");
+        }
+
+        Self {
+            joints,
+            inverse_bind_matrices,
+            root,
+            order,
+        }
+    }
+
+    #[inline]
+    pub fn joints(&self) -> &[Joint] {
+        &self.joints
+    }
+
+    #[inline]
+    pub fn inverse_bind_matrices(&self) -> &[Mat4] {
+        &self.inverse_bind_matrices
+    }
+
+    pub fn find_joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    /// Local transforms of the joints when not animated.
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// Matrices moving the vertices from the bind pose to the pose, in the space of the mesh.
+    pub fn joint_matrices(&self, pose: &[Transform], matrices: &mut Vec<Mat4>) {
+        matrices.clear();
+        matrices.resize(self.joints.len(), Mat4::IDENTITY);
+        for &index in &self.order {
+            let parent = match self.joints[index].parent {
+                Some(parent) => matrices[parent],
+                None => self.root,
+            };
+            matrices[index] = parent * pose[index].matrix();
+        }
+
+        for (matrix, inverse_bind) in matrices.iter_mut().zip(&self.inverse_bind_matrices) {
+            *matrix = *matrix * *inverse_bind;
+        }
+    }
+}
+
+/// A clip and how far into it the player is.
+#[derive(Clone)]
+struct PlayingClip {
+    clip: Arc<AnimationClip>,
+    time: f32,
+}
+
+/// Plays the clips of a skin, fading from one to the next.
+#[derive(Clone)]
+pub struct AnimationPlayer {
+    current: Option<PlayingClip>,
+    /// Fading out, still advancing
+    previous: Option<PlayingClip>,
+    /// Seconds
+    fade_duration: f32,
+    fade_elapsed: f32,
+    /// Multiplies the time step, negative plays backwards
+    pub speed: f32,
+    /// Wraps around at the end, holds the last pose otherwise
+    pub looping: bool,
+    pub paused: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            current: None,
+            previous: None,
+            fade_duration: 0.0,
+            fade_elapsed: 0.0,
+            speed: 1.0,
+            looping: true,
+            paused: false,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Switch to a clip right away, from its start.
+    pub fn play(&mut self, clip: Arc<AnimationClip>) {
+        self.current = Some(PlayingClip { clip, time: 0.0 });
+        self.previous = None;
+    }
+
+    /// Blend from the current pose to a clip over the given seconds.
+    /// A fade interrupted by another one starts from the clip faded to, it doesn't pop.
+    pub fn crossfade(&mut self, clip: Arc<AnimationClip>, duration: f32) {
+        if duration <= 0.0 || self.current.is_none() {
+            self.play(clip);
+            return;
+        }
+
+        self.previous = self.current.take();
+        self.current = Some(PlayingClip { clip, time: 0.0 });
+        self.fade_duration = duration;
+        self.fade_elapsed = 0.0;
+    }
+
+    /// Back to the rest pose.
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.previous = None;
+    }
+
+    #[inline]
+    pub fn clip(&self) -> Option<&Arc<AnimationClip>> {
+        self.current.as_ref().map(|playing| &playing.clip)
+    }
+
+    /// Seconds into the current clip.
+    #[inline]
+    pub fn time(&self) -> f32 {
+        self.current.as_ref().map_or(0.0, |playing| playing.time)
+    }
+
+    pub fn set_time(&mut self, time: f32) {
+        let looping = self.looping;
+        if let Some(playing) = &mut self.current {
+            playing.time = wrap_time(time, playing.clip.duration(), looping);
+        }
+    }
+
+    #[inline]
+    pub fn is_fading(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// A clip that doesn't loop is finished at its end, or its start when played backwards.
+    pub fn is_finished(&self) -> bool {
+        match &self.current {
+            Some(playing) if !self.looping => {
+                if self.speed < 0.0 {
+                    playing.time <= 0.0
+                } else {
+                    playing.time >= playing.clip.duration()
+                }
+            }
+            Some(_) => false,
+            None => true,
+        }
+    }
+
+    /// Move the clips forward, the fades go by real time, not scaled by the speed.
+    pub fn advance(&mut self, time_step: f32) {
+        if self.paused {
+            return;
+        }
+
+        let clip_step = time_step * self.speed;
+        for playing in self.current.iter_mut().chain(self.previous.iter_mut()) {
+            playing.time = wrap_time(
+                playing.time + clip_step,
+                playing.clip.duration(),
+                self.looping,
+            );
+        }
+
+        if self.previous.is_some() {
+            self.fade_elapsed += time_step;
+            if self.fade_elapsed >= self.fade_duration {
+                self.previous = None;
+            }
+        }
+    }
+
+    /// Local transforms of the joints of the skin, blended during fades.
+    /// The morph weights start with the values to use when not animated.
+    pub fn pose(&self, skin: &Skin, pose: &mut Vec<Transform>, morph_weights: &mut [f32]) {
+        pose.clear();
+        pose.extend_from_slice(&skin.rest_pose());
+        let current = match &self.current {
+            Some(current) => current,
+            None => return,
+        };
+
+        match &self.previous {
+            Some(previous) => {
+                let mut target = skin.rest_pose();
+                let mut target_weights = morph_weights.to_vec();
+                previous.clip.sample(previous.time, pose, morph_weights);
+                current
+                    .clip
+                    .sample(current.time, &mut target, &mut target_weights);
+
+                let weight = (self.fade_elapsed / self.fade_duration).clamp(0.0, 1.0);
+                for (transform, target) in pose.iter_mut().zip(&target) {
+                    *transform = transform.lerp(target, weight);
+                }
+                for (morph_weight, target) in morph_weights.iter_mut().zip(&target_weights) {
+                    *morph_weight += (target - *morph_weight) * weight;
+                }
+            }
+            None => current.clip.sample(current.time, pose, morph_weights),
+        }
+    }
+}
+
+fn wrap_time(time: f32, duration: f32, looping: bool) -> f32 {
+    if looping && duration > 0.0 {
+        time.rem_euclid(duration)
+    } else {
+        time.clamp(0.0, duration)
+    }
+}
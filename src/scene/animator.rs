@@ -0,0 +1,472 @@
+use crate::math::Vec2;
+use crate::scene::{AnimationClip, Skin, Transform};
+use std::sync::Arc;
+
+pub mod controller;
+
+pub use controller::{
+    AnimatorController, AnimatorError, AnimatorLayer, AnimatorState, Comparison, Condition, Motion,
+    Parameter, ParameterValue, StateTransition,
+};
+
+/// A state and how far into it a layer is.
+#[derive(Debug, Copy, Clone)]
+struct PlayingState {
+    state: usize,
+    /// Fraction of the state, the clips of a blend stay in sync
+    time: f32,
+}
+
+#[derive(Clone)]
+struct LayerPlayback {
+    /// Of each state, in the order of its motion
+    clips: Vec<Vec<Arc<AnimationClip>>>,
+    /// Whether the layer animates each joint of the skin
+    mask: Vec<bool>,
+    weight: f32,
+    current: PlayingState,
+    /// Fading out, still advancing
+    previous: Option<PlayingState>,
+    /// Seconds
+    fade_duration: f32,
+    fade_elapsed: f32,
+}
+
+/// Plays the states of a controller, driven by its parameters.
+/// Replaces the player of a [SkinnedMeshRenderer](crate::scene::SkinnedMeshRenderer) when set.
+#[derive(Clone)]
+pub struct Animator {
+    controller: Arc<AnimatorController>,
+    parameters: Vec<ParameterValue>,
+    layers: Vec<LayerPlayback>,
+    pub paused: bool,
+}
+
+impl Animator {
+    /// The clips are found by name, masks by the names of the joints of the skin.
+    pub fn new(
+        controller: Arc<AnimatorController>,
+        skin: &Skin,
+        clips: &[Arc<AnimationClip>],
+    ) -> Result<Self, AnimatorError> {
+        let find_clip = |name: &str| {
+            clips
+                .iter()
+                .find(|clip| clip.name() == name)
+                .cloned()
+                .ok_or_else(|| AnimatorError::MissingClip(name.to_owned()))
+        };
+
+        let mut layers = Vec::with_capacity(controller.layers.len());
+        for layer in &controller.layers {
+            let clips = layer
+                .states
+                .iter()
+                .map(|state| state.motion.clips().into_iter().map(find_clip).collect())
+                .collect::<Result<_, _>>()?;
+
+            let joints = skin.joints();
+            let mut mask = vec![layer.mask.is_empty(); joints.len()];
+            for name in &layer.mask {
+                let root = skin
+                    .find_joint(name)
+                    .ok_or_else(|| AnimatorError::MissingJoint(name.clone()))?;
+                mask[root] = true;
+            }
+            // Descendants of the masked joints, the parents aren't always before their children
+            let mut changed = !layer.mask.is_empty();
+            while changed {
+                changed = false;
+                for (index, joint) in joints.iter().enumerate() {
+                    if !mask[index] && joint.parent.is_some_and(|parent| mask[parent]) {
+                        mask[index] = true;
+                        changed = true;
+                    }
+                }
+            }
+
+            layers.push(LayerPlayback {
+                clips,
+                mask,
+                weight: layer.weight,
+                current: PlayingState {
+                    state: layer.default_state,
+                    time: 0.0,
+                },
+                previous: None,
+                fade_duration: 0.0,
+                fade_elapsed: 0.0,
+            });
+        }
+
+        Ok(Self {
+            parameters: controller
+                .parameters
+                .iter()
+                .map(|parameter| parameter.default)
+                .collect(),
+            controller,
+            layers,
+            paused: false,
+        })
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &Arc<AnimatorController> {
+        &self.controller
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<ParameterValue> {
+        self.controller
+            .find_parameter(name)
+            .map(|parameter| self.parameters[parameter])
+    }
+
+    /// Returns false when there is no float parameter with this name.
+    pub fn set_float(&mut self, name: &str, value: f32) -> bool {
+        self.set_parameter(name, ParameterValue::Float(value))
+    }
+
+    /// Returns false when there is no bool parameter with this name.
+    pub fn set_bool(&mut self, name: &str, value: bool) -> bool {
+        self.set_parameter(name, ParameterValue::Bool(value))
+    }
+
+    /// Returns false when there is no trigger with this name.
+    pub fn set_trigger(&mut self, name: &str) -> bool {
+        self.set_parameter(name, ParameterValue::Trigger(true))
+    }
+
+    /// Returns false when there is no trigger with this name.
+    pub fn reset_trigger(&mut self, name: &str) -> bool {
+        self.set_parameter(name, ParameterValue::Trigger(false))
+    }
+
+    fn set_parameter(&mut self, name: &str, value: ParameterValue) -> bool {
+        let parameter = match self.controller.find_parameter(name) {
+            Some(parameter) => parameter,
+            None => return false,
+        };
+        let current = &mut self.parameters[parameter];
+        if std::mem::discriminant(current) != std::mem::discriminant(&value) {
+            return false;
+        }
+        *current = value;
+        true
+    }
+
+    /// Name of the state a layer plays, or fades to.
+    pub fn current_state(&self, layer: usize) -> Option<&str> {
+        let playing = self.layers.get(layer)?.current;
+        Some(&self.controller.layers[layer].states[playing.state].name)
+    }
+
+    /// Fraction of the current state of a layer.
+    pub fn state_time(&self, layer: usize) -> Option<f32> {
+        self.layers.get(layer).map(|playback| playback.current.time)
+    }
+
+    pub fn is_in_transition(&self, layer: usize) -> bool {
+        self.layers
+            .get(layer)
+            .is_some_and(|playback| playback.previous.is_some())
+    }
+
+    /// Override the weight of a layer from the controller.
+    pub fn set_layer_weight(&mut self, layer: usize, weight: f32) {
+        if let Some(playback) = self.layers.get_mut(layer) {
+            playback.weight = weight.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Switch a layer to a state right away, from its start.
+    pub fn play(&mut self, layer: usize, state: &str) -> bool {
+        let state = match self.controller.layers.get(layer).and_then(|definition| {
+            definition
+                .states
+                .iter()
+                .position(|candidate| candidate.name == state)
+        }) {
+            Some(state) => state,
+            None => return false,
+        };
+
+        let playback = &mut self.layers[layer];
+        playback.current = PlayingState { state, time: 0.0 };
+        playback.previous = None;
+        true
+    }
+
+    /// Take the transitions allowed by the parameters and move the states forward.
+    pub fn advance(&mut self, time_step: f32) {
+        if self.paused {
+            return;
+        }
+
+        let controller = self.controller.clone();
+        for (index, definition) in controller.layers.iter().enumerate() {
+            let current = self.layers[index].current;
+            let transition = definition.transitions.iter().find(|transition| {
+                let from = match transition.from {
+                    Some(from) => from == current.state,
+                    None => transition.to != current.state,
+                };
+                from && transition
+                    .exit_time
+                    .is_none_or(|exit_time| current.time >= exit_time)
+                    && transition
+                        .conditions
+                        .iter()
+                        .all(|condition| self.holds(condition))
+            });
+
+            if let Some(transition) = transition {
+                for condition in &transition.conditions {
+                    if condition.comparison == Comparison::Triggered {
+                        self.parameters[condition.parameter] = ParameterValue::Trigger(false);
+                    }
+                }
+
+                let playback = &mut self.layers[index];
+                playback.previous = if transition.duration > 0.0 {
+                    Some(current)
+                } else {
+                    None
+                };
+                playback.current = PlayingState {
+                    state: transition.to,
+                    time: 0.0,
+                };
+                playback.fade_duration = transition.duration;
+                playback.fade_elapsed = 0.0;
+            }
+
+            let playback = &self.layers[index];
+            let current = self.advance_state(definition, playback, playback.current, time_step);
+            let previous = playback
+                .previous
+                .map(|previous| self.advance_state(definition, playback, previous, time_step));
+
+            let playback = &mut self.layers[index];
+            playback.current = current;
+            playback.previous = previous;
+            if playback.previous.is_some() {
+                playback.fade_elapsed += time_step;
+                if playback.fade_elapsed >= playback.fade_duration {
+                    playback.previous = None;
+                }
+            }
+        }
+    }
+
+    fn advance_state(
+        &self,
+        definition: &AnimatorLayer,
+        playback: &LayerPlayback,
+        mut playing: PlayingState,
+        time_step: f32,
+    ) -> PlayingState {
+        let state = &definition.states[playing.state];
+        let duration = self.state_duration(&playback.clips[playing.state], state);
+        if duration > 0.0 {
+            playing.time += time_step * state.speed / duration;
+        }
+        playing.time = if state.looping {
+            playing.time.rem_euclid(1.0)
+        } else {
+            playing.time.clamp(0.0, 1.0)
+        };
+        playing
+    }
+
+    /// Local transforms of the joints of the skin, with the layers on top of each other.
+    /// The morph weights start with the values to use when not animated.
+    pub fn pose(&self, skin: &Skin, pose: &mut Vec<Transform>, morph_weights: &mut [f32]) {
+        pose.clear();
+        pose.extend_from_slice(&skin.rest_pose());
+
+        let mut layer_pose = Vec::new();
+        let mut layer_weights = Vec::new();
+        for (definition, playback) in self.controller.layers.iter().zip(&self.layers) {
+            if playback.weight <= 0.0 {
+                continue;
+            }
+
+            layer_weights.clear();
+            layer_weights.extend_from_slice(morph_weights);
+            self.sample_state(
+                skin,
+                definition,
+                playback,
+                playback.current,
+                &mut layer_pose,
+                &mut layer_weights,
+            );
+            if let Some(previous) = playback.previous {
+                let mut previous_pose = Vec::new();
+                let mut previous_weights = morph_weights.to_vec();
+                self.sample_state(
+                    skin,
+                    definition,
+                    playback,
+                    previous,
+                    &mut previous_pose,
+                    &mut previous_weights,
+                );
+
+                let fade = (playback.fade_elapsed / playback.fade_duration).clamp(0.0, 1.0);
+                blend(
+                    &mut previous_pose,
+                    &mut previous_weights,
+                    &layer_pose,
+                    &layer_weights,
+                    fade,
+                    None,
+                );
+                layer_pose = previous_pose;
+                layer_weights = previous_weights;
+            }
+
+            blend(
+                pose,
+                morph_weights,
+                &layer_pose,
+                &layer_weights,
+                playback.weight,
+                Some(&playback.mask),
+            );
+        }
+    }
+
+    fn sample_state(
+        &self,
+        skin: &Skin,
+        definition: &AnimatorLayer,
+        playback: &LayerPlayback,
+        playing: PlayingState,
+        pose: &mut Vec<Transform>,
+        morph_weights: &mut [f32],
+    ) {
+        pose.clear();
+        pose.extend_from_slice(&skin.rest_pose());
+
+        let clips = &playback.clips[playing.state];
+        let weights = self.motion_weights(&definition.states[playing.state].motion);
+        let mut clip_pose = Vec::new();
+        let mut clip_weights = Vec::new();
+        let mut total = 0.0;
+        // Running weighted average, each clip is blended in by its share of the weights so far
+        for (clip, weight) in clips.iter().zip(weights) {
+            if weight <= 0.0 {
+                continue;
+            }
+            total += weight;
+
+            clip_pose.clear();
+            clip_pose.extend_from_slice(&skin.rest_pose());
+            clip_weights.clear();
+            clip_weights.extend_from_slice(morph_weights);
+            clip.sample(
+                playing.time * clip.duration(),
+                &mut clip_pose,
+                &mut clip_weights,
+            );
+            blend(
+                pose,
+                morph_weights,
+                &clip_pose,
+                &clip_weights,
+                weight / total,
+                None,
+            );
+        }
+    }
+
+    /// Weight of each clip of a motion, from the parameters.
+    fn motion_weights(&self, motion: &Motion) -> Vec<f32> {
+        match motion {
+            Motion::Clip(_) => vec![1.0],
+            Motion::Blend1d { parameter, points } => {
+                let value = self.float(*parameter);
+                let mut weights = vec![0.0; points.len()];
+                let next = points.partition_point(|(threshold, _)| *threshold <= value);
+                if next == 0 {
+                    weights[0] = 1.0;
+                } else if next == points.len() {
+                    weights[next - 1] = 1.0;
+                } else {
+                    let (start, end) = (points[next - 1].0, points[next].0);
+                    let t = (value - start) / (end - start);
+                    weights[next - 1] = 1.0 - t;
+                    weights[next] = t;
+                }
+                weights
+            }
+            Motion::Blend2d { x, y, points } => {
+                let position = Vec2::new(self.float(*x), self.float(*y));
+                let distances: Vec<f32> = points
+                    .iter()
+                    .map(|(point, _)| (*point - position).length())
+                    .collect();
+                // Right on a point, inverse distance would divide by 0
+                if let Some(exact) = distances.iter().position(|distance| *distance < 1e-4) {
+                    let mut weights = vec![0.0; points.len()];
+                    weights[exact] = 1.0;
+                    return weights;
+                }
+                let weights: Vec<f32> = distances
+                    .iter()
+                    .map(|distance| 1.0 / (distance * distance))
+                    .collect();
+                let sum: f32 = weights.iter().sum();
+                weights.iter().map(|weight| weight / sum).collect()
+            }
+        }
+    }
+
+    /// Seconds for the clips of a state to play once, blended like the clips.
+    fn state_duration(&self, clips: &[Arc<AnimationClip>], state: &AnimatorState) -> f32 {
+        clips
+            .iter()
+            .zip(self.motion_weights(&state.motion))
+            .map(|(clip, weight)| clip.duration() * weight)
+            .sum()
+    }
+
+    fn float(&self, parameter: usize) -> f32 {
+        match self.parameters[parameter] {
+            ParameterValue::Float(value) => value,
+            _ => 0.0,
+        }
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        match (condition.comparison, self.parameters[condition.parameter]) {
+            (Comparison::Greater(threshold), ParameterValue::Float(value)) => value > threshold,
+            (Comparison::Less(threshold), ParameterValue::Float(value)) => value < threshold,
+            (Comparison::IsTrue, ParameterValue::Bool(value)) => value,
+            (Comparison::IsFalse, ParameterValue::Bool(value)) => !value,
+            (Comparison::Triggered, ParameterValue::Trigger(value)) => value,
+            _ => false,
+        }
+    }
+}
+
+/// Move a pose and its morph weights towards another, only the masked joints when given.
+fn blend(
+    pose: &mut [Transform],
+    morph_weights: &mut [f32],
+    target: &[Transform],
+    target_weights: &[f32],
+    weight: f32,
+    mask: Option<&[bool]>,
+) {
+    for (index, (transform, target)) in pose.iter_mut().zip(target).enumerate() {
+        if mask.is_none_or(|mask| mask[index]) {
+            *transform = transform.lerp(target, weight);
+        }
+    }
+    for (morph_weight, target) in morph_weights.iter_mut().zip(target_weights) {
+        *morph_weight += (target - *morph_weight) * weight;
+    }
+}
@@ -0,0 +1,723 @@
+use crate::math::Vec2;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Why an animator can't be created.
+#[derive(Debug)]
+pub enum AnimatorError {
+    Io(io::Error),
+    /// A line of a controller file can't be understood
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// A state plays a clip that isn't given to the animator
+    MissingClip(String),
+    /// A layer mask names a joint the skin doesn't have
+    MissingJoint(String),
+}
+
+impl fmt::Display for AnimatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AnimatorError::Io(err) => write!(f, "Failed to read animator controller: {}", err),
+            AnimatorError::Parse { line, message } => {
+                write!(
+                    f,
+                    "Invalid animator controller at line {}: {}",
+                    line, message
+                )
+            }
+            AnimatorError::MissingClip(clip) => write!(f, "No animation clip named {:?}", clip),
+            AnimatorError::MissingJoint(joint) => write!(f, "No joint named {:?}", joint),
+        }
+    }
+}
+
+impl Error for AnimatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AnimatorError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AnimatorError {
+    fn from(err: io::Error) -> Self {
+        AnimatorError::Io(err)
+    }
+}
+
+/// Value of a parameter driving an animator.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParameterValue {
+    Float(f32),
+    Bool(bool),
+    /// Stays set until a transition checking it is taken
+    Trigger(bool),
+}
+
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub default: ParameterValue,
+}
+
+/// Test of a parameter for a transition to be taken.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Comparison {
+    Greater(f32),
+    Less(f32),
+    IsTrue,
+    IsFalse,
+    /// Resets the trigger when the transition is taken
+    Triggered,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Condition {
+    /// Index in the parameters of the controller
+    pub parameter: usize,
+    pub comparison: Comparison,
+}
+
+/// What a state plays, clips are named as in the file they come from.
+#[derive(Debug, Clone)]
+pub enum Motion {
+    Clip(String),
+    /// Clips placed along a float parameter, the two around its value are blended
+    Blend1d {
+        parameter: usize,
+        points: Vec<(f32, String)>,
+    },
+    /// Clips placed on the plane of two float parameters, weighted by inverse distance
+    Blend2d {
+        x: usize,
+        y: usize,
+        points: Vec<(Vec2, String)>,
+    },
+}
+
+impl Motion {
+    /// Clips in the order of the points.
+    pub fn clips(&self) -> Vec<&str> {
+        match self {
+            Motion::Clip(clip) => vec![clip],
+            Motion::Blend1d { points, .. } => points.iter().map(|(_, clip)| &clip[..]).collect(),
+            Motion::Blend2d { points, .. } => points.iter().map(|(_, clip)| &clip[..]).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimatorState {
+    pub name: String,
+    pub motion: Motion,
+    /// Multiplies the time step
+    pub speed: f32,
+    /// Holds the last pose otherwise
+    pub looping: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateTransition {
+    /// Index in the states of the layer, from any state but the target when none
+    pub from: Option<usize>,
+    pub to: usize,
+    /// Seconds of crossfade
+    pub duration: f32,
+    /// Fraction of the state to play before leaving it
+    pub exit_time: Option<f32>,
+    /// All of them have to hold
+    pub conditions: Vec<Condition>,
+}
+
+/// States of a part of the skeleton, layers are applied on top of each other in order.
+#[derive(Debug, Clone)]
+pub struct AnimatorLayer {
+    pub name: String,
+    pub states: Vec<AnimatorState>,
+    /// Checked in order, the first one allowed is taken
+    pub transitions: Vec<StateTransition>,
+    pub default_state: usize,
+    /// How much the layer overrides the ones below
+    pub weight: f32,
+    /// Joints animated by the layer along with their descendants, every joint when empty
+    pub mask: Vec<String>,
+}
+
+/// Parameters, states and transitions of an [Animator](super::Animator), shared between
+/// the animators using it.
+#[derive(Debug, Clone, Default)]
+pub struct AnimatorController {
+    pub parameters: Vec<Parameter>,
+    pub layers: Vec<AnimatorLayer>,
+}
+
+impl AnimatorController {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, AnimatorError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Read a controller from its text format, one declaration per line and `#` comments:
+    ///
+    /// ```text
+    /// # Parameters, before they are used, with an optional default
+    /// float speed 0
+    /// bool grounded true
+    /// trigger jump
+    /// float aim_x
+    /// float aim_y
+    ///
+    /// layer base
+    /// # The first state of a layer is its default one
+    /// state idle clip Idle
+    /// state move blend1d speed
+    /// point 1.5 Walk
+    /// point 5 Run
+    /// state jump clip Jump
+    /// once
+    /// speed 1.2
+    /// # From, to, seconds of crossfade, then the optional exit time and conditions
+    /// transition idle move 0.2 speed > 0.1
+    /// transition move idle 0.2 speed < 0.1
+    /// transition any jump 0.1 jump
+    /// transition jump idle 0.3 exit 0.9 grounded
+    ///
+    /// layer upper_body
+    /// weight 0.8
+    /// mask Spine2
+    /// state aim blend2d aim_x aim_y
+    /// point 0 0 AimCenter
+    /// point 1 0 AimRight
+    /// ```
+    ///
+    /// `point`, `once` and `speed` apply to the last state, `weight` and `mask` to the last
+    /// layer. Names can't contain whitespace.
+    pub fn parse(source: &str) -> Result<Self, AnimatorError> {
+        let mut controller = AnimatorController::default();
+        let mut pending = PendingLayer::default();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| AnimatorError::Parse {
+                line: line_number,
+                message,
+            };
+
+            let tokens: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+            let (keyword, args) = match tokens.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+
+            match *keyword {
+                "float" | "bool" | "trigger" => {
+                    let name = argument(args, 0, "a parameter name").map_err(error)?;
+                    if controller.find_parameter(name).is_some() {
+                        return Err(error(format!("Parameter {:?} declared twice", name)));
+                    }
+                    let default = match (*keyword, args.get(1)) {
+                        ("float", None) => ParameterValue::Float(0.0),
+                        ("float", Some(value)) => {
+                            ParameterValue::Float(parse_float(value).map_err(error)?)
+                        }
+                        ("bool", None) => ParameterValue::Bool(false),
+                        ("bool", Some(value)) => {
+                            ParameterValue::Bool(parse_bool(value).map_err(error)?)
+                        }
+                        _ => ParameterValue::Trigger(false),
+                    };
+                    controller.parameters.push(Parameter {
+                        name: name.to_owned(),
+                        default,
+                    });
+                }
+                "layer" => {
+                    controller.finish_layer(&mut pending)?;
+                    pending.line = line_number;
+                    controller.layers.push(AnimatorLayer {
+                        name: argument(args, 0, "a layer name").map_err(error)?.to_owned(),
+                        states: Vec::new(),
+                        transitions: Vec::new(),
+                        default_state: 0,
+                        weight: 1.0,
+                        mask: Vec::new(),
+                    });
+                }
+                "weight" => {
+                    let weight = parse_float(argument(args, 0, "a weight").map_err(error)?)
+                        .map_err(error)?;
+                    controller.last_layer().map_err(error)?.weight = weight;
+                }
+                "mask" => {
+                    let layer = controller.last_layer().map_err(error)?;
+                    layer
+                        .mask
+                        .extend(args.iter().map(|joint| joint.to_string()));
+                }
+                "state" => {
+                    let name = argument(args, 0, "a state name").map_err(error)?;
+                    let motion = match args.get(1).copied() {
+                        Some("clip") => {
+                            Motion::Clip(argument(args, 2, "a clip").map_err(error)?.to_owned())
+                        }
+                        Some("blend1d") => Motion::Blend1d {
+                            parameter: controller.float_parameter(args.get(2)).map_err(error)?,
+                            points: Vec::new(),
+                        },
+                        Some("blend2d") => Motion::Blend2d {
+                            x: controller.float_parameter(args.get(2)).map_err(error)?,
+                            y: controller.float_parameter(args.get(3)).map_err(error)?,
+                            points: Vec::new(),
+                        },
+                        _ => {
+                            return Err(error(
+                                "Expected clip, blend1d or blend2d after the state name".to_owned(),
+                            ))
+                        }
+                    };
+
+                    let layer = controller.last_layer().map_err(error)?;
+                    if layer.states.iter().any(|state| state.name == name) {
+                        return Err(error(format!("State {:?} declared twice", name)));
+                    }
+                    pending.state_lines.push(line_number);
+                    layer.states.push(AnimatorState {
+                        name: name.to_owned(),
+                        motion,
+                        speed: 1.0,
+                        looping: true,
+                    });
+                }
+                "point" => {
+                    let state = controller.last_state().map_err(error)?;
+                    match &mut state.motion {
+                        Motion::Blend1d { points, .. } => {
+                            let threshold =
+                                parse_float(argument(args, 0, "a threshold").map_err(error)?)
+                                    .map_err(error)?;
+                            let clip = argument(args, 1, "a clip").map_err(error)?;
+                            points.push((threshold, clip.to_owned()));
+                        }
+                        Motion::Blend2d { points, .. } => {
+                            let x = parse_float(argument(args, 0, "a position").map_err(error)?)
+                                .map_err(error)?;
+                            let y = parse_float(argument(args, 1, "a position").map_err(error)?)
+                                .map_err(error)?;
+                            let clip = argument(args, 2, "a clip").map_err(error)?;
+                            points.push((Vec2::new(x, y), clip.to_owned()));
+                        }
+                        Motion::Clip(_) => {
+                            return Err(error("Only blend states have points".to_owned()))
+                        }
+                    }
+                }
+                "once" => controller.last_state().map_err(error)?.looping = false,
+                "speed" => {
+                    let speed =
+                        parse_float(argument(args, 0, "a speed").map_err(error)?).map_err(error)?;
+                    controller.last_state().map_err(error)?.speed = speed;
+                }
+                "transition" => {
+                    controller.last_layer().map_err(error)?;
+                    pending.transitions.push((line_number, args.to_vec()));
+                }
+                _ => return Err(error(format!("Unknown declaration {:?}", keyword))),
+            }
+        }
+
+        controller.finish_layer(&mut pending)?;
+        Ok(controller)
+    }
+
+    pub fn find_parameter(&self, name: &str) -> Option<usize> {
+        self.parameters
+            .iter()
+            .position(|parameter| parameter.name == name)
+    }
+
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    fn last_layer(&mut self) -> Result<&mut AnimatorLayer, String> {
+        self.layers
+            .last_mut()
+            .ok_or_else(|| "Declared outside of a layer".to_owned())
+    }
+
+    fn last_state(&mut self) -> Result<&mut AnimatorState, String> {
+        self.last_layer()?
+            .states
+            .last_mut()
+            .ok_or_else(|| "Declared outside of a state".to_owned())
+    }
+
+    fn float_parameter(&self, name: Option<&&str>) -> Result<usize, String> {
+        let name = name.ok_or_else(|| "Expected a float parameter".to_owned())?;
+        match self.find_parameter(name) {
+            Some(parameter) => match self.parameters[parameter].default {
+                ParameterValue::Float(_) => Ok(parameter),
+                _ => Err(format!("Parameter {:?} isn't a float", name)),
+            },
+            None => Err(format!("Unknown parameter {:?}", name)),
+        }
+    }
+
+    /// Check the states of the last layer and resolve its transitions.
+    fn finish_layer(&mut self, pending: &mut PendingLayer) -> Result<(), AnimatorError> {
+        let pending = std::mem::take(pending);
+        let layer = match self.layers.last() {
+            Some(layer) => layer,
+            None => return Ok(()),
+        };
+        if layer.states.is_empty() {
+            return Err(AnimatorError::Parse {
+                line: pending.line,
+                message: format!("Layer {:?} has no state", layer.name),
+            });
+        }
+        for (state, line) in layer.states.iter().zip(pending.state_lines) {
+            let empty = match &state.motion {
+                Motion::Clip(_) => false,
+                Motion::Blend1d { points, .. } => points.is_empty(),
+                Motion::Blend2d { points, .. } => points.is_empty(),
+            };
+            if empty {
+                return Err(AnimatorError::Parse {
+                    line,
+                    message: format!("Blend state {:?} has no point", state.name),
+                });
+            }
+        }
+
+        let mut resolved = Vec::with_capacity(pending.transitions.len());
+        for (line, args) in pending.transitions {
+            resolved.push(
+                self.parse_transition(layer, &args)
+                    .map_err(|message| AnimatorError::Parse { line, message })?,
+            );
+        }
+
+        let layer = self.layers.last_mut().unwrap();
+        layer.transitions = resolved;
+        for state in &mut layer.states {
+            if let Motion::Blend1d { points, .. } = &mut state.motion {
+                points.sort_by(|a, b| a.0.total_cmp(&b.0));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_transition(
+        &self,
+        layer: &AnimatorLayer,
+        args: &[&str],
+    ) -> Result<StateTransition, String> {
+        let find_state = |name: &str| {
+            layer
+                .states
+                .iter()
+                .position(|state| state.name == name)
+                .ok_or_else(|| format!("Unknown state {:?}", name))
+        };
+
+        let from = match argument(args, 0, "a state to leave")? {
+            "any" => None,
+            name => Some(find_state(name)?),
+        };
+        let to = find_state(argument(args, 1, "a state to go to")?)?;
+        let duration = parse_float(argument(args, 2, "a crossfade duration")?)?;
+
+        let mut exit_time = None;
+        let mut conditions = Vec::new();
+        let mut tokens = args[3..].iter();
+        while let Some(token) = tokens.next() {
+            if *token == "exit" {
+                let time = tokens
+                    .next()
+                    .ok_or_else(|| "Expected an exit time".to_owned())?;
+                exit_time = Some(parse_float(time)?);
+                continue;
+            }
+
+            let (name, negated) = match token.strip_prefix('!') {
+                Some(name) => (name, true),
+                None => (*token, false),
+            };
+            let parameter = self
+                .find_parameter(name)
+                .ok_or_else(|| format!("Unknown parameter {:?}", name))?;
+            let comparison = match self.parameters[parameter].default {
+                ParameterValue::Float(_) => {
+                    let operator = tokens.next().copied();
+                    let value = tokens
+                        .next()
+                        .ok_or_else(|| format!("Expected a value to compare {:?} with", name))?;
+                    let value = parse_float(value)?;
+                    match (operator, negated) {
+                        (Some(">"), false) => Comparison::Greater(value),
+                        (Some("<"), false) => Comparison::Less(value),
+                        _ => return Err(format!("Expected > or < after {:?}", name)),
+                    }
+                }
+                ParameterValue::Bool(_) if negated => Comparison::IsFalse,
+                ParameterValue::Bool(_) => Comparison::IsTrue,
+                ParameterValue::Trigger(_) if negated => {
+                    return Err(format!("Trigger {:?} can't be negated", name))
+                }
+                ParameterValue::Trigger(_) => Comparison::Triggered,
+            };
+            conditions.push(Condition {
+                parameter,
+                comparison,
+            });
+        }
+
+        Ok(StateTransition {
+            from,
+            to,
+            duration,
+            exit_time,
+            conditions,
+        })
+    }
+}
+
+/// Declarations of the layer being parsed, checked once it is complete.
+#[derive(Default)]
+struct PendingLayer<'a> {
+    line: usize,
+    state_lines: Vec<usize>,
+    /// Can name states declared after them
+    transitions: Vec<(usize, Vec<&'a str>)>,
+}
+
+fn argument<'a>(args: &[&'a str], index: usize, expected: &str) -> Result<&'a str, String> {
+    args.get(index)
+        .copied()
+        .ok_or_else(|| format!("Expected {}", expected))
+}
+
+fn parse_float(token: &str) -> Result<f32, String> {
+    token
+        .parse()
+        .map_err(|_| format!("Expected a number, got {:?}", token))
+}
+
+fn parse_bool(token: &str) -> Result<bool, String> {
+    match token {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Expected true or false, got {:?}", token)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The example of [AnimatorController::parse].
+    const EXAMPLE: &str = "
+# Parameters, before they are used, with an optional default
+float speed 0
+bool grounded true
+trigger jump
+float aim_x
+float aim_y
+
+layer base
+# The first state of a layer is its default one
+state idle clip Idle
+state move blend1d speed
+point 1.5 Walk
+point 5 Run
+state jump clip Jump
+once
+speed 1.2
+# From, to, seconds of crossfade, then the optional exit time and conditions
+transition idle move 0.2 speed > 0.1
+transition move idle 0.2 speed < 0.1
+transition any jump 0.1 jump
+transition jump idle 0.3 exit 0.9 grounded
+
+layer upper_body
+weight 0.8
+mask Spine2
+state aim blend2d aim_x aim_y
+point 0 0 AimCenter
+point 1 0 AimRight
+";
+
+    fn parse_error(source: &str) -> (usize, String) {
+        match AnimatorController::parse(source) {
+            Err(AnimatorError::Parse { line, message }) => (line, message),
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(_) => panic!("Parsed an invalid controller"),
+        }
+    }
+
+    #[test]
+    fn parse_documented_example() {
+        let controller = AnimatorController::parse(EXAMPLE).unwrap();
+
+        let defaults: Vec<_> = controller
+            .parameters
+            .iter()
+            .map(|parameter| (&parameter.name[..], parameter.default))
+            .collect();
+        assert_eq!(
+            defaults,
+            [
+                ("speed", ParameterValue::Float(0.0)),
+                ("grounded", ParameterValue::Bool(true)),
+                ("jump", ParameterValue::Trigger(false)),
+                ("aim_x", ParameterValue::Float(0.0)),
+                ("aim_y", ParameterValue::Float(0.0)),
+            ]
+        );
+        assert_eq!(controller.find_layer("upper_body"), Some(1));
+
+        let base = &controller.layers[0];
+        assert_eq!(base.default_state, 0);
+        assert_eq!(base.weight, 1.0);
+        assert!(base.mask.is_empty());
+        let names: Vec<_> = base.states.iter().map(|state| &state.name[..]).collect();
+        assert_eq!(names, ["idle", "move", "jump"]);
+        assert_eq!(base.states[1].motion.clips(), ["Walk", "Run"]);
+        assert!(!base.states[2].looping);
+        assert_eq!(base.states[2].speed, 1.2);
+
+        let transitions: Vec<_> = base
+            .transitions
+            .iter()
+            .map(|transition| (transition.from, transition.to, transition.exit_time))
+            .collect();
+        assert_eq!(
+            transitions,
+            [
+                (Some(0), 1, None),
+                (Some(1), 0, None),
+                (None, 2, None),
+                (Some(2), 0, Some(0.9)),
+            ]
+        );
+        let condition = |index: usize| base.transitions[index].conditions[..].to_vec();
+        assert_eq!(
+            condition(0),
+            [Condition {
+                parameter: 0,
+                comparison: Comparison::Greater(0.1),
+            }]
+        );
+        assert_eq!(
+            condition(2),
+            [Condition {
+                parameter: 2,
+                comparison: Comparison::Triggered,
+            }]
+        );
+        assert_eq!(
+            condition(3),
+            [Condition {
+                parameter: 1,
+                comparison: Comparison::IsTrue,
+            }]
+        );
+
+        let upper_body = &controller.layers[1];
+        assert_eq!(upper_body.weight, 0.8);
+        assert_eq!(upper_body.mask, ["Spine2"]);
+        match &upper_body.states[0].motion {
+            Motion::Blend2d { x, y, points } => {
+                assert_eq!((*x, *y), (3, 4));
+                assert_eq!(points[1], (Vec2::new(1.0, 0.0), "AimRight".to_owned()));
+            }
+            motion => panic!("Expected a 2D blend space, got {:?}", motion),
+        }
+    }
+
+    #[test]
+    fn blend1d_points_are_sorted() {
+        let controller = AnimatorController::parse(
+            "float speed\nlayer base\nstate move blend1d speed\npoint 5 Run\npoint 1.5 Walk",
+        )
+        .unwrap();
+        assert_eq!(
+            controller.layers[0].states[0].motion.clips(),
+            ["Walk", "Run"]
+        );
+    }
+
+    #[test]
+    fn unknown_state() {
+        let (line, message) =
+            parse_error("layer base\nstate idle clip Idle\ntransition idle run 0.2\n");
+        assert_eq!(line, 3);
+        assert_eq!(message, "Unknown state \"run\"");
+    }
+
+    #[test]
+    fn bad_conditions() {
+        let layer =
+            "float speed\ntrigger jump\nlayer base\nstate idle clip Idle\nstate run clip Run\n";
+        let error = |transition: &str| parse_error(&format!("{}{}", layer, transition));
+
+        assert_eq!(
+            error("transition idle run 0.2 speed = 1"),
+            (6, "Expected > or < after \"speed\"".to_owned())
+        );
+        assert_eq!(
+            error("transition idle run 0.2 speed >"),
+            (6, "Expected a value to compare \"speed\" with".to_owned())
+        );
+        assert_eq!(
+            error("transition idle run 0.2 !jump"),
+            (6, "Trigger \"jump\" can't be negated".to_owned())
+        );
+        assert_eq!(
+            error("transition idle run 0.2 running"),
+            (6, "Unknown parameter \"running\"".to_owned())
+        );
+        assert_eq!(
+            error("transition idle run 0.2 exit"),
+            (6, "Expected an exit time".to_owned())
+        );
+    }
+
+    #[test]
+    fn bad_blend_space_entries() {
+        let header = "float speed\nfloat turn\nbool grounded\nlayer base\n";
+        let error = |states: &str| parse_error(&format!("{}{}", header, states));
+
+        assert_eq!(
+            error("state move blend1d speed\npoint fast Walk"),
+            (6, "Expected a number, got \"fast\"".to_owned())
+        );
+        assert_eq!(
+            error("state move blend1d speed\npoint 1"),
+            (6, "Expected a clip".to_owned())
+        );
+        assert_eq!(
+            error("state move blend2d speed turn\npoint 0 0"),
+            (6, "Expected a clip".to_owned())
+        );
+        assert_eq!(
+            error("state move blend1d grounded"),
+            (5, "Parameter \"grounded\" isn't a float".to_owned())
+        );
+        assert_eq!(
+            error("state idle clip Idle\npoint 0 Walk"),
+            (6, "Only blend states have points".to_owned())
+        );
+        assert_eq!(
+            error("state move blend1d speed\nstate idle clip Idle"),
+            (5, "Blend state \"move\" has no point".to_owned())
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use crate::audio::{Sound, SpatialSettings, VoiceSettings};
+
+/// Makes a scene node play a sound from its position, heard from the active camera.
+#[derive(Clone)]
+pub struct AudioEmitter {
+    pub sound: Sound,
+    /// The pan is added to the one from the position
+    pub settings: VoiceSettings,
+    pub spatial: SpatialSettings,
+    /// From 0 when nothing is in the way to 1 when fully blocked, muffles and quiets the sound
+    pub occlusion: f32,
+    /// Cutoff frequency in Hz of a low-pass filter, on top of the occlusion one
+    pub low_pass: Option<f32>,
+    /// The sound starts when set and stops when cleared, cleared once a sound that doesn't
+    /// loop ends
+    pub playing: bool,
+}
+
+impl AudioEmitter {
+    /// Starts playing on the next audio update.
+    pub fn new(sound: Sound) -> Self {
+        Self {
+            sound,
+            settings: VoiceSettings::default(),
+            spatial: SpatialSettings::default(),
+            occlusion: 0.0,
+            low_pass: None,
+            playing: true,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: VoiceSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_spatial(mut self, spatial: SpatialSettings) -> Self {
+        self.spatial = spatial;
+        self
+    }
+
+    pub fn with_low_pass(mut self, cutoff: f32) -> Self {
+        self.low_pass = Some(cutoff);
+        self
+    }
+}
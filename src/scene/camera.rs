@@ -0,0 +1,97 @@
+use crate::math::{Frustum, Mat4, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    /// Vertical field of view in radians
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    /// Height of the view volume, the width follows the aspect ratio
+    Orthographic { height: f32, near: f32, far: f32 },
+}
+
+/// Camera attached to a scene node, it looks down the node's -Z axis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::perspective(60f32.to_radians(), 0.1, 1000.0)
+    }
+}
+
+impl Camera {
+    #[inline]
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: Projection::Perspective { fov_y, near, far },
+        }
+    }
+
+    #[inline]
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: Projection::Orthographic { height, near, far },
+        }
+    }
+
+    pub fn near(&self) -> f32 {
+        match self.projection {
+            Projection::Perspective { near, .. } | Projection::Orthographic { near, .. } => near,
+        }
+    }
+
+    pub fn far(&self) -> f32 {
+        match self.projection {
+            Projection::Perspective { far, .. } | Projection::Orthographic { far, .. } => far,
+        }
+    }
+
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov_y, near, far } => {
+                Mat4::perspective(fov_y, aspect_ratio, near, far)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect_ratio;
+                Mat4::orthographic(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+}
+
+/// Matrices of the active camera for the frame being rendered.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraView {
+    /// World space
+    pub position: Vec3,
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    pub frustum: Frustum,
+}
+
+impl CameraView {
+    /// `world` is the world matrix of the camera's node.
+    pub fn new(camera: &Camera, world: &Mat4, aspect_ratio: f32) -> Self {
+        let view = world.inverse();
+        let projection = camera.projection_matrix(aspect_ratio);
+        let view_projection = projection * view;
+
+        Self {
+            position: world.translation(),
+            view,
+            projection,
+            view_projection,
+            frustum: Frustum::from_matrix(&view_projection),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::math::Vec3;
+use crate::renderer::environment::Environment;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away, like the sun, shining down the node's -Z axis
+    Directional,
+    /// Shines in every direction, fading out up to `range`
+    Point { range: f32 },
+    /// Cone along the node's -Z axis, angles from the axis in radians.
+    /// Full intensity inside the inner angle, fading to nothing at the outer one
+    Spot {
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// Shadow casting settings of a light, point lights don't cast shadows.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightShadow {
+    /// World units the receiving surface is pushed towards the light, against acne
+    pub depth_bias: f32,
+    /// World units the receiving surface is pushed along its normal, grows with the
+    /// slope relative to the light
+    pub normal_bias: f32,
+}
+
+impl Default for LightShadow {
+    fn default() -> Self {
+        Self {
+            depth_bias: 0.02,
+            normal_bias: 0.03,
+        }
+    }
+}
+
+/// Light attached to a scene node, which gives its position and direction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear RGB
+    pub color: Vec3,
+    pub intensity: f32,
+    pub shadow: Option<LightShadow>,
+}
+
+impl Light {
+    #[inline]
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            shadow: None,
+        }
+    }
+
+    #[inline]
+    pub fn point(color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point { range },
+            color,
+            intensity,
+            shadow: None,
+        }
+    }
+
+    #[inline]
+    pub fn spot(
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            },
+            color,
+            intensity,
+            shadow: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_shadow(mut self, shadow: LightShadow) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+}
+
+/// Ambient light blending between a sky color above and a ground color below.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HemisphereLight {
+    pub sky_color: Vec3,
+    pub ground_color: Vec3,
+    pub intensity: f32,
+}
+
+impl Default for HemisphereLight {
+    fn default() -> Self {
+        Self {
+            sky_color: Vec3::new(0.6, 0.7, 0.9),
+            ground_color: Vec3::new(0.3, 0.25, 0.2),
+            intensity: 0.3,
+        }
+    }
+}
+
+/// Image based lighting from a baked environment, replaces the hemisphere ambient.
+#[derive(Clone)]
+pub struct EnvironmentLight {
+    pub environment: Arc<Environment>,
+    pub intensity: f32,
+    /// Draw the environment behind the scene
+    pub skybox: bool,
+}
+
+impl EnvironmentLight {
+    pub fn new(environment: Arc<Environment>) -> Self {
+        Self {
+            environment,
+            intensity: 1.0,
+            skybox: true,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::math::Vec4;
+use crate::renderer::material::MaterialId;
+use crate::renderer::mesh::Mesh;
+use std::sync::Arc;
+
+/// Makes a scene node display a mesh.
+#[derive(Clone)]
+pub struct MeshRenderer {
+    pub mesh: Arc<Mesh>,
+    pub material: MaterialId,
+    /// Multiplied with the color of the material
+    pub tint: Vec4,
+    /// Free for the material's shaders to use
+    pub params: Vec4,
+}
+
+impl MeshRenderer {
+    pub fn new(mesh: Arc<Mesh>) -> Self {
+        Self {
+            mesh,
+            material: MaterialId::DEFAULT,
+            tint: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            params: Vec4::ZERO,
+        }
+    }
+
+    #[inline]
+    pub fn with_material(mut self, material: MaterialId) -> Self {
+        self.material = material;
+        self
+    }
+
+    #[inline]
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    #[inline]
+    pub fn with_params(mut self, params: Vec4) -> Self {
+        self.params = params;
+        self
+    }
+}
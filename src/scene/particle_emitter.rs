@@ -0,0 +1,198 @@
+use crate::math::{Vec3, Vec4};
+use crate::renderer::texture::Texture;
+use std::sync::Arc;
+
+/// Value changing over the life of a particle, linearly interpolated between keys.
+/// The time of the keys goes from 0 at birth to 1 at death.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+    /// Sorted by time
+    keys: Vec<(f32, T)>,
+}
+
+/// What a curve can interpolate.
+pub trait CurveValue: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl CurveValue for f32 {
+    #[inline]
+    fn lerp(self, other: f32, t: f32) -> f32 {
+        self + (other - self) * t
+    }
+}
+
+impl CurveValue for Vec3 {
+    #[inline]
+    fn lerp(self, other: Vec3, t: f32) -> Vec3 {
+        Vec3::lerp(self, other, t)
+    }
+}
+
+impl CurveValue for Vec4 {
+    #[inline]
+    fn lerp(self, other: Vec4, t: f32) -> Vec4 {
+        Vec4::lerp(self, other, t)
+    }
+}
+
+impl<T: CurveValue> Curve<T> {
+    /// The keys don't have to be sorted, there has to be at least one.
+    pub fn new(keys: &[(f32, T)]) -> Self {
+        assert!(!keys.is_empty(), "A curve needs at least one key !");
+        let mut keys = keys.to_vec();
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { keys }
+    }
+
+    #[inline]
+    pub fn constant(value: T) -> Self {
+        Self {
+            keys: vec![(0.0, value)],
+        }
+    }
+
+    /// From one value at birth to another at death.
+    #[inline]
+    pub fn linear(start: T, end: T) -> Self {
+        Self {
+            keys: vec![(0.0, start), (1.0, end)],
+        }
+    }
+
+    /// Before the first key and after the last one, the value of the closest key.
+    pub fn sample(&self, t: f32) -> T {
+        let next = self.keys.iter().position(|(time, _)| *time > t);
+        match next {
+            Some(0) => self.keys[0].1,
+            Some(index) => {
+                let (start_time, start) = self.keys[index - 1];
+                let (end_time, end) = self.keys[index];
+                start.lerp(end, (t - start_time) / (end_time - start_time))
+            }
+            None => self.keys[self.keys.len() - 1].1,
+        }
+    }
+}
+
+/// Volume the particles spawn in, in the space of the emitter's node.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EmitterShape {
+    Point,
+    Sphere { radius: f32 },
+    Box { half_extents: Vec3 },
+}
+
+/// Forces applied to every particle of an emitter, in world space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ParticleForces {
+    /// Constant acceleration
+    pub gravity: Vec3,
+    /// How fast the particles take the velocity of the wind, per second
+    pub drag: f32,
+    /// Velocity of the air
+    pub wind: Vec3,
+    /// Strength of the swirling noise acceleration
+    pub turbulence: f32,
+    /// Pulls the particles towards a point, or pushes them away when negative
+    pub attractor: Option<(Vec3, f32)>,
+}
+
+impl Default for ParticleForces {
+    fn default() -> Self {
+        Self {
+            gravity: Vec3::ZERO,
+            drag: 0.0,
+            wind: Vec3::ZERO,
+            turbulence: 0.0,
+            attractor: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ParticleBlend {
+    /// Covers what is behind, like smoke
+    #[default]
+    Alpha,
+    /// Adds light to what is behind, like sparks or fire
+    Additive,
+}
+
+/// Everything about how an emitter spawns and animates its particles.
+/// Shared between the emitters of the same effect.
+#[derive(Clone)]
+pub struct ParticleEffect {
+    /// Most particles alive at once, spawning stops while it is reached
+    pub capacity: u32,
+    /// Particles per second
+    pub spawn_rate: f32,
+    pub shape: EmitterShape,
+    /// Seconds, each particle lives a random time between the two
+    pub lifetime: (f32, f32),
+    /// At birth, in the space of the emitter's node
+    pub velocity: Vec3,
+    /// Radians, each particle goes in a random direction within this cone around the velocity
+    pub spread: f32,
+    /// Fraction of the speed each particle randomly loses or gains at birth
+    pub speed_variation: f32,
+    /// Radians per second, each particle spins at a random speed up to it in either direction
+    pub spin: f32,
+    pub forces: ParticleForces,
+    /// Linear color and alpha, can go over 1 for glowing particles
+    pub color_over_life: Curve<Vec4>,
+    /// World units, width and height of the particles
+    pub size_over_life: Curve<f32>,
+    /// Multiplies the velocity, to slow down or speed up the particles as they age
+    pub speed_over_life: Curve<f32>,
+    pub blend: ParticleBlend,
+    /// Multiplied with the color, a soft dot without
+    pub texture: Option<Arc<Texture>>,
+}
+
+impl Default for ParticleEffect {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            spawn_rate: 64.0,
+            shape: EmitterShape::Point,
+            lifetime: (1.0, 2.0),
+            velocity: Vec3::new(0.0, 1.0, 0.0),
+            spread: 0.3,
+            speed_variation: 0.2,
+            spin: 0.0,
+            forces: ParticleForces::default(),
+            color_over_life: Curve::linear(Vec4::new(1.0, 1.0, 1.0, 1.0), Vec4::ZERO),
+            size_over_life: Curve::constant(0.1),
+            speed_over_life: Curve::constant(1.0),
+            blend: ParticleBlend::Alpha,
+            texture: None,
+        }
+    }
+}
+
+/// Makes a scene node emit particles, simulated on the GPU.
+#[derive(Clone)]
+pub struct ParticleEmitter {
+    pub effect: Arc<ParticleEffect>,
+    /// Spawning stops when false, the particles alive keep going until they die
+    pub emitting: bool,
+    /// Particles spawned at once on the next frame, on top of the spawn rate
+    pub burst: u32,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect: Arc<ParticleEffect>) -> Self {
+        Self {
+            effect,
+            emitting: true,
+            burst: 0,
+        }
+    }
+
+    /// Spawn a number of particles at once on the next frame.
+    #[inline]
+    pub fn burst(&mut self, count: u32) {
+        self.burst += count;
+    }
+}
@@ -0,0 +1,599 @@
+use crate::math::{Mat4, Vec3};
+use crate::scene::Transform;
+use std::error::Error;
+use std::fmt;
+
+/// Handle to a node of a [SceneGraph].
+/// Stays invalid once the node is removed, even if its slot gets reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+struct Node {
+    name: String,
+    local: Transform,
+    /// Cached result of `parent world * local`, only valid when not dirty
+    world: Mat4,
+    /// When a node is dirty, all of its descendants are too
+    dirty: bool,
+    /// Some descendants may be dirty even though the node isn't, for update to find them
+    dirty_descendants: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+/// Hierarchy of transforms.
+///
+/// World matrices are computed lazily: changing a local transform only flags the node and
+/// its subtree as dirty, the matrices are recomputed when asked for or on [update](SceneGraph::update).
+#[derive(Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+    roots: Vec<NodeId>,
+    len: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneGraphError {
+    /// The node was removed or belongs to another graph
+    InvalidNode(NodeId),
+    /// The new parent is the node itself or one of its descendants
+    Cycle { node: NodeId, parent: NodeId },
+}
+
+impl fmt::Display for SceneGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneGraphError::InvalidNode(node) => write!(f, "Invalid scene node {:?}", node),
+            SceneGraphError::Cycle { node, parent } => write!(
+                f,
+                "Can't parent {:?} to {:?}, it is one of its descendants",
+                node, parent
+            ),
+        }
+    }
+}
+
+impl Error for SceneGraphError {}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Add a node at the root of the graph.
+    pub fn add_node(&mut self, name: &str, transform: Transform) -> NodeId {
+        let id = self.insert(name, transform, None);
+        self.roots.push(id);
+        id
+    }
+
+    pub fn add_child(
+        &mut self,
+        parent: NodeId,
+        name: &str,
+        transform: Transform,
+    ) -> Result<NodeId, SceneGraphError> {
+        if !self.contains(parent) {
+            return Err(SceneGraphError::InvalidNode(parent));
+        }
+
+        let id = self.insert(name, transform, Some(parent));
+        self.node_mut(parent).children.push(id);
+        self.flag_ancestors(id);
+        Ok(id)
+    }
+
+    /// Remove a node and its whole subtree.
+    pub fn remove(&mut self, id: NodeId) -> Result<(), SceneGraphError> {
+        let parent = self.get(id).ok_or(SceneGraphError::InvalidNode(id))?.parent;
+        self.detach(id, parent);
+
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            let slot = &mut self.slots[current.index as usize];
+            let node = slot.node.take().unwrap();
+            slot.generation = slot.generation.wrapping_add(1);
+
+            self.free_slots.push(current.index);
+            self.len -= 1;
+            stack.extend(node.children);
+        }
+
+        Ok(())
+    }
+
+    /// Move a node under another one, or to the root with `None`.
+    /// When `keep_world` is set the local transform is changed so that the node doesn't move,
+    /// otherwise the local transform is kept and the node follows its new parent.
+    pub fn set_parent(
+        &mut self,
+        id: NodeId,
+        parent: Option<NodeId>,
+        keep_world: bool,
+    ) -> Result<(), SceneGraphError> {
+        let old_parent = self.get(id).ok_or(SceneGraphError::InvalidNode(id))?.parent;
+
+        if let Some(parent) = parent {
+            if !self.contains(parent) {
+                return Err(SceneGraphError::InvalidNode(parent));
+            }
+            if self.is_ancestor_or_self(id, parent) {
+                return Err(SceneGraphError::Cycle { node: id, parent });
+            }
+        }
+
+        if old_parent == parent {
+            return Ok(());
+        }
+
+        if keep_world {
+            let world = self.world_matrix(id);
+            let parent_world = parent.map_or(Mat4::IDENTITY, |parent| self.world_matrix(parent));
+            self.node_mut(id).local = Transform::from_matrix(&(parent_world.inverse() * world));
+        }
+
+        self.detach(id, old_parent);
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+        self.node_mut(id).parent = parent;
+        self.mark_dirty(id);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    #[inline]
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    #[inline]
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    #[inline]
+    pub fn name(&self, id: NodeId) -> &str {
+        &self.node(id).name
+    }
+
+    /// First node with this name, in hierarchy order.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.iter()
+            .map(|(id, _)| id)
+            .find(|id| self.node(*id).name == name)
+    }
+
+    #[inline]
+    pub fn local_transform(&self, id: NodeId) -> &Transform {
+        &self.node(id).local
+    }
+
+    /// The node is flagged as dirty even if the transform isn't actually changed.
+    pub fn local_transform_mut(&mut self, id: NodeId) -> &mut Transform {
+        self.mark_dirty(id);
+        &mut self.node_mut(id).local
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, transform: Transform) {
+        *self.local_transform_mut(id) = transform;
+    }
+
+    /// World matrix of the node, recomputed only if it or one of its ancestors changed.
+    pub fn world_matrix(&mut self, id: NodeId) -> Mat4 {
+        if self.node(id).dirty {
+            // Clean nodes only have clean ancestors, so stop at the first one
+            let mut chain = vec![id];
+            let mut current = self.node(id).parent;
+            while let Some(parent) = current {
+                let node = self.node(parent);
+                if !node.dirty {
+                    break;
+                }
+                chain.push(parent);
+                current = node.parent;
+            }
+
+            for node in chain.into_iter().rev() {
+                self.refresh(node);
+            }
+        }
+
+        self.node(id).world
+    }
+
+    /// World matrix computed during the last update, if nothing changed since then.
+    #[inline]
+    pub fn cached_world_matrix(&self, id: NodeId) -> Option<&Mat4> {
+        let node = self.node(id);
+        if node.dirty {
+            None
+        } else {
+            Some(&node.world)
+        }
+    }
+
+    #[inline]
+    pub fn world_position(&mut self, id: NodeId) -> Vec3 {
+        self.world_matrix(id).translation()
+    }
+
+    /// Recompute every dirty world matrix, parents before children.
+    pub fn update(&mut self) {
+        let mut stack: Vec<_> = self.roots.iter().rev().copied().collect();
+        while let Some(id) = stack.pop() {
+            let node = self.node(id);
+            // Everything below a clean node without dirty descendants is up to date already
+            if node.dirty || node.dirty_descendants {
+                stack.extend(node.children.iter().rev());
+                if node.dirty {
+                    self.refresh(id);
+                }
+                self.node_mut(id).dirty_descendants = false;
+            }
+        }
+    }
+
+    /// Every node with its depth, parents before their children and siblings in insertion order.
+    pub fn iter(&self) -> HierarchyIter<'_> {
+        HierarchyIter {
+            graph: self,
+            stack: self.roots.iter().rev().map(|id| (*id, 0)).collect(),
+        }
+    }
+
+    /// A node and all of its descendants, in hierarchy order.
+    pub fn iter_subtree(&self, id: NodeId) -> HierarchyIter<'_> {
+        HierarchyIter {
+            graph: self,
+            stack: vec![(id, 0)],
+        }
+    }
+
+    fn insert(&mut self, name: &str, transform: Transform, parent: Option<NodeId>) -> NodeId {
+        let node = Node {
+            name: name.to_owned(),
+            local: transform,
+            world: Mat4::IDENTITY,
+            dirty: true,
+            dirty_descendants: false,
+            parent,
+            children: Vec::new(),
+        };
+        self.len += 1;
+
+        if let Some(index) = self.free_slots.pop() {
+            let slot = &mut self.slots[index as usize];
+            slot.node = Some(node);
+            NodeId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            self.slots.push(Slot {
+                generation: 0,
+                node: Some(node),
+            });
+            NodeId {
+                index: self.slots.len() as u32 - 1,
+                generation: 0,
+            }
+        }
+    }
+
+    /// Remove the node from the children of its parent, or from the roots.
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let siblings = match parent {
+            Some(parent) => &mut self.node_mut(parent).children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|sibling| *sibling != id);
+    }
+
+    fn is_ancestor_or_self(&self, ancestor: NodeId, mut id: NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.node(id).parent {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            let node = self.node_mut(current);
+            // Already dirty means the whole subtree is too
+            if !node.dirty {
+                node.dirty = true;
+                stack.extend(node.children.iter().copied());
+            }
+        }
+        self.flag_ancestors(id);
+    }
+
+    /// Lead update to a dirty node below clean ones.
+    fn flag_ancestors(&mut self, id: NodeId) {
+        let mut current = self.node(id).parent;
+        while let Some(parent) = current {
+            let node = self.node_mut(parent);
+            // Their own ancestors are flagged or dirty already
+            if node.dirty || node.dirty_descendants {
+                break;
+            }
+            node.dirty_descendants = true;
+            current = node.parent;
+        }
+    }
+
+    /// Recompute the world matrix of a node whose parent is up to date.
+    fn refresh(&mut self, id: NodeId) {
+        let parent_world = self
+            .node(id)
+            .parent
+            .map_or(Mat4::IDENTITY, |parent| self.node(parent).world);
+
+        let node = self.node_mut(id);
+        node.world = parent_world * node.local.matrix();
+        node.dirty = false;
+        // The children were dirty along with the node, and world_matrix only refreshes one
+        node.dirty_descendants = !node.children.is_empty();
+    }
+
+    #[inline]
+    fn get(&self, id: NodeId) -> Option<&Node> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    #[inline]
+    fn node(&self, id: NodeId) -> &Node {
+        self.get(id).expect("Invalid scene node !")
+    }
+
+    #[inline]
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .expect("Invalid scene node !")
+    }
+}
+
+/// Depth first iterator over a [SceneGraph], see [iter](SceneGraph::iter).
+pub struct HierarchyIter<'a> {
+    graph: &'a SceneGraph,
+    stack: Vec<(NodeId, usize)>,
+}
+
+impl<'a> Iterator for HierarchyIter<'a> {
+    /// The node and its depth, starting at 0 for the first node
+    type Item = (NodeId, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, depth) = self.stack.pop()?;
+        let children = &self.graph.node(id).children;
+        self.stack
+            .extend(children.iter().rev().map(|child| (*child, depth + 1)));
+        Some((id, depth))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Quat;
+
+    fn at(x: f32, y: f32, z: f32) -> Transform {
+        Transform::from_translation(Vec3::new(x, y, z))
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            (actual - expected).length() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    fn cached_position(graph: &SceneGraph, id: NodeId) -> Vec3 {
+        graph
+            .cached_world_matrix(id)
+            .expect("World matrix not updated")
+            .translation()
+    }
+
+    #[test]
+    fn moving_a_parent_moves_its_subtree() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("root", at(1.0, 0.0, 0.0));
+        let child = graph.add_child(root, "child", at(0.0, 2.0, 0.0)).unwrap();
+        let grandchild = graph
+            .add_child(child, "grandchild", at(0.0, 0.0, 3.0))
+            .unwrap();
+        graph.update();
+        assert_near(
+            cached_position(&graph, grandchild),
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+
+        graph
+            .local_transform_mut(root)
+            .translate(Vec3::new(10.0, 0.0, 0.0));
+        assert!(graph.cached_world_matrix(child).is_none());
+        assert!(graph.cached_world_matrix(grandchild).is_none());
+        graph.update();
+        assert_near(cached_position(&graph, child), Vec3::new(11.0, 2.0, 0.0));
+        assert_near(
+            cached_position(&graph, grandchild),
+            Vec3::new(11.0, 2.0, 3.0),
+        );
+    }
+
+    #[test]
+    fn update_finds_dirty_nodes_under_clean_ones() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("root", Transform::IDENTITY);
+        let parent = graph.add_child(root, "parent", at(1.0, 0.0, 0.0)).unwrap();
+        let camera = graph
+            .add_child(parent, "camera", at(0.0, 1.0, 0.0))
+            .unwrap();
+        let mesh = graph.add_child(parent, "mesh", at(0.0, 0.0, 1.0)).unwrap();
+        graph.update();
+
+        // Refreshes the parent on the way, but not its other child
+        graph.local_transform_mut(parent).translate(Vec3::X);
+        assert_near(graph.world_position(camera), Vec3::new(2.0, 1.0, 0.0));
+        assert!(graph.cached_world_matrix(parent).is_some());
+        assert!(graph.cached_world_matrix(mesh).is_none());
+        graph.update();
+        assert_near(cached_position(&graph, mesh), Vec3::new(2.0, 0.0, 1.0));
+
+        // Only a leaf moved
+        graph.local_transform_mut(mesh).translate(Vec3::Z);
+        graph.update();
+        assert_near(cached_position(&graph, mesh), Vec3::new(2.0, 0.0, 2.0));
+
+        // Added under an up to date parent
+        let light = graph
+            .add_child(camera, "light", at(0.0, 0.0, -1.0))
+            .unwrap();
+        graph.update();
+        assert_near(cached_position(&graph, light), Vec3::new(2.0, 1.0, -1.0));
+    }
+
+    #[test]
+    fn reparent_keeping_the_world_transform() {
+        let mut graph = SceneGraph::new();
+        let first = graph.add_node("first", at(5.0, 0.0, 0.0));
+        let second = graph.add_node(
+            "second",
+            at(0.0, 3.0, 0.0).with_rotation(Quat::from_axis_angle(Vec3::Y, 1.0)),
+        );
+        let node = graph.add_child(first, "node", at(1.0, 1.0, 1.0)).unwrap();
+
+        graph.set_parent(node, Some(second), true).unwrap();
+        assert_eq!(graph.parent(node), Some(second));
+        assert_eq!(graph.children(first), &[] as &[NodeId]);
+        assert_eq!(graph.children(second), &[node]);
+        assert_near(graph.world_position(node), Vec3::new(6.0, 1.0, 1.0));
+
+        graph.set_parent(node, None, true).unwrap();
+        assert_eq!(graph.roots(), &[first, second, node]);
+        assert_near(graph.world_position(node), Vec3::new(6.0, 1.0, 1.0));
+        assert_near(
+            graph.local_transform(node).translation,
+            Vec3::new(6.0, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn reparent_keeping_the_local_transform() {
+        let mut graph = SceneGraph::new();
+        let first = graph.add_node("first", at(5.0, 0.0, 0.0));
+        let second = graph.add_node("second", at(0.0, 3.0, 0.0));
+        let node = graph.add_child(first, "node", at(1.0, 1.0, 1.0)).unwrap();
+        graph.update();
+
+        graph.set_parent(node, Some(second), false).unwrap();
+        assert_eq!(graph.local_transform(node), &at(1.0, 1.0, 1.0));
+        graph.update();
+        assert_near(cached_position(&graph, node), Vec3::new(1.0, 4.0, 1.0));
+    }
+
+    #[test]
+    fn reparent_under_a_descendant_is_a_cycle() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("root", Transform::IDENTITY);
+        let child = graph.add_child(root, "child", Transform::IDENTITY).unwrap();
+
+        assert_eq!(
+            graph.set_parent(root, Some(child), false),
+            Err(SceneGraphError::Cycle {
+                node: root,
+                parent: child
+            })
+        );
+        assert_eq!(
+            graph.set_parent(root, Some(root), true),
+            Err(SceneGraphError::Cycle {
+                node: root,
+                parent: root
+            })
+        );
+        assert_eq!(graph.parent(child), Some(root));
+    }
+
+    #[test]
+    fn iterate_in_hierarchy_order() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add_node("a", Transform::IDENTITY);
+        let b = graph.add_node("b", Transform::IDENTITY);
+        let a1 = graph.add_child(a, "a1", Transform::IDENTITY).unwrap();
+        let a2 = graph.add_child(a, "a2", Transform::IDENTITY).unwrap();
+        let a11 = graph.add_child(a1, "a11", Transform::IDENTITY).unwrap();
+        let b1 = graph.add_child(b, "b1", Transform::IDENTITY).unwrap();
+
+        let order: Vec<_> = graph.iter().collect();
+        assert_eq!(order, [(a, 0), (a1, 1), (a11, 2), (a2, 1), (b, 0), (b1, 1)]);
+        let subtree: Vec<_> = graph.iter_subtree(a1).map(|(id, _)| id).collect();
+        assert_eq!(subtree, [a1, a11]);
+        assert_eq!(graph.find("a2"), Some(a2));
+    }
+
+    #[test]
+    fn removed_nodes_stay_invalid() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("root", Transform::IDENTITY);
+        let child = graph.add_child(root, "child", Transform::IDENTITY).unwrap();
+        graph.remove(root).unwrap();
+        assert!(graph.is_empty());
+        assert!(!graph.contains(child));
+
+        // Reuses the slot with another generation
+        let other = graph.add_node("other", Transform::IDENTITY);
+        assert!(!graph.contains(root));
+        assert!(graph.contains(other));
+        assert_eq!(
+            graph.remove(child),
+            Err(SceneGraphError::InvalidNode(child))
+        );
+    }
+}
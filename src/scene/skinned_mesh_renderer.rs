@@ -0,0 +1,78 @@
+use crate::math::Vec4;
+use crate::renderer::material::MaterialId;
+use crate::renderer::mesh::Mesh;
+use crate::scene::{AnimationPlayer, Animator, Skin};
+use std::sync::Arc;
+
+/// Makes a scene node display a mesh deformed by the joints of a skin.
+/// The mesh has to be made of [SkinnedVertex](crate::renderer::mesh::SkinnedVertex).
+#[derive(Clone)]
+pub struct SkinnedMeshRenderer {
+    pub mesh: Arc<Mesh>,
+    pub skin: Arc<Skin>,
+    pub material: MaterialId,
+    /// Multiplied with the color of the material
+    pub tint: Vec4,
+    /// Free for the material's shaders to use
+    pub params: Vec4,
+    /// Weight of each morph target of the mesh when not animated, missing ones are 0.
+    /// Animations overwrite the weights they animate.
+    pub morph_weights: Vec<f32>,
+    /// Poses the joints, advanced by [Scene::update_animations](crate::scene::Scene::update_animations)
+    pub player: AnimationPlayer,
+    /// Drives the joints from parameters instead of the player when set
+    pub animator: Option<Animator>,
+}
+
+impl SkinnedMeshRenderer {
+    pub fn new(mesh: Arc<Mesh>, skin: Arc<Skin>) -> Self {
+        Self {
+            mesh,
+            skin,
+            material: MaterialId::DEFAULT,
+            tint: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            params: Vec4::ZERO,
+            morph_weights: Vec::new(),
+            player: AnimationPlayer::new(),
+            animator: None,
+        }
+    }
+
+    #[inline]
+    pub fn with_material(mut self, material: MaterialId) -> Self {
+        self.material = material;
+        self
+    }
+
+    #[inline]
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    #[inline]
+    pub fn with_params(mut self, params: Vec4) -> Self {
+        self.params = params;
+        self
+    }
+
+    #[inline]
+    pub fn with_morph_weights(mut self, morph_weights: Vec<f32>) -> Self {
+        self.morph_weights = morph_weights;
+        self
+    }
+
+    #[inline]
+    pub fn with_animator(mut self, animator: Animator) -> Self {
+        self.animator = Some(animator);
+        self
+    }
+
+    /// Set the weight of a morph target, see [Mesh::find_morph_target].
+    pub fn set_morph_weight(&mut self, target: usize, weight: f32) {
+        if self.morph_weights.len() <= target {
+            self.morph_weights.resize(target + 1, 0.0);
+        }
+        self.morph_weights[target] = weight;
+    }
+}
@@ -0,0 +1,827 @@
+use crate::math::{Quat, Vec2, Vec3, Vec4};
+use crate::scene::{NodeId, Projection, Scene, Transform};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Shape of the progress of a tween, `In` starts slow, `Out` ends slow.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineIn,
+    SineOut,
+    SineInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    /// Goes slightly backwards before leaving
+    BackIn,
+    /// Overshoots before settling
+    BackOut,
+    BackInOut,
+    /// Springs around the end
+    ElasticOut,
+    BounceOut,
+}
+
+impl Easing {
+    /// Eased progress of a linear one between 0 and 1, the ends are kept in place.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        // Overshoot of the back easings
+        const BACK: f32 = 1.70158;
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::QuadInOut => in_out(t, Easing::QuadIn),
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Easing::CubicInOut => in_out(t, Easing::CubicIn),
+            Easing::SineIn => 1.0 - (t * PI / 2.0).cos(),
+            Easing::SineOut => (t * PI / 2.0).sin(),
+            Easing::SineInOut => (1.0 - (t * PI).cos()) / 2.0,
+            Easing::ExpoIn if t == 0.0 => 0.0,
+            Easing::ExpoIn => 2f32.powf(10.0 * t - 10.0),
+            Easing::ExpoOut if t == 1.0 => 1.0,
+            Easing::ExpoOut => 1.0 - 2f32.powf(-10.0 * t),
+            Easing::ExpoInOut => in_out(t, Easing::ExpoIn),
+            Easing::BackIn => (BACK + 1.0) * t * t * t - BACK * t * t,
+            Easing::BackOut => 1.0 - Easing::BackIn.apply(1.0 - t),
+            Easing::BackInOut => in_out(t, Easing::BackIn),
+            Easing::ElasticOut if t == 0.0 || t == 1.0 => t,
+            Easing::ElasticOut => {
+                2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+            }
+            Easing::BounceOut => {
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1.0 / D {
+                    N * t * t
+                } else if t < 2.0 / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// The first half of an in easing, then the second half mirrored.
+fn in_out(t: f32, ease_in: Easing) -> f32 {
+    if t < 0.5 {
+        ease_in.apply(t * 2.0) / 2.0
+    } else {
+        1.0 - ease_in.apply((1.0 - t) * 2.0) / 2.0
+    }
+}
+
+/// Values a tween can go through.
+pub trait Tweenable: Copy + 'static {
+    fn interpolate(self, to: Self, t: f32) -> Self;
+}
+
+impl Tweenable for f32 {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self + (to - self) * t
+    }
+}
+
+impl Tweenable for Vec2 {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self.lerp(to, t)
+    }
+}
+
+impl Tweenable for Vec3 {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self.lerp(to, t)
+    }
+}
+
+impl Tweenable for Vec4 {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self.lerp(to, t)
+    }
+}
+
+/// Slerped, the shortest way.
+impl Tweenable for Quat {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self.slerp(to, t)
+    }
+}
+
+impl Tweenable for Transform {
+    fn interpolate(self, to: Self, t: f32) -> Self {
+        self.lerp(&to, t)
+    }
+}
+
+/// How many more times a tween plays after the first time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Repeat {
+    Count(u32),
+    Forever,
+}
+
+impl Default for Repeat {
+    fn default() -> Self {
+        Repeat::Count(0)
+    }
+}
+
+/// Identifies tweens and sequences to cancel them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TweenId(u64);
+
+/// Shared by every scene, ids stay unique when tweens move between them.
+static NEXT_TWEEN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Applies the value of a tween for its progress.
+trait TweenTrack {
+    /// Called once the delay is over, to read the value to start from.
+    fn start(&mut self, scene: &Scene);
+    fn apply(&mut self, scene: &mut Scene, t: f32);
+}
+
+type Getter<T> = Box<dyn Fn(&Scene) -> Option<T>>;
+type Setter<T> = Box<dyn FnMut(&mut Scene, T)>;
+type Callback = Box<dyn FnOnce(&mut Scene)>;
+
+struct PropertyTrack<T: Tweenable> {
+    /// Read when the tween starts if not given
+    from: Option<T>,
+    to: T,
+    get: Option<Getter<T>>,
+    set: Setter<T>,
+}
+
+impl<T: Tweenable> TweenTrack for PropertyTrack<T> {
+    fn start(&mut self, scene: &Scene) {
+        if self.from.is_none() {
+            self.from = self.get.as_ref().and_then(|get| get(scene));
+        }
+    }
+
+    fn apply(&mut self, scene: &mut Scene, t: f32) {
+        // The property disappeared before the tween started
+        if let Some(from) = self.from {
+            (self.set)(scene, from.interpolate(self.to, t));
+        }
+    }
+}
+
+/// Nothing to animate, for delays and callbacks in sequences.
+struct EmptyTrack;
+
+impl TweenTrack for EmptyTrack {
+    fn start(&mut self, _scene: &Scene) {}
+    fn apply(&mut self, _scene: &mut Scene, _t: f32) {}
+}
+
+/// Animates a value over time, see [Scene::tweens_mut] to play it.
+pub struct Tween {
+    track: Box<dyn TweenTrack>,
+    /// Seconds of each play
+    duration: f32,
+    /// Seconds before starting, only once
+    delay: f32,
+    easing: Easing,
+    repeat: Repeat,
+    /// Every other play goes backwards
+    yoyo: bool,
+    on_complete: Option<Callback>,
+
+    started: bool,
+    /// Seconds into the current play
+    elapsed: f32,
+    iteration: u32,
+}
+
+impl Tween {
+    /// Tween any value, given to the function every update.
+    pub fn new<T, F>(from: T, to: T, duration: f32, set: F) -> Self
+    where
+        T: Tweenable,
+        F: FnMut(&mut Scene, T) + 'static,
+    {
+        Self::with_track(
+            duration,
+            PropertyTrack {
+                from: Some(from),
+                to,
+                get: None,
+                set: Box::new(set),
+            },
+        )
+    }
+
+    /// Tween a value from what it is when the tween starts, nothing happens if it can't
+    /// be read then.
+    pub fn to<T, G, F>(to: T, duration: f32, get: G, set: F) -> Self
+    where
+        T: Tweenable,
+        G: Fn(&Scene) -> Option<T> + 'static,
+        F: FnMut(&mut Scene, T) + 'static,
+    {
+        Self::with_track(
+            duration,
+            PropertyTrack {
+                from: None,
+                to,
+                get: Some(Box::new(get)),
+                set: Box::new(set),
+            },
+        )
+    }
+
+    /// Do nothing for a while, to space the steps of a sequence.
+    pub fn wait(duration: f32) -> Self {
+        Self::with_track(duration, EmptyTrack)
+    }
+
+    /// Call a function, as a step of a sequence.
+    pub fn call<F: FnOnce(&mut Scene) + 'static>(callback: F) -> Self {
+        Self::wait(0.0).on_complete(callback)
+    }
+
+    fn with_track<T: TweenTrack + 'static>(duration: f32, track: T) -> Self {
+        Self {
+            track: Box::new(track),
+            duration,
+            delay: 0.0,
+            easing: Easing::Linear,
+            repeat: Repeat::default(),
+            yoyo: false,
+            on_complete: None,
+            started: false,
+            elapsed: 0.0,
+            iteration: 0,
+        }
+    }
+
+    pub fn translation(node: NodeId, to: Vec3, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| node_transform(scene, node).map(|transform| transform.translation),
+            move |scene, translation| {
+                if scene.graph().contains(node) {
+                    scene.graph_mut().local_transform_mut(node).translation = translation;
+                }
+            },
+        )
+    }
+
+    pub fn rotation(node: NodeId, to: Quat, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| node_transform(scene, node).map(|transform| transform.rotation),
+            move |scene, rotation| {
+                if scene.graph().contains(node) {
+                    scene.graph_mut().local_transform_mut(node).rotation = rotation;
+                }
+            },
+        )
+    }
+
+    pub fn scale(node: NodeId, to: Vec3, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| node_transform(scene, node).map(|transform| transform.scale),
+            move |scene, scale| {
+                if scene.graph().contains(node) {
+                    scene.graph_mut().local_transform_mut(node).scale = scale;
+                }
+            },
+        )
+    }
+
+    /// The whole local transform of a node.
+    pub fn transform(node: NodeId, to: Transform, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| node_transform(scene, node),
+            move |scene, transform| {
+                if scene.graph().contains(node) {
+                    scene.graph_mut().set_local_transform(node, transform);
+                }
+            },
+        )
+    }
+
+    /// Tint of the mesh or skinned mesh of a node.
+    pub fn tint(node: NodeId, to: Vec4, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| {
+                scene
+                    .renderable(node)
+                    .map(|renderable| renderable.tint)
+                    .or_else(|| scene.skinned_mesh(node).map(|skinned| skinned.tint))
+            },
+            move |scene, tint| {
+                scene.set_tint(node, tint);
+            },
+        )
+    }
+
+    /// Material parameters of the mesh or skinned mesh of a node.
+    pub fn params(node: NodeId, to: Vec4, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| {
+                scene
+                    .renderable(node)
+                    .map(|renderable| renderable.params)
+                    .or_else(|| scene.skinned_mesh(node).map(|skinned| skinned.params))
+            },
+            move |scene, params| {
+                scene.set_params(node, params);
+            },
+        )
+    }
+
+    /// Vertical field of view of a perspective camera, in radians.
+    pub fn fov(node: NodeId, to: f32, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| match scene.camera(node)?.projection {
+                Projection::Perspective { fov_y, .. } => Some(fov_y),
+                Projection::Orthographic { .. } => None,
+            },
+            move |scene, fov| {
+                if let Some(camera) = scene.camera_mut(node) {
+                    if let Projection::Perspective { fov_y, .. } = &mut camera.projection {
+                        *fov_y = fov;
+                    }
+                }
+            },
+        )
+    }
+
+    /// Color of the light of a node, linear RGB.
+    pub fn light_color(node: NodeId, to: Vec3, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| scene.light(node).map(|light| light.color),
+            move |scene, color| {
+                if let Some(light) = scene.light_mut(node) {
+                    light.color = color;
+                }
+            },
+        )
+    }
+
+    pub fn light_intensity(node: NodeId, to: f32, duration: f32) -> Self {
+        Self::to(
+            to,
+            duration,
+            move |scene| scene.light(node).map(|light| light.intensity),
+            move |scene, intensity| {
+                if let Some(light) = scene.light_mut(node) {
+                    light.intensity = intensity;
+                }
+            },
+        )
+    }
+
+    #[inline]
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    #[inline]
+    pub fn with_delay(mut self, delay: f32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    #[inline]
+    pub fn with_repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Go back and forth, each way counts as a play.
+    #[inline]
+    pub fn with_yoyo(mut self, yoyo: bool) -> Self {
+        self.yoyo = yoyo;
+        self
+    }
+
+    /// Called once every play is done, not when cancelled.
+    pub fn on_complete<F: FnOnce(&mut Scene) + 'static>(mut self, callback: F) -> Self {
+        self.on_complete = Some(Box::new(callback));
+        self
+    }
+
+    /// Apply the value for the time, returns the time left over once the tween is done.
+    fn advance(&mut self, scene: &mut Scene, mut time_step: f32) -> Option<f32> {
+        if self.delay > 0.0 {
+            let waited = self.delay.min(time_step);
+            self.delay -= waited;
+            time_step -= waited;
+            if self.delay > 0.0 {
+                return None;
+            }
+        }
+        if !self.started {
+            self.started = true;
+            self.track.start(scene);
+        }
+
+        self.elapsed += time_step;
+        loop {
+            let backwards = self.yoyo && self.iteration % 2 == 1;
+            if self.elapsed < self.duration {
+                let t = self.elapsed / self.duration;
+                let t = if backwards { 1.0 - t } else { t };
+                self.track.apply(scene, self.easing.apply(t));
+                return None;
+            }
+
+            let last = match self.repeat {
+                Repeat::Count(count) => self.iteration >= count,
+                Repeat::Forever => false,
+            };
+            if last {
+                self.track.apply(scene, if backwards { 0.0 } else { 1.0 });
+                return Some(self.elapsed - self.duration.max(0.0));
+            }
+            // Nothing to wait for, holds the end of the first play instead of looping forever
+            if self.duration <= 0.0 && self.repeat == Repeat::Forever {
+                self.track.apply(scene, if backwards { 0.0 } else { 1.0 });
+                return None;
+            }
+
+            self.elapsed -= self.duration;
+            self.iteration += 1;
+        }
+    }
+}
+
+fn node_transform(scene: &Scene, node: NodeId) -> Option<Transform> {
+    if scene.graph().contains(node) {
+        Some(*scene.graph().local_transform(node))
+    } else {
+        None
+    }
+}
+
+/// Tweens played one after the other.
+struct Sequence {
+    id: TweenId,
+    steps: VecDeque<Tween>,
+}
+
+/// Plays the tweens of a scene, moved forward by [Scene::update].
+#[derive(Default)]
+pub struct Tweener {
+    sequences: Vec<Sequence>,
+    /// Sequences taken out of the scene to be advanced, still playing for the callbacks
+    advancing: Vec<TweenId>,
+    /// Sequences of `advancing` the callbacks cancelled, removed once they are put back
+    cancelled: Vec<TweenId>,
+}
+
+impl Tweener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a tween, in parallel with the others.
+    pub fn play(&mut self, tween: Tween) -> TweenId {
+        self.play_sequence(vec![tween])
+    }
+
+    /// Start tweens that play one after the other, cancelled as a whole.
+    pub fn play_sequence(&mut self, tweens: Vec<Tween>) -> TweenId {
+        let id = TweenId(NEXT_TWEEN_ID.fetch_add(1, Ordering::Relaxed));
+        self.sequences.push(Sequence {
+            id,
+            steps: tweens.into(),
+        });
+        id
+    }
+
+    /// Stop a tween or sequence where it is, its callbacks aren't called.
+    /// Returns false when it is already done.
+    pub fn cancel(&mut self, id: TweenId) -> bool {
+        if let Some(index) = self.advancing.iter().position(|advancing| *advancing == id) {
+            self.advancing.remove(index);
+            self.cancelled.push(id);
+            return true;
+        }
+        let count = self.sequences.len();
+        self.sequences.retain(|sequence| sequence.id != id);
+        self.sequences.len() != count
+    }
+
+    pub fn cancel_all(&mut self) {
+        self.sequences.clear();
+        self.cancelled.append(&mut self.advancing);
+    }
+
+    pub fn is_playing(&self, id: TweenId) -> bool {
+        self.advancing.contains(&id) || self.sequences.iter().any(|sequence| sequence.id == id)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.sequences.len() + self.advancing.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Move the tweens forward by a number of seconds and apply them to a scene, the
+    /// tweener has to be taken out of the scene, see [Scene::update_tweens].
+    ///
+    /// The callbacks see the tweens through the scene and can cancel them, what they play
+    /// or cancel there is moved into this tweener afterwards.
+    pub fn advance(&mut self, scene: &mut Scene, time_step: f32) {
+        scene.tweens_mut().advancing = self.sequences.iter().map(|sequence| sequence.id).collect();
+
+        for sequence in &mut self.sequences {
+            let mut time_step = time_step;
+            while !scene.tweens().cancelled.contains(&sequence.id) {
+                let tween = match sequence.steps.front_mut() {
+                    Some(tween) => tween,
+                    None => break,
+                };
+                let left = match tween.advance(scene, time_step) {
+                    Some(left) => left,
+                    None => break,
+                };
+                let callback = tween.on_complete.take();
+                sequence.steps.pop_front();
+                if sequence.steps.is_empty() {
+                    // Done, even for its own callback
+                    scene.tweens_mut().advancing.retain(|id| *id != sequence.id);
+                }
+                if let Some(callback) = callback {
+                    callback(scene);
+                }
+                time_step = left;
+            }
+        }
+
+        let mut played = std::mem::take(scene.tweens_mut());
+        self.sequences.retain(|sequence| {
+            !sequence.steps.is_empty() && !played.cancelled.contains(&sequence.id)
+        });
+        self.sequences.append(&mut played.sequences);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Values set by the tweens and callbacks.
+    type Log = Rc<RefCell<Vec<f32>>>;
+
+    fn logged(from: f32, to: f32, duration: f32, log: &Log) -> Tween {
+        let log = log.clone();
+        Tween::new(from, to, duration, move |_, value| {
+            log.borrow_mut().push(value)
+        })
+    }
+
+    fn last(log: &Log) -> f32 {
+        *log.borrow().last().unwrap()
+    }
+
+    fn update(scene: &mut Scene, steps: &[f32]) {
+        for step in steps {
+            scene.update_tweens(*step);
+        }
+    }
+
+    #[test]
+    fn easings_keep_their_ends() {
+        let easings = [
+            Easing::Linear,
+            Easing::QuadIn,
+            Easing::QuadOut,
+            Easing::QuadInOut,
+            Easing::CubicIn,
+            Easing::CubicOut,
+            Easing::CubicInOut,
+            Easing::SineIn,
+            Easing::SineOut,
+            Easing::SineInOut,
+            Easing::ExpoIn,
+            Easing::ExpoOut,
+            Easing::ExpoInOut,
+            Easing::BackIn,
+            Easing::BackOut,
+            Easing::BackInOut,
+            Easing::ElasticOut,
+            Easing::BounceOut,
+        ];
+        for easing in &easings {
+            assert!(easing.apply(0.0).abs() < 1e-5, "{:?} at 0", easing);
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-5, "{:?} at 1", easing);
+            assert!(
+                (easing.apply(-1.0) - easing.apply(0.0)).abs() < 1e-6,
+                "{:?} before 0",
+                easing
+            );
+        }
+        assert!((Easing::QuadInOut.apply(0.5) - 0.5).abs() < 1e-6);
+        assert!(Easing::QuadIn.apply(0.5) < 0.5);
+        assert!(Easing::QuadOut.apply(0.5) > 0.5);
+        assert!(Easing::BackIn.apply(0.2) < 0.0);
+        assert!(Easing::BackOut.apply(0.8) > 1.0);
+    }
+
+    #[test]
+    fn tweens_end_exactly_on_their_target() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        let id = scene.tweens_mut().play(logged(0.0, 10.0, 1.0, &log));
+
+        update(&mut scene, &[0.25]);
+        assert!((last(&log) - 2.5).abs() < 1e-5);
+        assert!(scene.tweens().is_playing(id));
+
+        update(&mut scene, &[1.0]);
+        assert_eq!(last(&log), 10.0);
+        assert!(!scene.tweens().is_playing(id));
+        assert!(scene.tweens().is_empty());
+    }
+
+    #[test]
+    fn delays_only_hold_the_start() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        scene.tweens_mut().play(
+            logged(0.0, 1.0, 1.0, &log)
+                .with_delay(0.5)
+                .with_repeat(Repeat::Count(1)),
+        );
+
+        update(&mut scene, &[0.25]);
+        assert!(log.borrow().is_empty());
+        // What is left of the step after the delay goes into the tween
+        update(&mut scene, &[0.5]);
+        assert!((last(&log) - 0.25).abs() < 1e-5);
+        // The repeat starts right away
+        update(&mut scene, &[1.0]);
+        assert!((last(&log) - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn yoyo_plays_every_other_time_backwards() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        scene.tweens_mut().play(
+            logged(0.0, 1.0, 1.0, &log)
+                .with_yoyo(true)
+                .with_repeat(Repeat::Count(1)),
+        );
+
+        update(&mut scene, &[0.75]);
+        assert!((last(&log) - 0.75).abs() < 1e-5);
+        update(&mut scene, &[0.5]);
+        assert!((last(&log) - 0.75).abs() < 1e-5);
+        update(&mut scene, &[1.0]);
+        assert_eq!(last(&log), 0.0);
+        assert!(scene.tweens().is_empty());
+    }
+
+    #[test]
+    fn repeat_counts_add_plays() {
+        let done = Rc::new(RefCell::new(0));
+        let mut scene = Scene::new();
+        let counter = done.clone();
+        scene.tweens_mut().play(
+            Tween::wait(1.0)
+                .with_repeat(Repeat::Count(2))
+                .on_complete(move |_| *counter.borrow_mut() += 1),
+        );
+
+        update(&mut scene, &[1.0, 1.0]);
+        assert_eq!(*done.borrow(), 0);
+        update(&mut scene, &[1.0]);
+        assert_eq!(*done.borrow(), 1);
+        assert!(scene.tweens().is_empty());
+    }
+
+    #[test]
+    fn zero_duration_repeats_complete() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        let counted = scene.tweens_mut().play(
+            logged(0.0, 1.0, 0.0, &log)
+                .with_yoyo(true)
+                .with_repeat(Repeat::Count(3)),
+        );
+        let forever = scene
+            .tweens_mut()
+            .play(Tween::wait(0.0).with_repeat(Repeat::Forever));
+
+        update(&mut scene, &[0.0]);
+        assert!(!scene.tweens().is_playing(counted));
+        assert_eq!(last(&log), 0.0);
+        assert!(scene.tweens().is_playing(forever));
+    }
+
+    #[test]
+    fn sequences_carry_the_time_left_over() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        let call_log = log.clone();
+        let id = scene.tweens_mut().play_sequence(vec![
+            logged(0.0, 1.0, 1.0, &log),
+            Tween::call(move |_| call_log.borrow_mut().push(-1.0)),
+            logged(10.0, 20.0, 1.0, &log),
+        ]);
+
+        update(&mut scene, &[1.5]);
+        assert_eq!(&log.borrow()[..], &[1.0, -1.0, 15.0]);
+        assert!(scene.tweens().is_playing(id));
+        update(&mut scene, &[0.5]);
+        assert_eq!(last(&log), 20.0);
+        assert!(!scene.tweens().is_playing(id));
+    }
+
+    #[test]
+    fn cancelled_tweens_skip_their_callbacks() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        let call_log = log.clone();
+        let id = scene.tweens_mut().play(
+            logged(0.0, 1.0, 1.0, &log).on_complete(move |_| call_log.borrow_mut().push(-1.0)),
+        );
+
+        update(&mut scene, &[0.5]);
+        assert!(scene.tweens_mut().cancel(id));
+        assert!(!scene.tweens_mut().cancel(id));
+        update(&mut scene, &[1.0]);
+        assert_eq!(&log.borrow()[..], &[0.5]);
+    }
+
+    #[test]
+    fn callbacks_see_and_cancel_the_other_tweens() {
+        let log = Log::default();
+        let mut scene = Scene::new();
+        let later = scene
+            .tweens_mut()
+            .play(logged(0.0, 1.0, 1.0, &log).with_delay(1.0));
+        let seen = Rc::new(RefCell::new(false));
+        let seen_in_callback = seen.clone();
+        let first = scene.tweens_mut().play(Tween::call(move |scene| {
+            *seen_in_callback.borrow_mut() = scene.tweens().is_playing(later);
+            assert!(scene.tweens_mut().cancel(later));
+            assert!(!scene.tweens().is_playing(later));
+        }));
+        // Played by a callback during the update, kept for the next ones
+        let added_log = log.clone();
+        scene.tweens_mut().play(Tween::call(move |scene| {
+            scene
+                .tweens_mut()
+                .play(Tween::new(5.0, 6.0, 1.0, move |_, value| {
+                    added_log.borrow_mut().push(value)
+                }));
+        }));
+
+        update(&mut scene, &[0.0]);
+        assert!(*seen.borrow());
+        assert!(!scene.tweens().is_playing(first));
+        assert!(!scene.tweens().is_playing(later));
+        assert_eq!(scene.tweens().len(), 1);
+
+        update(&mut scene, &[2.0]);
+        assert_eq!(&log.borrow()[..], &[6.0]);
+        assert!(scene.tweens().is_empty());
+    }
+}